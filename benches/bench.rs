@@ -1,7 +1,17 @@
-use std::sync::{atomic::AtomicU32, mpsc, Arc, Mutex};
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    sync::{atomic::AtomicU32, mpsc, Arc},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
-use cmu_db_rs::{BufferPoolManager, DiskManager, ExtendibleHashTable, ThreadPool};
+use cmu_db_rs::{
+    rng_seed, seeded_rng, BufferPoolManager, BufferPoolStats, DiskManager, ExtendibleHashTable,
+    ThreadPool,
+};
 use criterion::{criterion_group, criterion_main, Criterion};
+use rand::Rng;
+use serde::Serialize;
 use tempfile::TempDir;
 
 const ENTRIES_NUMBER: u32 = 50;
@@ -10,6 +20,15 @@ const BUFFER_POOL_SIZE: usize = 1000;
 const REPLACER_K: usize = 4;
 const BUCKET_MAX_DEPTH: u32 = 14;
 const PAGE_SIZE: usize = 200;
+// working set of small pool benches is far larger than the pool
+const SMALL_POOL_ENTRIES_NUMBER: u32 = 5000;
+const SMALL_POOL_BUCKET_MAX_SIZE: usize = 16;
+const SMALL_POOL_SIZES: [usize; 2] = [8, 32];
+const FRAME_CHURN_POOL_SIZE: usize = 8;
+const FRAME_CHURN_PAGES: usize = 64;
+/// Path of file which small pool benches append JSON report of every configuration to,
+/// one report per line, so runs of different commits can be compared
+const REPORT_ENV: &str = "BENCH_REPORT";
 
 fn parallel_get_bench(c: &mut Criterion) {
     let mut group = c.benchmark_group("parallel get");
@@ -18,7 +37,7 @@ fn parallel_get_bench(c: &mut Criterion) {
         group.bench_with_input(
             format!("{}-thread threadpool", thread_number),
             thread_number,
-            |b, thread_number| {
+            |b, _thread_number| {
                 let client_thread_pool = ThreadPool::new(THREADS_NUMBER);
                 let disk_manager = DiskManager::new();
                 let buffer_pool_manager =
@@ -57,7 +76,7 @@ fn parallel_get_bench(c: &mut Criterion) {
 
                         client_thread_pool.spawn(move || {
                             let (key, value) = data.get(i as usize).unwrap();
-                            let result = match hash_table.get(key.to_string()) {
+                            let result = match hash_table.get(key.to_string()).unwrap() {
                                 Some(value) => {
                                     println!("Found value for key {key}");
                                     value
@@ -86,6 +105,31 @@ fn parallel_get_bench(c: &mut Criterion) {
     group.finish();
 }
 
+fn parallel_multi_get_bench(c: &mut Criterion) {
+    let thread_pool = ThreadPool::new(THREADS_NUMBER);
+    let disk_manager = DiskManager::new();
+    let buffer_pool_manager = BufferPoolManager::new(disk_manager, BUFFER_POOL_SIZE, REPLACER_K);
+    let hash_table = Arc::new(ExtendibleHashTable::<String, u32>::new(
+        "Test".into(),
+        Arc::new(buffer_pool_manager),
+        BUCKET_MAX_DEPTH,
+        PAGE_SIZE,
+    ));
+    let keys = (0..ENTRIES_NUMBER)
+        .map(|i| format!("key{}", i))
+        .collect::<Vec<String>>();
+    for key in &keys {
+        hash_table.insert(key.clone(), 111).unwrap();
+    }
+
+    c.bench_function("parallel multi get", |b| {
+        b.iter(|| {
+            let values = hash_table.get_parallel(keys.clone(), &thread_pool).unwrap();
+            assert!(values.iter().all(|value| *value == Some(111)));
+        });
+    });
+}
+
 fn parallel_mixed_bench(c: &mut Criterion) {
     let mut group = c.benchmark_group("parallel mixed");
 
@@ -93,7 +137,7 @@ fn parallel_mixed_bench(c: &mut Criterion) {
         group.bench_with_input(
             format!("{}-thread threadpool", thread_number),
             thread_number,
-            |b, thread_number| {
+            |b, _thread_number| {
                 let read_thread_pool = ThreadPool::new(THREADS_NUMBER);
                 let write_thread_pool = ThreadPool::new(THREADS_NUMBER);
                 let disk_manager = DiskManager::new();
@@ -107,15 +151,17 @@ fn parallel_mixed_bench(c: &mut Criterion) {
                 );
                 let (end_work_sender, end_work_receiver) = mpsc::channel::<()>();
 
+                let words = random_word::all(random_word::Lang::En);
+                let mut rng = seeded_rng("parallel mixed words");
                 let data_to_read = (0..ENTRIES_NUMBER)
                     .map(|i| {
-                        let word = random_word::gen(random_word::Lang::En);
+                        let word = words[rng.gen_range(0..words.len())];
                         (format!("{word} read {i}"), 111)
                     })
                     .collect::<Vec<(String, u32)>>();
                 let data_to_write = (0..ENTRIES_NUMBER)
                     .map(|i| {
-                        let word = random_word::gen(random_word::Lang::En);
+                        let word = words[rng.gen_range(0..words.len())];
                         (format!("{word} write {i}"), 222)
                     })
                     .collect::<Vec<(String, u32)>>();
@@ -152,7 +198,7 @@ fn parallel_mixed_bench(c: &mut Criterion) {
                         let hash_table_read = Arc::clone(&hash_table);
                         read_thread_pool.spawn(move || {
                             let (key, value) = data_to_read.get(i as usize).unwrap();
-                            let result = match hash_table_read.get(key.to_string()) {
+                            let result = match hash_table_read.get(key.to_string()).unwrap() {
                                 Some(value) => value,
                                 None => {
                                     println!("missing value for key {key}");
@@ -161,7 +207,7 @@ fn parallel_mixed_bench(c: &mut Criterion) {
                                 }
                             };
 
-                            //assert_eq!(&result, value);
+                            assert_eq!(&result, value);
 
                             let prev = counter.fetch_add(1, std::sync::atomic::Ordering::AcqRel);
 
@@ -178,5 +224,244 @@ fn parallel_mixed_bench(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(benches, parallel_mixed_bench, parallel_get_bench);
-criterion_main!(benches);
+// totals of the stats counters between two snapshots
+fn stats_delta(before: &BufferPoolStats, after: &BufferPoolStats) -> BufferPoolStats {
+    BufferPoolStats {
+        hits: after.hits - before.hits,
+        misses: after.misses - before.misses,
+        disk_reads: after.disk_reads - before.disk_reads,
+        disk_writes: after.disk_writes - before.disk_writes,
+        ..*after
+    }
+}
+
+/// Result of one bench configuration
+#[derive(Debug, Serialize)]
+struct BenchReport {
+    name: String,
+    /// Unix time of the run in seconds
+    timestamp: u64,
+    seed: u64,
+    config: BenchConfig,
+    ops: u64,
+    ops_per_sec: f64,
+    latency_us: LatencyPercentiles,
+    /// Share of page fetches served from buffer pool
+    hit_rate: f64,
+    disk_reads: u64,
+    disk_writes: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct BenchConfig {
+    pool_size: usize,
+    replacer_k: usize,
+    entries: u32,
+    bucket_max_size: usize,
+    write: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct LatencyPercentiles {
+    p50: f64,
+    p95: f64,
+    p99: f64,
+    max: f64,
+}
+
+impl LatencyPercentiles {
+    fn of(mut latencies: Vec<Duration>) -> Self {
+        latencies.sort_unstable();
+        let percentile = |percentile: f64| {
+            let index = (latencies.len() as f64 * percentile) as usize;
+            latencies
+                .get(index.min(latencies.len().saturating_sub(1)))
+                .map_or(0.0, |latency| latency.as_secs_f64() * 1e6)
+        };
+
+        Self {
+            p50: percentile(0.5),
+            p95: percentile(0.95),
+            p99: percentile(0.99),
+            max: percentile(1.0),
+        }
+    }
+}
+
+impl BenchReport {
+    fn new(
+        name: &str,
+        config: BenchConfig,
+        elapsed: Duration,
+        latencies: Vec<Duration>,
+        io: &BufferPoolStats,
+    ) -> Self {
+        let ops = latencies.len() as u64;
+
+        Self {
+            name: name.to_string(),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            seed: rng_seed(),
+            config,
+            ops,
+            ops_per_sec: ops as f64 / elapsed.as_secs_f64().max(f64::MIN_POSITIVE),
+            latency_us: LatencyPercentiles::of(latencies),
+            hit_rate: io.hits as f64 / (io.hits + io.misses).max(1) as f64,
+            disk_reads: io.disk_reads,
+            disk_writes: io.disk_writes,
+        }
+    }
+
+    fn print(&self) {
+        let ops = self.ops.max(1) as f64;
+        println!(
+            "{}: hit rate {:.1}%, {:.2} disk reads/op, {:.2} disk writes/op, p99 {:.1}us",
+            self.name,
+            self.hit_rate * 100.0,
+            self.disk_reads as f64 / ops,
+            self.disk_writes as f64 / ops,
+            self.latency_us.p99
+        );
+    }
+
+    // appended as one line, so the file is valid JSON Lines across runs
+    fn save(&self) {
+        let Ok(path) = std::env::var(REPORT_ENV) else {
+            return;
+        };
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+        writeln!(file, "{}", serde_json::to_string(self).unwrap()).unwrap();
+    }
+}
+
+// pages live in a real file, in-memory disk manager sleeps on every request
+fn small_pool_hash_table(
+    dir: &TempDir,
+    pool_size: usize,
+) -> (Arc<BufferPoolManager>, ExtendibleHashTable<String, u32>) {
+    let disk_manager = DiskManager::open(dir.path().join("bench.db")).unwrap();
+    let buffer_pool_manager = Arc::new(BufferPoolManager::new(disk_manager, pool_size, REPLACER_K));
+    let hash_table = ExtendibleHashTable::<String, u32>::new(
+        "Test".into(),
+        Arc::clone(&buffer_pool_manager),
+        BUCKET_MAX_DEPTH,
+        SMALL_POOL_BUCKET_MAX_SIZE,
+    );
+    for i in 0..SMALL_POOL_ENTRIES_NUMBER {
+        hash_table.insert(format!("key{}", i), i).unwrap();
+    }
+
+    (buffer_pool_manager, hash_table)
+}
+
+/// Random gets and inserts over working set which doesn't fit into pool, so every
+/// configuration exercises eviction and disk I/O. Hit rate, I/O per op and latency are
+/// printed after each configuration and saved to `BENCH_REPORT` file if it is set.
+fn small_pool_bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("small pool");
+
+    for pool_size in SMALL_POOL_SIZES {
+        for write in [false, true] {
+            let name = format!(
+                "{}-frame pool {}",
+                pool_size,
+                if write { "insert" } else { "get" }
+            );
+            let dir = TempDir::new().unwrap();
+            let (buffer_pool_manager, hash_table) = small_pool_hash_table(&dir, pool_size);
+            let mut rng = seeded_rng(&name);
+            let mut latencies = vec![];
+            let mut total_elapsed = Duration::ZERO;
+            let mut io = BufferPoolStats::default();
+
+            group.bench_function(&name, |b| {
+                b.iter_custom(|iters| {
+                    let before = buffer_pool_manager.stats().unwrap();
+                    let start = Instant::now();
+                    for _ in 0..iters {
+                        let i = rng.gen_range(0..SMALL_POOL_ENTRIES_NUMBER);
+                        let op_start = Instant::now();
+                        if write {
+                            hash_table.insert(format!("key{}", i), i + 1).unwrap();
+                        } else {
+                            assert!(hash_table.get(format!("key{}", i)).unwrap().is_some());
+                        }
+                        latencies.push(op_start.elapsed());
+                    }
+                    let elapsed = start.elapsed();
+
+                    let delta = stats_delta(&before, &buffer_pool_manager.stats().unwrap());
+                    total_elapsed += elapsed;
+                    io.hits += delta.hits;
+                    io.misses += delta.misses;
+                    io.disk_reads += delta.disk_reads;
+                    io.disk_writes += delta.disk_writes;
+
+                    elapsed
+                })
+            });
+            // configuration skipped by bench filter has nothing to report
+            if latencies.is_empty() {
+                continue;
+            }
+            let config = BenchConfig {
+                pool_size,
+                replacer_k: REPLACER_K,
+                entries: SMALL_POOL_ENTRIES_NUMBER,
+                bucket_max_size: SMALL_POOL_BUCKET_MAX_SIZE,
+                write,
+            };
+            let report = BenchReport::new(&name, config, total_elapsed, latencies, &io);
+            report.print();
+            report.save();
+        }
+    }
+    group.finish();
+}
+
+/// Cost of handing frames over to other pages: every new page and every fetch takes
+/// a frame which held another page before
+fn frame_churn_bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("frame churn");
+    let dir = TempDir::new().unwrap();
+    let disk_manager = DiskManager::open(dir.path().join("bench.db")).unwrap();
+    let buffer_pool_manager =
+        BufferPoolManager::new(disk_manager, FRAME_CHURN_POOL_SIZE, REPLACER_K);
+
+    let page_ids = (0..FRAME_CHURN_PAGES)
+        .map(|_| buffer_pool_manager.new_page().unwrap().0)
+        .collect::<Vec<_>>();
+    buffer_pool_manager.flush_all_pages().unwrap();
+
+    group.bench_function("new page", |b| {
+        b.iter(|| drop(buffer_pool_manager.new_page().unwrap()))
+    });
+    let mut next = 0;
+    group.bench_function("fetch evicted page", |b| {
+        b.iter(|| {
+            next = (next + 1) % page_ids.len();
+            drop(buffer_pool_manager.fetch_page_read(page_ids[next]).unwrap());
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    parallel_mixed_bench,
+    parallel_get_bench,
+    parallel_multi_get_bench
+);
+criterion_group! {
+    name = small_pool_benches;
+    config = Criterion::default().measurement_time(Duration::from_secs(3));
+    targets = small_pool_bench, frame_churn_bench
+}
+criterion_main!(benches, small_pool_benches);
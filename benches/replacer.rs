@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+
+use cmu_db_rs::{
+    read_access_trace, seeded_rng, AccessType, ArcReplacer, ClockReplacer, FrameId, LruKReplacer,
+    PageId, Replacer, SlruReplacer, TwoQReplacer,
+};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::Rng;
+
+const POOL_SIZE: usize = 64;
+const TRACE_LEN: usize = 20_000;
+const REPLACER_K: usize = 2;
+
+type Policy = (&'static str, fn() -> Box<dyn Replacer>);
+
+const POLICIES: [Policy; 5] = [
+    ("lru-k", || {
+        Box::new(LruKReplacer::new(POOL_SIZE, REPLACER_K))
+    }),
+    ("clock", || Box::new(ClockReplacer::new(POOL_SIZE))),
+    ("2q", || Box::new(TwoQReplacer::new(POOL_SIZE))),
+    ("arc", || Box::new(ArcReplacer::new(POOL_SIZE))),
+    ("slru", || Box::new(SlruReplacer::new(POOL_SIZE))),
+];
+
+/// Page access traces shaped like typical workloads, plus trace recorded by
+/// `BufferPoolManager::start_access_trace` if `ACCESS_TRACE` points to it
+fn traces() -> Vec<(&'static str, Vec<usize>)> {
+    let mut rng = seeded_rng("replacer traces");
+
+    // few pages get most of accesses
+    let skewed = (0..TRACE_LEN)
+        .map(|_| (rng.gen::<f64>().powi(3) * 1000.0) as usize)
+        .collect();
+    // hot set lookups interleaved with long one-off scans
+    let mut next_scan_page = 1000;
+    let scan_and_hot = (0..TRACE_LEN)
+        .map(|i| {
+            if i % 100 < 30 {
+                next_scan_page += 1;
+                next_scan_page
+            } else {
+                rng.gen_range(0..48)
+            }
+        })
+        .collect();
+    // loop over slightly more pages than pool holds
+    let looping = (0..TRACE_LEN).map(|i| i % (POOL_SIZE + 8)).collect();
+
+    let mut traces = vec![
+        ("skewed", skewed),
+        ("scan+hot", scan_and_hot),
+        ("loop", looping),
+    ];
+    if let Ok(path) = std::env::var("ACCESS_TRACE") {
+        let recorded = read_access_trace(path)
+            .unwrap()
+            .into_iter()
+            .map(|record| record.page_id.as_usize())
+            .collect();
+        traces.push(("recorded", recorded));
+    }
+
+    traces
+}
+
+/// Replay trace the way buffer pool drives replacer, returns number of hits
+fn replay(replacer: &mut dyn Replacer, trace: &[usize]) -> usize {
+    let mut frames: HashMap<usize, FrameId> = HashMap::new();
+    let mut pages: Vec<Option<usize>> = vec![None; POOL_SIZE];
+    let mut free_frames: Vec<FrameId> = (0..POOL_SIZE).map(FrameId::new).collect();
+    let mut hits = 0;
+
+    for &page_id in trace {
+        if let Some(&frame_id) = frames.get(&page_id) {
+            hits += 1;
+            replacer.record_access(frame_id, AccessType::Unknown);
+            replacer.set_evictable(frame_id, false);
+            replacer.set_evictable(frame_id, true);
+            continue;
+        }
+
+        let frame_id = match free_frames.pop() {
+            Some(frame_id) => frame_id,
+            None => {
+                let frame_id = replacer.evict().unwrap();
+                frames.remove(&pages[frame_id.as_usize()].unwrap());
+                replacer.remove(frame_id);
+                frame_id
+            }
+        };
+        pages[frame_id.as_usize()] = Some(page_id);
+        frames.insert(page_id, frame_id);
+        replacer.record_load(frame_id, PageId::new(page_id));
+        replacer.record_access(frame_id, AccessType::Unknown);
+        replacer.set_evictable(frame_id, true);
+    }
+
+    hits
+}
+
+fn replacer_bench(c: &mut Criterion) {
+    let traces = traces();
+
+    println!("hit rates, pool of {} frames:", POOL_SIZE);
+    for (trace_name, trace) in &traces {
+        let hit_rates = POLICIES
+            .iter()
+            .map(|(policy_name, new_replacer)| {
+                let hits = replay(new_replacer().as_mut(), trace);
+                format!("{policy_name} {:.3}", hits as f64 / trace.len() as f64)
+            })
+            .collect::<Vec<String>>();
+        println!("  {:<10} {}", trace_name, hit_rates.join("  "));
+    }
+
+    for (trace_name, trace) in &traces {
+        let mut group = c.benchmark_group(format!("replacer {trace_name}"));
+        group.sample_size(10);
+        for (policy_name, new_replacer) in POLICIES {
+            group.bench_with_input(
+                BenchmarkId::from_parameter(policy_name),
+                trace,
+                |b, trace| {
+                    b.iter(|| replay(new_replacer().as_mut(), trace));
+                },
+            );
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, replacer_bench);
+criterion_main!(benches);
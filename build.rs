@@ -0,0 +1,28 @@
+fn main() {
+    // gRPC service is described in Rust, so no protoc is needed
+    #[cfg(feature = "grpc")]
+    {
+        use tonic_build::manual::{Builder, Method, Service};
+
+        let method = |name: &str, route_name: &str, message: &str| {
+            Method::builder()
+                .name(name)
+                .route_name(route_name)
+                .input_type(format!("crate::admin_service::{message}Request"))
+                .output_type(format!("crate::admin_service::{message}Response"))
+                .codec_path("tonic::codec::ProstCodec")
+                .build()
+        };
+        let service = Service::builder()
+            .name("Admin")
+            .package("cmudb.admin")
+            .method(method("status", "Status", "Status"))
+            .method(method("flush", "Flush", "Flush"))
+            .method(method("get", "Get", "Get"))
+            .method(method("put", "Put", "Put"))
+            .method(method("delete", "Delete", "Delete"))
+            .build();
+
+        Builder::new().build_client(false).compile(&[service]);
+    }
+}
@@ -0,0 +1,55 @@
+//! Arbitrary bytes written over header, directory or bucket page must surface as errors
+
+#![no_main]
+
+use std::sync::Arc;
+
+use arbitrary::Arbitrary;
+use cmu_db_rs::{BufferPoolManager, DiskManager, ExtendibleHashTable};
+use libfuzzer_sys::fuzz_target;
+
+const PAGE_SIZE: usize = 4096;
+
+#[derive(Arbitrary, Debug)]
+enum Target {
+    Header,
+    Directory,
+    Bucket,
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    target: Target,
+    data: Vec<u8>,
+    key: u8,
+}
+
+fuzz_target!(|input: Input| {
+    // pool is large enough to never hit simulated disk
+    let buffer_pool_manager = Arc::new(BufferPoolManager::new(DiskManager::new(), 16, 2));
+    let hash_table = ExtendibleHashTable::<String, u32>::new(
+        "fuzz".into(),
+        Arc::clone(&buffer_pool_manager),
+        2,
+        4,
+    );
+    hash_table.insert("key".into(), 1).unwrap();
+
+    // pages are allocated in order: header, directory, bucket
+    let page_id = hash_table.header_page_id()
+        + match input.target {
+            Target::Header => 0,
+            Target::Directory => 1,
+            Target::Bucket => 2,
+        };
+    let mut page = buffer_pool_manager.fetch_page_write(page_id).unwrap();
+    *page = input.data;
+    page.truncate(PAGE_SIZE);
+    drop(page);
+
+    let key = format!("key{}", input.key % 8);
+    let _ = hash_table.get(key.clone());
+    let _ = hash_table.insert(key.clone(), 2);
+    let _ = hash_table.remove(key);
+    let _ = hash_table.scan();
+});
@@ -0,0 +1,58 @@
+//! Random insert/get/remove sequences applied to hash table and `HashMap` model at once
+
+#![no_main]
+
+use std::{collections::HashMap, sync::Arc};
+
+use arbitrary::Arbitrary;
+use cmu_db_rs::{BufferPoolManager, DiskManager, ExtendibleHashTable, ExtendibleHashTableError};
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Arbitrary, Debug)]
+enum Operation {
+    Insert(u8, u32),
+    Remove(u8),
+    Get(u8),
+}
+
+fuzz_target!(|operations: Vec<Operation>| {
+    // pool is large enough to never hit simulated disk
+    let buffer_pool_manager = BufferPoolManager::new(DiskManager::new(), 256, 2);
+    let hash_table =
+        ExtendibleHashTable::<String, u32>::new("fuzz".into(), Arc::new(buffer_pool_manager), 6, 4);
+    let mut model: HashMap<String, u32> = HashMap::new();
+
+    for operation in operations {
+        match operation {
+            Operation::Insert(key, value) => {
+                let key = format!("key{}", key % 64);
+                match hash_table.insert(key.clone(), value) {
+                    Ok(()) => {
+                        model.insert(key, value);
+                    }
+                    Err(ExtendibleHashTableError::DirectoryMaxSizeReached) => {}
+                    Err(error) => panic!("{error}"),
+                }
+            }
+            Operation::Remove(key) => {
+                let key = format!("key{}", key % 64);
+                assert_eq!(hash_table.remove(key.clone()).unwrap(), model.remove(&key));
+            }
+            Operation::Get(key) => {
+                let key = format!("key{}", key % 64);
+                assert_eq!(
+                    hash_table.get(key.clone()).unwrap(),
+                    model.get(&key).copied()
+                );
+            }
+        }
+        hash_table.verify_integrity();
+    }
+
+    let entries = hash_table
+        .scan()
+        .unwrap()
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+    assert_eq!(entries, model);
+});
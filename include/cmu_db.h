@@ -0,0 +1,103 @@
+#ifndef CMU_DB_H
+#define CMU_DB_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define CMUDB_OK 0
+
+// Key is absent
+#define CMUDB_NOT_FOUND 1
+
+// Iterator has no more entries
+#define CMUDB_DONE 2
+
+#define CMUDB_INVALID_ARGUMENT -1
+
+#define CMUDB_ERROR -2
+
+// Opaque handle of opened store
+typedef struct CmuDb CmuDb;
+
+// Opaque handle of iterator over entries
+typedef struct CmuDbIter CmuDbIter;
+
+// Message of the last error on calling thread, NULL if there was none.
+// String is valid until the next failed call on the same thread
+const char *cmudb_last_error(void);
+
+// Open store at path, data file is created if it doesn't exist
+//
+// # Safety
+// `path` is NUL terminated string, `db` is valid pointer to write handle to
+int cmudb_open(const char *path, struct CmuDb **db);
+
+// Flush and close store, NULL is ignored
+//
+// # Safety
+// `db` is handle returned by `cmudb_open` which is not closed yet
+int cmudb_close(struct CmuDb *db);
+
+// Read value of key, returns `CMUDB_NOT_FOUND` if key is absent.
+// Value buffer is owned by caller and released by `cmudb_free`
+//
+// # Safety
+// `db` is open handle, `key` points to `key_len` bytes,
+// `value` and `value_len` are valid pointers
+int cmudb_get(const struct CmuDb *db,
+              const uint8_t *key,
+              size_t key_len,
+              uint8_t **value,
+              size_t *value_len);
+
+// Insert or replace value of key
+//
+// # Safety
+// `db` is open handle, `key` and `value` point to `key_len` and `value_len` bytes
+int cmudb_put(const struct CmuDb *db,
+              const uint8_t *key,
+              size_t key_len,
+              const uint8_t *value,
+              size_t value_len);
+
+// Remove key, returns `CMUDB_NOT_FOUND` if key is absent
+//
+// # Safety
+// `db` is open handle, `key` points to `key_len` bytes
+int cmudb_delete(const struct CmuDb *db, const uint8_t *key, size_t key_len);
+
+// Release buffer returned by `cmudb_get`
+//
+// # Safety
+// `data` and `len` are exactly as returned by `cmudb_get`, buffer is not freed yet
+void cmudb_free(uint8_t *data, size_t len);
+
+// Start iteration over entries as of this moment, entries come in no particular order
+//
+// # Safety
+// `db` is open handle, `iter` is valid pointer to write handle to
+int cmudb_iter_new(const struct CmuDb *db, struct CmuDbIter **iter);
+
+// Move to the next entry, returns `CMUDB_DONE` when there are no more entries.
+// Key and value stay owned by iterator and are valid until the next call
+//
+// # Safety
+// `iter` is handle returned by `cmudb_iter_new`, output pointers are valid
+int cmudb_iter_next(struct CmuDbIter *iter,
+                    const uint8_t **key,
+                    size_t *key_len,
+                    const uint8_t **value,
+                    size_t *value_len);
+
+// Release iterator, NULL is ignored
+//
+// # Safety
+// `iter` is handle returned by `cmudb_iter_new` which is not freed yet
+void cmudb_iter_free(struct CmuDbIter *iter);
+
+#endif  /* CMU_DB_H */
@@ -0,0 +1,176 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{
+    lru_k_replacer::{AccessType, FrameId},
+    page::PageId,
+    replacer::Replacer,
+};
+
+// little endian timestamp in microseconds, page id and flags byte
+const RECORD_SIZE: usize = 17;
+const HIT_FLAG: u8 = 0b1000_0000;
+
+/// Single page access of buffer pool
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AccessRecord {
+    /// Time since recording started
+    pub timestamp: Duration,
+    pub page_id: PageId,
+    pub access_type: AccessType,
+    /// Page was already in buffer pool
+    pub hit: bool,
+}
+
+impl AccessRecord {
+    fn to_bytes(self) -> [u8; RECORD_SIZE] {
+        let mut bytes = [0; RECORD_SIZE];
+        bytes[..8].copy_from_slice(&(self.timestamp.as_micros() as u64).to_le_bytes());
+        bytes[8..16].copy_from_slice(&(self.page_id.as_usize() as u64).to_le_bytes());
+        let access_type = match self.access_type {
+            AccessType::Unknown => 0,
+            AccessType::Lookup => 1,
+            AccessType::Scan => 2,
+            AccessType::Index => 3,
+        };
+        bytes[16] = access_type | if self.hit { HIT_FLAG } else { 0 };
+
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8; RECORD_SIZE]) -> Result<Self> {
+        let timestamp = u64::from_le_bytes(bytes[..8].try_into()?);
+        let page_id = u64::from_le_bytes(bytes[8..16].try_into()?);
+        let access_type = match bytes[16] & !HIT_FLAG {
+            0 => AccessType::Unknown,
+            1 => AccessType::Lookup,
+            2 => AccessType::Scan,
+            3 => AccessType::Index,
+            flags => bail!("Unknown access type {} in trace.", flags),
+        };
+
+        Ok(Self {
+            timestamp: Duration::from_micros(timestamp),
+            page_id: PageId::new(page_id as usize),
+            access_type,
+            hit: bytes[16] & HIT_FLAG != 0,
+        })
+    }
+}
+
+/// Appends buffer pool accesses to trace file, see `read_access_trace`
+#[derive(Debug)]
+pub(crate) struct AccessTraceRecorder {
+    writer: BufWriter<File>,
+    started_at: Instant,
+}
+
+impl AccessTraceRecorder {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::create(path)
+            .with_context(|| format!("Can't create access trace {}.", path.display()))?;
+
+        Ok(Self {
+            writer: BufWriter::new(file),
+            started_at: Instant::now(),
+        })
+    }
+
+    pub fn record(&mut self, page_id: PageId, access_type: AccessType, hit: bool) -> Result<()> {
+        let record = AccessRecord {
+            timestamp: self.started_at.elapsed(),
+            page_id,
+            access_type,
+            hit,
+        };
+        self.writer.write_all(&record.to_bytes())?;
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        self.writer.flush()?;
+
+        Ok(())
+    }
+}
+
+/// Read trace written by `BufferPoolManager::start_access_trace`,
+/// partially written last record is ignored
+pub fn read_access_trace(path: impl AsRef<Path>) -> Result<Vec<AccessRecord>> {
+    let path = path.as_ref();
+    let file =
+        File::open(path).with_context(|| format!("Can't open access trace {}.", path.display()))?;
+    let mut data = vec![];
+    BufReader::new(file).read_to_end(&mut data)?;
+
+    data.chunks_exact(RECORD_SIZE)
+        .map(|chunk| AccessRecord::from_bytes(chunk.try_into()?))
+        .collect()
+}
+
+/// Outcome of replaying trace against pool configuration
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ReplayStats {
+    pub hits: usize,
+    pub misses: usize,
+    pub evictions: usize,
+}
+
+impl ReplayStats {
+    pub fn hit_rate(&self) -> f64 {
+        self.hits as f64 / (self.hits + self.misses).max(1) as f64
+    }
+}
+
+/// Feed trace into pool of `pool_size` frames evicting by `replacer`.
+/// Every access pins and unpins its page right away
+pub fn replay_access_trace(
+    records: &[AccessRecord],
+    pool_size: usize,
+    replacer: &mut dyn Replacer,
+) -> ReplayStats {
+    let mut frames: HashMap<PageId, FrameId> = HashMap::new();
+    let mut pages: Vec<Option<PageId>> = vec![None; pool_size];
+    let mut free_frames: Vec<FrameId> = (0..pool_size).rev().map(FrameId::new).collect();
+    let mut stats = ReplayStats::default();
+
+    for record in records {
+        if let Some(&frame_id) = frames.get(&record.page_id) {
+            stats.hits += 1;
+            replacer.record_access(frame_id, record.access_type);
+            continue;
+        }
+
+        stats.misses += 1;
+        let frame_id = match free_frames.pop() {
+            Some(frame_id) => frame_id,
+            None => {
+                let Some(frame_id) = replacer.evict() else {
+                    continue;
+                };
+                stats.evictions += 1;
+                if let Some(page_id) = pages[frame_id.as_usize()] {
+                    frames.remove(&page_id);
+                }
+                replacer.remove(frame_id);
+                frame_id
+            }
+        };
+        pages[frame_id.as_usize()] = Some(record.page_id);
+        frames.insert(record.page_id, frame_id);
+        replacer.record_load(frame_id, record.page_id);
+        replacer.record_access(frame_id, record.access_type);
+        replacer.set_evictable(frame_id, true);
+    }
+
+    stats
+}
@@ -0,0 +1,202 @@
+use std::sync::Arc;
+
+use tonic::{Request, Response, Status};
+
+use crate::{
+    db_instance::{DbInstance, RecoveryPath},
+    kv::Kv,
+};
+
+// service trait and server are generated by build.rs
+include!(concat!(env!("OUT_DIR"), "/cmudb.admin.Admin.rs"));
+
+pub use admin_server::{Admin, AdminServer};
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct StatusRequest {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct StatusResponse {
+    #[prost(uint64, tag = "1")]
+    pub pool_size: u64,
+    #[prost(uint64, tag = "2")]
+    pub free_frames: u64,
+    #[prost(uint64, tag = "3")]
+    pub resident_pages: u64,
+    #[prost(uint64, tag = "4")]
+    pub dirty_pages: u64,
+    #[prost(uint64, tag = "5")]
+    pub evictable_frames: u64,
+    #[prost(uint64, tag = "6")]
+    pub disk_pages: u64,
+    #[prost(bool, tag = "7")]
+    pub recovered_from_crash: bool,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FlushRequest {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct FlushResponse {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct GetRequest {
+    #[prost(string, tag = "1")]
+    pub key: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct GetResponse {
+    #[prost(string, optional, tag = "1")]
+    pub value: Option<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PutRequest {
+    #[prost(string, tag = "1")]
+    pub key: String,
+    #[prost(string, tag = "2")]
+    pub value: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct PutResponse {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct DeleteRequest {
+    #[prost(string, tag = "1")]
+    pub key: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct DeleteResponse {
+    #[prost(string, optional, tag = "1")]
+    pub old_value: Option<String>,
+}
+
+fn internal(error: anyhow::Error) -> Status {
+    Status::internal(format!("{:#}", error))
+}
+
+/// gRPC service reporting engine status, flushing it and serving simple KV operations
+/// over `String` keys and values
+#[derive(Debug)]
+pub struct AdminService {
+    db: Arc<DbInstance>,
+    kv: Kv<String, String>,
+}
+
+impl AdminService {
+    /// Serve default namespace of the database
+    pub fn new(db: Arc<DbInstance>) -> anyhow::Result<Self> {
+        let kv = Kv::open_namespace(Arc::clone(&db), crate::kv::DEFAULT_NAMESPACE)?;
+
+        Ok(Self { db, kv })
+    }
+
+    pub fn into_server(self) -> AdminServer<Self> {
+        AdminServer::new(self)
+    }
+}
+
+#[tonic::async_trait]
+impl Admin for AdminService {
+    async fn status(
+        &self,
+        _request: Request<StatusRequest>,
+    ) -> Result<Response<StatusResponse>, Status> {
+        let stats = self.db.buffer_pool_manager().stats().map_err(internal)?;
+
+        Ok(Response::new(StatusResponse {
+            pool_size: stats.pool_size as u64,
+            free_frames: stats.free_frames as u64,
+            resident_pages: stats.resident_pages as u64,
+            dirty_pages: stats.dirty_pages as u64,
+            evictable_frames: stats.evictable_frames as u64,
+            disk_pages: stats.disk_pages as u64,
+            recovered_from_crash: self.db.recovery_path() == RecoveryPath::CrashRecovery,
+        }))
+    }
+
+    async fn flush(
+        &self,
+        _request: Request<FlushRequest>,
+    ) -> Result<Response<FlushResponse>, Status> {
+        self.db.flush().map_err(internal)?;
+
+        Ok(Response::new(FlushResponse {}))
+    }
+
+    async fn get(&self, request: Request<GetRequest>) -> Result<Response<GetResponse>, Status> {
+        let value = self.kv.get(request.into_inner().key).map_err(internal)?;
+
+        Ok(Response::new(GetResponse { value }))
+    }
+
+    async fn put(&self, request: Request<PutRequest>) -> Result<Response<PutResponse>, Status> {
+        let PutRequest { key, value } = request.into_inner();
+        self.kv.insert(key, value).map_err(internal)?;
+
+        Ok(Response::new(PutResponse {}))
+    }
+
+    async fn delete(
+        &self,
+        request: Request<DeleteRequest>,
+    ) -> Result<Response<DeleteResponse>, Status> {
+        let old_value = self.kv.remove(request.into_inner().key).map_err(internal)?;
+
+        Ok(Response::new(DeleteResponse { old_value }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_admin_service() {
+        let dir = TempDir::new().unwrap();
+        let db = Arc::new(DbInstance::open(dir.path().join("test.db")).unwrap());
+        let service = AdminService::new(db).unwrap();
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        runtime.block_on(async {
+            service
+                .put(Request::new(PutRequest {
+                    key: "key".into(),
+                    value: "value".into(),
+                }))
+                .await
+                .unwrap();
+            let response = service
+                .get(Request::new(GetRequest { key: "key".into() }))
+                .await
+                .unwrap();
+            assert_eq!(response.into_inner().value, Some("value".into()));
+
+            let status = service
+                .status(Request::new(StatusRequest {}))
+                .await
+                .unwrap()
+                .into_inner();
+            assert!(status.resident_pages > 0);
+
+            service.flush(Request::new(FlushRequest {})).await.unwrap();
+            let status = service
+                .status(Request::new(StatusRequest {}))
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(status.dirty_pages, 0);
+
+            let response = service
+                .delete(Request::new(DeleteRequest { key: "key".into() }))
+                .await
+                .unwrap();
+            assert_eq!(response.into_inner().old_value, Some("value".into()));
+        });
+    }
+}
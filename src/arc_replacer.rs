@@ -0,0 +1,207 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::{
+    lru_k_replacer::{AccessType, FrameId},
+    page::PageId,
+    replacer::Replacer,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum List {
+    // seen once recently
+    Recent,
+    // seen at least twice recently
+    Frequent,
+}
+
+#[derive(Debug)]
+struct ArcEntry {
+    list: List,
+    page_id: Option<PageId>,
+    is_evictable: bool,
+}
+
+/// Adaptive replacement cache: resident frames are split between recency and frequency
+/// lists, target size of recency list adapts by hits in ghost lists of recently evicted pages
+#[derive(Debug)]
+pub struct ArcReplacer {
+    num_of_frames: usize,
+    // target size of recent list
+    target: usize,
+    // front is least recently used end of every list
+    recent: VecDeque<FrameId>,
+    frequent: VecDeque<FrameId>,
+    recent_ghosts: VecDeque<PageId>,
+    frequent_ghosts: VecDeque<PageId>,
+    entries: HashMap<FrameId, ArcEntry>,
+    loaded: HashMap<FrameId, (PageId, List)>,
+}
+
+impl ArcReplacer {
+    pub fn new(num_of_frames: usize) -> Self {
+        Self {
+            num_of_frames,
+            target: 0,
+            recent: VecDeque::new(),
+            frequent: VecDeque::new(),
+            recent_ghosts: VecDeque::new(),
+            frequent_ghosts: VecDeque::new(),
+            entries: HashMap::new(),
+            loaded: HashMap::new(),
+        }
+    }
+
+    fn list_mut(&mut self, list: List) -> &mut VecDeque<FrameId> {
+        match list {
+            List::Recent => &mut self.recent,
+            List::Frequent => &mut self.frequent,
+        }
+    }
+
+    fn evict_from(&mut self, list: List) -> Option<FrameId> {
+        let frames = match list {
+            List::Recent => &mut self.recent,
+            List::Frequent => &mut self.frequent,
+        };
+        let position = frames
+            .iter()
+            .position(|frame_id| self.entries[frame_id].is_evictable)?;
+        let frame_id = frames.remove(position)?;
+        let entry = self.entries.remove(&frame_id)?;
+
+        if let Some(page_id) = entry.page_id {
+            let ghosts = match list {
+                List::Recent => &mut self.recent_ghosts,
+                List::Frequent => &mut self.frequent_ghosts,
+            };
+            ghosts.push_back(page_id);
+            // ghosts never outnumber frames
+            if self.recent_ghosts.len() + self.frequent_ghosts.len() > self.num_of_frames {
+                if self.recent_ghosts.len() > self.frequent_ghosts.len() {
+                    self.recent_ghosts.pop_front();
+                } else {
+                    self.frequent_ghosts.pop_front();
+                }
+            }
+        }
+
+        Some(frame_id)
+    }
+}
+
+impl Replacer for ArcReplacer {
+    fn record_load(&mut self, frame_id: FrameId, page_id: PageId) {
+        let recent_ghosts = self.recent_ghosts.len().max(1);
+        let frequent_ghosts = self.frequent_ghosts.len().max(1);
+
+        let list = if let Some(position) = self.recent_ghosts.iter().position(|id| *id == page_id) {
+            // recency list was evicted too early, let it grow
+            self.recent_ghosts.remove(position);
+            self.target =
+                (self.target + (frequent_ghosts / recent_ghosts).max(1)).min(self.num_of_frames);
+            List::Frequent
+        } else if let Some(position) = self.frequent_ghosts.iter().position(|id| *id == page_id) {
+            self.frequent_ghosts.remove(position);
+            self.target = self
+                .target
+                .saturating_sub((recent_ghosts / frequent_ghosts).max(1));
+            List::Frequent
+        } else {
+            List::Recent
+        };
+        self.loaded.insert(frame_id, (page_id, list));
+    }
+
+    fn record_access(&mut self, frame_id: FrameId, _access_type: AccessType) {
+        if let Some(entry) = self.entries.get_mut(&frame_id) {
+            let list = entry.list;
+            entry.list = List::Frequent;
+            self.list_mut(list).retain(|id| *id != frame_id);
+            self.frequent.push_back(frame_id);
+            return;
+        }
+
+        let (page_id, list) = match self.loaded.remove(&frame_id) {
+            Some((page_id, list)) => (Some(page_id), list),
+            None => (None, List::Recent),
+        };
+        self.list_mut(list).push_back(frame_id);
+        self.entries.insert(
+            frame_id,
+            ArcEntry {
+                list,
+                page_id,
+                is_evictable: false,
+            },
+        );
+    }
+
+    fn set_evictable(&mut self, frame_id: FrameId, is_evictable: bool) {
+        if let Some(entry) = self.entries.get_mut(&frame_id) {
+            entry.is_evictable = is_evictable;
+        }
+    }
+
+    fn evict(&mut self) -> Option<FrameId> {
+        if !self.recent.is_empty() && self.recent.len() > self.target {
+            self.evict_from(List::Recent)
+                .or_else(|| self.evict_from(List::Frequent))
+        } else {
+            self.evict_from(List::Frequent)
+                .or_else(|| self.evict_from(List::Recent))
+        }
+    }
+
+    fn remove(&mut self, frame_id: FrameId) {
+        self.loaded.remove(&frame_id);
+        if let Some(entry) = self.entries.remove(&frame_id) {
+            self.list_mut(entry.list).retain(|id| *id != frame_id);
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.entries
+            .values()
+            .filter(|entry| entry.is_evictable)
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(replacer: &mut ArcReplacer, frame_id: usize, page_id: usize) {
+        let frame_id = FrameId::new(frame_id);
+        replacer.record_load(frame_id, PageId::new(page_id));
+        replacer.record_access(frame_id, AccessType::Unknown);
+        replacer.set_evictable(frame_id, true);
+    }
+
+    #[test]
+    fn test_frequent_frames_outlive_recent_ones() {
+        let mut replacer = ArcReplacer::new(3);
+        for frame_id in 0..3 {
+            load(&mut replacer, frame_id, frame_id);
+        }
+        replacer.record_access(FrameId::new(0), AccessType::Unknown);
+
+        assert_eq!(replacer.evict(), Some(FrameId::new(1)));
+        assert_eq!(replacer.evict(), Some(FrameId::new(2)));
+        assert_eq!(replacer.evict(), Some(FrameId::new(0)));
+        assert_eq!(replacer.size(), 0);
+    }
+
+    #[test]
+    fn test_ghost_hit_grows_recent_target() {
+        let mut replacer = ArcReplacer::new(2);
+        load(&mut replacer, 0, 10);
+        load(&mut replacer, 1, 11);
+
+        assert_eq!(replacer.evict(), Some(FrameId::new(0)));
+        load(&mut replacer, 0, 10);
+
+        assert_eq!(replacer.target, 1);
+        assert_eq!(replacer.entries[&FrameId::new(0)].list, List::Frequent);
+    }
+}
@@ -0,0 +1,341 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Debug,
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::{bail, Result};
+
+use crate::ThreadPool;
+
+// scheduler wakes up at least this often even if no job is due
+const MAX_SCHEDULER_WAIT: Duration = Duration::from_secs(1);
+
+type JobFn = dyn Fn() -> Result<()> + Send + Sync;
+
+struct Job {
+    run: Arc<JobFn>,
+    interval: Duration,
+    paused: bool,
+    running: bool,
+    next_run: Instant,
+    runs: u64,
+    last_run: Option<SystemTime>,
+    last_duration: Option<Duration>,
+    last_error: Option<String>,
+}
+
+impl Job {
+    fn status(&self, name: &str) -> JobStatus {
+        JobStatus {
+            name: name.to_string(),
+            interval: self.interval,
+            paused: self.paused,
+            running: self.running,
+            runs: self.runs,
+            last_run: self.last_run,
+            last_duration: self.last_duration,
+            last_error: self.last_error.clone(),
+        }
+    }
+}
+
+/// State of recurring job as seen by `BackgroundJobs::status`
+#[derive(Debug, Clone, PartialEq)]
+pub struct JobStatus {
+    pub name: String,
+    pub interval: Duration,
+    pub paused: bool,
+    pub running: bool,
+    /// Number of finished runs
+    pub runs: u64,
+    /// Start time of the last finished run
+    pub last_run: Option<SystemTime>,
+    pub last_duration: Option<Duration>,
+    /// Error or panic of the last run, `None` if it succeeded
+    pub last_error: Option<String>,
+}
+
+#[derive(Default)]
+struct SchedulerState {
+    jobs: BTreeMap<String, Job>,
+    // runs in progress, including ones of removed jobs
+    running: usize,
+    shutdown: bool,
+}
+
+struct Scheduler {
+    state: Mutex<SchedulerState>,
+    // signalled when jobs are changed or finish their run
+    changed: Condvar,
+    thread_pool: Arc<ThreadPool>,
+}
+
+impl Scheduler {
+    fn lock(&self) -> MutexGuard<'_, SchedulerState> {
+        self.state.lock().unwrap_or_else(|error| error.into_inner())
+    }
+
+    // start due jobs on thread pool until shutdown
+    fn run(self: Arc<Self>) {
+        let mut state = self.lock();
+        while !state.shutdown {
+            let now = Instant::now();
+            let mut wake_at = now + MAX_SCHEDULER_WAIT;
+
+            let SchedulerState { jobs, running, .. } = &mut *state;
+            for (name, job) in jobs.iter_mut() {
+                if job.paused || job.running {
+                    continue;
+                }
+                if job.next_run > now {
+                    wake_at = wake_at.min(job.next_run);
+                    continue;
+                }
+
+                job.running = true;
+                *running += 1;
+                let scheduler = Arc::clone(&self);
+                let name = name.clone();
+                let run = Arc::clone(&job.run);
+                self.thread_pool.spawn(move || scheduler.run_job(name, run));
+            }
+
+            state = self
+                .changed
+                .wait_timeout(state, wake_at.saturating_duration_since(now))
+                .unwrap_or_else(|error| error.into_inner())
+                .0;
+        }
+    }
+
+    fn run_job(&self, name: String, run: Arc<JobFn>) {
+        let started_at = SystemTime::now();
+        let started = Instant::now();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| run()));
+        let last_error = match result {
+            Ok(Ok(())) => None,
+            Ok(Err(error)) => Some(format!("{:#}", error)),
+            Err(_) => Some("Job panicked.".to_string()),
+        };
+        if let Some(error) = &last_error {
+            tracing::warn!(job = name, error, "background job failed");
+        }
+
+        let mut state = self.lock();
+        state.running -= 1;
+        // job could be removed while it was running
+        if let Some(job) = state.jobs.get_mut(&name) {
+            job.running = false;
+            job.runs += 1;
+            job.last_run = Some(started_at);
+            job.last_duration = Some(started.elapsed());
+            job.last_error = last_error;
+            job.next_run = Instant::now() + job.interval;
+        }
+        drop(state);
+        self.changed.notify_all();
+    }
+}
+
+/// Recurring maintenance jobs of the database like dirty page writer or checkpointer.
+/// Scheduler thread starts every job on thread pool once its interval passes since the end
+/// of its previous run, so a job never overlaps with itself.
+///
+/// Dropping the scheduler waits for running jobs to finish, so jobs must not own it.
+pub struct BackgroundJobs {
+    scheduler: Arc<Scheduler>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Debug for BackgroundJobs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BackgroundJobs")
+            .field("jobs", &self.status())
+            .finish()
+    }
+}
+
+impl BackgroundJobs {
+    /// Run jobs on given thread pool
+    pub fn new(thread_pool: Arc<ThreadPool>) -> Self {
+        let scheduler = Arc::new(Scheduler {
+            state: Mutex::new(SchedulerState::default()),
+            changed: Condvar::new(),
+            thread_pool,
+        });
+
+        let thread = thread::Builder::new()
+            .name("background-jobs".into())
+            .spawn({
+                let scheduler = Arc::clone(&scheduler);
+                move || scheduler.run()
+            })
+            .expect("Can't spawn background jobs scheduler thread.");
+
+        Self {
+            scheduler,
+            thread: Some(thread),
+        }
+    }
+
+    /// Run `job` every `interval`, the first run happens after one interval.
+    /// Job registered under the same name before is replaced.
+    pub fn register<F>(&self, name: impl Into<String>, interval: Duration, job: F)
+    where
+        F: Fn() -> Result<()> + Send + Sync + 'static,
+    {
+        let job = Job {
+            run: Arc::new(job),
+            interval,
+            paused: false,
+            running: false,
+            next_run: Instant::now() + interval,
+            runs: 0,
+            last_run: None,
+            last_duration: None,
+            last_error: None,
+        };
+
+        self.scheduler.lock().jobs.insert(name.into(), job);
+        self.scheduler.changed.notify_all();
+    }
+
+    /// Stop running job, run in progress is not interrupted. Returns false if there is no
+    /// such job.
+    pub fn remove(&self, name: &str) -> bool {
+        self.scheduler.lock().jobs.remove(name).is_some()
+    }
+
+    /// Skip runs of the job until it is resumed
+    pub fn pause(&self, name: &str) -> Result<()> {
+        self.update(name, |job| job.paused = true)
+    }
+
+    /// Resume paused job, it runs right away if its interval passed meanwhile
+    pub fn resume(&self, name: &str) -> Result<()> {
+        self.update(name, |job| job.paused = false)
+    }
+
+    /// Change interval of the job, the next run happens one new interval from now
+    pub fn set_interval(&self, name: &str, interval: Duration) -> Result<()> {
+        self.update(name, |job| {
+            job.next_run = Instant::now() + interval;
+            job.interval = interval;
+        })
+    }
+
+    fn update(&self, name: &str, f: impl FnOnce(&mut Job)) -> Result<()> {
+        let mut state = self.scheduler.lock();
+        let Some(job) = state.jobs.get_mut(name) else {
+            bail!("Background job {} is not registered.", name);
+        };
+        f(job);
+        drop(state);
+        self.scheduler.changed.notify_all();
+
+        Ok(())
+    }
+
+    /// Status of all registered jobs ordered by name
+    pub fn status(&self) -> Vec<JobStatus> {
+        self.scheduler
+            .lock()
+            .jobs
+            .iter()
+            .map(|(name, job)| job.status(name))
+            .collect()
+    }
+
+    pub fn job_status(&self, name: &str) -> Option<JobStatus> {
+        self.scheduler
+            .lock()
+            .jobs
+            .get(name)
+            .map(|job| job.status(name))
+    }
+
+    /// Stop starting jobs and wait for running ones to finish, registered jobs are removed.
+    /// It is done on drop as well.
+    pub fn stop(&mut self) {
+        self.scheduler.lock().shutdown = true;
+        self.scheduler.changed.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+
+        let mut state = self.scheduler.lock();
+        while state.running > 0 {
+            state = self
+                .scheduler
+                .changed
+                .wait(state)
+                .unwrap_or_else(|error| error.into_inner());
+        }
+        state.jobs.clear();
+    }
+}
+
+impl Drop for BackgroundJobs {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use anyhow::anyhow;
+
+    use super::*;
+
+    fn wait_for(mut condition: impl FnMut() -> bool) {
+        for _ in 0..200 {
+            if condition() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        panic!("Condition is not met in time.");
+    }
+
+    #[test]
+    fn test_background_jobs() {
+        let jobs = BackgroundJobs::new(Arc::new(ThreadPool::new(2)));
+        let counter = Arc::new(AtomicUsize::new(0));
+        jobs.register("counter", Duration::from_millis(5), {
+            let counter = Arc::clone(&counter);
+            move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            }
+        });
+        jobs.register("failing", Duration::from_millis(5), || {
+            Err(anyhow!("Disk is full."))
+        });
+
+        wait_for(|| counter.load(Ordering::SeqCst) >= 3);
+        wait_for(|| jobs.job_status("failing").unwrap().runs > 0);
+        let status = jobs.job_status("failing").unwrap();
+        assert_eq!(status.last_error.as_deref(), Some("Disk is full."));
+        assert!(status.last_run.is_some());
+        assert_eq!(jobs.status().len(), 2);
+
+        jobs.pause("counter").unwrap();
+        wait_for(|| !jobs.job_status("counter").unwrap().running);
+        let runs = counter.load(Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(30));
+        assert_eq!(counter.load(Ordering::SeqCst), runs);
+        assert!(jobs.job_status("counter").unwrap().paused);
+
+        jobs.resume("counter").unwrap();
+        wait_for(|| counter.load(Ordering::SeqCst) > runs);
+        assert!(jobs.pause("missing").is_err());
+        assert!(jobs.remove("failing"));
+        assert_eq!(jobs.status().len(), 1);
+    }
+}
@@ -0,0 +1,34 @@
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use cmu_db_rs::{inspect, DiskManager, PageKind};
+
+/// Dump data file page by page for offline debugging, file is only read
+fn main() -> Result<()> {
+    let Some(path) = std::env::args().nth(1) else {
+        bail!("Usage: db-inspect <path>");
+    };
+    // opening data file would create it
+    if !Path::new(&path).is_file() {
+        bail!("Data file {} doesn't exist.", path);
+    }
+
+    let reports = inspect(&DiskManager::open(&path)?)?;
+    for report in &reports {
+        println!("{}", report);
+    }
+
+    let free_page_ids = reports
+        .iter()
+        .filter(|report| report.kind == PageKind::Free)
+        .map(|report| report.page_id)
+        .collect::<Vec<_>>();
+    let corrupted = reports
+        .iter()
+        .filter(|report| matches!(report.kind, PageKind::Corrupted { .. }))
+        .count();
+    println!("free pages: {:?}", free_page_ids);
+    println!("corrupted pages: {}", corrupted);
+
+    Ok(())
+}
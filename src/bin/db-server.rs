@@ -0,0 +1,40 @@
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use anyhow::{bail, Context, Result};
+use cmu_db_rs::{AdminService, DbInstance};
+use tonic::transport::Server;
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1:50051";
+// requests taking longer are aborted, clients can ask for shorter one with grpc-timeout
+const DEFAULT_REQUEST_TIMEOUT_MS: u64 = 30_000;
+
+/// Run database as standalone process with gRPC admin service
+fn main() -> Result<()> {
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    let Some(path) = args.first() else {
+        bail!("Usage: db-server <path> [address] [request timeout ms]");
+    };
+    let address: SocketAddr = args
+        .get(1)
+        .map_or(DEFAULT_ADDRESS, String::as_str)
+        .parse()
+        .context("Invalid address.")?;
+    let timeout = match args.get(2) {
+        Some(timeout) => timeout.parse().context("Invalid request timeout.")?,
+        None => DEFAULT_REQUEST_TIMEOUT_MS,
+    };
+
+    let db = Arc::new(DbInstance::open(path)?);
+    let service = AdminService::new(Arc::clone(&db))?;
+    let runtime = tokio::runtime::Runtime::new()?;
+
+    println!("serving {} on {}", path, address);
+    runtime.block_on(
+        Server::builder()
+            .timeout(Duration::from_millis(timeout))
+            .add_service(service.into_server())
+            .serve(address),
+    )?;
+
+    Ok(())
+}
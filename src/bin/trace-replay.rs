@@ -0,0 +1,47 @@
+use anyhow::{bail, Context, Result};
+use cmu_db_rs::{
+    read_access_trace, replay_access_trace, ArcReplacer, ClockReplacer, LruKReplacer, Replacer,
+    SlruReplacer, TwoQReplacer,
+};
+
+const REPLACER_K: usize = 2;
+
+/// Replay recorded access trace against every replacement policy for given pool sizes
+fn main() -> Result<()> {
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    let Some((path, pool_sizes)) = args.split_first() else {
+        bail!("Usage: trace-replay <trace> <pool size>...");
+    };
+    if pool_sizes.is_empty() {
+        bail!("Usage: trace-replay <trace> <pool size>...");
+    }
+
+    let records = read_access_trace(path)?;
+    println!("{} accesses", records.len());
+
+    for pool_size in pool_sizes {
+        let pool_size = pool_size
+            .parse::<usize>()
+            .with_context(|| format!("Invalid pool size {}.", pool_size))?;
+        let policies: [(&str, Box<dyn Replacer>); 5] = [
+            ("lru-k", Box::new(LruKReplacer::new(pool_size, REPLACER_K))),
+            ("clock", Box::new(ClockReplacer::new(pool_size))),
+            ("2q", Box::new(TwoQReplacer::new(pool_size))),
+            ("arc", Box::new(ArcReplacer::new(pool_size))),
+            ("slru", Box::new(SlruReplacer::new(pool_size))),
+        ];
+
+        for (name, mut replacer) in policies {
+            let stats = replay_access_trace(&records, pool_size, replacer.as_mut());
+            println!(
+                "pool {:>6} {:<6} hit rate {:.3}, {} evictions",
+                pool_size,
+                name,
+                stats.hit_rate(),
+                stats.evictions
+            );
+        }
+    }
+
+    Ok(())
+}
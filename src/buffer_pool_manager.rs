@@ -1,237 +1,1784 @@
 use anyhow::{bail, Context, Result};
 use dashmap::DashMap;
-use parking_lot::{RwLockReadGuard, RwLockWriteGuard};
-use std::sync::{mpsc, Arc, Mutex};
+use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+    path::Path,
+    sync::{
+        mpsc::{self, Receiver},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+    time::{Duration, Instant},
+};
 
 use crate::{
-    disk_manager::DiskManager,
-    disk_scheduler::DiskScheduler,
+    access_trace::AccessTraceRecorder,
+    disk_manager::{DiskManager, DiskStats},
+    disk_scheduler::{ChaosConfig, DiskScheduler},
+    latency_breakdown::{self, Phase},
     lru_k_replacer::{AccessType, FrameId, LruKReplacer},
-    page::{Page, PageId},
+    owner_quotas::{OwnerId, OwnerQuotas},
+    page::{overwrite_data, Page, PageId, PAGE_SIZE},
+    page_guard::{ReadPageGuard, WritePageGuard},
+    replacer::Replacer,
+    sharded_counter::{self, ShardedCounter, SHARDS},
+    structure_admission::{StructureAdmission, StructurePermit},
+    structure_log::{StructureChange, StructureLog, StructureRecord},
 };
 
+// frames scans stream through, in addition to pool size
+const SCAN_RING_SIZE: usize = 4;
+// most pages flush merges into a single write
+const MAX_WRITE_RUN_PAGES: usize = 64;
+// most prefetched pages waiting to be fetched, further prefetches are skipped
+const MAX_PREFETCHED_PAGES: usize = 64;
+// page ids thread reserves at once
+const PAGE_ID_BATCH_SIZE: usize = 32;
+// frames a hash table split or directory growth pins at most at once
+const FRAMES_PER_STRUCTURE_CHANGE: usize = 4;
+// smallest memory page of supported platforms, prefault touches a byte of each
+const PREFAULT_STRIDE: usize = 4096;
+
+/// What `new_page` and page fetches do when there is no free frame and no page to evict
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FrameWaitPolicy {
+    /// Return no page right away
+    #[default]
+    FailFast,
+    /// Wait until some page is unpinned, return no page if none is within timeout
+    Wait(Duration),
+    /// Write back dirty unpinned pages and try once more, so eviction doesn't depend on
+    /// write of the victim
+    EmergencyFlush,
+}
+
+/// How memory of frames is prepared when buffer pool is created
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FrameMemory {
+    /// Memory is faulted in by the first access to frame
+    #[default]
+    Lazy,
+    /// Every frame is touched, so the first access to it doesn't take page fault
+    Prefaulted,
+    /// Frames are prefaulted and locked against swapping by `mlock`. Locking is
+    /// supported on Linux and limited by `RLIMIT_MEMLOCK`.
+    Locked,
+}
+
+/// Point in time counters of buffer pool
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BufferPoolStats {
+    pub pool_size: usize,
+    pub free_frames: usize,
+    pub resident_pages: usize,
+    pub dirty_pages: usize,
+    /// Frames replacer may evict
+    pub evictable_frames: usize,
+    /// Pages in data file
+    pub disk_pages: usize,
+    /// Pages data file has space reserved for, see `DiskManager::set_growth_extent`
+    pub disk_capacity_pages: usize,
+    /// Fetches of pages found in buffer pool since it was created
+    pub hits: u64,
+    /// Fetches which had to read page from disk
+    pub misses: u64,
+    pub disk_reads: u64,
+    /// Write requests, adjacent pages written back by `flush_all_pages` count once
+    pub disk_writes: u64,
+    /// Bytes read by disk scheduler, prefetches included
+    pub disk_bytes_read: u64,
+    pub disk_bytes_written: u64,
+    /// Disk requests which waited or were served longer than slow request threshold
+    pub slow_disk_requests: u64,
+    /// Reads served from pages disk scheduler read ahead
+    pub read_ahead_hits: u64,
+    /// Duplicated completions of disk chaos which were dropped, see `set_disk_chaos`
+    pub duplicate_completions: u64,
+    /// Hash table structure changes which queued behind the structure change limit
+    pub structure_change_waits: u64,
+}
+
+// cumulative counters reported by `stats`
+#[derive(Debug, Default)]
+struct Counters {
+    hits: ShardedCounter,
+    misses: ShardedCounter,
+    disk_reads: ShardedCounter,
+    disk_writes: ShardedCounter,
+}
+
+// frame search done under latch
+enum FrameSearch {
+    Found(FrameId),
+    // victim holds dirty page, it is pinned until page is written back without latch
+    Dirty(FrameId),
+    NotFound,
+}
+
 #[derive(Debug)]
 pub struct BufferPoolManager {
     free_list: Arc<Mutex<Vec<FrameId>>>,
+    // pool frames followed by scan ring frames, which are never tracked by replacer
     pages: Vec<Page>,
-    replacer: Arc<Mutex<LruKReplacer>>,
+    pool_size: usize,
+    // ring frame the next scan miss is loaded into
+    scan_ring_next: Mutex<usize>,
+    replacer: Arc<Mutex<Box<dyn Replacer>>>,
     disk_scheduler: Arc<DiskScheduler>,
     pages_map: DashMap<PageId, FrameId>,
-    // TODO: should be atomic
-    next_page_id: Arc<Mutex<PageId>>,
+    // page ids reserved and not allocated yet, by shard of threads allocating them
+    page_id_batches: [Mutex<Range<PageId>>; SHARDS],
+    // serializes frame allocation, eviction and pinning against each other,
+    // never held while waiting for page latch or disk
+    latch: Mutex<()>,
+    // signaled under latch when frame may become available
+    frame_released: Condvar,
+    frame_wait_policy: Mutex<FrameWaitPolicy>,
+    // unpinned frames of these pages are not evictable unless nothing else is
+    kept_resident: Mutex<HashSet<PageId>>,
+    // pinned pages deleted once their last pin is released, changed under latch
+    pending_deletes: Mutex<HashSet<PageId>>,
+    // reads of pages not in buffer pool started by `prefetch_page`, changed under latch
+    prefetched: Mutex<HashMap<PageId, Receiver<Result<Vec<u8>>>>>,
+    access_trace: Mutex<Option<AccessTraceRecorder>>,
+    // changed under latch only
+    owner_quotas: Mutex<OwnerQuotas>,
+    counters: Counters,
+    structure_log: Mutex<Option<StructureLog>>,
+    // table writes hold it shared for their whole duration, `quiesce_writes` exclusively
+    write_gate: RwLock<()>,
+    structure_admission: StructureAdmission,
 }
 
 impl BufferPoolManager {
     pub fn new(disk_manager: DiskManager, pool_size: usize, replacer_k: usize) -> Self {
         let replacer = LruKReplacer::new(pool_size, replacer_k);
+
+        Self::with_replacer(disk_manager, pool_size, replacer)
+    }
+
+    /// Create buffer pool which evicts pages by given policy
+    pub fn with_replacer(
+        disk_manager: DiskManager,
+        pool_size: usize,
+        replacer: impl Replacer + 'static,
+    ) -> Self {
         let disk_scheduler = DiskScheduler::new(disk_manager);
         let pages_map: DashMap<PageId, FrameId> = DashMap::default();
-        let mut pages: Vec<Page> = Vec::with_capacity(pool_size);
+        let mut pages: Vec<Page> = Vec::with_capacity(pool_size + SCAN_RING_SIZE);
         let mut free_list: Vec<FrameId> = Vec::with_capacity(pool_size);
 
         for i in 0..pool_size {
-            free_list.push(i);
+            free_list.push(FrameId::new(i));
             pages.push(Page::new());
         }
+        pages.extend((0..SCAN_RING_SIZE).map(|_| Page::new()));
 
         Self {
             pages,
+            pool_size,
+            scan_ring_next: Mutex::new(0),
             free_list: Arc::new(Mutex::new(free_list)),
-            replacer: Arc::new(Mutex::new(replacer)),
+            replacer: Arc::new(Mutex::new(Box::new(replacer))),
             disk_scheduler: Arc::new(disk_scheduler),
             pages_map,
-            next_page_id: Arc::new(Mutex::new(0)),
+            page_id_batches: std::array::from_fn(|_| Mutex::new(PageId::INVALID..PageId::INVALID)),
+            latch: Mutex::new(()),
+            frame_released: Condvar::new(),
+            frame_wait_policy: Mutex::new(FrameWaitPolicy::default()),
+            kept_resident: Mutex::new(HashSet::new()),
+            pending_deletes: Mutex::new(HashSet::new()),
+            prefetched: Mutex::new(HashMap::new()),
+            access_trace: Mutex::new(None),
+            owner_quotas: Mutex::new(OwnerQuotas::default()),
+            counters: Counters::default(),
+            structure_log: Mutex::new(None),
+            write_gate: RwLock::new(()),
+            structure_admission: StructureAdmission::new(Some(
+                (pool_size / FRAMES_PER_STRUCTURE_CHANGE).max(1),
+            )),
         }
     }
 
-    pub fn new_page(&self) -> Option<(PageId, RwLockWriteGuard<'_, Vec<u8>>)> {
-        let replacer = self.replacer.lock().unwrap();
-        let mut free_list = self.free_list.lock().unwrap();
-        let frame_id = free_list.pop().or_else(|| replacer.evict());
-        drop(replacer);
-        drop(free_list);
-
-        frame_id.map(|frame_id| {
-            let page_id = self.allocate_page();
-            let page = self.pages.get(frame_id).unwrap();
-
-            if page.is_dirty() {
-                let (sender, receiver) = mpsc::channel::<Result<()>>();
-                //self.disk_scheduler.schedule_write(&guard, sender);
-                let _ = receiver.recv().unwrap();
+    /// Create buffer pool whose frame memory is prepared up front, see `FrameMemory`
+    pub fn with_frame_memory(
+        disk_manager: DiskManager,
+        pool_size: usize,
+        replacer_k: usize,
+        frame_memory: FrameMemory,
+    ) -> Result<Self> {
+        let buffer_pool_manager = Self::new(disk_manager, pool_size, replacer_k);
+        if frame_memory == FrameMemory::Lazy {
+            return Ok(buffer_pool_manager);
+        }
+        for page in &buffer_pool_manager.pages {
+            let mut data = page.get_data_write();
+            // one byte per memory page is enough, its value is written back as it was
+            for offset in (0..data.len()).step_by(PREFAULT_STRIDE) {
+                data[offset] = std::hint::black_box(data[offset]);
+            }
+            drop(data);
+            if frame_memory == FrameMemory::Locked {
+                page.lock_memory()?;
             }
-            page.reset();
-            page.set_id(page_id);
+        }
 
-            self.pages_map.insert(page_id, frame_id);
-            let mut replacer = self.replacer.lock().unwrap();
-            replacer.record_access(frame_id, AccessType::Unknown);
-            replacer.set_evictable(frame_id, false);
+        Ok(buffer_pool_manager)
+    }
 
-            (page.get_id().unwrap(), page.get_data_write())
-        })
+    pub fn new_page(&self) -> Option<(PageId, WritePageGuard<'_>)> {
+        self.allocate_new_page(None)
     }
 
-    pub fn fetch_page_read(&self, page_id: PageId) -> Option<RwLockReadGuard<'_, Vec<u8>>> {
-        let frame_id = self.pages_map.get(&page_id);
-        if let Some(frame_id) = frame_id {
-            let page = self.pages.get(*frame_id).unwrap();
+    /// Create page on behalf of owner, see `set_owner_quota`
+    pub fn new_page_as(&self, owner: OwnerId) -> Option<(PageId, WritePageGuard<'_>)> {
+        self.allocate_new_page(Some(owner))
+    }
 
-            return Some(page.get_data_read());
+    fn allocate_new_page(&self, owner: Option<OwnerId>) -> Option<(PageId, WritePageGuard<'_>)> {
+        if self.is_read_only() {
+            return None;
         }
+        let mut latch = self.latch.lock().unwrap();
+        let started_at = Instant::now();
+        let mut attempt = 0;
+        let frame_id = loop {
+            match self.acquire_frame(owner) {
+                FrameSearch::Found(frame_id) => break frame_id,
+                FrameSearch::Dirty(frame_id) => {
+                    let written;
+                    (latch, written) = self.write_back_victim(latch, frame_id);
+                    if written {
+                        continue;
+                    }
+                }
+                FrameSearch::NotFound => {}
+            }
+            latch = self.wait_for_frame(latch, started_at, attempt)?;
+            attempt += 1;
+        };
+        let page_id = self.allocate_page();
+        let page = self.pages.get(frame_id.as_usize()).unwrap();
 
-        let replacer = self.replacer.lock().unwrap();
-        let mut free_list = self.free_list.lock().unwrap();
-        let frame_id = free_list.pop().or_else(|| replacer.evict());
-        drop(free_list);
+        page.reset();
+        page.set_id(page_id);
+        page.pin();
+
+        self.pages_map.insert(page_id, frame_id);
+        let mut replacer = self.replacer.lock().unwrap();
+        replacer.record_load(frame_id, page_id);
+        replacer.record_access(frame_id, AccessType::Unknown);
+        replacer.set_evictable(frame_id, false);
         drop(replacer);
-        frame_id.map(|frame_id| {
-            let page = self.pages.get(frame_id).unwrap();
+        self.trace_access(page_id, AccessType::Unknown, false);
+        drop(latch);
 
-            if page.is_dirty() {
-                let (sender, receiver) = mpsc::channel::<Result<()>>();
-                //self.disk_scheduler
-                //    .schedule_write(Arc::clone(&page_arc), sender);
-                let _ = receiver.recv().unwrap();
-            }
-            page.reset();
-            page.set_id(page_id);
-            let (sender, receiver) = mpsc::channel::<Result<()>>();
-            //self.disk_scheduler
-            //    .schedule_read(Arc::clone(&page_arc), sender);
-            let _ = receiver.recv().unwrap();
-
-            self.pages_map.insert(page_id, frame_id);
-            let mut replacer = self.replacer.lock().unwrap();
-            replacer.set_evictable(frame_id, false);
-            replacer.record_access(frame_id, AccessType::Unknown);
+        Some((page_id, WritePageGuard::new(self, page_id, page)))
+    }
 
-            page.get_data_read()
-        })
+    pub fn fetch_page_read(&self, page_id: PageId) -> Option<ReadPageGuard<'_>> {
+        self.fetch_page_read_inner(page_id, None, AccessType::Unknown)
     }
 
-    pub fn fetch_page_write(&self, page_id: PageId) -> Option<RwLockWriteGuard<'_, Vec<u8>>> {
-        let frame_id = self.pages_map.get(&page_id);
-        if let Some(frame_id) = frame_id {
-            let page = self.pages.get(*frame_id).unwrap();
+    /// Fetch page on behalf of owner, see `set_owner_quota`
+    pub fn fetch_page_read_as(&self, owner: OwnerId, page_id: PageId) -> Option<ReadPageGuard<'_>> {
+        self.fetch_page_read_inner(page_id, Some(owner), AccessType::Unknown)
+    }
+
+    /// Fetch page for given kind of access. `AccessType::Scan` page which is not in buffer
+    /// pool is read into a small ring of frames reused by scans, so scanning the whole
+    /// table doesn't evict pages of other queries.
+    pub fn fetch_page_read_for(
+        &self,
+        access_type: AccessType,
+        page_id: PageId,
+    ) -> Option<ReadPageGuard<'_>> {
+        self.fetch_page_read_inner(page_id, None, access_type)
+    }
 
-            return Some(page.get_data_write());
+    fn fetch_page_read_inner(
+        &self,
+        page_id: PageId,
+        owner: Option<OwnerId>,
+        access_type: AccessType,
+    ) -> Option<ReadPageGuard<'_>> {
+        let frame_id = self.pin_page(page_id, owner, access_type)?;
+        let page = self.pages.get(frame_id.as_usize()).unwrap();
+
+        Some(ReadPageGuard::new(self, page_id, page))
+    }
+
+    pub fn fetch_page_write(&self, page_id: PageId) -> Option<WritePageGuard<'_>> {
+        self.fetch_page_write_inner(page_id, None)
+    }
+
+    /// Fetch page on behalf of owner, see `set_owner_quota`
+    pub fn fetch_page_write_as(
+        &self,
+        owner: OwnerId,
+        page_id: PageId,
+    ) -> Option<WritePageGuard<'_>> {
+        self.fetch_page_write_inner(page_id, Some(owner))
+    }
+
+    fn fetch_page_write_inner(
+        &self,
+        page_id: PageId,
+        owner: Option<OwnerId>,
+    ) -> Option<WritePageGuard<'_>> {
+        // page changed in buffer pool could never be written back
+        if self.is_read_only() {
+            return None;
         }
+        let frame_id = self.pin_page(page_id, owner, AccessType::Unknown)?;
+        let page = self.pages.get(frame_id.as_usize()).unwrap();
 
-        let replacer = self.replacer.lock().unwrap();
-        let mut free_list = self.free_list.lock().unwrap();
-        let frame_id = free_list.pop().or_else(|| replacer.evict());
-        drop(replacer);
-        drop(free_list);
-        frame_id.map(|frame_id| {
-            let page = self.pages.get(frame_id).unwrap();
+        Some(WritePageGuard::new(self, page_id, page))
+    }
 
-            if page.is_dirty() {
-                let (sender, receiver) = mpsc::channel::<Result<()>>();
-                //self.disk_scheduler
-                //    .schedule_write(Arc::clone(&page_arc), sender);
-                let _ = receiver.recv().unwrap();
-            }
-            page.reset();
-            page.set_id(page_id);
-            let (sender, receiver) = mpsc::channel::<Result<()>>();
-            //self.disk_scheduler
-            //    .schedule_read(Arc::clone(&page_arc), sender);
-            let _ = receiver.recv().unwrap();
-
-            self.pages_map.insert(page_id, frame_id);
-            let mut replacer = self.replacer.lock().unwrap();
-            replacer.set_evictable(frame_id, false);
-            replacer.record_access(frame_id, AccessType::Unknown);
+    /// Whether page still holds data of the version it was read at, see
+    /// `ReadPageGuard::version`. Page which was written or left buffer pool since then is
+    /// reported as changed, so data read without holding its latch can be validated.
+    pub fn is_page_unchanged(&self, page_id: PageId, version: u64) -> bool {
+        self.pages_map
+            .get(&page_id)
+            .is_some_and(|frame_id| self.pages[frame_id.as_usize()].version() == version)
+    }
 
-            page.get_data_write()
-        })
+    /// Limit number of frames pages loaded on behalf of owner may take, `None` lifts the limit.
+    /// Owner at its limit reuses the earliest loaded of its unpinned frames instead of
+    /// evicting pages of others, and gets no page if all its frames are pinned.
+    /// Pages already in buffer pool are fetched without counting against the limit.
+    pub fn set_owner_quota(&self, owner: OwnerId, max_frames: Option<usize>) {
+        let _latch = self.latch.lock().unwrap();
+
+        self.owner_quotas
+            .lock()
+            .unwrap()
+            .set_quota(owner, max_frames);
+    }
+
+    /// Number of frames holding pages loaded on behalf of owner
+    pub fn owner_resident_frames(&self, owner: OwnerId) -> usize {
+        let _latch = self.latch.lock().unwrap();
+
+        self.owner_quotas.lock().unwrap().resident_frames(owner)
     }
 
     pub fn unpin_page(&self, page_id: PageId, is_dirty: bool) -> Result<()> {
-        let frame_id = self
+        let latch = self.latch.lock().unwrap();
+        let frame_id = *self
             .pages_map
             .get(&page_id)
             .with_context(|| format!("Page {} is not in buffer pool.", page_id))?;
         let frame = self
             .pages
-            .get(*frame_id)
+            .get(frame_id.as_usize())
             .with_context(|| format!("Page {} is not in buffer pool.", page_id))?;
 
+        if !frame.is_pinned() {
+            bail!("Page {} is not pinned.", page_id);
+        }
         frame.unpin();
-        frame.set_dirty(is_dirty);
+        if is_dirty {
+            frame.set_dirty(true);
+        }
 
-        if !frame.is_pinned() {
+        if !frame.is_pinned() && self.pending_deletes.lock().unwrap().remove(&page_id) {
+            self.release_frame(page_id, frame_id);
+            drop(latch);
+
+            return self.deallocate_page(page_id);
+        }
+        if !frame.is_pinned() && !self.is_scan_ring_frame(frame_id) {
+            let kept_resident = self.kept_resident.lock().unwrap().contains(&page_id);
             let mut replacer = self.replacer.lock().unwrap();
-            replacer.set_evictable(*frame_id, true);
+            replacer.set_evictable(frame_id, !kept_resident);
+            self.frame_released.notify_all();
         }
 
         Ok(())
     }
 
     pub fn flush_page(&self, page_id: PageId) -> Result<()> {
+        // page is pinned for the duration of flush so it can't be evicted meanwhile
         let frame_id = self
-            .pages_map
-            .get(&page_id)
-            .with_context(|| format!("Page {} is not in buffer pool.", page_id))?;
-        let frame = self
-            .pages
-            .get(*frame_id)
+            .pin_resident_page(page_id)
             .with_context(|| format!("Page {} is not in buffer pool.", page_id))?;
+        let frame = self.pages.get(frame_id.as_usize()).unwrap();
 
-        let (sender, receiver) = mpsc::channel::<Result<()>>();
-        //self.disk_scheduler
-        //    .schedule_write(Arc::clone(frame_arc), sender);
-        let _ = receiver.recv().unwrap();
+        let data = frame.get_data_read();
+        let data_to_write = Arc::new(data.clone());
         frame.set_dirty(false);
+        drop(data);
+
+        let result = self.write_to_disk(page_id, data_to_write);
+        if result.is_err() {
+            frame.set_dirty(true);
+        }
+        self.unpin_page(page_id, false)?;
+
+        result
+    }
+
+    /// Write back all dirty pages. Pages are sorted by id and adjacent ones are merged
+    /// into a single write of up to `MAX_WRITE_RUN_PAGES` pages, all writes are queued
+    /// together.
+    pub fn flush_all_pages(&self) -> Result<()> {
+        self.write_back(|_| true)
+    }
+
+    // writes back dirty pages which match filter as `flush_all_pages` does
+    fn write_back(&self, filter: impl Fn(&Page) -> bool) -> Result<()> {
+        let mut page_ids = self
+            .pages_map
+            .iter()
+            .filter(|entry| {
+                let page = &self.pages[entry.value().as_usize()];
+                page.is_dirty() && filter(page)
+            })
+            .map(|entry| *entry.key())
+            .collect::<Vec<PageId>>();
+        page_ids.sort_unstable();
+
+        // pages are pinned until written, so they can't be evicted meanwhile
+        let mut pinned: Vec<(PageId, FrameId)> = vec![];
+        let mut runs: Vec<(PageId, Vec<u8>)> = vec![];
+        for page_id in page_ids {
+            let Some(frame_id) = self.pin_resident_page(page_id) else {
+                continue;
+            };
+            let frame = &self.pages[frame_id.as_usize()];
+            let mut data = frame.get_data_read().clone();
+            frame.set_dirty(false);
+            data.resize(PAGE_SIZE, 0);
+            pinned.push((page_id, frame_id));
+
+            match runs.last_mut() {
+                Some((first_page_id, run_data))
+                    if *first_page_id + run_data.len() / PAGE_SIZE == page_id
+                        && run_data.len() < MAX_WRITE_RUN_PAGES * PAGE_SIZE =>
+                {
+                    run_data.extend(data);
+                }
+                _ => runs.push((page_id, data)),
+            }
+        }
+
+        let num_runs = runs.len();
+        let (sender, receiver) = mpsc::channel::<Result<()>>();
+        self.counters.disk_writes.add(num_runs as u64);
+        self.disk_scheduler.schedule_write_batch(runs, sender);
+
+        // results come in completion order, so failed run can't be told apart and all
+        // pages stay dirty
+        let mut result = Ok(());
+        for _ in 0..num_runs {
+            if let Err(error) = receiver.recv()? {
+                result = Err(error);
+            }
+        }
+        for (page_id, frame_id) in pinned {
+            if result.is_err() {
+                self.pages[frame_id.as_usize()].set_dirty(true);
+            }
+            self.unpin_page(page_id, false)?;
+        }
+
+        result
+    }
+
+    pub fn stats(&self) -> Result<BufferPoolStats> {
+        let _latch = self.latch.lock().unwrap();
+        let dirty_pages = self
+            .pages_map
+            .iter()
+            .filter(|entry| self.pages[entry.value().as_usize()].is_dirty())
+            .count();
+        let scheduler_counters = self.disk_scheduler.counters();
+
+        Ok(BufferPoolStats {
+            pool_size: self.pool_size,
+            free_frames: self.free_list.lock().unwrap().len(),
+            resident_pages: self.pages_map.len(),
+            dirty_pages,
+            evictable_frames: self.replacer.lock().unwrap().size(),
+            disk_pages: self.disk_scheduler.disk_manager().num_pages()?,
+            disk_capacity_pages: self.disk_scheduler.disk_manager().capacity_pages()?,
+            hits: self.counters.hits.get(),
+            misses: self.counters.misses.get(),
+            disk_reads: self.counters.disk_reads.get(),
+            disk_writes: self.counters.disk_writes.get(),
+            disk_bytes_read: scheduler_counters.bytes_read.get(),
+            disk_bytes_written: scheduler_counters.bytes_written.get(),
+            slow_disk_requests: scheduler_counters.slow_requests.get(),
+            read_ahead_hits: scheduler_counters.read_ahead_hits.get(),
+            duplicate_completions: scheduler_counters.duplicate_completions.get(),
+            structure_change_waits: self.structure_admission.waits(),
+        })
+    }
+
+    /// Physical I/O of disk manager, to be read together with hits and misses of `stats`
+    pub fn disk_stats(&self) -> DiskStats {
+        self.disk_scheduler.disk_manager().stats()
+    }
+
+    /// Wait until pages written so far reach durable storage
+    pub fn sync(&self) -> Result<()> {
+        self.disk_scheduler.sync()
+    }
+
+    pub(crate) fn disk_manager(&self) -> &DiskManager {
+        self.disk_scheduler.disk_manager()
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.disk_scheduler.disk_manager().is_read_only()
+    }
+
+    /// Check pages of data file in background thread, one per `page_interval`, and receive
+    /// ids of corrupted ones, see `DiskManager::start_scrubber`
+    pub fn start_page_scrubber(&self, page_interval: Duration) -> Result<Receiver<PageId>> {
+        let (sender, receiver) = mpsc::channel();
+        self.disk_scheduler
+            .disk_manager()
+            .start_scrubber(page_interval, sender)?;
+
+        Ok(receiver)
+    }
+
+    pub fn stop_page_scrubber(&self) {
+        self.disk_manager().stop_scrubber();
+    }
+
+    /// Copy data file to plain data file at `path` as it is at this point in time: table
+    /// writes wait until copy is done, dirty pages are flushed first and reads go on
+    /// meanwhile
+    pub fn snapshot_to(&self, path: impl AsRef<Path>) -> Result<()> {
+        let _quiesced = self.quiesce_writes();
+        self.flush_all_pages()?;
+
+        self.disk_manager().snapshot_to(path)
+    }
+
+    /// Wait for hash table writes in progress and block new ones until returned guard is
+    /// dropped. Pages written directly through buffer pool are not blocked.
+    pub fn quiesce_writes(&self) -> RwLockWriteGuard<'_, ()> {
+        self.write_gate.write()
+    }
+
+    // held by table write for its whole duration, the same thread may take it again
+    pub(crate) fn write_gate(&self) -> RwLockReadGuard<'_, ()> {
+        self.write_gate.read_recursive()
+    }
+
+    /// Limit how many hash table splits and directory growths run at once across tables
+    /// of this pool, excess writers wait in queue. By default it's a quarter of pool
+    /// size, `None` lifts the limit.
+    pub fn set_structure_change_limit(&self, limit: Option<usize>) {
+        self.structure_admission
+            .set_limit(limit.map(|limit| limit.max(1)));
+    }
+
+    // held by table for its whole structure change, taken after table's structure latch
+    // and before any page is pinned, so writers wait in queue without holding frames
+    pub(crate) fn admit_structure_change(&self) -> StructurePermit<'_> {
+        self.structure_admission.admit()
+    }
+
+    /// Finish queued disk requests and join disk workers, any disk I/O afterwards fails.
+    /// Dirty pages are not written, flush them first.
+    pub fn stop_disk_workers(&self) {
+        self.disk_scheduler.shutdown();
+    }
+
+    /// Start reading page which is not in buffer pool, so its next fetch waits for read in
+    /// flight instead of starting its own. Nothing is done if page is resident or too many
+    /// prefetched pages weren't fetched yet.
+    pub fn prefetch_page(&self, page_id: PageId) {
+        let _latch = self.latch.lock().unwrap();
+        let mut prefetched = self.prefetched.lock().unwrap();
+        if self.pages_map.contains_key(&page_id)
+            || prefetched.contains_key(&page_id)
+            || prefetched.len() >= MAX_PREFETCHED_PAGES
+        {
+            return;
+        }
+
+        self.counters.disk_reads.increment();
+        let (sender, receiver) = mpsc::channel::<Result<Vec<u8>>>();
+        self.disk_scheduler.schedule_prefetch(page_id, sender);
+        prefetched.insert(page_id, receiver);
+    }
+
+    /// Hint that page is touched by every operation of its structure: while unpinned it is
+    /// evicted only if no other page can be. Hint is dropped when page is deleted.
+    pub fn set_keep_resident(&self, page_id: PageId, keep_resident: bool) {
+        let _latch = self.latch.lock().unwrap();
+        let mut kept_resident = self.kept_resident.lock().unwrap();
+        if keep_resident {
+            kept_resident.insert(page_id);
+        } else {
+            kept_resident.remove(&page_id);
+        }
+        drop(kept_resident);
+
+        let Some(frame_id) = self.pages_map.get(&page_id).map(|frame_id| *frame_id) else {
+            return;
+        };
+        if !self.pages[frame_id.as_usize()].is_pinned() && !self.is_scan_ring_frame(frame_id) {
+            self.replacer
+                .lock()
+                .unwrap()
+                .set_evictable(frame_id, !keep_resident);
+        }
+    }
+
+    /// Choose what to do when all frames are pinned, fail fast by default
+    pub fn set_frame_wait_policy(&self, policy: FrameWaitPolicy) {
+        *self.frame_wait_policy.lock().unwrap() = policy;
+    }
+
+    /// Log (via tracing) disk requests waiting or served longer than threshold,
+    /// `None` turns logging off
+    pub fn set_slow_io_threshold(&self, threshold: Option<Duration>) {
+        self.disk_scheduler.set_slow_request_threshold(threshold);
+    }
+
+    /// Let disk scheduler reorder, delay and duplicate completions of requests, to test
+    /// code above buffer pool against orders disk may serve requests in. `None` turns
+    /// it off.
+    pub fn set_disk_chaos(&self, config: Option<ChaosConfig>) -> Result<()> {
+        self.disk_scheduler.set_chaos(config)
+    }
+
+    /// Pages disk scheduler reads at once when reads go through consecutive pages, like
+    /// bucket scans do. 8 by default, 0 turns read ahead off.
+    pub fn set_read_ahead_pages(&self, pages: usize) {
+        self.disk_scheduler.set_read_ahead_pages(pages);
+    }
+
+    /// Move pages not read or written within `window` to cold storage tier
+    pub fn migrate_cold_pages(&self, window: Duration) -> Result<usize> {
+        self.disk_scheduler
+            .disk_manager()
+            .migrate_cold_pages(window)
+    }
+
+    /// Record every page fetch to trace file until `stop_access_trace`,
+    /// trace is read back by `read_access_trace`
+    pub fn start_access_trace(&self, path: impl AsRef<Path>) -> Result<()> {
+        let recorder = AccessTraceRecorder::create(path)?;
+        let previous = self.access_trace.lock().unwrap().replace(recorder);
+        if let Some(previous) = previous {
+            previous.finish()?;
+        }
 
         Ok(())
     }
 
-    // pub fn flush_all_pages(&self) {
-    //     let page_ids = self.pages_map.keys().to_owned().collect::<Vec<&usize>>();
-    //     for page_id in page_ids {
-    //         self.flush_page(*page_id).unwrap_or(())
-    //     }
-    // }
+    pub fn stop_access_trace(&self) -> Result<()> {
+        let recorder = self.access_trace.lock().unwrap().take();
+        if let Some(recorder) = recorder {
+            recorder.finish()?;
+        }
+
+        Ok(())
+    }
+
+    /// Write structural changes of hash tables through log at path, so each of them reaches
+    /// data file all-or-nothing. With `redo` change logged before crash is written again
+    /// here, so this must be called before any page is fetched.
+    pub fn open_structure_log(&self, path: impl AsRef<Path>, redo: bool) -> Result<()> {
+        let mut log = StructureLog::open(path)?;
+        if let Some(record) = log.read()?.filter(|_| redo) {
+            tracing::info!(change = ?record.change, pages = record.pages.len(), "redoing structure change");
+            self.write_through(&record.pages)?;
+            log.clear()?;
+
+            // pages of the change may be past the end of data file seen on open
+            for (page_id, _) in &record.pages {
+                self.disk_manager().mark_allocated(*page_id);
+            }
+        }
+        *self.structure_log.lock().unwrap() = Some(log);
+
+        Ok(())
+    }
+
+    /// Write pages changed together by structural change to data file all-or-nothing, pages
+    /// must stay write latched until this returns. Without structure log pages are written
+    /// back as usual.
+    pub(crate) fn log_structure_change(
+        &self,
+        change: StructureChange,
+        pages: &[&WritePageGuard<'_>],
+    ) -> Result<()> {
+        let mut structure_log = self.structure_log.lock().unwrap();
+        let Some(log) = structure_log.as_mut() else {
+            return Ok(());
+        };
+
+        let record = StructureRecord {
+            change,
+            pages: pages
+                .iter()
+                .map(|page| (page.page_id(), page.to_vec()))
+                .collect(),
+        };
+        log.write(&record)?;
+        self.write_through(&record.pages)?;
+
+        log.clear()
+    }
+
+    // write pages to disk bypassing their frames and wait until they are durable
+    fn write_through(&self, pages: &[(PageId, Vec<u8>)]) -> Result<()> {
+        for (page_id, data) in pages {
+            self.write_to_disk(*page_id, Arc::new(data.clone()))?;
+        }
+
+        self.sync()
+    }
 
     pub fn delete_page(&self, page_id: PageId) -> Result<()> {
-        let frame_id = self
+        let latch = self.latch.lock().unwrap();
+        let frame_id = *self
             .pages_map
             .get(&page_id)
             .with_context(|| format!("Page {} is not in buffer pool.", page_id))?;
-        let frame_id = *frame_id;
         let frame = self
             .pages
-            .get(frame_id)
+            .get(frame_id.as_usize())
             .with_context(|| format!("Page {} is not in buffer pool.", page_id))?;
 
         if frame.is_pinned() {
             bail!("Page {} is pinned and cannot be deleted.", page_id);
         }
 
+        self.release_frame(page_id, frame_id);
+        drop(latch);
+
+        self.deallocate_page(page_id)?;
+
+        Ok(())
+    }
+
+    /// Delete page like `delete_page`, pinned page is deleted once its last pin is
+    /// released instead of failing. Page which is not in buffer pool is deallocated
+    /// right away.
+    pub fn delete_page_when_unpinned(&self, page_id: PageId) -> Result<()> {
+        let latch = self.latch.lock().unwrap();
+        let frame_id = self.pages_map.get(&page_id).map(|entry| *entry.value());
+        if let Some(frame_id) = frame_id {
+            if self.pages[frame_id.as_usize()].is_pinned() {
+                self.pending_deletes.lock().unwrap().insert(page_id);
+                return Ok(());
+            }
+            self.release_frame(page_id, frame_id);
+        } else {
+            self.prefetched.lock().unwrap().remove(&page_id);
+        }
+        drop(latch);
+
+        self.deallocate_page(page_id)
+    }
+
+    /// Drop unpinned pages which weren't changed, so they are read from disk again on the
+    /// next fetch. Reader of data file written by another process sees its changes this
+    /// way. Returns number of dropped pages.
+    pub fn discard_clean_pages(&self) -> usize {
+        let latch = self.latch.lock().unwrap();
+        let page_ids = self
+            .pages_map
+            .iter()
+            .filter(|entry| {
+                let frame = &self.pages[entry.value().as_usize()];
+                !frame.is_pinned() && !frame.is_dirty()
+            })
+            .map(|entry| (*entry.key(), *entry.value()))
+            .collect::<Vec<_>>();
+        for (page_id, frame_id) in &page_ids {
+            self.release_frame(*page_id, *frame_id);
+        }
+        self.prefetched.lock().unwrap().clear();
+        self.disk_scheduler.clear_read_ahead();
+        drop(latch);
+
+        page_ids.len()
+    }
+
+    // forget page of unpinned frame, called under latch
+    fn release_frame(&self, page_id: PageId, frame_id: FrameId) {
         self.pages_map.remove(&page_id);
-        let mut replacer = self.replacer.lock().unwrap();
-        replacer.remove(frame_id);
+        self.kept_resident.lock().unwrap().remove(&page_id);
+        self.prefetched.lock().unwrap().remove(&page_id);
+        self.pending_deletes.lock().unwrap().remove(&page_id);
+        // free frame is zeroed when it is taken by new page
+        self.pages[frame_id.as_usize()].reset_metadata();
+        // emptied ring frame just stays in the ring
+        if !self.is_scan_ring_frame(frame_id) {
+            self.replacer.lock().unwrap().remove(frame_id);
+            self.owner_quotas.lock().unwrap().release(frame_id);
+            self.free_list.lock().unwrap().push(frame_id);
+            self.frame_released.notify_all();
+        }
+    }
+
+    /// Pin page and return its frame, page is read from disk if it is not in buffer pool.
+    /// Latch is released while page is read, frame is mapped to the page meanwhile, so
+    /// fetchers of the same page wait for the frame instead of reading it again.
+    fn pin_page(
+        &self,
+        page_id: PageId,
+        owner: Option<OwnerId>,
+        access_type: AccessType,
+    ) -> Option<FrameId> {
+        let mut latch = self.latch.lock().unwrap();
+        let started_at = Instant::now();
+        let mut attempt = 0;
+        // page can be loaded by another thread while latch is released for waiting
+        let frame_id = loop {
+            if let Some(frame_id) = self.pin_resident_frame(page_id, access_type) {
+                self.counters.hits.increment();
+                self.trace_access(page_id, access_type, true);
+                drop(latch);
+
+                return self.wait_for_load(page_id, frame_id);
+            }
+
+            // scan falls back to pool frame only if all ring frames are pinned
+            let search = match access_type {
+                AccessType::Scan => match self.take_scan_ring_frame() {
+                    FrameSearch::NotFound => self.acquire_frame(owner),
+                    search => search,
+                },
+                _ => self.acquire_frame(owner),
+            };
+            match search {
+                FrameSearch::Found(frame_id) => break frame_id,
+                FrameSearch::Dirty(frame_id) => {
+                    let written;
+                    (latch, written) = self.write_back_victim(latch, frame_id);
+                    if written {
+                        continue;
+                    }
+                }
+                FrameSearch::NotFound => {}
+            }
+            latch = self.wait_for_frame(latch, started_at, attempt)?;
+            attempt += 1;
+        };
+        self.counters.misses.increment();
+        let page = self.pages.get(frame_id.as_usize()).unwrap();
+
+        // frame is nobody's, so its data latch is free
+        page.reset_metadata();
+        page.set_id(page_id);
+        page.pin();
+        let mut data = page.get_data_write();
+        page.begin_load();
+
+        self.pages_map.insert(page_id, frame_id);
+        if !self.is_scan_ring_frame(frame_id) {
+            let mut replacer = self.replacer.lock().unwrap();
+            replacer.record_load(frame_id, page_id);
+            replacer.record_access(frame_id, access_type);
+            replacer.set_evictable(frame_id, false);
+        }
+        self.trace_access(page_id, access_type, false);
+        let prefetched = self.prefetched.lock().unwrap().remove(&page_id);
+        drop(latch);
+
+        // data read from disk is copied into frame buffer, which stays in place
+        let loaded = match self.read_page_data(page_id, prefetched) {
+            Ok(loaded_data) => {
+                overwrite_data(&mut data, &loaded_data);
+                true
+            }
+            Err(_) => false,
+        };
+        page.end_load(loaded);
+        drop(data);
+        if !loaded {
+            self.release_failed_load(page_id, frame_id);
+            return None;
+        }
+
+        Some(frame_id)
+    }
+
+    fn pin_resident_page(&self, page_id: PageId) -> Option<FrameId> {
+        let latch = self.latch.lock().unwrap();
+        let frame_id = self.pin_resident_frame(page_id, AccessType::Unknown)?;
+        drop(latch);
+
+        self.wait_for_load(page_id, frame_id)
+    }
+
+    // must be called under latch
+    fn pin_resident_frame(&self, page_id: PageId, access_type: AccessType) -> Option<FrameId> {
+        let frame_id = *self.pages_map.get(&page_id)?;
+        let page = self.pages.get(frame_id.as_usize()).unwrap();
+        page.pin();
+
+        // page in scan ring stays there even if it is fetched by lookup
+        if !self.is_scan_ring_frame(frame_id) {
+            let mut replacer = self.replacer.lock().unwrap();
+            replacer.record_access(frame_id, access_type);
+            replacer.set_evictable(frame_id, false);
+        }
+
+        Some(frame_id)
+    }
+
+    // called without latch for frame pinned while its page may still be read, pin is
+    // released if read failed
+    fn wait_for_load(&self, page_id: PageId, frame_id: FrameId) -> Option<FrameId> {
+        if self.pages[frame_id.as_usize()].wait_loaded() {
+            return Some(frame_id);
+        }
+        self.release_failed_load(page_id, frame_id);
+
+        None
+    }
+
+    // drop pin of frame whose page couldn't be read, the last pin frees the frame
+    fn release_failed_load(&self, page_id: PageId, frame_id: FrameId) {
+        let latch = self.latch.lock().unwrap();
+        self.pages_map
+            .remove_if(&page_id, |_, mapped_frame_id| *mapped_frame_id == frame_id);
+        let page = &self.pages[frame_id.as_usize()];
+        page.unpin();
+        if page.is_pinned() {
+            return;
+        }
+
+        let deleted = self.pending_deletes.lock().unwrap().remove(&page_id);
+        page.reset_metadata();
+        if !self.is_scan_ring_frame(frame_id) {
+            self.replacer.lock().unwrap().remove(frame_id);
+            self.owner_quotas.lock().unwrap().release(frame_id);
+            self.free_list.lock().unwrap().push(frame_id);
+            self.frame_released.notify_all();
+        }
+        drop(latch);
+
+        if deleted {
+            let _ = self.deallocate_page(page_id);
+        }
+    }
+
+    fn is_scan_ring_frame(&self, frame_id: FrameId) -> bool {
+        frame_id.as_usize() >= self.pool_size
+    }
+
+    // must be called under latch, takes the oldest unpinned ring frame
+    fn take_scan_ring_frame(&self) -> FrameSearch {
+        let mut next = self.scan_ring_next.lock().unwrap();
+        for _ in 0..SCAN_RING_SIZE {
+            let frame_id = FrameId::new(self.pool_size + *next);
+            *next = (*next + 1) % SCAN_RING_SIZE;
+
+            let page = &self.pages[frame_id.as_usize()];
+            if page.is_pinned() {
+                continue;
+            }
+            if page.is_dirty() {
+                page.pin();
+                return FrameSearch::Dirty(frame_id);
+            }
+            self.unmap_frame(frame_id);
+            // frame must not unmap page it no longer holds if read fails
+            page.reset_metadata();
+            return FrameSearch::Found(frame_id);
+        }
+
+        FrameSearch::NotFound
+    }
+
+    // must be called under latch, found frame is removed from page table and replacer
+    // and is recorded as loaded by owner
+    fn acquire_frame(&self, owner: Option<OwnerId>) -> FrameSearch {
+        let search = match owner {
+            Some(owner) if self.owner_quotas.lock().unwrap().is_at_quota(owner) => {
+                self.recycle_owner_frame(owner)
+            }
+            _ => self.take_frame(),
+        };
+        let FrameSearch::Found(frame_id) = search else {
+            return search;
+        };
+
+        let mut owner_quotas = self.owner_quotas.lock().unwrap();
+        owner_quotas.release(frame_id);
+        if let Some(owner) = owner {
+            owner_quotas.record_load(owner, frame_id);
+        }
+
+        search
+    }
+
+    // must be called under latch, which is released meanwhile. Returns latch once frame
+    // may be available again or `None` if caller should give up.
+    fn wait_for_frame<'a>(
+        &'a self,
+        latch: MutexGuard<'a, ()>,
+        started_at: Instant,
+        attempt: usize,
+    ) -> Option<MutexGuard<'a, ()>> {
+        let policy = *self.frame_wait_policy.lock().unwrap();
+        match policy {
+            FrameWaitPolicy::FailFast => None,
+            FrameWaitPolicy::Wait(timeout) => {
+                let remaining = timeout.checked_sub(started_at.elapsed())?;
+                let (latch, _) = self.frame_released.wait_timeout(latch, remaining).unwrap();
+
+                Some(latch)
+            }
+            FrameWaitPolicy::EmergencyFlush if attempt == 0 => {
+                drop(latch);
+                if let Err(error) = self.write_back(|page| !page.is_pinned()) {
+                    tracing::warn!(%error, "emergency flush failed");
+                    return None;
+                }
+
+                Some(self.latch.lock().unwrap())
+            }
+            FrameWaitPolicy::EmergencyFlush => None,
+        }
+    }
+
+    // must be called under latch
+    fn take_frame(&self) -> FrameSearch {
         let mut free_list = self.free_list.lock().unwrap();
-        free_list.push(frame_id);
+        if let Some(frame_id) = free_list.pop() {
+            return FrameSearch::Found(frame_id);
+        }
         drop(free_list);
-        frame.reset();
-        drop(frame);
 
-        self.deallocate_page(page_id)?;
+        let mut replacer = self.replacer.lock().unwrap();
+        let Some(frame_id) = replacer
+            .evict()
+            .or_else(|| self.evict_kept_resident(replacer.as_mut()))
+        else {
+            return FrameSearch::NotFound;
+        };
+        let page = &self.pages[frame_id.as_usize()];
+        if page.is_dirty() {
+            page.pin();
+            return FrameSearch::Dirty(frame_id);
+        }
+        self.unmap_frame(frame_id);
 
-        Ok(())
+        FrameSearch::Found(frame_id)
+    }
+
+    // must be called under latch, unpinned pages kept resident are made evictable just for
+    // this eviction
+    fn evict_kept_resident(&self, replacer: &mut dyn Replacer) -> Option<FrameId> {
+        let frame_ids = self
+            .kept_resident
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|page_id| self.pages_map.get(page_id).map(|frame_id| *frame_id))
+            .filter(|frame_id| {
+                !self.is_scan_ring_frame(*frame_id) && !self.pages[frame_id.as_usize()].is_pinned()
+            })
+            .collect::<Vec<FrameId>>();
+        for frame_id in &frame_ids {
+            replacer.set_evictable(*frame_id, true);
+        }
+        let victim = replacer.evict();
+        for frame_id in frame_ids {
+            if Some(frame_id) != victim {
+                replacer.set_evictable(frame_id, false);
+            }
+        }
+
+        victim
+    }
+
+    // must be called under latch, owner at quota evicts the earliest loaded of its own frames
+    fn recycle_owner_frame(&self, owner: OwnerId) -> FrameSearch {
+        let Some(frame_id) = self
+            .owner_quotas
+            .lock()
+            .unwrap()
+            .frames(owner)
+            .find(|frame_id| !self.pages[frame_id.as_usize()].is_pinned())
+        else {
+            return FrameSearch::NotFound;
+        };
+
+        self.replacer.lock().unwrap().remove(frame_id);
+        let page = &self.pages[frame_id.as_usize()];
+        if page.is_dirty() {
+            page.pin();
+            return FrameSearch::Dirty(frame_id);
+        }
+        self.unmap_frame(frame_id);
+
+        FrameSearch::Found(frame_id)
     }
 
+    // must be called under latch, which is released while page of victim pinned by frame
+    // search is written back. Victim nobody fetched meanwhile is unmapped and its frame is
+    // freed for the next search, otherwise it is evictable again once unpinned. Returns
+    // whether page was written.
+    fn write_back_victim<'a>(
+        &'a self,
+        latch: MutexGuard<'a, ()>,
+        frame_id: FrameId,
+    ) -> (MutexGuard<'a, ()>, bool) {
+        drop(latch);
+        let page = &self.pages[frame_id.as_usize()];
+        // pinned page keeps its id
+        let page_id = page.get_id().unwrap();
+        let data = page.get_data_read();
+        let data_to_write = Arc::new(data.clone());
+        page.set_dirty(false);
+        drop(data);
+
+        let written = self.write_to_disk(page_id, data_to_write).is_ok();
+        if !written {
+            page.set_dirty(true);
+        }
+
+        let latch = self.latch.lock().unwrap();
+        page.unpin();
+        // fetchers make it evictable again when they unpin it
+        if page.is_pinned() {
+            return (latch, written);
+        }
+        if self.pending_deletes.lock().unwrap().remove(&page_id) {
+            self.release_frame(page_id, frame_id);
+            drop(latch);
+            let _ = self.deallocate_page(page_id);
+
+            return (self.latch.lock().unwrap(), true);
+        }
+        if !page.is_dirty() {
+            self.unmap_frame(frame_id);
+            page.reset_metadata();
+            // emptied ring frame just stays in the ring
+            if !self.is_scan_ring_frame(frame_id) {
+                self.replacer.lock().unwrap().remove(frame_id);
+                self.owner_quotas.lock().unwrap().release(frame_id);
+                self.free_list.lock().unwrap().push(frame_id);
+                self.frame_released.notify_all();
+            }
+        } else if !self.is_scan_ring_frame(frame_id) {
+            let kept_resident = self.kept_resident.lock().unwrap().contains(&page_id);
+            let mut replacer = self.replacer.lock().unwrap();
+            replacer.record_access(frame_id, AccessType::Unknown);
+            replacer.set_evictable(frame_id, !kept_resident);
+            self.frame_released.notify_all();
+        }
+
+        (latch, written)
+    }
+
+    // remove clean page of the frame from page table
+    fn unmap_frame(&self, frame_id: FrameId) {
+        let page = self.pages.get(frame_id.as_usize()).unwrap();
+
+        if let Some(page_id) = page.get_id() {
+            self.pages_map.remove(&page_id);
+        }
+    }
+
+    fn trace_access(&self, page_id: PageId, access_type: AccessType, hit: bool) {
+        let mut access_trace = self.access_trace.lock().unwrap();
+        let Some(recorder) = access_trace.as_mut() else {
+            return;
+        };
+        // broken trace must not fail page access, recording just stops
+        if let Err(error) = recorder.record(page_id, access_type, hit) {
+            tracing::warn!(%error, "access trace recording stopped");
+            *access_trace = None;
+        }
+    }
+
+    // page isn't written to disk while it is not resident, so data of its prefetch taken
+    // when frame was mapped is still current, failed prefetch is read again
+    fn read_page_data(
+        &self,
+        page_id: PageId,
+        prefetched: Option<Receiver<Result<Vec<u8>>>>,
+    ) -> Result<Vec<u8>> {
+        latency_breakdown::phase(Phase::DiskWait, || {
+            if let Some(Ok(Ok(data))) = prefetched.map(|receiver| receiver.recv()) {
+                return Ok(data);
+            }
+
+            self.read_from_disk(page_id)
+        })
+    }
+
+    fn read_from_disk(&self, page_id: PageId) -> Result<Vec<u8>> {
+        self.counters.disk_reads.increment();
+        let (sender, receiver) = mpsc::channel::<Result<Vec<u8>>>();
+        self.disk_scheduler.schedule_read(page_id, sender);
+
+        receiver.recv()?
+    }
+
+    fn write_to_disk(&self, page_id: PageId, data: Arc<Vec<u8>>) -> Result<()> {
+        self.counters.disk_writes.increment();
+        let (sender, receiver) = mpsc::channel::<Result<()>>();
+        self.disk_scheduler.schedule_write(page_id, data, sender);
+
+        receiver.recv()?
+    }
+
+    // pages freed by `delete_page` are reused first. New ids are reserved in batches per
+    // shard of threads, so concurrent allocations don't contend on shared counter. Ids
+    // left in batches are given back when buffer pool is dropped.
     fn allocate_page(&self) -> PageId {
-        let mut next_page_id = self.next_page_id.lock().unwrap();
-        *next_page_id += 1;
+        if let Some(page_id) = self.disk_manager().take_free_page() {
+            return page_id;
+        }
+
+        let mut batch = self.page_id_batches[sharded_counter::thread_shard()]
+            .lock()
+            .unwrap();
+        if batch.start == batch.end {
+            *batch = self.disk_manager().reserve_pages(PAGE_ID_BATCH_SIZE);
+        }
+        let page_id = batch.start;
+        batch.start = page_id + 1;
 
-        *next_page_id
+        page_id
     }
 
-    fn deallocate_page(&self, _page_id: PageId) -> Result<()> {
-        Ok(())
+    fn deallocate_page(&self, page_id: PageId) -> Result<()> {
+        self.disk_scheduler.disk_manager().deallocate_page(page_id)
+    }
+}
+
+impl Drop for BufferPoolManager {
+    fn drop(&mut self) {
+        for batch in &mut self.page_id_batches {
+            let batch = batch.get_mut().unwrap_or_else(|error| error.into_inner());
+            self.disk_scheduler
+                .disk_manager()
+                .release_pages(batch.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::access_trace::{read_access_trace, replay_access_trace};
+    use crate::disk_manager::{DiskLatencyProfile, InjectedFault};
+
+    #[test]
+    fn test_evicted_page_is_read_back() {
+        let dir = TempDir::new().unwrap();
+        let disk_manager = DiskManager::open(dir.path().join("test.db")).unwrap();
+        let buffer_pool_manager = BufferPoolManager::new(disk_manager, 2, 2);
+
+        let mut page_ids = vec![];
+        for i in 0..5 {
+            let (page_id, mut page) = buffer_pool_manager.new_page().unwrap();
+            page[0] = i;
+            page_ids.push(page_id);
+        }
+
+        for (i, page_id) in page_ids.into_iter().enumerate() {
+            let page = buffer_pool_manager.fetch_page_read(page_id).unwrap();
+            assert_eq!(page[0], i as u8);
+        }
+    }
+
+    #[test]
+    fn test_access_trace() {
+        let dir = TempDir::new().unwrap();
+        let disk_manager = DiskManager::open(dir.path().join("test.db")).unwrap();
+        let buffer_pool_manager = BufferPoolManager::new(disk_manager, 2, 2);
+        let trace_path = dir.path().join("test.trace");
+
+        buffer_pool_manager.start_access_trace(&trace_path).unwrap();
+        let (first, page) = buffer_pool_manager.new_page().unwrap();
+        drop(page);
+        drop(buffer_pool_manager.fetch_page_read(first).unwrap());
+        buffer_pool_manager.stop_access_trace().unwrap();
+        drop(buffer_pool_manager.fetch_page_read(first).unwrap());
+
+        let records = read_access_trace(&trace_path).unwrap();
+        assert_eq!(
+            records
+                .iter()
+                .map(|record| (record.page_id, record.hit))
+                .collect::<Vec<_>>(),
+            vec![(first, false), (first, true)]
+        );
+
+        let mut replacer = LruKReplacer::new(1, 2);
+        let stats = replay_access_trace(&records, 1, &mut replacer);
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.misses, 1);
+    }
+
+    #[test]
+    fn test_stats() {
+        let dir = TempDir::new().unwrap();
+        let disk_manager = DiskManager::open(dir.path().join("test.db")).unwrap();
+        let buffer_pool_manager = BufferPoolManager::new(disk_manager, 4, 2);
+
+        let (page_id, page) = buffer_pool_manager.new_page().unwrap();
+        drop(page);
+        let stats = buffer_pool_manager.stats().unwrap();
+        assert_eq!(stats.free_frames, 3);
+        assert_eq!(stats.resident_pages, 1);
+        assert_eq!(stats.dirty_pages, 1);
+        assert_eq!(stats.evictable_frames, 1);
+
+        buffer_pool_manager.flush_page(page_id).unwrap();
+        let stats = buffer_pool_manager.stats().unwrap();
+        assert_eq!(stats.dirty_pages, 0);
+        assert_eq!(stats.disk_pages, page_id.as_usize() + 1);
+        assert_eq!(stats.disk_writes, 1);
+
+        drop(buffer_pool_manager.fetch_page_read(page_id).unwrap());
+        let stats = buffer_pool_manager.stats().unwrap();
+        assert_eq!((stats.hits, stats.misses, stats.disk_reads), (1, 0, 0));
+    }
+
+    #[test]
+    fn test_flush_merges_adjacent_pages() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("test.db");
+        let buffer_pool_manager = BufferPoolManager::new(DiskManager::open(&path).unwrap(), 8, 2);
+
+        let mut page_ids = vec![];
+        for i in 0..5u8 {
+            let (page_id, mut page) = buffer_pool_manager.new_page().unwrap();
+            // short data is padded to page size on disk
+            page.overwrite(&[i + 1; 10]);
+            page_ids.push(page_id);
+        }
+        buffer_pool_manager.flush_all_pages().unwrap();
+        let stats = buffer_pool_manager.stats().unwrap();
+        assert_eq!((stats.dirty_pages, stats.disk_writes), (0, 1));
+        drop(buffer_pool_manager);
+
+        let disk_manager = DiskManager::open(&path).unwrap();
+        for (i, page_id) in page_ids.into_iter().enumerate() {
+            let data = disk_manager.read_page(page_id).unwrap();
+            assert_eq!(data[..10], [i as u8 + 1; 10]);
+            assert!(data[10..].iter().all(|byte| *byte == 0));
+        }
+    }
+
+    #[test]
+    fn test_failed_flush_keeps_page_dirty() {
+        let mut disk_manager = DiskManager::new();
+        disk_manager.set_latency_profile(DiskLatencyProfile::NONE);
+        let buffer_pool_manager = BufferPoolManager::new(disk_manager, 4, 2);
+        let (page_id, mut page) = buffer_pool_manager.new_page().unwrap();
+        page.overwrite(&[7; 10]);
+        drop(page);
+
+        buffer_pool_manager.disk_manager().fail_next_n_writes(1);
+        let error = buffer_pool_manager.flush_page(page_id).unwrap_err();
+        assert!(error.downcast_ref::<InjectedFault>().is_some());
+        assert_eq!(buffer_pool_manager.stats().unwrap().dirty_pages, 1);
+
+        buffer_pool_manager.flush_page(page_id).unwrap();
+        assert_eq!(buffer_pool_manager.stats().unwrap().dirty_pages, 0);
+        assert_eq!(
+            buffer_pool_manager
+                .disk_manager()
+                .read_page(page_id)
+                .unwrap()[0],
+            7
+        );
+    }
+
+    #[test]
+    fn test_pinned_pages_are_not_evicted() {
+        let dir = TempDir::new().unwrap();
+        let disk_manager = DiskManager::open(dir.path().join("test.db")).unwrap();
+        let buffer_pool_manager = BufferPoolManager::new(disk_manager, 2, 2);
+
+        let first = buffer_pool_manager.new_page().unwrap();
+        let second = buffer_pool_manager.new_page().unwrap();
+        assert!(buffer_pool_manager.new_page().is_none());
+
+        drop(first);
+        assert!(buffer_pool_manager.new_page().is_some());
+        drop(second);
+    }
+
+    #[test]
+    fn test_kept_resident_page_is_evicted_last() {
+        let dir = TempDir::new().unwrap();
+        let disk_manager = DiskManager::open(dir.path().join("test.db")).unwrap();
+        let buffer_pool_manager = BufferPoolManager::new(disk_manager, 2, 2);
+
+        let (kept_page_id, _) = buffer_pool_manager.new_page().unwrap();
+        buffer_pool_manager.set_keep_resident(kept_page_id, true);
+        for _ in 0..4 {
+            buffer_pool_manager.new_page().unwrap();
+        }
+        assert!(buffer_pool_manager.pages_map.contains_key(&kept_page_id));
+
+        // with every other frame pinned kept page is evicted after all
+        let _pinned = buffer_pool_manager.new_page().unwrap();
+        assert!(buffer_pool_manager.new_page().is_some());
+        assert!(!buffer_pool_manager.pages_map.contains_key(&kept_page_id));
+    }
+
+    #[test]
+    fn test_prefetched_page_is_not_read_again() {
+        let dir = TempDir::new().unwrap();
+        let disk_manager = DiskManager::open(dir.path().join("test.db")).unwrap();
+        let buffer_pool_manager = BufferPoolManager::new(disk_manager, 2, 2);
+
+        let (page_id, mut page) = buffer_pool_manager.new_page().unwrap();
+        page[0] = 7;
+        drop(page);
+        for _ in 0..2 {
+            buffer_pool_manager.new_page().unwrap();
+        }
+        assert!(!buffer_pool_manager.pages_map.contains_key(&page_id));
+
+        buffer_pool_manager.prefetch_page(page_id);
+        buffer_pool_manager.prefetch_page(page_id);
+        let disk_reads = buffer_pool_manager.stats().unwrap().disk_reads;
+        assert_eq!(buffer_pool_manager.fetch_page_read(page_id).unwrap()[0], 7);
+        assert_eq!(buffer_pool_manager.stats().unwrap().disk_reads, disk_reads);
+    }
+
+    #[test]
+    fn test_sequential_reads_are_read_ahead() {
+        let dir = TempDir::new().unwrap();
+        let disk_manager = DiskManager::open(dir.path().join("test.db")).unwrap();
+        let buffer_pool_manager = BufferPoolManager::new(disk_manager, 4, 2);
+        let mut page_ids = vec![];
+        for i in 0..16u8 {
+            let (page_id, mut page) = buffer_pool_manager.new_page().unwrap();
+            page[0] = i;
+            page_ids.push(page_id);
+        }
+        buffer_pool_manager.flush_all_pages().unwrap();
+
+        for (i, page_id) in page_ids.iter().enumerate() {
+            assert_eq!(
+                buffer_pool_manager.fetch_page_read(*page_id).unwrap()[0],
+                i as u8
+            );
+        }
+        assert!(buffer_pool_manager.stats().unwrap().read_ahead_hits > 0);
+    }
+
+    #[test]
+    fn test_pages_survive_disk_chaos() {
+        let dir = TempDir::new().unwrap();
+        let disk_manager = DiskManager::open(dir.path().join("test.db")).unwrap();
+        let buffer_pool_manager = BufferPoolManager::new(disk_manager, 4, 2);
+        buffer_pool_manager
+            .set_disk_chaos(Some(ChaosConfig {
+                reorder_probability: 0.5,
+                max_delay: Duration::from_millis(1),
+                duplicate_probability: 0.5,
+            }))
+            .unwrap();
+
+        let page_ids = (0..32u8)
+            .map(|i| {
+                let (page_id, mut page) = buffer_pool_manager.new_page().unwrap();
+                page[0] = i;
+                page_id
+            })
+            .collect::<Vec<_>>();
+        buffer_pool_manager.flush_all_pages().unwrap();
+        for (i, page_id) in page_ids.iter().enumerate() {
+            let mut page = buffer_pool_manager.fetch_page_write(*page_id).unwrap();
+            assert_eq!(page[0], i as u8);
+            page[1] = i as u8;
+        }
+        buffer_pool_manager.flush_all_pages().unwrap();
+
+        for (i, page_id) in page_ids.iter().enumerate() {
+            let page = buffer_pool_manager.fetch_page_read(*page_id).unwrap();
+            assert_eq!(page[..2], [i as u8, i as u8]);
+        }
+        assert!(buffer_pool_manager.stats().unwrap().duplicate_completions > 0);
+        assert!(buffer_pool_manager
+            .set_disk_chaos(Some(ChaosConfig {
+                reorder_probability: 2.0,
+                max_delay: Duration::ZERO,
+                duplicate_probability: 0.0,
+            }))
+            .is_err());
+    }
+
+    #[test]
+    fn test_frame_buffers_stay_in_place() {
+        let buffer_pool_manager =
+            BufferPoolManager::with_frame_memory(DiskManager::new(), 1, 2, FrameMemory::Prefaulted)
+                .unwrap();
+        let buffer = buffer_pool_manager.pages[0].get_data_read().as_ptr();
+
+        let (page_id, mut page) = buffer_pool_manager.new_page().unwrap();
+        page.overwrite(&[7; 10]);
+        drop(page);
+        let (_, page) = buffer_pool_manager.new_page().unwrap();
+        drop(page);
+
+        // page is read back from disk into the same buffer
+        assert_eq!(buffer_pool_manager.fetch_page_read(page_id).unwrap()[0], 7);
+        assert_eq!(
+            buffer_pool_manager.pages[0].get_data_read().as_ptr(),
+            buffer
+        );
+    }
+
+    #[test]
+    fn test_pinned_page_is_deleted_when_unpinned() {
+        let buffer_pool_manager = BufferPoolManager::new(DiskManager::new(), 4, 2);
+        let (page_id, page) = buffer_pool_manager.new_page().unwrap();
+        drop(page);
+
+        let reader = buffer_pool_manager.fetch_page_read(page_id).unwrap();
+        assert!(buffer_pool_manager.delete_page(page_id).is_err());
+        buffer_pool_manager
+            .delete_page_when_unpinned(page_id)
+            .unwrap();
+        assert!(!buffer_pool_manager.disk_manager().is_free_page(page_id));
+
+        drop(reader);
+        assert!(buffer_pool_manager.disk_manager().is_free_page(page_id));
+        assert_eq!(buffer_pool_manager.stats().unwrap().resident_pages, 0);
+    }
+
+    #[test]
+    fn test_unused_page_ids_are_given_back() {
+        let buffer_pool_manager = BufferPoolManager::new(DiskManager::new(), 4, 2);
+        let disk_scheduler = Arc::clone(&buffer_pool_manager.disk_scheduler);
+        let mut page_ids = vec![buffer_pool_manager.allocate_page()];
+        page_ids.push(std::thread::scope(|scope| {
+            scope
+                .spawn(|| buffer_pool_manager.allocate_page())
+                .join()
+                .unwrap()
+        }));
+        drop(buffer_pool_manager);
+
+        // ids left in batches are handed out again, so they don't stay holes
+        let disk_manager = disk_scheduler.disk_manager();
+        page_ids.extend((0..62).map(|_| disk_manager.allocate_page()));
+        page_ids.sort();
+        assert_eq!(page_ids, (1..=64).map(PageId::new).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_page_ids_are_unique_across_threads() {
+        let buffer_pool_manager = BufferPoolManager::new(DiskManager::new(), 8, 2);
+
+        let page_ids = std::thread::scope(|scope| {
+            let threads = (0..4)
+                .map(|_| {
+                    scope.spawn(|| {
+                        (0..50)
+                            .map(|_| buffer_pool_manager.allocate_page())
+                            .collect::<Vec<PageId>>()
+                    })
+                })
+                .collect::<Vec<_>>();
+            threads
+                .into_iter()
+                .flat_map(|thread| thread.join().unwrap())
+                .collect::<Vec<PageId>>()
+        });
+
+        assert_eq!(page_ids.iter().collect::<HashSet<_>>().len(), 200);
+        assert!(!page_ids.contains(&PageId::new(0)));
+    }
+
+    #[test]
+    fn test_wait_for_unpinned_frame() {
+        let dir = TempDir::new().unwrap();
+        let disk_manager = DiskManager::open(dir.path().join("test.db")).unwrap();
+        let buffer_pool_manager = BufferPoolManager::new(disk_manager, 2, 2);
+
+        let first = buffer_pool_manager.new_page().unwrap();
+        let _second = buffer_pool_manager.new_page().unwrap();
+        buffer_pool_manager.set_frame_wait_policy(FrameWaitPolicy::Wait(Duration::from_millis(50)));
+        assert!(buffer_pool_manager.new_page().is_none());
+
+        buffer_pool_manager.set_frame_wait_policy(FrameWaitPolicy::Wait(Duration::from_secs(10)));
+        std::thread::scope(|scope| {
+            scope.spawn(move || {
+                std::thread::sleep(Duration::from_millis(50));
+                drop(first);
+            });
+            assert!(buffer_pool_manager.new_page().is_some());
+        });
+    }
+
+    #[test]
+    fn test_disk_io_runs_without_latch() {
+        let mut disk_manager = DiskManager::new();
+        disk_manager.set_latency_profile(DiskLatencyProfile {
+            read: Duration::from_millis(100),
+            write: Duration::from_millis(300),
+        });
+        let buffer_pool_manager = BufferPoolManager::new(disk_manager, 8, 2);
+        let mut page_ids = vec![];
+        for i in 0..4 {
+            let (page_id, mut page) = buffer_pool_manager.new_page().unwrap();
+            page[0] = i;
+            page_ids.push(page_id);
+        }
+        buffer_pool_manager.flush_all_pages().unwrap();
+        assert_eq!(buffer_pool_manager.discard_clean_pages(), 4);
+
+        // misses of different pages read at once, fetchers of the same page share its read
+        let misses = buffer_pool_manager.stats().unwrap().misses;
+        let started_at = Instant::now();
+        std::thread::scope(|scope| {
+            for i in 0..8 {
+                let buffer_pool_manager = &buffer_pool_manager;
+                let page_id = page_ids[i % 4];
+                scope.spawn(move || {
+                    let page = buffer_pool_manager.fetch_page_read(page_id).unwrap();
+                    assert_eq!(page[0], (i % 4) as u8);
+                });
+            }
+        });
+        assert!(started_at.elapsed() < Duration::from_millis(300));
+        assert_eq!(buffer_pool_manager.stats().unwrap().misses, misses + 4);
+
+        // dirty victim is written back while resident pages are fetched
+        let _pinned = page_ids
+            .iter()
+            .map(|page_id| buffer_pool_manager.fetch_page_read(*page_id).unwrap())
+            .collect::<Vec<_>>();
+        for _ in 0..4 {
+            let (_, mut page) = buffer_pool_manager.new_page().unwrap();
+            page[0] = 9;
+        }
+        std::thread::scope(|scope| {
+            let evicting =
+                scope.spawn(|| buffer_pool_manager.new_page().map(|(page_id, _)| page_id));
+            std::thread::sleep(Duration::from_millis(50));
+            let fetching = scope.spawn(|| {
+                let started_at = Instant::now();
+                drop(buffer_pool_manager.fetch_page_read(page_ids[0]).unwrap());
+                started_at.elapsed()
+            });
+            assert!(fetching.join().unwrap() < Duration::from_millis(100));
+            assert!(evicting.join().unwrap().is_some());
+        });
+    }
+
+    #[test]
+    fn test_owner_quota() {
+        let dir = TempDir::new().unwrap();
+        let disk_manager = DiskManager::open(dir.path().join("test.db")).unwrap();
+        let buffer_pool_manager = BufferPoolManager::new(disk_manager, 4, 2);
+        let (lookup_page_id, page) = buffer_pool_manager.new_page().unwrap();
+        drop(page);
+
+        buffer_pool_manager.set_owner_quota(1, Some(2));
+        let mut scan_page_ids = vec![];
+        for i in 0..5 {
+            let (page_id, mut page) = buffer_pool_manager.new_page_as(1).unwrap();
+            page[0] = i;
+            scan_page_ids.push(page_id);
+        }
+        assert_eq!(buffer_pool_manager.owner_resident_frames(1), 2);
+        assert!(buffer_pool_manager.pages_map.contains_key(&lookup_page_id));
+
+        let first = buffer_pool_manager
+            .fetch_page_read_as(1, scan_page_ids[0])
+            .unwrap();
+        assert_eq!(first[0], 0);
+        let second = buffer_pool_manager
+            .fetch_page_read_as(1, scan_page_ids[1])
+            .unwrap();
+        assert!(buffer_pool_manager.new_page_as(1).is_none());
+        drop((first, second));
+        assert!(buffer_pool_manager.pages_map.contains_key(&lookup_page_id));
+    }
+
+    #[test]
+    fn test_logged_structure_change_is_redone() {
+        let dir = TempDir::new().unwrap();
+        let log_path = dir.path().join("test.db.structure");
+
+        // change was logged but crash happened before its pages reached data file
+        let mut log = StructureLog::open(&log_path).unwrap();
+        log.write(&StructureRecord {
+            change: StructureChange::BucketSplit,
+            pages: vec![(PageId::new(2), vec![1; 8]), (PageId::new(5), vec![2; 8])],
+        })
+        .unwrap();
+        drop(log);
+
+        let disk_manager = DiskManager::open(dir.path().join("test.db")).unwrap();
+        let buffer_pool_manager = BufferPoolManager::new(disk_manager, 4, 2);
+        buffer_pool_manager
+            .open_structure_log(&log_path, true)
+            .unwrap();
+
+        assert_eq!(
+            buffer_pool_manager.fetch_page_read(PageId::new(2)).unwrap()[..8],
+            [1; 8]
+        );
+        assert_eq!(
+            buffer_pool_manager.fetch_page_read(PageId::new(5)).unwrap()[..8],
+            [2; 8]
+        );
+        assert_eq!(buffer_pool_manager.new_page().unwrap().0, PageId::new(6));
+        assert!(StructureLog::open(&log_path)
+            .unwrap()
+            .read()
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_scan_does_not_evict_pool_pages() {
+        let dir = TempDir::new().unwrap();
+        let disk_manager = DiskManager::open(dir.path().join("test.db")).unwrap();
+        let buffer_pool_manager = BufferPoolManager::new(disk_manager, 4, 2);
+
+        let mut page_ids = vec![];
+        for i in 0..12 {
+            let (page_id, mut page) = buffer_pool_manager.new_page().unwrap();
+            page[0] = i;
+            page_ids.push(page_id);
+        }
+        let (scanned_page_ids, hot_page_ids) = page_ids.split_at(8);
+
+        for (i, page_id) in scanned_page_ids.iter().enumerate() {
+            let page = buffer_pool_manager
+                .fetch_page_read_for(AccessType::Scan, *page_id)
+                .unwrap();
+            assert_eq!(page[0], i as u8);
+        }
+        for page_id in hot_page_ids {
+            assert!(buffer_pool_manager.pages_map.contains_key(page_id));
+        }
+        let stats = buffer_pool_manager.stats().unwrap();
+        assert_eq!(stats.resident_pages, 4 + SCAN_RING_SIZE);
+        assert_eq!(stats.evictable_frames, 4);
+
+        // page left in ring is a hit for lookup too
+        let misses = stats.misses;
+        let page = buffer_pool_manager.fetch_page_read(page_ids[7]).unwrap();
+        assert_eq!(page[0], 7);
+        drop(page);
+        assert_eq!(buffer_pool_manager.stats().unwrap().misses, misses);
     }
 }
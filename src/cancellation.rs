@@ -0,0 +1,82 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// Shared flag telling long-running operation to stop, checked by scans between pages.
+/// Cancelled operation fails with `ExtendibleHashTableError::Cancelled`.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Token which cancels itself once `timeout` passes
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self {
+            cancelled: Arc::default(),
+            deadline: Some(Instant::now() + timeout),
+        }
+    }
+
+    /// Cancel operations holding this token or its clones
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, thread};
+
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{ExtendibleHashTableError, Kv};
+
+    #[test]
+    fn test_cancelled_scan() {
+        let dir = TempDir::new().unwrap();
+        let kv = Arc::new(Kv::<String, u64>::open(dir.path().join("kv.db")).unwrap());
+        for i in 0..100 {
+            kv.insert(format!("key{i}"), i).unwrap();
+        }
+        let snapshot = kv.snapshot();
+
+        assert_eq!(
+            snapshot
+                .scan_cancellable(&CancellationToken::new())
+                .unwrap()
+                .len(),
+            100
+        );
+
+        let token = CancellationToken::new();
+        token.clone().cancel();
+        let error = snapshot.scan_cancellable(&token).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<ExtendibleHashTableError>(),
+            Some(ExtendibleHashTableError::Cancelled)
+        ));
+
+        let token = CancellationToken::with_timeout(Duration::from_millis(10));
+        assert!(!token.is_cancelled());
+        thread::sleep(Duration::from_millis(20));
+        assert!(token.is_cancelled());
+    }
+}
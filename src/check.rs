@@ -0,0 +1,224 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use anyhow::Result;
+
+use crate::{
+    disk_manager::{CorruptPage, DiskManager},
+    page::PageId,
+    storage::{
+        extendible_hash_table::{
+            extendible_hash_table_bucket_page::decode_occupancy,
+            extendible_hash_table_directory_page::ExtendibleHTableDirectoryPage,
+            extendible_hash_table_header_page::ExtendibleHTableHeaderPage,
+            extendible_hash_table_key_dictionary_page::ExtendibleHTableKeyDictionaryPage,
+        },
+        metadata_page::{MetadataPage, METADATA_PAGE_ID},
+    },
+};
+
+/// Problem found by `DbInstance::check`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckProblem {
+    pub page_id: PageId,
+    pub description: String,
+}
+
+/// Result of `DbInstance::check`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CheckReport {
+    /// Pages in data file
+    pub pages: usize,
+    /// Hash tables registered in catalog
+    pub hash_tables: usize,
+    /// Whether storage keeps page checksums, they are verified for every page if it does
+    pub checksums_verified: bool,
+    pub problems: Vec<CheckProblem>,
+    /// Pages no table refers to which still hold data, like pages of structure change
+    /// interrupted by crash
+    pub unreferenced_pages: Vec<PageId>,
+}
+
+impl CheckReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl fmt::Display for CheckReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} pages, {} hash tables, {} problems, {} unreferenced pages",
+            self.pages,
+            self.hash_tables,
+            self.problems.len(),
+            self.unreferenced_pages.len()
+        )?;
+        for problem in &self.problems {
+            writeln!(f, "page {}: {}", problem.page_id, problem.description)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Verify checksums of every page and structure of every hash table in catalog, pages
+/// are read directly from disk
+pub(crate) fn check(disk_manager: &DiskManager) -> Result<CheckReport> {
+    let mut checker = Checker {
+        disk_manager,
+        num_pages: disk_manager.num_pages()?,
+        roles: BTreeMap::from([(METADATA_PAGE_ID, "metadata".to_string())]),
+        problems: vec![],
+    };
+
+    let checksums_verified = disk_manager.keeps_checksums();
+    if checksums_verified {
+        for page_id in (0..checker.num_pages).map(PageId::new) {
+            for copy in disk_manager.corrupted_copies(page_id)? {
+                checker.problem(page_id, format!("Checksum of {} copy doesn't match.", copy));
+            }
+        }
+    }
+
+    let mut hash_tables = 0;
+    let metadata = read_verified(disk_manager, METADATA_PAGE_ID)?.unwrap_or_default();
+    match MetadataPage::from_bytes(&metadata) {
+        Ok(metadata) => {
+            for name in metadata.get_names() {
+                hash_tables += 1;
+                if let Some(header_page_id) = metadata.get_header_page_id(&name) {
+                    checker.check_hash_table(&name, header_page_id)?;
+                }
+            }
+        }
+        Err(_) => checker.problem(METADATA_PAGE_ID, "Catalog can't be decoded.".to_string()),
+    }
+
+    let mut unreferenced_pages = vec![];
+    for page_id in (0..checker.num_pages).map(PageId::new) {
+        if !checker.roles.contains_key(&page_id)
+            && !disk_manager.is_free_page(page_id)
+            && read_verified(disk_manager, page_id)?
+                .is_none_or(|data| data.iter().any(|byte| *byte != 0))
+        {
+            unreferenced_pages.push(page_id);
+        }
+    }
+
+    Ok(CheckReport {
+        pages: checker.num_pages,
+        hash_tables,
+        checksums_verified,
+        problems: checker.problems,
+        unreferenced_pages,
+    })
+}
+
+// page which fails its checksum is reported by checksum pass and read as None
+fn read_verified(disk_manager: &DiskManager, page_id: PageId) -> Result<Option<Vec<u8>>> {
+    match disk_manager.read_page(page_id) {
+        Ok(data) => Ok(Some(data)),
+        Err(error) if error.is::<CorruptPage>() => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+struct Checker<'a> {
+    disk_manager: &'a DiskManager,
+    num_pages: usize,
+    // what every referenced page is used for
+    roles: BTreeMap<PageId, String>,
+    problems: Vec<CheckProblem>,
+}
+
+impl Checker<'_> {
+    fn problem(&mut self, page_id: PageId, description: String) {
+        self.problems.push(CheckProblem {
+            page_id,
+            description,
+        });
+    }
+
+    // returns whether page should be read, page outside of data file or with another
+    // role is a problem
+    fn reference(&mut self, page_id: PageId, role: String) -> bool {
+        if page_id.as_usize() >= self.num_pages {
+            self.problem(page_id, format!("{} is beyond end of data file.", role));
+            return false;
+        }
+        if let Some(other) = self.roles.get(&page_id) {
+            let description = format!("{} is also {}.", role, other);
+            self.problem(page_id, description);
+            return false;
+        }
+        self.roles.insert(page_id, role);
+
+        true
+    }
+
+    fn check_hash_table(&mut self, name: &str, header_page_id: PageId) -> Result<()> {
+        let role = format!("Header of {:?}", name);
+        if !self.reference(header_page_id, role.clone()) {
+            return Ok(());
+        }
+        let Some(data) = read_verified(self.disk_manager, header_page_id)? else {
+            return Ok(());
+        };
+        let Ok(header) = ExtendibleHTableHeaderPage::from_bytes(&data) else {
+            self.problem(header_page_id, format!("{} can't be decoded.", role));
+            return Ok(());
+        };
+
+        if let Some(dictionary_page_id) = header.get_key_dictionary_page_id() {
+            let role = format!("Key dictionary of {:?}", name);
+            if self.reference(dictionary_page_id, role.clone())
+                && read_verified(self.disk_manager, dictionary_page_id)?.is_some_and(|data| {
+                    ExtendibleHTableKeyDictionaryPage::from_bytes(&data).is_err()
+                })
+            {
+                self.problem(dictionary_page_id, format!("{} can't be decoded.", role));
+            }
+        }
+
+        for directory_index in 0..header.get_max_size() {
+            let Some(directory_page_id) = header.get_directory_page_id(directory_index).copied()
+            else {
+                continue;
+            };
+            let role = format!("Directory {} of {:?}", directory_index, name);
+            if !self.reference(directory_page_id, role.clone()) {
+                continue;
+            }
+            let Some(data) = read_verified(self.disk_manager, directory_page_id)? else {
+                continue;
+            };
+            let Ok(directory) = ExtendibleHTableDirectoryPage::from_bytes(&data) else {
+                self.problem(directory_page_id, format!("{} can't be decoded.", role));
+                continue;
+            };
+            for description in directory.integrity_problems() {
+                self.problem(directory_page_id, format!("{}: {}.", role, description));
+            }
+
+            // several directory slots point to the same bucket when its local depth is lower
+            let bucket_page_ids = (0..directory.get_size())
+                .filter_map(|bucket_index| directory.get_bucket_page_id(bucket_index).copied())
+                .collect::<BTreeSet<PageId>>();
+            for bucket_page_id in bucket_page_ids {
+                let role = format!("Bucket of {:?}", name);
+                if self.reference(bucket_page_id, role.clone())
+                    && read_verified(self.disk_manager, bucket_page_id)?
+                        .is_some_and(|data| decode_occupancy(&data).is_err())
+                {
+                    self.problem(bucket_page_id, format!("{} can't be decoded.", role));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use anyhow::Result;
+use parking_lot::Mutex;
+
+use crate::disk_manager::{open_data_file, CorruptPage};
+use crate::page::{PageId, PAGE_SIZE};
+
+// page data followed by little endian crc32 of it
+pub(crate) const FRAME_SIZE: usize = PAGE_SIZE + 4;
+
+/// Data file which stores every page with checksum in its trailer, page which doesn't
+/// match its checksum is read as `CorruptPage` error
+#[derive(Debug)]
+pub(crate) struct ChecksummedBackend {
+    file: Mutex<File>,
+}
+
+impl ChecksummedBackend {
+    pub fn open(path: &Path) -> Result<Self> {
+        Ok(Self {
+            file: Mutex::new(open_data_file(path)?),
+        })
+    }
+
+    pub fn read_page(&self, page_id: PageId) -> Result<Vec<u8>> {
+        let frame = read_frame(&mut self.file.lock(), page_id)?;
+
+        Ok(verify_frame(&frame).ok_or(CorruptPage { page_id })?)
+    }
+
+    /// Write page data of page size with its checksum
+    pub fn write_page(&self, page_id: PageId, data: &[u8]) -> Result<()> {
+        write_frame(&mut self.file.lock(), page_id, &encode_frame(data))
+    }
+
+    pub fn is_corrupted(&self, page_id: PageId) -> Result<bool> {
+        Ok(verify_frame(&read_frame(&mut self.file.lock(), page_id)?).is_none())
+    }
+
+    pub fn sync(&self) -> Result<()> {
+        self.file.lock().sync_all()?;
+
+        Ok(())
+    }
+
+    pub fn num_pages(&self) -> Result<usize> {
+        let len = self.file.lock().metadata()?.len() as usize;
+
+        Ok(len.div_ceil(FRAME_SIZE))
+    }
+}
+
+pub(crate) fn encode_frame(data: &[u8]) -> Vec<u8> {
+    let mut frame = data.to_vec();
+    frame.extend_from_slice(&crc32fast::hash(data).to_le_bytes());
+
+    frame
+}
+
+// frame beyond end of file is read as zeroes
+pub(crate) fn read_frame(file: &mut File, page_id: PageId) -> Result<Vec<u8>> {
+    let mut frame = vec![0; FRAME_SIZE];
+    file.seek(SeekFrom::Start((page_id.as_usize() * FRAME_SIZE) as u64))?;
+
+    let mut read = 0;
+    while read < FRAME_SIZE {
+        let bytes = file.read(&mut frame[read..])?;
+        if bytes == 0 {
+            break;
+        }
+        read += bytes;
+    }
+
+    Ok(frame)
+}
+
+pub(crate) fn write_frame(file: &mut File, page_id: PageId, frame: &[u8]) -> Result<()> {
+    file.seek(SeekFrom::Start((page_id.as_usize() * FRAME_SIZE) as u64))?;
+    file.write_all(frame)?;
+
+    Ok(())
+}
+
+// page data if checksum matches, never written page is all zeroes including checksum
+pub(crate) fn verify_frame(frame: &[u8]) -> Option<Vec<u8>> {
+    let (data, checksum) = frame.split_at(PAGE_SIZE);
+    let checksum = u32::from_le_bytes(checksum.try_into().unwrap());
+
+    if crc32fast::hash(data) == checksum || frame.iter().all(|byte| *byte == 0) {
+        return Some(data.to_vec());
+    }
+
+    None
+}
@@ -0,0 +1,106 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::{
+    lru_k_replacer::{AccessType, FrameId},
+    replacer::Replacer,
+};
+
+#[derive(Debug)]
+struct ClockEntry {
+    is_referenced: bool,
+    is_evictable: bool,
+}
+
+/// Second chance policy: hand sweeps frames in ring, referenced frame gets its bit cleared
+/// and is skipped once
+#[derive(Debug, Default)]
+pub struct ClockReplacer {
+    // front of the ring is under the hand
+    ring: VecDeque<FrameId>,
+    entries: HashMap<FrameId, ClockEntry>,
+}
+
+impl ClockReplacer {
+    pub fn new(num_of_frames: usize) -> Self {
+        Self {
+            ring: VecDeque::with_capacity(num_of_frames),
+            entries: HashMap::with_capacity(num_of_frames),
+        }
+    }
+}
+
+impl Replacer for ClockReplacer {
+    fn record_access(&mut self, frame_id: FrameId, _access_type: AccessType) {
+        match self.entries.get_mut(&frame_id) {
+            Some(entry) => entry.is_referenced = true,
+            None => {
+                self.entries.insert(
+                    frame_id,
+                    ClockEntry {
+                        is_referenced: true,
+                        is_evictable: false,
+                    },
+                );
+                self.ring.push_back(frame_id);
+            }
+        }
+    }
+
+    fn set_evictable(&mut self, frame_id: FrameId, is_evictable: bool) {
+        if let Some(entry) = self.entries.get_mut(&frame_id) {
+            entry.is_evictable = is_evictable;
+        }
+    }
+
+    fn evict(&mut self) -> Option<FrameId> {
+        // two sweeps clear every reference bit, so evictable frame is found if there is one
+        for _ in 0..2 * self.ring.len() {
+            let frame_id = self.ring.pop_front()?;
+            let entry = self.entries.get_mut(&frame_id).unwrap();
+
+            if entry.is_evictable && !entry.is_referenced {
+                self.entries.remove(&frame_id);
+                return Some(frame_id);
+            }
+            entry.is_referenced = false;
+            self.ring.push_back(frame_id);
+        }
+
+        None
+    }
+
+    fn remove(&mut self, frame_id: FrameId) {
+        if self.entries.remove(&frame_id).is_some() {
+            self.ring.retain(|id| *id != frame_id);
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.entries
+            .values()
+            .filter(|entry| entry.is_evictable)
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_referenced_frame_gets_second_chance() {
+        let mut replacer = ClockReplacer::new(3);
+        for frame_id in (0..3).map(FrameId::new) {
+            replacer.record_access(frame_id, AccessType::Unknown);
+            replacer.set_evictable(frame_id, true);
+        }
+
+        // first sweep clears all bits, frame 0 is the first one without it
+        assert_eq!(replacer.evict(), Some(FrameId::new(0)));
+        replacer.record_access(FrameId::new(1), AccessType::Unknown);
+        assert_eq!(replacer.evict(), Some(FrameId::new(2)));
+
+        replacer.set_evictable(FrameId::new(1), false);
+        assert_eq!(replacer.evict(), None);
+    }
+}
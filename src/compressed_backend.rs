@@ -0,0 +1,159 @@
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use anyhow::{bail, Context, Result};
+use parking_lot::Mutex;
+
+use crate::disk_manager::{open_data_file, punch_hole};
+use crate::page::{PageId, PAGE_SIZE};
+
+// codec flag, 3 reserved bytes and little endian length of stored data
+const HEADER_SIZE: usize = 8;
+// page which doesn't compress is stored as is
+const SLOT_SIZE: usize = HEADER_SIZE + PAGE_SIZE;
+
+const STORED: u8 = 0;
+#[cfg(feature = "lz4")]
+const LZ4: u8 = 1;
+#[cfg(feature = "zstd")]
+const ZSTD: u8 = 2;
+
+/// Codec pages are compressed with on write, every page is read with the codec it was
+/// written with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PageCompression {
+    /// Pages are stored as is, pages compressed by other codecs are still read
+    None,
+    #[cfg(feature = "lz4")]
+    Lz4,
+    /// Level from 1 (fastest) to 22 (smallest)
+    #[cfg(feature = "zstd")]
+    Zstd { level: i32 },
+}
+
+/// Data file with a slot of page size and header per page. Page is compressed into the
+/// start of its slot and the rest of slot is punched out, so filesystem keeps only
+/// blocks holding compressed data.
+#[derive(Debug)]
+pub(crate) struct CompressedBackend {
+    file: Mutex<File>,
+    compression: PageCompression,
+}
+
+impl CompressedBackend {
+    pub fn open(path: &Path, compression: PageCompression) -> Result<Self> {
+        Ok(Self {
+            file: Mutex::new(open_data_file(path)?),
+            compression,
+        })
+    }
+
+    pub fn read_page(&self, page_id: PageId) -> Result<Vec<u8>> {
+        let mut slot = vec![0; SLOT_SIZE];
+        {
+            let mut file = self.file.lock();
+            file.seek(SeekFrom::Start(slot_offset(page_id)))?;
+
+            // slot can be partially (or not at all) written at the end of file
+            let mut read = 0;
+            while read < SLOT_SIZE {
+                let bytes = file.read(&mut slot[read..])?;
+                if bytes == 0 {
+                    break;
+                }
+                read += bytes;
+            }
+        }
+
+        decode_slot(page_id, &slot)
+    }
+
+    /// Write page data of page size, compressed if it gets smaller
+    pub fn write_page(&self, page_id: PageId, data: &[u8]) -> Result<()> {
+        let (codec, payload) = match self.compress(data)? {
+            Some((codec, compressed)) if compressed.len() < PAGE_SIZE => (codec, compressed),
+            _ => (STORED, data.to_vec()),
+        };
+        let mut slot = Vec::with_capacity(HEADER_SIZE + payload.len());
+        slot.extend_from_slice(&[codec, 0, 0, 0]);
+        slot.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        slot.extend_from_slice(&payload);
+
+        let mut file = self.file.lock();
+        let offset = slot_offset(page_id);
+        file.seek(SeekFrom::Start(offset))?;
+        file.write_all(&slot)?;
+        // rest of slot is never read, stale data of previous write is freed with it
+        if slot.len() < SLOT_SIZE {
+            punch_hole(&file, offset as usize + slot.len(), SLOT_SIZE - slot.len())?;
+        }
+
+        Ok(())
+    }
+
+    pub fn deallocate_page(&self, page_id: PageId) -> Result<()> {
+        punch_hole(&self.file.lock(), slot_offset(page_id) as usize, SLOT_SIZE)
+    }
+
+    pub fn sync(&self) -> Result<()> {
+        self.file.lock().sync_all()?;
+
+        Ok(())
+    }
+
+    pub fn num_pages(&self) -> Result<usize> {
+        let len = self.file.lock().metadata()?.len() as usize;
+
+        Ok(len.div_ceil(SLOT_SIZE))
+    }
+
+    // codec flag and compressed data, none if pages are stored as is
+    #[cfg_attr(not(any(feature = "lz4", feature = "zstd")), allow(unused_variables))]
+    fn compress(&self, data: &[u8]) -> Result<Option<(u8, Vec<u8>)>> {
+        Ok(match self.compression {
+            PageCompression::None => None,
+            #[cfg(feature = "lz4")]
+            PageCompression::Lz4 => Some((LZ4, lz4_flex::block::compress(data))),
+            #[cfg(feature = "zstd")]
+            PageCompression::Zstd { level } => Some((ZSTD, zstd::bulk::compress(data, level)?)),
+        })
+    }
+}
+
+fn slot_offset(page_id: PageId) -> u64 {
+    (page_id.as_usize() * SLOT_SIZE) as u64
+}
+
+// never written slot is all zeroes, which reads as stored page of zeroes
+fn decode_slot(page_id: PageId, slot: &[u8]) -> Result<Vec<u8>> {
+    let (header, data) = slot.split_at(HEADER_SIZE);
+    if header[0] == STORED {
+        return Ok(data.to_vec());
+    }
+    let len = u32::from_le_bytes(header[4..8].try_into().unwrap()) as usize;
+    if len > PAGE_SIZE {
+        bail!("Page {} has invalid compressed length {}.", page_id, len);
+    }
+
+    let page = decompress(header[0], &data[..len])
+        .with_context(|| format!("Can't decompress page {}.", page_id))?;
+    if page.len() != PAGE_SIZE {
+        bail!("Page {} decompresses to {} bytes.", page_id, page.len());
+    }
+
+    Ok(page)
+}
+
+#[cfg_attr(not(any(feature = "lz4", feature = "zstd")), allow(unused_variables))]
+fn decompress(codec: u8, compressed: &[u8]) -> Result<Vec<u8>> {
+    match codec {
+        #[cfg(feature = "lz4")]
+        LZ4 => Ok(lz4_flex::block::decompress(compressed, PAGE_SIZE)?),
+        #[cfg(feature = "zstd")]
+        ZSTD => Ok(zstd::bulk::decompress(compressed, PAGE_SIZE)?),
+        codec => bail!("Codec {} is not enabled.", codec),
+    }
+}
@@ -0,0 +1,887 @@
+use std::{
+    fmt::Debug,
+    fs::{self, File},
+    hash::Hash,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    background_jobs::BackgroundJobs,
+    buffer_pool_manager::{BufferPoolManager, FrameMemory},
+    check::{self, CheckReport},
+    disk_manager::DiskManager,
+    key_normalization::KeyNormalization,
+    page::{PageId, PAGE_SIZE},
+    space_report::{self, SpaceReport},
+    storage::metadata_page::{MetadataPage, TableParams, METADATA_PAGE_ID},
+    structure_log::StructureChange,
+    temp_page_allocator::TempPageAllocator,
+    CompactionPolicy, ExtendibleHashTable, ThreadPool,
+};
+
+const BUFFER_POOL_SIZE: usize = 64;
+const REPLACER_K: usize = 2;
+const BACKGROUND_THREADS: u32 = 2;
+const TEMP_BUFFER_POOL_SIZE: usize = 16;
+const DIRTY_PAGE_WRITER_INTERVAL: Duration = Duration::from_secs(1);
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(30);
+const STRUCTURE_LOG_SUFFIX: &str = ".structure";
+const SNAPSHOTS_SUFFIX: &str = ".snapshots";
+const BATCH_LOG_EXTENSION: &str = "batch";
+
+/// How database was brought to consistent state on open
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecoveryPath {
+    /// Previous session was closed cleanly, logs were not read
+    CleanShutdown,
+    /// Previous session crashed (or database is new), logged changes were redone
+    CrashRecovery,
+}
+
+/// Database stored in a single data file, owns buffer pool, disk I/O and
+/// thread pool for background work. Dropped instance is shut down like by `close`,
+/// errors are logged.
+#[derive(Debug)]
+pub struct DbInstance {
+    path: PathBuf,
+    background_jobs: BackgroundJobs,
+    buffer_pool_manager: Arc<BufferPoolManager>,
+    thread_pool: Arc<ThreadPool>,
+    recovery_path: RecoveryPath,
+    read_only: bool,
+    closed: bool,
+}
+
+impl DbInstance {
+    /// Open database file, create it if it doesn't exist
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let disk_manager = DiskManager::open(&path)?;
+
+        Self::open_with_disk_manager(path, disk_manager)
+    }
+
+    /// Open database file like `open`, memory of buffer pool frames is prepared up front
+    pub fn open_with_frame_memory(
+        path: impl AsRef<Path>,
+        frame_memory: FrameMemory,
+    ) -> Result<Self> {
+        let disk_manager = DiskManager::open(&path)?;
+
+        Self::open_with(path, disk_manager, frame_memory)
+    }
+
+    /// Open database over given disk manager, `path` is used for auxiliary files
+    /// like batch logs
+    pub fn open_with_disk_manager(
+        path: impl AsRef<Path>,
+        disk_manager: DiskManager,
+    ) -> Result<Self> {
+        Self::open_with(path, disk_manager, FrameMemory::Lazy)
+    }
+
+    fn open_with(
+        path: impl AsRef<Path>,
+        disk_manager: DiskManager,
+        frame_memory: FrameMemory,
+    ) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        // flag is read from disk, so metadata page isn't cached before structure log is redone
+        let clean_shutdown = MetadataPage::from_bytes(&disk_manager.read_page(METADATA_PAGE_ID)?)?
+            .is_clean_shutdown();
+        let recovery_path = if clean_shutdown {
+            RecoveryPath::CleanShutdown
+        } else {
+            RecoveryPath::CrashRecovery
+        };
+        tracing::info!(?recovery_path, "opening database");
+        // page freed before crash may still be referenced by pages which weren't written
+        if !clean_shutdown {
+            let discarded = disk_manager.discard_free_pages()?;
+            if discarded > 0 {
+                tracing::warn!(discarded, "free pages of crashed session are not reused");
+            }
+        }
+
+        let buffer_pool_manager = Arc::new(BufferPoolManager::with_frame_memory(
+            disk_manager,
+            BUFFER_POOL_SIZE,
+            REPLACER_K,
+            frame_memory,
+        )?);
+        // hash table split interrupted by crash is finished before anything is read
+        let mut structure_log_path = path.as_os_str().to_owned();
+        structure_log_path.push(STRUCTURE_LOG_SUFFIX);
+        buffer_pool_manager.open_structure_log(structure_log_path, !clean_shutdown)?;
+        // crash from now on must not be taken for clean shutdown
+        if clean_shutdown {
+            Self::mark_clean_shutdown(&buffer_pool_manager, false)?;
+        }
+        let thread_pool = Arc::new(ThreadPool::new(BACKGROUND_THREADS));
+        let background_jobs = BackgroundJobs::new(Arc::clone(&thread_pool));
+
+        // dirty pages are written in background, so eviction rarely waits for writes
+        let pool = Arc::clone(&buffer_pool_manager);
+        background_jobs.register("dirty-page-writer", DIRTY_PAGE_WRITER_INTERVAL, move || {
+            pool.flush_all_pages()
+        });
+        let pool = Arc::clone(&buffer_pool_manager);
+        background_jobs.register("checkpointer", CHECKPOINT_INTERVAL, move || {
+            pool.flush_all_pages()?;
+            pool.sync()
+        });
+
+        Ok(Self {
+            path,
+            background_jobs,
+            buffer_pool_manager,
+            thread_pool,
+            recovery_path,
+            read_only: false,
+            closed: false,
+        })
+    }
+
+    /// Open database for reading while one writer process may have it open, see
+    /// `DiskManager::open_read_only`. Nothing is recovered or written, tables are not
+    /// created. Pages are cached until `refresh`, catalog is read from disk every time.
+    pub fn open_read_only(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let disk_manager = DiskManager::open_read_only(&path)?;
+        let buffer_pool_manager = Arc::new(BufferPoolManager::new(
+            disk_manager,
+            BUFFER_POOL_SIZE,
+            REPLACER_K,
+        ));
+        let thread_pool = Arc::new(ThreadPool::new(BACKGROUND_THREADS));
+        let background_jobs = BackgroundJobs::new(Arc::clone(&thread_pool));
+        tracing::info!("opening database read-only");
+
+        Ok(Self {
+            path,
+            background_jobs,
+            buffer_pool_manager,
+            thread_pool,
+            recovery_path: RecoveryPath::CleanShutdown,
+            read_only: true,
+            closed: false,
+        })
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Drop cached pages, so reads see what writer process has written since. Returns
+    /// number of dropped pages.
+    pub fn refresh(&self) -> usize {
+        self.buffer_pool_manager.discard_clean_pages()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether logs were redone on open because previous session didn't close cleanly
+    pub fn recovery_path(&self) -> RecoveryPath {
+        self.recovery_path
+    }
+
+    pub fn buffer_pool_manager(&self) -> Arc<BufferPoolManager> {
+        Arc::clone(&self.buffer_pool_manager)
+    }
+
+    /// Move pages not accessed on disk within `window` to cold tier of tiered storage,
+    /// pages kept in buffer pool meanwhile count as not accessed
+    pub fn migrate_cold_pages(&self, window: Duration) -> Result<usize> {
+        self.buffer_pool_manager.migrate_cold_pages(window)
+    }
+
+    /// Thread pool shared by background jobs of the database
+    pub fn thread_pool(&self) -> &ThreadPool {
+        &self.thread_pool
+    }
+
+    /// Recurring maintenance jobs: `dirty-page-writer` and `checkpointer` are registered
+    /// on open, other components add their own
+    pub fn background_jobs(&self) -> &BackgroundJobs {
+        &self.background_jobs
+    }
+
+    /// Merge sparse buckets of hash table by `compact_buckets` in background job
+    /// `bucket-compaction:<table>`, so file size follows live data after removes
+    pub fn schedule_bucket_compaction<K, V>(
+        &self,
+        hash_table: Arc<ExtendibleHashTable<K, V>>,
+        policy: CompactionPolicy,
+    ) where
+        K: Hash
+            + Eq
+            + Clone
+            + Debug
+            + Serialize
+            + DeserializeOwned
+            + ToString
+            + Send
+            + Sync
+            + 'static,
+        V: Clone + Debug + Serialize + DeserializeOwned + Send + Sync + 'static,
+    {
+        let name = format!("bucket-compaction:{}", hash_table.name());
+        self.background_jobs
+            .register(name, policy.interval, move || {
+                let merges = hash_table.compact_buckets(policy.max_merged_fill)?;
+                if merges > 0 {
+                    tracing::debug!(table = hash_table.name(), merges, "compacted buckets");
+                }
+
+                Ok(())
+            });
+    }
+
+    /// Allocator of temporary pages for spilling operators, its pages are kept in a separate
+    /// file next to the database and are freed when allocator is dropped
+    pub fn temp_page_allocator(&self) -> Result<TempPageAllocator> {
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+
+        TempPageAllocator::new(dir, TEMP_BUFFER_POOL_SIZE)
+    }
+
+    /// Write all dirty pages to disk and wait until they are durable
+    pub fn flush(&self) -> Result<()> {
+        self.buffer_pool_manager.flush_all_pages()?;
+        self.buffer_pool_manager.sync()
+    }
+
+    /// Verify page checksums, structure of every hash table and references of catalog.
+    /// Dirty pages are flushed and data file is read directly, writes running meanwhile
+    /// may show up as problems.
+    pub fn check(&self) -> Result<CheckReport> {
+        self.flush()?;
+
+        check::check(self.buffer_pool_manager.disk_manager())
+    }
+
+    /// Pages of data file by table and kind with fill of buckets. Dirty pages are flushed
+    /// and data file is read directly.
+    pub fn space_report(&self) -> Result<SpaceReport> {
+        self.flush()?;
+
+        space_report::space_report(self.buffer_pool_manager.disk_manager())
+    }
+
+    /// Copy database as it is at this point in time to snapshot `name` in directory next to
+    /// data file, table writes wait until copy is done. Data file is copied page by page,
+    /// so snapshot of any storage is a plain data file. Snapshot is built in temporary
+    /// directory which is renamed once copy is durable, so crash never leaves partial
+    /// snapshot behind. Returns path of snapshot data file.
+    pub fn create_snapshot(&self, name: &str) -> Result<PathBuf> {
+        let dir = self.snapshot_dir(name)?;
+        if dir.exists() {
+            bail!("Snapshot {} already exists.", name);
+        }
+        let snapshots_dir = dir.parent().context("Snapshot has no parent directory.")?;
+        let temp_dir = snapshots_dir.join(format!(".{}.tmp", name));
+        // left by snapshot interrupted by crash
+        if temp_dir.exists() {
+            fs::remove_dir_all(&temp_dir)?;
+        }
+        fs::create_dir_all(&temp_dir)
+            .with_context(|| format!("Can't create directory {}.", temp_dir.display()))?;
+
+        let file_name = self
+            .path
+            .file_name()
+            .context("Database path has no file name.")?;
+        {
+            let _quiesced = self.buffer_pool_manager.quiesce_writes();
+            self.flush()?;
+            copy_data_file(
+                self.buffer_pool_manager.disk_manager(),
+                &temp_dir.join(file_name),
+            )?;
+
+            // batch committed but not fully applied yet is finished when snapshot is opened
+            for batch_log in self.batch_logs()? {
+                let copy = temp_dir.join(batch_log.file_name().unwrap());
+                fs::copy(&batch_log, &copy)?;
+                File::open(&copy)?.sync_all()?;
+            }
+        }
+
+        File::open(&temp_dir)?.sync_all()?;
+        fs::rename(&temp_dir, &dir)?;
+        File::open(snapshots_dir)?.sync_all()?;
+        tracing::info!(name, "snapshot created");
+
+        Ok(dir.join(file_name))
+    }
+
+    /// Open snapshot created by `create_snapshot` as separate database, its changes
+    /// don't affect this one
+    pub fn open_snapshot(&self, name: &str) -> Result<DbInstance> {
+        let dir = self.snapshot_dir(name)?;
+        if !dir.exists() {
+            bail!("Snapshot {} doesn't exist.", name);
+        }
+
+        DbInstance::open(dir.join(self.path.file_name().unwrap()))
+    }
+
+    fn snapshot_dir(&self, name: &str) -> Result<PathBuf> {
+        if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+            bail!("Snapshot name {:?} is not a plain file name.", name);
+        }
+        let mut snapshots_dir = self.path.as_os_str().to_owned();
+        snapshots_dir.push(SNAPSHOTS_SUFFIX);
+
+        Ok(PathBuf::from(snapshots_dir).join(name))
+    }
+
+    // batch logs of namespaces, named as data file followed by namespace
+    fn batch_logs(&self) -> Result<Vec<PathBuf>> {
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let mut prefix = self.path.file_name().unwrap_or_default().to_owned();
+        prefix.push(".");
+        let prefix = prefix.to_string_lossy().into_owned();
+
+        let mut batch_logs = vec![];
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let is_batch_log = path
+                .extension()
+                .is_some_and(|ext| ext == BATCH_LOG_EXTENSION)
+                && path
+                    .file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with(&prefix));
+            if is_batch_log && path.is_file() {
+                batch_logs.push(path);
+            }
+        }
+
+        Ok(batch_logs)
+    }
+
+    /// Shut down database: background jobs are stopped and jobs queued on thread pool
+    /// finish, then dirty pages are written and synced, clean shutdown is marked in
+    /// metadata and disk workers are joined last. Tables and pools still held elsewhere
+    /// fail on disk I/O afterwards.
+    pub fn close(mut self) -> Result<()> {
+        self.shutdown()
+    }
+
+    fn shutdown(&mut self) -> Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+        self.closed = true;
+
+        self.background_jobs.stop();
+        self.thread_pool.join();
+        if self.read_only {
+            self.buffer_pool_manager.stop_disk_workers();
+            return Ok(());
+        }
+        self.flush()?;
+
+        // marker reaches disk only after all other pages are durable
+        Self::mark_clean_shutdown(&self.buffer_pool_manager, true)?;
+        self.buffer_pool_manager.stop_disk_workers();
+
+        Ok(())
+    }
+
+    fn mark_clean_shutdown(
+        buffer_pool_manager: &BufferPoolManager,
+        clean_shutdown: bool,
+    ) -> Result<()> {
+        let mut metadata_page = buffer_pool_manager
+            .fetch_page_write(METADATA_PAGE_ID)
+            .context("Can't fetch metadata page.")?;
+        let mut metadata = MetadataPage::try_from(&metadata_page)?;
+        metadata.set_clean_shutdown(clean_shutdown);
+        metadata_page.overwrite(&metadata.to_bytes());
+        drop(metadata_page);
+        buffer_pool_manager.flush_page(METADATA_PAGE_ID)?;
+
+        buffer_pool_manager.sync()
+    }
+
+    /// Names of hash tables registered in catalog
+    pub fn hash_table_names(&self) -> Result<Vec<String>> {
+        self.refresh_metadata();
+        let metadata_page = self
+            .buffer_pool_manager
+            .fetch_page_read(METADATA_PAGE_ID)
+            .context("Can't fetch metadata page.")?;
+
+        Ok(MetadataPage::try_from(&metadata_page)?.get_names())
+    }
+
+    /// Open hash table by name, it is created and registered in catalog if it doesn't exist.
+    /// Existing table uses key normalization it was created with.
+    pub fn open_hash_table<K, V>(
+        &self,
+        name: &str,
+        directory_max_depth: u32,
+        bucket_max_size: usize,
+    ) -> Result<ExtendibleHashTable<K, V>>
+    where
+        K: Hash + Eq + Clone + Debug + Serialize + DeserializeOwned + ToString,
+        V: Clone + Debug + Serialize + DeserializeOwned,
+    {
+        self.open_hash_table_inner(name, directory_max_depth, bucket_max_size, None)
+    }
+
+    /// Like `open_hash_table`, table which doesn't exist is created with given key
+    /// normalization, which is stored in catalog. Opening existing table with another
+    /// normalization fails.
+    pub fn open_hash_table_with_normalization<K, V>(
+        &self,
+        name: &str,
+        directory_max_depth: u32,
+        bucket_max_size: usize,
+        key_normalization: KeyNormalization,
+    ) -> Result<ExtendibleHashTable<K, V>>
+    where
+        K: Hash + Eq + Clone + Debug + Serialize + DeserializeOwned + ToString,
+        V: Clone + Debug + Serialize + DeserializeOwned,
+    {
+        self.open_hash_table_inner(
+            name,
+            directory_max_depth,
+            bucket_max_size,
+            Some(key_normalization),
+        )
+    }
+
+    fn open_hash_table_inner<K, V>(
+        &self,
+        name: &str,
+        directory_max_depth: u32,
+        bucket_max_size: usize,
+        key_normalization: Option<KeyNormalization>,
+    ) -> Result<ExtendibleHashTable<K, V>>
+    where
+        K: Hash + Eq + Clone + Debug + Serialize + DeserializeOwned + ToString,
+        V: Clone + Debug + Serialize + DeserializeOwned,
+    {
+        if self.read_only {
+            return self.open_existing_hash_table(name, directory_max_depth, bucket_max_size);
+        }
+        let _write_gate = self.buffer_pool_manager.write_gate();
+        // metadata page stays write latched, so the same table can't be created twice
+        let mut metadata_page = self
+            .buffer_pool_manager
+            .fetch_page_write(METADATA_PAGE_ID)
+            .context("Can't fetch metadata page.")?;
+        let mut metadata = MetadataPage::try_from(&metadata_page)?;
+        let buffer_pool_manager = self.buffer_pool_manager();
+
+        if let Some(header_page_id) = metadata.get_header_page_id(name) {
+            if let Some(params) = metadata
+                .get_table_params(name)
+                .filter(|params| !params.has_types::<K, V>())
+            {
+                bail!(
+                    "Hash table {} holds {} => {}.",
+                    name,
+                    params.key_type,
+                    params.value_type
+                );
+            }
+            let stored_normalization = metadata.get_key_normalization(name);
+            if key_normalization.is_some_and(|requested| requested != stored_normalization) {
+                bail!(
+                    "Hash table {} was created with {:?} key normalization.",
+                    name,
+                    stored_normalization
+                );
+            }
+
+            return Ok(ExtendibleHashTable::open(
+                name.to_string(),
+                buffer_pool_manager,
+                header_page_id,
+                directory_max_depth,
+                bucket_max_size,
+            )
+            .with_key_normalization(stored_normalization));
+        }
+
+        let key_normalization = key_normalization.unwrap_or_default();
+        metadata.set_header_page_id(name.to_string(), PageId::INVALID);
+        metadata.set_key_normalization(name.to_string(), key_normalization);
+        metadata.set_table_params(
+            name.to_string(),
+            TableParams::new::<K, V>(directory_max_depth, bucket_max_size),
+        );
+        if metadata.to_bytes().len() > PAGE_SIZE {
+            bail!("Catalog is full, can't create hash table {}.", name);
+        }
+
+        let hash_table = ExtendibleHashTable::new(
+            name.to_string(),
+            buffer_pool_manager,
+            directory_max_depth,
+            bucket_max_size,
+        )
+        .with_key_normalization(key_normalization);
+        metadata.set_header_page_id(name.to_string(), hash_table.header_page_id());
+        metadata_page.overwrite(&metadata.to_bytes());
+
+        // catalog must not point to header which didn't reach disk
+        let header_page = self
+            .buffer_pool_manager
+            .fetch_page_write(hash_table.header_page_id())
+            .context("Can't fetch header page.")?;
+        self.buffer_pool_manager.log_structure_change(
+            StructureChange::NewHashTable,
+            &[&metadata_page, &header_page],
+        )?;
+
+        Ok(hash_table)
+    }
+
+    // catalog of read-only database is only read, table must exist
+    fn open_existing_hash_table<K, V>(
+        &self,
+        name: &str,
+        directory_max_depth: u32,
+        bucket_max_size: usize,
+    ) -> Result<ExtendibleHashTable<K, V>>
+    where
+        K: Hash + Eq + Clone + Debug + Serialize + DeserializeOwned + ToString,
+        V: Clone + Debug + Serialize + DeserializeOwned,
+    {
+        self.refresh_metadata();
+        let metadata_page = self
+            .buffer_pool_manager
+            .fetch_page_read(METADATA_PAGE_ID)
+            .context("Can't fetch metadata page.")?;
+        let metadata = MetadataPage::try_from(&metadata_page)?;
+        let Some(header_page_id) = metadata.get_header_page_id(name) else {
+            bail!("Hash table {} doesn't exist.", name);
+        };
+        if let Some(params) = metadata
+            .get_table_params(name)
+            .filter(|params| !params.has_types::<K, V>())
+        {
+            bail!(
+                "Hash table {} holds {} => {}.",
+                name,
+                params.key_type,
+                params.value_type
+            );
+        }
+
+        Ok(ExtendibleHashTable::open(
+            name.to_string(),
+            self.buffer_pool_manager(),
+            header_page_id,
+            directory_max_depth,
+            bucket_max_size,
+        )
+        .with_key_normalization(metadata.get_key_normalization(name)))
+    }
+
+    // writer process may have changed catalog since it was read
+    fn refresh_metadata(&self) {
+        if self.read_only {
+            self.refresh();
+        }
+    }
+}
+
+// snapshot has no structure log, so it's marked as cleanly shut down and opened without redo
+fn copy_data_file(disk_manager: &DiskManager, path: &Path) -> Result<()> {
+    disk_manager.snapshot_to(path)?;
+
+    let copy = DiskManager::open(path)?;
+    let mut metadata = MetadataPage::from_bytes(&copy.read_page(METADATA_PAGE_ID)?)?;
+    metadata.set_clean_shutdown(true);
+    copy.write_page(METADATA_PAGE_ID, &metadata.to_bytes())?;
+
+    copy.sync()
+}
+
+impl Drop for DbInstance {
+    fn drop(&mut self) {
+        if let Err(error) = self.shutdown() {
+            tracing::warn!(%error, "database shutdown failed");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{
+        storage::extendible_hash_table::extendible_hash_table_header_page::ExtendibleHTableHeaderPage,
+        ExtendibleHashTableError,
+    };
+
+    #[test]
+    fn test_close_writes_queued_work() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("test.db");
+
+        let db = DbInstance::open(&path).unwrap();
+        let hash_table = Arc::new(db.open_hash_table::<u32, u32>("numbers", 4, 4).unwrap());
+        for i in 0..10 {
+            let hash_table = Arc::clone(&hash_table);
+            db.thread_pool().spawn(move || {
+                hash_table.insert(i, i * 10).unwrap();
+            });
+        }
+        let buffer_pool_manager = db.buffer_pool_manager();
+        db.close().unwrap();
+        assert!(buffer_pool_manager
+            .fetch_page_read(PageId::new(1000))
+            .is_none());
+        drop(hash_table);
+        drop(buffer_pool_manager);
+
+        let db = DbInstance::open(&path).unwrap();
+        assert_eq!(db.recovery_path(), RecoveryPath::CleanShutdown);
+        let hash_table = db.open_hash_table::<u32, u32>("numbers", 4, 4).unwrap();
+        assert_eq!(hash_table.scan().unwrap().len(), 10);
+    }
+
+    #[test]
+    fn test_crash_is_detected_on_open() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("test.db");
+
+        let db = DbInstance::open(&path).unwrap();
+        assert_eq!(db.recovery_path(), RecoveryPath::CrashRecovery);
+        db.close().unwrap();
+
+        // instance dropped without shutdown leaves data file as after crash
+        let mut db = DbInstance::open(&path).unwrap();
+        assert_eq!(db.recovery_path(), RecoveryPath::CleanShutdown);
+        db.closed = true;
+        drop(db);
+
+        let db = DbInstance::open(&path).unwrap();
+        assert_eq!(db.recovery_path(), RecoveryPath::CrashRecovery);
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let dir = TempDir::new().unwrap();
+        let db = DbInstance::open(dir.path().join("test.db")).unwrap();
+        let hash_table = db.open_hash_table::<u32, u32>("numbers", 4, 4).unwrap();
+        for i in 0..20 {
+            hash_table.insert(i, i).unwrap();
+        }
+
+        let path = db.create_snapshot("first").unwrap();
+        assert_eq!(path, dir.path().join("test.db.snapshots/first/test.db"));
+        assert!(db.create_snapshot("first").is_err());
+        assert!(db.create_snapshot("../first").is_err());
+        hash_table.insert(100, 100).unwrap();
+
+        let snapshot = db.open_snapshot("first").unwrap();
+        assert_eq!(snapshot.recovery_path(), RecoveryPath::CleanShutdown);
+        let snapshot_table = snapshot
+            .open_hash_table::<u32, u32>("numbers", 4, 4)
+            .unwrap();
+        assert_eq!(snapshot_table.scan().unwrap().len(), 20);
+        snapshot_table.remove(0).unwrap();
+        assert_eq!(hash_table.get(0).unwrap(), Some(0));
+        assert!(db.open_snapshot("second").is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let dir = TempDir::new().unwrap();
+        let db = DbInstance::open(dir.path().join("test.db")).unwrap();
+        let hash_table = db.open_hash_table::<u32, u32>("numbers", 4, 4).unwrap();
+        for i in 0..20 {
+            hash_table.insert(i, i).unwrap();
+        }
+
+        let report = db.check().unwrap();
+        assert!(report.is_ok(), "{report}");
+        assert_eq!(report.hash_tables, 1);
+
+        // catalog entry pointing to header of another table
+        let mut metadata_page = db
+            .buffer_pool_manager
+            .fetch_page_write(METADATA_PAGE_ID)
+            .unwrap();
+        let mut metadata = MetadataPage::try_from(&metadata_page).unwrap();
+        metadata.set_header_page_id("copy".to_string(), hash_table.header_page_id());
+        metadata_page.overwrite(&metadata.to_bytes());
+        drop(metadata_page);
+
+        let report = db.check().unwrap();
+        assert_eq!(report.problems.len(), 1);
+        assert_eq!(report.problems[0].page_id, hash_table.header_page_id());
+    }
+
+    #[test]
+    fn test_space_report() {
+        let dir = TempDir::new().unwrap();
+        let db = DbInstance::open(dir.path().join("test.db")).unwrap();
+        let hash_table = db.open_hash_table::<u32, u32>("numbers", 4, 4).unwrap();
+        let directory_page_id = || {
+            let header_page = db
+                .buffer_pool_manager
+                .fetch_page_read(hash_table.header_page_id())
+                .unwrap();
+            let header = ExtendibleHTableHeaderPage::try_from(&header_page).unwrap();
+            *header.get_directory_page_id(0).unwrap()
+        };
+        hash_table.insert(0, 0).unwrap();
+        let first_directory_page_id = directory_page_id();
+
+        for i in 1..20 {
+            hash_table.insert(i, i).unwrap();
+        }
+        assert_ne!(directory_page_id(), first_directory_page_id);
+
+        let space = db.space_report().unwrap();
+        assert_eq!(space.tables.len(), 1);
+        assert_eq!(space.tables[0].entries, 20);
+        assert_eq!(space.unreferenced_pages, 0);
+        assert!(space.free_pages > 0, "{space}");
+        assert_eq!(
+            space.metadata_pages + space.free_pages + space.tables[0].pages,
+            space.pages
+        );
+        assert!(space.fill() > 0.0 && space.fill() <= 1.0);
+        let free_pages = space.free_pages;
+        let table_pages = space.tables[0].pages;
+
+        // emptied buckets are merged away
+        for i in 0..18 {
+            hash_table.remove(i).unwrap();
+        }
+
+        let space = db.space_report().unwrap();
+        assert_eq!(space.tables[0].entries, 2);
+        assert_eq!(space.unreferenced_pages, 0);
+        assert!(space.tables[0].pages < table_pages, "{space}");
+        assert!(space.free_pages > free_pages, "{space}");
+        assert_eq!(
+            space.metadata_pages + space.free_pages + space.tables[0].pages,
+            space.pages
+        );
+    }
+
+    #[test]
+    fn test_open_by_name() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("test.db");
+
+        let db = DbInstance::open(&path).unwrap();
+        let hash_table = db.open_hash_table::<String, u32>("Test", 4, 3).unwrap();
+        hash_table.insert("key".into(), 1).unwrap();
+        drop(hash_table);
+        db.close().unwrap();
+
+        let db = DbInstance::open(&path).unwrap();
+        let hash_table =
+            ExtendibleHashTable::<String, u32>::open_by_name("Test", db.buffer_pool_manager())
+                .unwrap();
+        assert_eq!(hash_table.name(), "Test");
+        assert_eq!(hash_table.get("key".into()).unwrap(), Some(1));
+
+        assert!(matches!(
+            ExtendibleHashTable::<String, u32>::open_by_name("Other", db.buffer_pool_manager()),
+            Err(ExtendibleHashTableError::NotInCatalog(_))
+        ));
+        assert!(matches!(
+            ExtendibleHashTable::<u32, u32>::open_by_name("Test", db.buffer_pool_manager()),
+            Err(ExtendibleHashTableError::TypeMismatch { .. })
+        ));
+        assert!(db.open_hash_table::<u32, u32>("Test", 4, 3).is_err());
+    }
+
+    #[test]
+    fn test_key_normalization_is_stored_in_catalog() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("test.db");
+
+        let db = DbInstance::open(&path).unwrap();
+        let hash_table = db
+            .open_hash_table_with_normalization::<String, u32>(
+                "names",
+                4,
+                4,
+                KeyNormalization::CaseInsensitive,
+            )
+            .unwrap();
+        for i in 0..20 {
+            hash_table.insert(format!("Key{i}"), i).unwrap();
+        }
+        hash_table.insert("KEY0".into(), 100).unwrap();
+        assert_eq!(hash_table.scan().unwrap().len(), 20);
+        drop(hash_table);
+        drop(db);
+
+        let db = DbInstance::open(&path).unwrap();
+        let hash_table = db.open_hash_table::<String, u32>("names", 4, 4).unwrap();
+        assert_eq!(
+            hash_table.key_normalization(),
+            KeyNormalization::CaseInsensitive
+        );
+        assert_eq!(hash_table.get("key0".into()).unwrap(), Some(100));
+        assert_eq!(hash_table.remove("kEy7".into()).unwrap(), Some(7));
+        assert!(db
+            .open_hash_table_with_normalization::<String, u32>(
+                "names",
+                4,
+                4,
+                KeyNormalization::Exact
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_reader_process_mode() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("test.db");
+
+        let db = DbInstance::open(&path).unwrap();
+        let writer = db.open_hash_table::<u32, u32>("numbers", 4, 4).unwrap();
+        writer.insert(1, 10).unwrap();
+        db.flush().unwrap();
+
+        let reader_db = DbInstance::open_read_only(&path).unwrap();
+        assert!(DbInstance::open(&path).is_err());
+        assert!(db.buffer_pool_manager().disk_manager().has_readers());
+        let reader = reader_db
+            .open_hash_table::<u32, u32>("numbers", 4, 4)
+            .unwrap();
+        assert_eq!(reader.get(1).unwrap(), Some(10));
+        assert!(reader.insert(2, 20).is_err());
+        assert!(reader_db
+            .open_hash_table::<u32, u32>("other", 4, 4)
+            .is_err());
+
+        writer.insert(2, 20).unwrap();
+        db.flush().unwrap();
+        reader_db.refresh();
+        assert_eq!(reader.get(2).unwrap(), Some(20));
+        assert_eq!(reader_db.hash_table_names().unwrap(), vec!["numbers"]);
+
+        drop(reader);
+        reader_db.close().unwrap();
+        assert!(!db.buffer_pool_manager().disk_manager().has_readers());
+    }
+}
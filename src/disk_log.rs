@@ -0,0 +1,127 @@
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use anyhow::{bail, Context, Result};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+/// Log sequence number, offset of the first byte of log record in log
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Lsn(u64);
+
+impl Lsn {
+    pub const fn new(offset: u64) -> Self {
+        Self(offset)
+    }
+
+    pub const fn as_u64(self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for Lsn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[derive(Debug)]
+enum LogStorage {
+    Memory(Vec<u8>),
+    // length is tracked, so appends don't ask filesystem for it
+    File { file: File, len: u64 },
+}
+
+/// Append-only log kept apart from pages, records are read back by offset they start at
+#[derive(Debug)]
+pub(crate) struct DiskLog {
+    storage: Mutex<LogStorage>,
+}
+
+impl DiskLog {
+    pub fn in_memory() -> Self {
+        Self {
+            storage: Mutex::new(LogStorage::Memory(vec![])),
+        }
+    }
+
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)
+            .with_context(|| format!("Can't open log file {}.", path.display()))?;
+        let len = file.metadata()?.len();
+
+        Ok(Self {
+            storage: Mutex::new(LogStorage::File { file, len }),
+        })
+    }
+
+    /// Append data and wait until it is durable, returns where it starts
+    pub fn append(&self, data: &[u8]) -> Result<Lsn> {
+        match &mut *self.storage.lock() {
+            LogStorage::Memory(log) => {
+                let lsn = Lsn(log.len() as u64);
+                log.extend_from_slice(data);
+
+                Ok(lsn)
+            }
+            LogStorage::File { file, len } => {
+                let lsn = Lsn(*len);
+                // part of failed append is cut off, so the next record starts at log end
+                if let Err(error) = file.write_all(data).and_then(|()| file.sync_data()) {
+                    let _ = file.set_len(*len);
+                    return Err(error).context("Can't append to log file.");
+                }
+                *len += data.len() as u64;
+
+                Ok(lsn)
+            }
+        }
+    }
+
+    pub fn read(&self, lsn: Lsn, len: usize) -> Result<Vec<u8>> {
+        let mut storage = self.storage.lock();
+        let log_len = match &*storage {
+            LogStorage::Memory(log) => log.len() as u64,
+            LogStorage::File { len, .. } => *len,
+        };
+        if lsn.0.saturating_add(len as u64) > log_len {
+            bail!(
+                "Log range of {} bytes at {} is past log end {}.",
+                len,
+                lsn,
+                log_len
+            );
+        }
+
+        match &mut *storage {
+            LogStorage::Memory(log) => {
+                let start = lsn.0 as usize;
+                Ok(log[start..start + len].to_vec())
+            }
+            LogStorage::File { file, .. } => {
+                let mut data = vec![0; len];
+                file.seek(SeekFrom::Start(lsn.0))?;
+                file.read_exact(&mut data)?;
+
+                Ok(data)
+            }
+        }
+    }
+
+    /// Offset the next record is appended at
+    pub fn end(&self) -> Lsn {
+        match &*self.storage.lock() {
+            LogStorage::Memory(log) => Lsn(log.len() as u64),
+            LogStorage::File { len, .. } => Lsn(*len),
+        }
+    }
+}
@@ -1,21 +1,1867 @@
-use std::{thread, time::Duration};
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions, TryLockError},
+    io::{Read, Seek, SeekFrom, Write},
+    ops::{Deref, Range},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::Sender,
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
 
-use crate::page::Page;
+use anyhow::{bail, Context, Result};
+use parking_lot::{MappedRwLockReadGuard, Mutex};
+use rand::{rngs::StdRng, Rng};
+use thiserror::Error;
 
-pub struct DiskManager {}
+use crate::checksummed_backend::ChecksummedBackend;
+use crate::compressed_backend::{CompressedBackend, PageCompression};
+use crate::disk_log::{DiskLog, Lsn};
+use crate::double_write_buffer::DoubleWriteBuffer;
+#[cfg(feature = "io-uring")]
+use crate::io_uring_backend::{IoUringBackend, RING_ENTRIES};
+use crate::mirrored_backend::MirroredBackend;
+#[cfg(feature = "mmap")]
+use crate::mmap_backend::MmapBackend;
+#[cfg(feature = "object-store")]
+use crate::object_store_backend::ObjectStoreBackend;
+use crate::page::{PageId, PAGE_SIZE};
+use crate::page_allocator::PageAllocator;
+use crate::page_scrubber::PageScrubber;
+use crate::rng::seeded_rng;
+use crate::sharded_counter::ShardedCounter;
+use crate::tablespace_backend::{TablespaceBackend, TablespaceLayout};
+use crate::tiered_backend::TieredBackend;
+
+const FREE_PAGES_SUFFIX: &str = ".free";
+const DOUBLE_WRITE_SUFFIX: &str = ".dwb";
+const READERS_SUFFIX: &str = ".readers";
+const LOG_SUFFIX: &str = ".log";
+// 1 MiB
+const DEFAULT_GROWTH_EXTENT_PAGES: usize = 256;
+
+/// Data file is locked by another open disk manager, in this or another process
+#[derive(Error, Debug)]
+#[error("Data file {} is already in use.", .path.display())]
+pub struct AlreadyInUse {
+    pub path: PathBuf,
+}
+
+/// Page read from disk doesn't match checksum stored with it, it was torn by crash
+/// or damaged on disk
+#[derive(Error, Debug)]
+#[error("Page {page_id} doesn't match its checksum.")]
+pub struct CorruptPage {
+    pub page_id: PageId,
+}
+
+/// Page access failed by fault injection, see `DiskManager::fail_next_n_writes`
+#[derive(Error, Debug)]
+#[error("Injected I/O error on page {page_id}.")]
+pub struct InjectedFault {
+    pub page_id: PageId,
+}
+
+/// Delays every access of simulated in-memory disk pays
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskLatencyProfile {
+    pub read: Duration,
+    pub write: Duration,
+}
+
+impl DiskLatencyProfile {
+    /// No delays, for tests and benchmarks of code above disk
+    pub const NONE: Self = Self {
+        read: Duration::ZERO,
+        write: Duration::ZERO,
+    };
+}
+
+impl Default for DiskLatencyProfile {
+    fn default() -> Self {
+        Self {
+            read: Duration::from_millis(300),
+            write: Duration::from_millis(200),
+        }
+    }
+}
+
+/// Point in time I/O counters of disk manager, see `DiskManager::stats`. Failed
+/// operations are not counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DiskStats {
+    /// Read calls, pages read at once by `read_pages` count once
+    pub reads: u64,
+    /// Write calls, pages written at once by `write_pages` count once
+    pub writes: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    pub syncs: u64,
+    pub avg_read_latency: Duration,
+    pub avg_write_latency: Duration,
+    pub avg_sync_latency: Duration,
+    /// Requests disk scheduler served
+    pub queued_requests: u64,
+    /// Time requests waited in disk scheduler queue before they were served
+    pub avg_queue_wait: Duration,
+}
+
+/// When writes served by disk scheduler are synced to durable storage, besides explicit
+/// `DiskManager::sync`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DurabilityPolicy {
+    /// Sync before every write is acknowledged
+    Always,
+    /// Sync after every `n` writes, the rest may be lost on crash
+    EveryNWrites(usize),
+    /// Sync written pages once the period passed since the last sync, idle disk workers
+    /// check it every period
+    Periodic(Duration),
+    /// Sync only on explicit `sync`
+    #[default]
+    Never,
+}
+
+// writes served since the last sync, taken into account by durability policy
+#[derive(Debug)]
+struct Durability {
+    policy: DurabilityPolicy,
+    unsynced_writes: AtomicUsize,
+    last_sync: Mutex<Instant>,
+}
+
+impl Default for Durability {
+    fn default() -> Self {
+        Self {
+            policy: DurabilityPolicy::default(),
+            unsynced_writes: AtomicUsize::new(0),
+            last_sync: Mutex::new(Instant::now()),
+        }
+    }
+}
+
+// count, bytes and total time of one kind of operation
+#[derive(Debug, Default)]
+struct IoCounter {
+    count: ShardedCounter,
+    bytes: ShardedCounter,
+    nanos: ShardedCounter,
+}
+
+impl IoCounter {
+    fn record(&self, bytes: usize, started_at: Instant) {
+        self.add(bytes, started_at.elapsed());
+    }
+
+    fn add(&self, bytes: usize, duration: Duration) {
+        self.count.increment();
+        self.bytes.add(bytes as u64);
+        self.nanos.add(duration.as_nanos() as u64);
+    }
+
+    fn average(&self) -> Duration {
+        let count = self.count.get();
+        if count == 0 {
+            return Duration::ZERO;
+        }
+
+        Duration::from_nanos(self.nanos.get() / count)
+    }
+}
+
+#[derive(Debug, Default)]
+struct IoCounters {
+    reads: IoCounter,
+    writes: IoCounter,
+    syncs: IoCounter,
+    queue_waits: IoCounter,
+}
+
+/// Fails page accesses on request, so error paths of scheduler and buffer pool can be
+/// exercised without broken disk
+#[derive(Debug, Default)]
+struct FaultInjector {
+    failing_writes: AtomicUsize,
+    // probability of failing any access and generator deciding it
+    random_errors: Mutex<Option<(f64, StdRng)>>,
+}
+
+impl FaultInjector {
+    fn check(&self, page_id: PageId, write: bool) -> Result<()> {
+        let failing_write = write
+            && self
+                .failing_writes
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+                    count.checked_sub(1)
+                })
+                .is_ok();
+        let random_error = match &mut *self.random_errors.lock() {
+            Some((probability, rng)) => rng.gen_bool(*probability),
+            None => false,
+        };
+        if failing_write || random_error {
+            return Err(InjectedFault { page_id }.into());
+        }
+
+        Ok(())
+    }
+}
+
+/// Page data read by `DiskManager::read_page_view`
+#[derive(Debug)]
+pub enum PageView<'a> {
+    Owned(Vec<u8>),
+    /// Page borrowed from memory mapping, mapping is read latched until it is dropped
+    Mapped(MappedRwLockReadGuard<'a, [u8]>),
+}
+
+impl Deref for PageView<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            PageView::Owned(data) => data,
+            PageView::Mapped(page) => page,
+        }
+    }
+}
+
+/// Page operation of disk scheduler request, see `DiskManager::serve_batch`
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum PageIo<'a> {
+    Read(PageId),
+    /// Data of a single page, which is padded, or of consecutive whole pages
+    Write(PageId, &'a [u8]),
+}
+
+#[derive(Debug)]
+enum Storage {
+    /// Simulated disk: pages are kept in memory and every access pays an artificial delay.
+    Memory(Mutex<HashMap<PageId, Vec<u8>>>),
+    /// Pages are stored in a single data file at offset `page_id * PAGE_SIZE`.
+    File(Mutex<File>),
+    /// Pages are stored in a single data file followed by their checksums.
+    Checksummed(ChecksummedBackend),
+    /// Pages are compressed into slots of a single data file, with codec in slot header.
+    Compressed(CompressedBackend),
+    /// Pages are stored like in `File`, reads and writes are submitted to io_uring.
+    #[cfg(feature = "io-uring")]
+    IoUring(IoUringBackend),
+    /// Pages are stored like in `File`, served from memory mapping of data file.
+    #[cfg(feature = "mmap")]
+    Mmap(MmapBackend),
+    /// Pages are stored in object store behind local write-back cache.
+    #[cfg(feature = "object-store")]
+    ObjectStore(ObjectStoreBackend),
+    /// Every page is written to two files with checksum, corrupted page is read from the other.
+    Mirrored(MirroredBackend),
+    /// Pages not accessed for a while are moved from hot file to cold disk manager, hot
+    /// slot of moved page is emptied.
+    Tiered(Box<TieredBackend>),
+    /// Pages are spread over several data files.
+    Tablespace(TablespaceBackend),
+}
+
+#[derive(Debug)]
+pub struct DiskManager {
+    storage: Storage,
+    punch_holes: bool,
+    allocator: PageAllocator,
+    latency: DiskLatencyProfile,
+    faults: FaultInjector,
+    // file storage only, pages are staged in it before they are written in place
+    double_write: Option<DoubleWriteBuffer>,
+    durability: Durability,
+    // readers hold shared lock on it, writer holds no lock and only checks for them
+    readers: Option<File>,
+    // whether readers were there when writer last checked on open or sync, freed pages
+    // aren't reused meanwhile
+    readers_seen: AtomicBool,
+    read_only: bool,
+    io: IoCounters,
+    // storages of data file opened by path and simulated disk only
+    log: Option<DiskLog>,
+    // file storage only, data file space is reserved by this many pages at once
+    growth_extent: usize,
+    capacity: AtomicUsize,
+    scrubber: Mutex<Option<PageScrubber>>,
+}
+
+impl Default for DiskManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl DiskManager {
+    /// Create simulated in-memory disk
     pub fn new() -> Self {
-        Self {}
+        Self {
+            storage: Storage::Memory(Mutex::new(HashMap::new())),
+            punch_holes: false,
+            allocator: PageAllocator::new(0),
+            latency: DiskLatencyProfile::default(),
+            faults: FaultInjector::default(),
+            double_write: None,
+            durability: Durability::default(),
+            readers: None,
+            readers_seen: AtomicBool::new(false),
+            read_only: false,
+            io: IoCounters::default(),
+            log: Some(DiskLog::in_memory()),
+            growth_extent: DEFAULT_GROWTH_EXTENT_PAGES,
+            capacity: AtomicUsize::new(0),
+            scrubber: Mutex::new(None),
+        }
+    }
+
+    /// Open data file, create it if it doesn't exist. File is locked exclusively until
+    /// disk manager is dropped, `AlreadyInUse` is returned if it is locked already.
+    /// Free pages are stored next to data file, see `allocate_page`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let storage = Storage::File(Mutex::new(open_data_file(path.as_ref())?));
+        let mut disk_manager = Self::with_data_file(storage, path.as_ref())?;
+        disk_manager.readers = Some(open_readers_file(path.as_ref())?);
+        disk_manager.refresh_readers();
+
+        Ok(disk_manager)
+    }
+
+    /// Open existing data file for reading while another process may write it: file is
+    /// locked shared next to data file, so writer doesn't reuse freed pages meanwhile.
+    /// Writes fail. Page written by writer during read may be read torn, and pages of
+    /// structure change in progress may be read half changed.
+    pub fn open_read_only(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let readers = open_readers_file(path)?;
+        readers
+            .lock_shared()
+            .with_context(|| format!("Can't lock readers of data file {}.", path.display()))?;
+        let file = File::open(path)
+            .with_context(|| format!("Can't open data file {}.", path.display()))?;
+
+        let mut disk_manager = Self::with_storage(Storage::File(Mutex::new(file)), None)?;
+        disk_manager.readers = Some(readers);
+        disk_manager.read_only = true;
+
+        Ok(disk_manager)
+    }
+
+    /// Like `open`, pages are first written to double-write buffer next to data file and
+    /// then in place, pages torn by crash during in-place write are restored from it
+    /// here. Every write waits for the buffer to be synced.
+    pub fn open_with_double_write(path: impl AsRef<Path>) -> Result<Self> {
+        let mut file = open_data_file(path.as_ref())?;
+        let mut double_write_path = path.as_ref().as_os_str().to_owned();
+        double_write_path.push(DOUBLE_WRITE_SUFFIX);
+        let double_write = DoubleWriteBuffer::open(Path::new(&double_write_path))?;
+
+        let restored = double_write.recover(|page_id, page| {
+            file.seek(SeekFrom::Start(page_id.offset()))?;
+            file.write_all(page)?;
+
+            Ok(())
+        })?;
+        if restored > 0 {
+            tracing::info!(pages = restored, "restored pages from double-write buffer");
+            file.sync_all()?;
+        }
+        double_write.clear()?;
+
+        let mut disk_manager =
+            Self::with_data_file(Storage::File(Mutex::new(file)), path.as_ref())?;
+        disk_manager.double_write = Some(double_write);
+        disk_manager.readers = Some(open_readers_file(path.as_ref())?);
+        disk_manager.refresh_readers();
+
+        Ok(disk_manager)
+    }
+
+    /// Store pages in object store (S3 and alike) under `prefix`,
+    /// up to `cache_pages` pages are cached locally and written back on `sync`
+    #[cfg(feature = "object-store")]
+    pub fn open_object_store(
+        store: std::sync::Arc<dyn object_store::ObjectStore>,
+        prefix: &str,
+        cache_pages: usize,
+    ) -> Result<Self> {
+        let storage = Storage::ObjectStore(ObjectStoreBackend::open(store, prefix, cache_pages)?);
+
+        Self::with_storage(storage, None)
+    }
+
+    /// Like `open`, pages are served from memory mapping of data file: reads copy from it
+    /// or borrow page by `read_page_view`, writes are synced by `msync` before they
+    /// return
+    #[cfg(feature = "mmap")]
+    pub fn open_mmap(path: impl AsRef<Path>) -> Result<Self> {
+        let storage = Storage::Mmap(MmapBackend::open(path.as_ref())?);
+
+        Self::with_data_file(storage, path.as_ref())
+    }
+
+    /// Like `open`, pages are read and written through io_uring. Disk scheduler submits
+    /// batches of queued requests at once from a single thread instead of serving them
+    /// by blocking worker threads.
+    #[cfg(feature = "io-uring")]
+    pub fn open_io_uring(path: impl AsRef<Path>) -> Result<Self> {
+        let storage = Storage::IoUring(IoUringBackend::open(path.as_ref())?);
+
+        Self::with_data_file(storage, path.as_ref())
+    }
+
+    /// Like `open`, every page is stored with checksum which is verified on read, page
+    /// which doesn't match it fails with `CorruptPage`. Data file has different layout
+    /// than the one of `open`.
+    pub fn open_checksummed(path: impl AsRef<Path>) -> Result<Self> {
+        let storage = Storage::Checksummed(ChecksummedBackend::open(path.as_ref())?);
+
+        Self::with_data_file(storage, path.as_ref())
+    }
+
+    /// Like `open`, pages are compressed on write and decompressed on read. Space of
+    /// slot which compressed page doesn't use is given back to filesystem, supported
+    /// on Linux. Data file has different layout than the one of `open`.
+    pub fn open_compressed(path: impl AsRef<Path>, compression: PageCompression) -> Result<Self> {
+        let storage = Storage::Compressed(CompressedBackend::open(path.as_ref(), compression)?);
+
+        Self::with_data_file(storage, path.as_ref())
+    }
+
+    /// Write every page to both files, page which fails checksum is read from mirror
+    /// and repaired in primary file
+    pub fn open_mirrored(
+        primary_path: impl AsRef<Path>,
+        mirror_path: impl AsRef<Path>,
+    ) -> Result<Self> {
+        let storage = Storage::Mirrored(MirroredBackend::open(
+            primary_path.as_ref(),
+            mirror_path.as_ref(),
+        )?);
+
+        Self::with_storage(storage, None)
+    }
+
+    /// Keep pages in hot data file at path, pages which are not accessed for a while
+    /// are moved to `cold` storage by `migrate_cold_pages`
+    pub fn open_tiered(hot_path: impl AsRef<Path>, cold: DiskManager) -> Result<Self> {
+        let storage = Storage::Tiered(Box::new(TieredBackend::open(hot_path.as_ref(), cold)?));
+
+        Self::with_storage(storage, None)
+    }
+
+    /// Spread pages over data files given with most pages they hold, so database isn't
+    /// limited by size of a single file or filesystem. Files have to be given in the same
+    /// order on every open, free pages are stored next to the first one.
+    pub fn open_tablespace(files: &[(&Path, usize)], layout: TablespaceLayout) -> Result<Self> {
+        let storage = Storage::Tablespace(TablespaceBackend::open(files, layout)?);
+        let mut free_pages_path = files[0].0.as_os_str().to_owned();
+        free_pages_path.push(FREE_PAGES_SUFFIX);
+
+        Self::with_storage(storage, Some(free_pages_path.into()))
+    }
+
+    /// Add data file to tablespace opened by `open_tablespace`, see `TablespaceLayout`
+    /// for when it is allowed
+    pub fn add_data_file(&self, path: impl AsRef<Path>, max_pages: usize) -> Result<()> {
+        let Storage::Tablespace(backend) = &self.storage else {
+            bail!("Data files can be added to tablespace only.");
+        };
+
+        backend.add_data_file(path.as_ref(), max_pages)
+    }
+
+    // free pages of storage without bitmap path are kept in memory only
+    // storage of data file at `path`, free pages and log are kept next to it
+    fn with_data_file(storage: Storage, path: &Path) -> Result<Self> {
+        let mut free_pages_path = path.as_os_str().to_owned();
+        free_pages_path.push(FREE_PAGES_SUFFIX);
+        let mut log_path = path.as_os_str().to_owned();
+        log_path.push(LOG_SUFFIX);
+
+        let mut disk_manager = Self::with_storage(storage, Some(free_pages_path.into()))?;
+        disk_manager.log = Some(DiskLog::open(Path::new(&log_path))?);
+
+        Ok(disk_manager)
+    }
+
+    fn with_storage(storage: Storage, free_pages_path: Option<PathBuf>) -> Result<Self> {
+        let mut disk_manager = Self {
+            storage,
+            punch_holes: false,
+            allocator: PageAllocator::new(0),
+            latency: DiskLatencyProfile::default(),
+            faults: FaultInjector::default(),
+            double_write: None,
+            durability: Durability::default(),
+            readers: None,
+            readers_seen: AtomicBool::new(false),
+            read_only: false,
+            io: IoCounters::default(),
+            log: None,
+            growth_extent: DEFAULT_GROWTH_EXTENT_PAGES,
+            capacity: AtomicUsize::new(0),
+            scrubber: Mutex::new(None),
+        };
+        let num_pages = disk_manager.num_pages()?;
+        disk_manager.capacity = AtomicUsize::new(num_pages);
+        disk_manager.allocator = match free_pages_path {
+            Some(path) => PageAllocator::open(path, num_pages)?,
+            None => PageAllocator::new(num_pages),
+        };
+
+        Ok(disk_manager)
+    }
+
+    /// Give space of deallocated pages back to filesystem by punching holes in data file,
+    /// supported by file storage on Linux
+    pub fn set_punch_holes(&mut self, punch_holes: bool) {
+        self.punch_holes = punch_holes;
+    }
+
+    /// Reserve space of data file by `pages` at once when writes reach past reserved
+    /// space, so file doesn't grow by a page at a time and stays less fragmented. Space is
+    /// reserved by `fallocate` on Linux and file size changes only by writes. 256 pages
+    /// (1 MiB) by default, 1 or 0 turns reservation off. File storage only.
+    pub fn set_growth_extent(&mut self, pages: usize) {
+        self.growth_extent = pages;
+    }
+
+    /// Pages data file has space reserved for, at least `size_pages`
+    pub fn capacity_pages(&self) -> Result<usize> {
+        let size = self.size_pages()?;
+
+        Ok(match &self.storage {
+            Storage::File(_) => self.capacity.load(Ordering::Relaxed).max(size),
+            _ => size,
+        })
+    }
+
+    /// Pages data file holds, same as `num_pages`
+    pub fn size_pages(&self) -> Result<usize> {
+        self.num_pages()
+    }
+
+    // called under file latch before pages up to `end` are written
+    fn reserve_space(&self, file: &File, end: PageId) -> Result<()> {
+        let capacity = self.capacity.load(Ordering::Relaxed);
+        let end = end.as_usize();
+        if end <= capacity {
+            return Ok(());
+        }
+        if self.growth_extent <= 1 {
+            self.capacity.store(end, Ordering::Relaxed);
+            return Ok(());
+        }
+
+        let new_capacity = end.next_multiple_of(self.growth_extent);
+        preallocate(
+            file,
+            capacity * PAGE_SIZE,
+            (new_capacity - capacity) * PAGE_SIZE,
+        )?;
+        self.capacity.store(new_capacity, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    /// Set delays of simulated in-memory disk, other storages don't pay them
+    pub fn set_latency_profile(&mut self, latency: DiskLatencyProfile) {
+        self.latency = latency;
+    }
+
+    /// Choose when disk scheduler syncs pages it writes, trading durability of
+    /// acknowledged writes for throughput. Never synced by default.
+    pub fn set_durability_policy(&mut self, policy: DurabilityPolicy) {
+        self.durability.policy = policy;
+    }
+
+    pub fn durability_policy(&self) -> DurabilityPolicy {
+        self.durability.policy
+    }
+
+    /// Count writes served by disk scheduler and sync if durability policy asks for it
+    pub(crate) fn note_writes(&self, count: usize) -> Result<()> {
+        let unsynced = self
+            .durability
+            .unsynced_writes
+            .fetch_add(count, Ordering::Relaxed)
+            + count;
+        let due = match self.durability.policy {
+            DurabilityPolicy::Always => true,
+            DurabilityPolicy::EveryNWrites(n) => unsynced >= n,
+            DurabilityPolicy::Periodic(period) => {
+                self.durability.last_sync.lock().elapsed() >= period
+            }
+            DurabilityPolicy::Never => false,
+        };
+
+        if due {
+            self.sync()?;
+        }
+
+        Ok(())
+    }
+
+    /// Sync writes made since the last sync if periodic policy's period passed
+    pub(crate) fn sync_if_due(&self) -> Result<()> {
+        let DurabilityPolicy::Periodic(period) = self.durability.policy else {
+            return Ok(());
+        };
+        if self.durability.unsynced_writes.load(Ordering::Relaxed) > 0
+            && self.durability.last_sync.lock().elapsed() >= period
+        {
+            self.sync()?;
+        }
+
+        Ok(())
+    }
+
+    /// Fail next `count` page writes with `InjectedFault`, writes of several pages at
+    /// once count as one
+    pub fn fail_next_n_writes(&self, count: usize) {
+        self.faults.failing_writes.store(count, Ordering::Relaxed);
+    }
+
+    /// Fail every page access with `InjectedFault` at probability, 0 stops failing.
+    /// Failures are drawn from seeded stream, so a run fails the same accesses when
+    /// they come in the same order.
+    pub fn set_error_probability(&self, probability: f64) -> Result<()> {
+        if !(0.0..=1.0).contains(&probability) {
+            bail!("Error probability {} is not within 0 and 1.", probability);
+        }
+        *self.faults.random_errors.lock() =
+            (probability > 0.0).then(|| (probability, seeded_rng("disk_faults")));
+
+        Ok(())
+    }
+
+    /// Read page data, pages which were never written are read as zeroes
+    pub fn read_page(&self, page_id: PageId) -> Result<Vec<u8>> {
+        let started_at = Instant::now();
+        let data = self.read_page_uncounted(page_id)?;
+        self.io.reads.record(PAGE_SIZE, started_at);
+
+        Ok(data)
+    }
+
+    fn read_page_uncounted(&self, page_id: PageId) -> Result<Vec<u8>> {
+        self.faults.check(page_id, false)?;
+        match &self.storage {
+            Storage::Memory(pages) => {
+                thread::sleep(self.latency.read);
+                let pages = pages.lock();
+
+                Ok(pages
+                    .get(&page_id)
+                    .cloned()
+                    .unwrap_or_else(|| vec![0; PAGE_SIZE]))
+            }
+            Storage::File(file) => {
+                let mut file = file.lock();
+                let mut data = vec![0; PAGE_SIZE];
+                file.seek(SeekFrom::Start(page_id.offset()))?;
+
+                // page can be partially (or not at all) written at the end of file
+                let mut read = 0;
+                while read < PAGE_SIZE {
+                    let bytes = file.read(&mut data[read..])?;
+                    if bytes == 0 {
+                        break;
+                    }
+                    read += bytes;
+                }
+
+                Ok(data)
+            }
+            #[cfg(feature = "object-store")]
+            Storage::ObjectStore(backend) => backend.read_page(page_id),
+            Storage::Checksummed(backend) => backend.read_page(page_id),
+            Storage::Compressed(backend) => backend.read_page(page_id),
+            #[cfg(feature = "io-uring")]
+            Storage::IoUring(backend) => backend.read_page(page_id),
+            #[cfg(feature = "mmap")]
+            Storage::Mmap(backend) => Ok(backend.read_page(page_id)),
+            Storage::Mirrored(backend) => backend.read_page(page_id),
+            Storage::Tiered(backend) => backend.read_page(page_id),
+            Storage::Tablespace(backend) => backend.read_page(page_id),
+        }
+    }
+
+    /// Read page data like `read_page`, mmap storage lends page from its mapping instead
+    /// of copying it. Writes of mmap storage which grow data file wait until view is
+    /// dropped.
+    pub fn read_page_view(&self, page_id: PageId) -> Result<PageView<'_>> {
+        #[cfg(feature = "mmap")]
+        if let Storage::Mmap(backend) = &self.storage {
+            self.faults.check(page_id, false)?;
+            let started_at = Instant::now();
+            let view = match backend.page(page_id) {
+                Some(page) => PageView::Mapped(page),
+                None => PageView::Owned(vec![0; PAGE_SIZE]),
+            };
+            self.io.reads.record(PAGE_SIZE, started_at);
+
+            return Ok(view);
+        }
+
+        Ok(PageView::Owned(self.read_page(page_id)?))
+    }
+
+    /// Read data of pages in given order, file storage reads every run of consecutive
+    /// pages at once and simulated disk pays a single delay for all of them
+    pub fn read_pages(&self, page_ids: &[PageId]) -> Result<Vec<Vec<u8>>> {
+        let started_at = Instant::now();
+        let pages = self.read_pages_uncounted(page_ids)?;
+        self.io.reads.record(pages.len() * PAGE_SIZE, started_at);
+
+        Ok(pages)
+    }
+
+    fn read_pages_uncounted(&self, page_ids: &[PageId]) -> Result<Vec<Vec<u8>>> {
+        match &self.storage {
+            Storage::Memory(pages) => {
+                self.check_faults(page_ids)?;
+                thread::sleep(self.latency.read);
+                let pages = pages.lock();
+
+                Ok(page_ids
+                    .iter()
+                    .map(|page_id| {
+                        pages
+                            .get(page_id)
+                            .cloned()
+                            .unwrap_or_else(|| vec![0; PAGE_SIZE])
+                    })
+                    .collect())
+            }
+            Storage::File(file) => {
+                self.check_faults(page_ids)?;
+                let mut file = file.lock();
+                let mut pages = Vec::with_capacity(page_ids.len());
+                for run in page_ids.chunk_by(|page_id, next| *page_id + 1 == *next) {
+                    let mut data = vec![0; run.len() * PAGE_SIZE];
+                    file.seek(SeekFrom::Start(run[0].offset()))?;
+
+                    // run can reach past the end of file, the rest is read as zeroes
+                    let mut read = 0;
+                    while read < data.len() {
+                        let bytes = file.read(&mut data[read..])?;
+                        if bytes == 0 {
+                            break;
+                        }
+                        read += bytes;
+                    }
+                    pages.extend(data.chunks(PAGE_SIZE).map(<[u8]>::to_vec));
+                }
+
+                Ok(pages)
+            }
+            _ => page_ids
+                .iter()
+                .map(|page_id| self.read_page_uncounted(*page_id))
+                .collect(),
+        }
+    }
+
+    // reads of several pages fail as a whole
+    fn check_faults(&self, page_ids: &[PageId]) -> Result<()> {
+        page_ids
+            .iter()
+            .try_for_each(|page_id| self.faults.check(*page_id, false))
+    }
+
+    /// Write page data, data shorter than page size is padded with zeroes
+    pub fn write_page(&self, page_id: PageId, data: &[u8]) -> Result<()> {
+        let started_at = Instant::now();
+        self.write_page_uncounted(page_id, data)?;
+        self.io.writes.record(PAGE_SIZE, started_at);
+
+        Ok(())
+    }
+
+    fn write_page_uncounted(&self, page_id: PageId, data: &[u8]) -> Result<()> {
+        if data.len() > PAGE_SIZE {
+            bail!(
+                "Page {} data is {} bytes and doesn't fit into page size.",
+                page_id,
+                data.len()
+            );
+        }
+        self.check_writable()?;
+        self.faults.check(page_id, true)?;
+        let mut page = data.to_vec();
+        page.resize(PAGE_SIZE, 0);
+
+        match &self.storage {
+            Storage::Memory(pages) => {
+                thread::sleep(self.latency.write);
+                let mut pages = pages.lock();
+                pages.insert(page_id, page);
+            }
+            Storage::File(file) => {
+                let mut file = file.lock();
+                self.reserve_space(&file, page_id + 1)?;
+                if let Some(double_write) = &self.double_write {
+                    double_write.stage(page_id, &page)?;
+                }
+                file.seek(SeekFrom::Start(page_id.offset()))?;
+                file.write_all(&page)?;
+            }
+            #[cfg(feature = "object-store")]
+            Storage::ObjectStore(backend) => backend.write_page(page_id, page)?,
+            Storage::Checksummed(backend) => backend.write_page(page_id, &page)?,
+            Storage::Compressed(backend) => backend.write_page(page_id, &page)?,
+            #[cfg(feature = "io-uring")]
+            Storage::IoUring(backend) => backend.write_pages(page_id, &page)?,
+            #[cfg(feature = "mmap")]
+            Storage::Mmap(backend) => backend.write_pages(page_id, &page)?,
+            Storage::Mirrored(backend) => backend.write_page(page_id, &page)?,
+            Storage::Tiered(backend) => backend.write_page(page_id, &page)?,
+            Storage::Tablespace(backend) => backend.write_page(page_id, &page)?,
+        }
+
+        Ok(())
+    }
+
+    /// Write consecutive pages starting at `first_page_id`, data holds whole pages.
+    /// File storage writes them at once, other storages page by page.
+    pub fn write_pages(&self, first_page_id: PageId, data: &[u8]) -> Result<()> {
+        let started_at = Instant::now();
+        self.write_pages_uncounted(first_page_id, data)?;
+        self.io.writes.record(data.len(), started_at);
+
+        Ok(())
+    }
+
+    fn write_pages_uncounted(&self, first_page_id: PageId, data: &[u8]) -> Result<()> {
+        if !data.len().is_multiple_of(PAGE_SIZE) {
+            bail!(
+                "Data of pages from {} is {} bytes and doesn't consist of whole pages.",
+                first_page_id,
+                data.len()
+            );
+        }
+        self.check_writable()?;
+
+        match &self.storage {
+            Storage::File(file) => {
+                self.faults.check(first_page_id, true)?;
+                let mut file = file.lock();
+                self.reserve_space(&file, first_page_id + data.len() / PAGE_SIZE)?;
+                if let Some(double_write) = &self.double_write {
+                    double_write.stage(first_page_id, data)?;
+                }
+                file.seek(SeekFrom::Start(first_page_id.offset()))?;
+                file.write_all(data)?;
+            }
+            #[cfg(feature = "io-uring")]
+            Storage::IoUring(backend) => {
+                self.faults.check(first_page_id, true)?;
+                backend.write_pages(first_page_id, data)?
+            }
+            #[cfg(feature = "mmap")]
+            Storage::Mmap(backend) => {
+                self.faults.check(first_page_id, true)?;
+                backend.write_pages(first_page_id, data)?
+            }
+            _ => {
+                for (index, page) in data.chunks(PAGE_SIZE).enumerate() {
+                    self.write_page_uncounted(first_page_id + index, page)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Most requests disk scheduler hands to `serve_batch` at once, storage which serves
+    /// requests one by one takes them from several worker threads instead
+    pub(crate) fn max_batch_size(&self) -> usize {
+        match &self.storage {
+            #[cfg(feature = "io-uring")]
+            Storage::IoUring(_) => RING_ENTRIES,
+            _ => 1,
+        }
+    }
+
+    /// Serve page operations, io_uring storage submits them at once and others serve them
+    /// one by one. Reads return page data and writes no data. Operations must not touch
+    /// the same page.
+    pub(crate) fn serve_batch(&self, batch: &[PageIo<'_>]) -> Vec<Result<Vec<u8>>> {
+        #[cfg(feature = "io-uring")]
+        if let Storage::IoUring(backend) = &self.storage {
+            // operations failed by fault injection are not submitted
+            let checks = batch
+                .iter()
+                .map(|page_io| match page_io {
+                    PageIo::Read(page_id) => self.faults.check(*page_id, false),
+                    PageIo::Write(page_id, _) => self.faults.check(*page_id, true),
+                })
+                .collect::<Vec<_>>();
+            let submitted = batch
+                .iter()
+                .zip(&checks)
+                .filter(|(_, check)| check.is_ok())
+                .map(|(page_io, _)| *page_io)
+                .collect::<Vec<_>>();
+            let started_at = Instant::now();
+            let mut served = backend.serve_batch(&submitted).into_iter();
+            let results = checks
+                .into_iter()
+                .map(|check| check.and_then(|()| served.next().unwrap()))
+                .collect::<Vec<_>>();
+            // operations of batch are served together, each is counted with batch latency
+            let elapsed = started_at.elapsed();
+            for (page_io, result) in batch.iter().zip(&results) {
+                match (page_io, result) {
+                    (PageIo::Read(_), Ok(data)) => self.io.reads.add(data.len(), elapsed),
+                    (PageIo::Write(_, data), Ok(_)) => self.io.writes.add(data.len(), elapsed),
+                    _ => {}
+                }
+            }
+
+            return results;
+        }
+
+        batch
+            .iter()
+            .map(|page_io| match page_io {
+                PageIo::Read(page_id) => self.read_page(*page_id),
+                PageIo::Write(page_id, data) if data.len() > PAGE_SIZE => {
+                    self.write_pages(*page_id, data).map(|()| vec![])
+                }
+                PageIo::Write(page_id, data) => self.write_page(*page_id, data).map(|()| vec![]),
+            })
+            .collect()
+    }
+
+    /// Append record to log file next to data file, apart from pages, and wait until it
+    /// is durable. Returns LSN record is read back by. Storages other than single data
+    /// file opened by path and simulated disk have no log.
+    pub fn write_log(&self, data: &[u8]) -> Result<Lsn> {
+        self.check_writable()?;
+
+        self.log()?.append(data)
+    }
+
+    /// Read `len` bytes of log starting at `offset`, range must be within written log
+    pub fn read_log(&self, offset: Lsn, len: usize) -> Result<Vec<u8>> {
+        self.log()?.read(offset, len)
+    }
+
+    /// LSN the next log record gets
+    pub fn log_end(&self) -> Result<Lsn> {
+        Ok(self.log()?.end())
+    }
+
+    fn log(&self) -> Result<&DiskLog> {
+        self.log
+            .as_ref()
+            .context("Storage of disk manager has no log.")
+    }
+
+    /// Copy pages to plain data file at `path`, which is created, after pages written so
+    /// far are synced. Writes made meanwhile may or may not make it into the copy, see
+    /// `BufferPoolManager::snapshot_to` for consistent copy of open database.
+    pub fn snapshot_to(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        if path.exists() {
+            bail!("Snapshot file {} already exists.", path.display());
+        }
+        self.sync()?;
+
+        let copy = DiskManager::open(path)?;
+        for page_id in (0..self.num_pages()?).map(PageId::new) {
+            copy.write_page(page_id, &self.read_page(page_id)?)?;
+        }
+
+        copy.sync()
+    }
+
+    /// Snapshot of I/O counters since disk manager was opened
+    pub fn stats(&self) -> DiskStats {
+        DiskStats {
+            reads: self.io.reads.count.get(),
+            writes: self.io.writes.count.get(),
+            bytes_read: self.io.reads.bytes.get(),
+            bytes_written: self.io.writes.bytes.get(),
+            syncs: self.io.syncs.count.get(),
+            avg_read_latency: self.io.reads.average(),
+            avg_write_latency: self.io.writes.average(),
+            avg_sync_latency: self.io.syncs.average(),
+            queued_requests: self.io.queue_waits.count.get(),
+            avg_queue_wait: self.io.queue_waits.average(),
+        }
+    }
+
+    /// Time request waited in disk scheduler queue before it was served
+    pub(crate) fn note_queue_wait(&self, queue_wait: Duration) {
+        self.io.queue_waits.add(0, queue_wait);
+    }
+
+    /// Whether storage keeps checksums of pages, which `corrupted_copies` verifies
+    pub fn keeps_checksums(&self) -> bool {
+        matches!(self.storage, Storage::Checksummed(_) | Storage::Mirrored(_))
+    }
+
+    /// Copies of page which fail their checksum, empty if storage keeps no checksums
+    pub fn corrupted_copies(&self, page_id: PageId) -> Result<Vec<&'static str>> {
+        match &self.storage {
+            Storage::Checksummed(backend) => Ok(if backend.is_corrupted(page_id)? {
+                vec!["data file"]
+            } else {
+                vec![]
+            }),
+            Storage::Mirrored(backend) => backend.corrupted_copies(page_id),
+            _ => Ok(vec![]),
+        }
+    }
+
+    /// Whether page fails its checksum in any copy, or can't be decoded by storage which
+    /// keeps no checksums. Free pages aren't checked.
+    pub fn is_page_corrupted(&self, page_id: PageId) -> Result<bool> {
+        if self.is_free_page(page_id) {
+            return Ok(false);
+        }
+        if self.keeps_checksums() {
+            return Ok(!self.corrupted_copies(page_id)?.is_empty());
+        }
+
+        match self.read_page_uncounted(page_id) {
+            Ok(_) => Ok(false),
+            Err(error) if error.downcast_ref::<CorruptPage>().is_some() => Ok(true),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Check pages in background thread one per `page_interval`, from the first to the
+    /// last one and over again, so corruption is found before page is needed. Corrupted
+    /// pages are sent to `corrupted_sender`, scrubber stops once nobody receives them or
+    /// disk manager is dropped. Scrub reads aren't counted by `stats`.
+    pub fn start_scrubber(
+        self: &Arc<Self>,
+        page_interval: Duration,
+        corrupted_sender: Sender<PageId>,
+    ) -> Result<()> {
+        let mut scrubber = self.scrubber.lock();
+        if scrubber.is_some() {
+            bail!("Page scrubber is already running.");
+        }
+        *scrubber = Some(PageScrubber::start(
+            Arc::downgrade(self),
+            page_interval,
+            corrupted_sender,
+        ));
+
+        Ok(())
+    }
+
+    /// Stop scrubber started by `start_scrubber`, if any, and wait until it stops
+    pub fn stop_scrubber(&self) {
+        // taken out first, so scrubber isn't joined under the latch
+        let scrubber = self.scrubber.lock().take();
+        if let Some(scrubber) = scrubber {
+            scrubber.stop();
+        }
+    }
+
+    /// Flush written pages to durable storage, free pages are stored after them
+    pub fn sync(&self) -> Result<()> {
+        let started_at = Instant::now();
+        self.sync_uncounted()?;
+        self.io.syncs.record(0, started_at);
+
+        Ok(())
+    }
+
+    fn sync_uncounted(&self) -> Result<()> {
+        self.refresh_readers();
+        // writes counted meanwhile may miss this sync, so they stay counted
+        let synced_writes = self.durability.unsynced_writes.load(Ordering::Relaxed);
+        match &self.storage {
+            Storage::Memory(_) => {}
+            Storage::File(file) => {
+                // copies are dropped under file latch, so no write is staged meanwhile
+                let file = file.lock();
+                file.sync_all()?;
+                if let Some(double_write) = &self.double_write {
+                    double_write.clear()?;
+                }
+            }
+            #[cfg(feature = "object-store")]
+            Storage::ObjectStore(backend) => backend.sync()?,
+            Storage::Checksummed(backend) => backend.sync()?,
+            Storage::Compressed(backend) => backend.sync()?,
+            #[cfg(feature = "io-uring")]
+            Storage::IoUring(backend) => backend.sync()?,
+            #[cfg(feature = "mmap")]
+            Storage::Mmap(backend) => backend.sync()?,
+            Storage::Mirrored(backend) => backend.sync()?,
+            Storage::Tiered(backend) => backend.sync()?,
+            Storage::Tablespace(backend) => backend.sync()?,
+        }
+        self.allocator.persist()?;
+
+        self.durability
+            .unsynced_writes
+            .fetch_sub(synced_writes, Ordering::Relaxed);
+        *self.durability.last_sync.lock() = Instant::now();
+
+        Ok(())
+    }
+
+    /// Id of page for new data: page freed by `deallocate_page` is reused before new page
+    /// past the end of data file is handed out. Page 0 is never returned.
+    pub fn allocate_page(&self) -> PageId {
+        if self.readers_seen.load(Ordering::Relaxed) {
+            return self.allocator.reserve(1).start;
+        }
+        self.allocator.allocate()
     }
 
-    pub fn read_page(&self, page: &Vec<u8>) -> Vec<u8> {
-        thread::sleep(Duration::from_millis(300));
+    /// Forget page data and make page free for `allocate_page`, page is read as zeroes
+    /// afterwards if its space is reclaimed
+    pub fn deallocate_page(&self, page_id: PageId) -> Result<()> {
+        self.check_writable()?;
+        match &self.storage {
+            Storage::Memory(_) => self.reclaim_page(page_id)?,
+            _ if self.punch_holes => self.reclaim_page(page_id)?,
+            _ => {}
+        }
+        self.allocator.deallocate(page_id);
 
-        vec![0]
+        Ok(())
     }
 
-    pub fn write_page(&self, page: &Vec<u8>) {
-        thread::sleep(Duration::from_millis(200));
+    /// Give space of page slot back to filesystem, page is read as zeroes afterwards if
+    /// storage can reclaim it. Page stays allocated.
+    pub(crate) fn reclaim_page(&self, page_id: PageId) -> Result<()> {
+        match &self.storage {
+            Storage::Memory(pages) => {
+                pages.lock().remove(&page_id);
+            }
+            Storage::File(file) => {
+                let file = file.lock();
+                punch_hole(&file, page_id.as_usize() * PAGE_SIZE, PAGE_SIZE)?;
+            }
+            #[cfg(feature = "io-uring")]
+            Storage::IoUring(backend) => {
+                punch_hole(backend.file(), page_id.as_usize() * PAGE_SIZE, PAGE_SIZE)?;
+            }
+            Storage::Compressed(backend) => backend.deallocate_page(page_id)?,
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Free page for buffer pool to reuse, if any
+    pub(crate) fn take_free_page(&self) -> Option<PageId> {
+        if self.readers_seen.load(Ordering::Relaxed) {
+            return None;
+        }
+        self.allocator.take_free()
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Whether data file is opened by `open_read_only` in this or another process. Freed
+    /// pages are not reused while readers were there on the last open or `sync`, reader
+    /// may still follow stale pointer to one.
+    pub fn has_readers(&self) -> bool {
+        let Some(readers) = self.readers.as_ref().filter(|_| !self.read_only) else {
+            return false;
+        };
+        match readers.try_lock() {
+            Ok(()) => {
+                let _ = readers.unlock();
+                false
+            }
+            Err(_) => true,
+        }
+    }
+
+    // readers lock is checked on open and sync only, so allocation doesn't take a syscall.
+    // Reader which comes later may meanwhile follow stale pointer to reused page.
+    fn refresh_readers(&self) {
+        self.readers_seen
+            .store(self.has_readers(), Ordering::Relaxed);
+    }
+
+    fn check_writable(&self) -> Result<()> {
+        if self.read_only {
+            bail!("Data file is opened read-only.");
+        }
+
+        Ok(())
+    }
+
+    /// New pages past the end of data file, free pages are not reused
+    pub(crate) fn reserve_pages(&self, count: usize) -> Range<PageId> {
+        self.allocator.reserve(count)
+    }
+
+    /// Give back reserved pages which were never handed out
+    pub(crate) fn release_pages(&self, page_ids: Range<PageId>) {
+        self.allocator.release(page_ids)
+    }
+
+    /// Page written without allocation, like by redo of structure log, is in use
+    pub(crate) fn mark_allocated(&self, page_id: PageId) {
+        self.allocator.mark_allocated(page_id)
+    }
+
+    pub(crate) fn is_free_page(&self, page_id: PageId) -> bool {
+        self.allocator.is_free(page_id)
+    }
+
+    /// Stop reusing free pages, they may still be referenced by pages written after
+    /// the last sync. Returns number of discarded pages.
+    pub(crate) fn discard_free_pages(&self) -> Result<usize> {
+        let discarded = self.allocator.discard_free();
+        self.allocator.persist()?;
+
+        Ok(discarded)
+    }
+
+    /// Move pages not accessed within `window` to cold tier, returns number of moved pages.
+    /// Storage without tiers has nothing to move.
+    pub fn migrate_cold_pages(&self, window: Duration) -> Result<usize> {
+        match &self.storage {
+            Storage::Tiered(backend) => backend.migrate_cold_pages(window),
+            _ => Ok(0),
+        }
+    }
+
+    /// Number of pages disk holds, including never written pages in the middle
+    pub fn num_pages(&self) -> Result<usize> {
+        match &self.storage {
+            Storage::Memory(pages) => {
+                let pages = pages.lock();
+
+                Ok(pages
+                    .keys()
+                    .max()
+                    .map_or(0, |page_id| page_id.as_usize() + 1))
+            }
+            Storage::File(file) => {
+                let file = file.lock();
+                let len = file.metadata()?.len() as usize;
+
+                Ok(len.div_ceil(PAGE_SIZE))
+            }
+            #[cfg(feature = "object-store")]
+            Storage::ObjectStore(backend) => Ok(backend.num_pages()),
+            Storage::Checksummed(backend) => backend.num_pages(),
+            Storage::Compressed(backend) => backend.num_pages(),
+            #[cfg(feature = "io-uring")]
+            Storage::IoUring(backend) => backend.num_pages(),
+            #[cfg(feature = "mmap")]
+            Storage::Mmap(backend) => Ok(backend.num_pages()),
+            Storage::Mirrored(backend) => backend.num_pages(),
+            Storage::Tiered(backend) => backend.num_pages(),
+            Storage::Tablespace(backend) => backend.num_pages(),
+        }
+    }
+}
+
+/// Open or create data file and take exclusive advisory lock on it, so two engines
+/// don't write the same file
+pub(crate) fn open_data_file(path: &Path) -> Result<File> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .with_context(|| format!("Can't open data file {}.", path.display()))?;
+
+    // lock is released when file is closed, including when process dies
+    match file.try_lock() {
+        Ok(()) => Ok(file),
+        Err(TryLockError::WouldBlock) => Err(AlreadyInUse {
+            path: path.to_path_buf(),
+        }
+        .into()),
+        Err(TryLockError::Error(error)) => {
+            Err(error).with_context(|| format!("Can't lock data file {}.", path.display()))
+        }
+    }
+}
+
+// lock file of readers, data file itself is locked exclusively by writer
+fn open_readers_file(path: &Path) -> Result<File> {
+    let mut readers_path = path.as_os_str().to_owned();
+    readers_path.push(READERS_SUFFIX);
+
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&readers_path)
+        .with_context(|| format!("Can't open readers lock of data file {}.", path.display()))
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn punch_hole(file: &File, offset: usize, len: usize) -> Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let result = unsafe {
+        libc::fallocate(
+            file.as_raw_fd(),
+            libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+            offset as libc::off_t,
+            len as libc::off_t,
+        )
+    };
+    if result != 0 {
+        let error = std::io::Error::last_os_error();
+        // filesystem can't punch holes, page slot just stays allocated
+        if error.raw_os_error() == Some(libc::EOPNOTSUPP) {
+            return Ok(());
+        }
+        return Err(error).context("Can't punch hole in data file.");
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn punch_hole(_file: &File, _offset: usize, _len: usize) -> Result<()> {
+    Ok(())
+}
+
+// space is reserved without changing file size, so unwritten pages aren't counted
+#[cfg(target_os = "linux")]
+fn preallocate(file: &File, offset: usize, len: usize) -> Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let result = unsafe {
+        libc::fallocate(
+            file.as_raw_fd(),
+            libc::FALLOC_FL_KEEP_SIZE,
+            offset as libc::off_t,
+            len as libc::off_t,
+        )
+    };
+    if result != 0 {
+        let error = std::io::Error::last_os_error();
+        // filesystem can't reserve space, file just grows by writes
+        if error.raw_os_error() == Some(libc::EOPNOTSUPP) {
+            return Ok(());
+        }
+        return Err(error).context("Can't reserve space of data file.");
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn preallocate(_file: &File, _offset: usize, _len: usize) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, sync::mpsc};
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_file_read_write() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("test.db");
+        let disk_manager = DiskManager::open(&path).unwrap();
+
+        assert_eq!(disk_manager.num_pages().unwrap(), 0);
+        assert_eq!(
+            disk_manager.read_page(PageId::new(3)).unwrap(),
+            vec![0; PAGE_SIZE]
+        );
+
+        disk_manager.write_page(PageId::new(2), &[1, 2, 3]).unwrap();
+        drop(disk_manager);
+
+        let disk_manager = DiskManager::open(&path).unwrap();
+        let data = disk_manager.read_page(PageId::new(2)).unwrap();
+
+        assert_eq!(disk_manager.num_pages().unwrap(), 3);
+        assert_eq!(&data[..4], &[1, 2, 3, 0]);
+        assert_eq!(data.len(), PAGE_SIZE);
+        assert!(disk_manager
+            .write_page(PageId::new(1), &[0; PAGE_SIZE + 1])
+            .is_err());
+    }
+
+    #[test]
+    fn test_data_file_is_locked() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("test.db");
+        let disk_manager = DiskManager::open(&path).unwrap();
+
+        let error = DiskManager::open(&path).unwrap_err();
+        assert_eq!(error.downcast_ref::<AlreadyInUse>().unwrap().path, path);
+
+        drop(disk_manager);
+        DiskManager::open(&path).unwrap();
+    }
+
+    #[test]
+    fn test_freed_page_is_reused() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("test.db");
+        let disk_manager = DiskManager::open(&path).unwrap();
+
+        assert_eq!(disk_manager.allocate_page(), PageId::new(1));
+        assert_eq!(disk_manager.allocate_page(), PageId::new(2));
+        assert_eq!(disk_manager.allocate_page(), PageId::new(3));
+        disk_manager.write_page(PageId::new(3), &[3]).unwrap();
+        disk_manager.deallocate_page(PageId::new(2)).unwrap();
+        disk_manager.deallocate_page(PageId::new(1)).unwrap();
+        assert_eq!(disk_manager.allocate_page(), PageId::new(1));
+        disk_manager.sync().unwrap();
+        drop(disk_manager);
+
+        // free pages outlive reopen
+        let disk_manager = DiskManager::open(&path).unwrap();
+        assert!(disk_manager.is_free_page(PageId::new(2)));
+        assert_eq!(disk_manager.allocate_page(), PageId::new(2));
+        assert_eq!(disk_manager.allocate_page(), PageId::new(4));
+    }
+
+    #[test]
+    fn test_freed_page_is_not_reused_while_reader_was_seen() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("test.db");
+        let disk_manager = DiskManager::open(&path).unwrap();
+        assert_eq!(disk_manager.allocate_page(), PageId::new(1));
+        disk_manager.write_page(PageId::new(1), &[1]).unwrap();
+        disk_manager.sync().unwrap();
+
+        // reader is noticed on sync, not on allocation
+        let reader = DiskManager::open_read_only(&path).unwrap();
+        disk_manager.sync().unwrap();
+        disk_manager.deallocate_page(PageId::new(1)).unwrap();
+        assert_eq!(disk_manager.take_free_page(), None);
+        assert_eq!(disk_manager.allocate_page(), PageId::new(2));
+
+        drop(reader);
+        assert_eq!(disk_manager.take_free_page(), None);
+        disk_manager.sync().unwrap();
+        assert_eq!(disk_manager.take_free_page(), Some(PageId::new(1)));
+    }
+
+    #[test]
+    fn test_scrubber_finds_corrupted_page() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("test.db");
+        let disk_manager = DiskManager::open_checksummed(&path).unwrap();
+        for page_id in 0..4 {
+            disk_manager
+                .write_page(PageId::new(page_id), &[page_id as u8; 100])
+                .unwrap();
+        }
+        disk_manager.sync().unwrap();
+        drop(disk_manager);
+
+        // torn write of page 2
+        let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.seek(SeekFrom::Start((2 * (PAGE_SIZE + 4) + 10) as u64))
+            .unwrap();
+        file.write_all(&[0xff; 10]).unwrap();
+        drop(file);
+
+        let disk_manager = Arc::new(DiskManager::open_checksummed(&path).unwrap());
+        let (sender, receiver) = mpsc::channel();
+        disk_manager
+            .start_scrubber(Duration::from_millis(1), sender.clone())
+            .unwrap();
+        assert!(disk_manager
+            .start_scrubber(Duration::from_millis(1), sender)
+            .is_err());
+
+        // pages are checked over and over, only the corrupted one is reported
+        for _ in 0..2 {
+            assert_eq!(
+                receiver.recv_timeout(Duration::from_secs(10)).unwrap(),
+                PageId::new(2)
+            );
+        }
+        disk_manager.stop_scrubber();
+        assert!(receiver.recv().is_err());
+    }
+
+    #[test]
+    fn test_corrupted_page_fails_checksum() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("test.db");
+        let disk_manager = DiskManager::open_checksummed(&path).unwrap();
+        disk_manager.write_page(PageId::new(1), &[7; 100]).unwrap();
+        disk_manager.write_page(PageId::new(2), &[8; 100]).unwrap();
+        disk_manager.sync().unwrap();
+        drop(disk_manager);
+
+        // torn write of page 1
+        let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.seek(SeekFrom::Start((PAGE_SIZE + 4 + 10) as u64))
+            .unwrap();
+        file.write_all(&[0; 10]).unwrap();
+        drop(file);
+
+        let disk_manager = DiskManager::open_checksummed(&path).unwrap();
+        let error = disk_manager.read_page(PageId::new(1)).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<CorruptPage>().unwrap().page_id,
+            PageId::new(1)
+        );
+        assert_eq!(
+            disk_manager.corrupted_copies(PageId::new(1)).unwrap(),
+            vec!["data file"]
+        );
+        assert_eq!(
+            &disk_manager.read_page(PageId::new(2)).unwrap()[..100],
+            &[8; 100]
+        );
+        assert_eq!(
+            disk_manager.read_page(PageId::new(5)).unwrap(),
+            vec![0; PAGE_SIZE]
+        );
+        assert_eq!(disk_manager.num_pages().unwrap(), 3);
+    }
+
+    #[test]
+    fn test_torn_page_is_restored_from_double_write_buffer() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("test.db");
+        let disk_manager = DiskManager::open_with_double_write(&path).unwrap();
+        disk_manager.write_page(PageId::new(1), &[7; 100]).unwrap();
+        disk_manager.sync().unwrap();
+        disk_manager
+            .write_pages(PageId::new(1), &[8; 2 * PAGE_SIZE])
+            .unwrap();
+        // crash before sync
+        drop(disk_manager);
+
+        // torn in-place write of page 2 and torn copy staged after it
+        let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.seek(SeekFrom::Start(PageId::new(2).offset() + 10))
+            .unwrap();
+        file.write_all(&[0; 10]).unwrap();
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(dir.path().join("test.db.dwb"))
+            .unwrap();
+        file.write_all(&[9; 100]).unwrap();
+        drop(file);
+
+        let disk_manager = DiskManager::open_with_double_write(&path).unwrap();
+        assert_eq!(
+            disk_manager.read_page(PageId::new(1)).unwrap(),
+            vec![8; PAGE_SIZE]
+        );
+        assert_eq!(
+            disk_manager.read_page(PageId::new(2)).unwrap(),
+            vec![8; PAGE_SIZE]
+        );
+        // copies are dropped once restored pages are synced
+        assert_eq!(
+            fs::metadata(dir.path().join("test.db.dwb")).unwrap().len(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_durability_policy() {
+        let dir = TempDir::new().unwrap();
+        let mut disk_manager = DiskManager::open(dir.path().join("test.db")).unwrap();
+        let unsynced = |disk_manager: &DiskManager| {
+            disk_manager
+                .durability
+                .unsynced_writes
+                .load(Ordering::Relaxed)
+        };
+
+        disk_manager.set_durability_policy(DurabilityPolicy::EveryNWrites(3));
+        disk_manager.note_writes(2).unwrap();
+        assert_eq!(unsynced(&disk_manager), 2);
+        disk_manager.note_writes(1).unwrap();
+        assert_eq!(unsynced(&disk_manager), 0);
+
+        disk_manager.set_durability_policy(DurabilityPolicy::Periodic(Duration::from_millis(50)));
+        disk_manager.note_writes(1).unwrap();
+        disk_manager.sync_if_due().unwrap();
+        assert_eq!(unsynced(&disk_manager), 1);
+        thread::sleep(Duration::from_millis(50));
+        disk_manager.sync_if_due().unwrap();
+        assert_eq!(unsynced(&disk_manager), 0);
+
+        disk_manager.set_durability_policy(DurabilityPolicy::Always);
+        disk_manager.note_writes(1).unwrap();
+        assert_eq!(unsynced(&disk_manager), 0);
+    }
+
+    #[test]
+    fn test_log_is_kept_apart_from_pages() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("test.db");
+        let disk_manager = DiskManager::open(&path).unwrap();
+
+        let first = disk_manager.write_log(b"first").unwrap();
+        let second = disk_manager.write_log(b"second").unwrap();
+        assert_eq!(first, Lsn::new(0));
+        assert_eq!(second, Lsn::new(5));
+        assert_eq!(disk_manager.read_log(second, 6).unwrap(), b"second");
+        assert!(disk_manager.read_log(second, 7).is_err());
+        assert_eq!(disk_manager.num_pages().unwrap(), 0);
+        drop(disk_manager);
+
+        let disk_manager = DiskManager::open(&path).unwrap();
+        assert_eq!(disk_manager.read_log(first, 5).unwrap(), b"first");
+        assert_eq!(disk_manager.write_log(b"third").unwrap(), Lsn::new(11));
+        assert_eq!(disk_manager.log_end().unwrap(), Lsn::new(16));
+    }
+
+    #[test]
+    fn test_data_file_grows_by_extents() {
+        let dir = TempDir::new().unwrap();
+        let mut disk_manager = DiskManager::open(dir.path().join("test.db")).unwrap();
+        disk_manager.set_growth_extent(16);
+        assert_eq!(disk_manager.capacity_pages().unwrap(), 0);
+
+        disk_manager.write_page(PageId::new(0), &[1]).unwrap();
+        assert_eq!(disk_manager.size_pages().unwrap(), 1);
+        assert_eq!(disk_manager.capacity_pages().unwrap(), 16);
+
+        disk_manager
+            .write_pages(PageId::new(15), &[0; PAGE_SIZE * 2])
+            .unwrap();
+        assert_eq!(disk_manager.size_pages().unwrap(), 17);
+        assert_eq!(disk_manager.capacity_pages().unwrap(), 32);
+        assert_eq!(disk_manager.read_page(PageId::new(0)).unwrap()[0], 1);
+    }
+
+    #[test]
+    fn test_snapshot_to() {
+        let dir = TempDir::new().unwrap();
+        let disk_manager = DiskManager::open(dir.path().join("test.db")).unwrap();
+        disk_manager.write_page(PageId::new(0), &[1]).unwrap();
+        disk_manager.write_page(PageId::new(2), &[2]).unwrap();
+
+        let path = dir.path().join("copy.db");
+        disk_manager.snapshot_to(&path).unwrap();
+        disk_manager.write_page(PageId::new(0), &[3]).unwrap();
+        assert!(disk_manager.snapshot_to(&path).is_err());
+
+        let copy = DiskManager::open(&path).unwrap();
+        assert_eq!(copy.num_pages().unwrap(), 3);
+        assert_eq!(copy.read_page(PageId::new(0)).unwrap()[0], 1);
+        assert_eq!(copy.read_page(PageId::new(2)).unwrap()[0], 2);
+    }
+
+    #[test]
+    fn test_stats() {
+        let dir = TempDir::new().unwrap();
+        let disk_manager = DiskManager::open(dir.path().join("test.db")).unwrap();
+
+        disk_manager.write_page(PageId::new(1), &[1]).unwrap();
+        disk_manager
+            .write_pages(PageId::new(2), &[0; PAGE_SIZE * 2])
+            .unwrap();
+        disk_manager.read_page(PageId::new(1)).unwrap();
+        disk_manager
+            .read_pages(&[PageId::new(2), PageId::new(3)])
+            .unwrap();
+        disk_manager.sync().unwrap();
+        disk_manager.fail_next_n_writes(1);
+        assert!(disk_manager.write_page(PageId::new(1), &[2]).is_err());
+        disk_manager.note_queue_wait(Duration::from_millis(2));
+
+        let stats = disk_manager.stats();
+        assert_eq!(stats.reads, 2);
+        assert_eq!(stats.writes, 2);
+        assert_eq!(stats.bytes_read, 3 * PAGE_SIZE as u64);
+        assert_eq!(stats.bytes_written, 3 * PAGE_SIZE as u64);
+        assert_eq!(stats.syncs, 1);
+        assert_eq!(stats.queued_requests, 1);
+        assert_eq!(stats.avg_queue_wait, Duration::from_millis(2));
+    }
+
+    #[cfg(feature = "io-uring")]
+    #[test]
+    fn test_io_uring_batch() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("test.db");
+        let disk_manager = DiskManager::open_io_uring(&path).unwrap();
+        let run = vec![2; 2 * PAGE_SIZE];
+
+        let results = disk_manager.serve_batch(&[
+            PageIo::Write(PageId::new(1), &[1]),
+            PageIo::Write(PageId::new(2), &run),
+            PageIo::Write(PageId::new(5), &run[1..]),
+            PageIo::Read(PageId::new(7)),
+        ]);
+        assert!(results[0].as_ref().unwrap().is_empty());
+        assert!(results[1].is_ok());
+        assert!(results[2].is_err());
+        assert_eq!(results[3].as_ref().unwrap(), &vec![0; PAGE_SIZE]);
+
+        let results =
+            disk_manager.serve_batch(&[PageIo::Read(PageId::new(1)), PageIo::Read(PageId::new(3))]);
+        assert_eq!(results[0].as_ref().unwrap()[..2], [1, 0]);
+        assert_eq!(results[1].as_ref().unwrap(), &vec![2; PAGE_SIZE]);
+        assert_eq!(disk_manager.num_pages().unwrap(), 4);
+        drop(disk_manager);
+
+        // pages are laid out like in plain data file
+        let disk_manager = DiskManager::open(&path).unwrap();
+        assert_eq!(
+            disk_manager.read_page(PageId::new(2)).unwrap(),
+            vec![2; PAGE_SIZE]
+        );
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_mmap() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("test.db");
+        let disk_manager = DiskManager::open_mmap(&path).unwrap();
+        assert_eq!(
+            &*disk_manager.read_page_view(PageId::new(0)).unwrap(),
+            &[0; PAGE_SIZE]
+        );
+
+        disk_manager.write_page(PageId::new(1), &[1]).unwrap();
+        disk_manager
+            .write_pages(PageId::new(2), &[2; 2 * PAGE_SIZE])
+            .unwrap();
+        let view = disk_manager.read_page_view(PageId::new(1)).unwrap();
+        assert!(matches!(view, PageView::Mapped(_)));
+        assert_eq!(view[..2], [1, 0]);
+        drop(view);
+        assert_eq!(
+            disk_manager.read_page(PageId::new(3)).unwrap(),
+            vec![2; PAGE_SIZE]
+        );
+        assert_eq!(disk_manager.num_pages().unwrap(), 4);
+        drop(disk_manager);
+
+        // pages are laid out like in plain data file
+        let disk_manager = DiskManager::open(&path).unwrap();
+        assert_eq!(disk_manager.read_page(PageId::new(1)).unwrap()[0], 1);
+    }
+
+    #[test]
+    fn test_tablespace() {
+        let dir = TempDir::new().unwrap();
+        let first = dir.path().join("first.db");
+        let second = dir.path().join("second.db");
+
+        let disk_manager =
+            DiskManager::open_tablespace(&[(&first, 2)], TablespaceLayout::FillThenSpill).unwrap();
+        disk_manager.write_page(PageId::new(1), &[1]).unwrap();
+        assert!(disk_manager.write_page(PageId::new(2), &[2]).is_err());
+        disk_manager.add_data_file(&second, 2).unwrap();
+        disk_manager.write_page(PageId::new(2), &[2]).unwrap();
+        assert_eq!(disk_manager.num_pages().unwrap(), 3);
+        drop(disk_manager);
+        assert_eq!(fs::metadata(&second).unwrap().len(), PAGE_SIZE as u64);
+
+        let disk_manager = DiskManager::open_tablespace(
+            &[(&first, 2), (&second, 2)],
+            TablespaceLayout::FillThenSpill,
+        )
+        .unwrap();
+        assert_eq!(disk_manager.read_page(PageId::new(1)).unwrap()[0], 1);
+        assert_eq!(disk_manager.read_page(PageId::new(2)).unwrap()[0], 2);
+        drop(disk_manager);
+
+        let round_robin = [(dir.path().join("a.db"), 4), (dir.path().join("b.db"), 4)];
+        let files = round_robin
+            .iter()
+            .map(|(path, max_pages)| (path.as_path(), *max_pages))
+            .collect::<Vec<_>>();
+        let disk_manager =
+            DiskManager::open_tablespace(&files, TablespaceLayout::RoundRobin).unwrap();
+        disk_manager.write_page(PageId::new(3), &[3]).unwrap();
+        assert_eq!(disk_manager.num_pages().unwrap(), 4);
+        assert_eq!(
+            fs::metadata(&round_robin[1].0).unwrap().len(),
+            2 * PAGE_SIZE as u64
+        );
+        assert!(disk_manager
+            .add_data_file(dir.path().join("c.db"), 4)
+            .is_err());
+    }
+
+    #[test]
+    fn test_compressed_pages() {
+        let dir = TempDir::new().unwrap();
+        let compressions = [
+            PageCompression::None,
+            #[cfg(feature = "lz4")]
+            PageCompression::Lz4,
+            #[cfg(feature = "zstd")]
+            PageCompression::Zstd { level: 3 },
+        ];
+        // random page doesn't compress and is stored as is
+        let random = seeded_rng("compressed_pages")
+            .sample_iter(rand::distributions::Standard)
+            .take(PAGE_SIZE)
+            .collect::<Vec<u8>>();
+
+        for (index, compression) in compressions.into_iter().enumerate() {
+            let path = dir.path().join(format!("{}.db", index));
+            let disk_manager = DiskManager::open_compressed(&path, compression).unwrap();
+            disk_manager.write_page(PageId::new(0), &[7; 100]).unwrap();
+            disk_manager.write_page(PageId::new(1), &random).unwrap();
+            drop(disk_manager);
+
+            // pages are read with codec they were written with
+            let disk_manager = DiskManager::open_compressed(&path, PageCompression::None).unwrap();
+            let page = disk_manager.read_page(PageId::new(0)).unwrap();
+            assert_eq!(page[..100], [7; 100]);
+            assert!(page[100..].iter().all(|byte| *byte == 0));
+            assert_eq!(disk_manager.read_page(PageId::new(1)).unwrap(), random);
+            assert_eq!(
+                disk_manager.read_page(PageId::new(2)).unwrap(),
+                vec![0; PAGE_SIZE]
+            );
+            assert_eq!(disk_manager.num_pages().unwrap(), 2);
+        }
+    }
+
+    #[test]
+    fn test_deallocated_page_is_read_as_zeroes() {
+        let dir = TempDir::new().unwrap();
+        let mut disk_manager = DiskManager::open(dir.path().join("test.db")).unwrap();
+        disk_manager.set_punch_holes(true);
+
+        disk_manager
+            .write_page(PageId::new(1), &[7; PAGE_SIZE])
+            .unwrap();
+        disk_manager
+            .write_page(PageId::new(2), &[8; PAGE_SIZE])
+            .unwrap();
+        disk_manager.deallocate_page(PageId::new(1)).unwrap();
+
+        // filesystems without hole punching keep old data
+        let data = disk_manager.read_page(PageId::new(1)).unwrap();
+        assert!(data == vec![0; PAGE_SIZE] || data == vec![7; PAGE_SIZE]);
+        assert_eq!(
+            disk_manager.read_page(PageId::new(2)).unwrap(),
+            vec![8; PAGE_SIZE]
+        );
+        assert_eq!(disk_manager.num_pages().unwrap(), 3);
     }
 }
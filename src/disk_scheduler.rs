@@ -1,57 +1,330 @@
-use anyhow::Result;
-use parking_lot::{Mutex, RwLockWriteGuard};
+use anyhow::{anyhow, bail, Result};
+use parking_lot::{Condvar, Mutex, MutexGuard};
+use rand::{rngs::StdRng, Rng};
 use std::{
-    collections::{HashMap, HashSet, VecDeque},
-    mem,
+    collections::{HashMap, VecDeque},
+    iter, mem,
+    ops::Range,
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
         mpsc::Sender,
         Arc,
     },
     thread,
+    time::{Duration, Instant},
 };
 
 use crate::{
-    disk_manager::DiskManager,
-    page::{Page, PageId},
+    disk_manager::{DiskManager, DurabilityPolicy, PageIo},
+    page::{iter_page_ids, PageId, PAGE_SIZE},
+    rng::seeded_rng,
+    sharded_counter::ShardedCounter,
 };
 
+// requests waiting in queue or served longer than this are logged
+const DEFAULT_SLOW_REQUEST_THRESHOLD: Duration = Duration::from_secs(1);
+// reads of consecutive pages after which the following pages are read ahead
+const SEQUENTIAL_READS: usize = 3;
+const DEFAULT_READ_AHEAD_PAGES: usize = 8;
+
+/// Cumulative counters of served requests, prefetches count as reads
+#[derive(Debug, Default)]
+pub(crate) struct SchedulerCounters {
+    pub bytes_read: ShardedCounter,
+    pub bytes_written: ShardedCounter,
+    pub slow_requests: ShardedCounter,
+    /// Reads served from pages read ahead
+    pub read_ahead_hits: ShardedCounter,
+    /// Completions duplicated by chaos and dropped before they reached caller
+    pub duplicate_completions: ShardedCounter,
+}
+
+/// Perturbation of how disk scheduler serves requests, to stress ordering assumptions
+/// of upper layers, see `DiskScheduler::set_chaos`. Requests touching the same page are
+/// still served in order they were queued. Random values come from `seeded_rng`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChaosConfig {
+    /// Probability request is served before requests queued earlier
+    pub reorder_probability: f64,
+    /// Longest delay before batch of requests is served, delays are uniform up to it
+    pub max_delay: Duration,
+    /// Probability completion of request is delivered twice
+    pub duplicate_probability: f64,
+}
+
+#[derive(Debug, Default)]
+struct Chaos {
+    config: Mutex<Option<(ChaosConfig, StdRng)>>,
+    enabled: AtomicBool,
+}
+
+impl Chaos {
+    fn set(&self, config: Option<ChaosConfig>) -> Result<()> {
+        if let Some(config) = &config {
+            for probability in [config.reorder_probability, config.duplicate_probability] {
+                if !(0.0..=1.0).contains(&probability) {
+                    bail!("Chaos probability {} is not within 0 and 1.", probability);
+                }
+            }
+        }
+        let mut current = self.config.lock();
+        self.enabled.store(config.is_some(), Ordering::Relaxed);
+        *current = config.map(|config| (config, seeded_rng("disk_chaos")));
+
+        Ok(())
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    // index of ready request to serve, requests are ordered by time they were queued
+    fn pick(&self, ready: usize) -> usize {
+        let mut current = self.config.lock();
+        let Some((config, rng)) = current.as_mut().filter(|_| ready > 1) else {
+            return 0;
+        };
+        if rng.gen_bool(config.reorder_probability) {
+            rng.gen_range(0..ready)
+        } else {
+            0
+        }
+    }
+
+    fn delay(&self) -> Duration {
+        match &mut *self.config.lock() {
+            Some((config, rng)) if !config.max_delay.is_zero() => {
+                rng.gen_range(Duration::ZERO..=config.max_delay)
+            }
+            _ => Duration::ZERO,
+        }
+    }
+
+    fn duplicate(&self) -> bool {
+        match &mut *self.config.lock() {
+            Some((config, rng)) => rng.gen_bool(config.duplicate_probability),
+            None => false,
+        }
+    }
+}
+
+// sends only the first result of request, so duplicated completion never reaches caller
+#[derive(Debug)]
+struct Completion<T> {
+    sender: Sender<Result<T>>,
+    delivered: bool,
+}
+
+impl<T: Clone> Completion<T> {
+    fn new(sender: Sender<Result<T>>) -> Self {
+        Self {
+            sender,
+            delivered: false,
+        }
+    }
+
+    // returns whether result was sent or dropped as duplicate
+    fn send(&mut self, result: Result<T>) -> bool {
+        if mem::replace(&mut self.delivered, true) {
+            return false;
+        }
+        let _ = self.sender.send(result);
+
+        true
+    }
+
+    fn complete(mut self, result: Result<T>, chaos: &Chaos, counters: &SchedulerCounters) {
+        let duplicate = chaos.duplicate().then(|| match &result {
+            Ok(value) => Ok(value.clone()),
+            Err(error) => Err(anyhow!("{error:#}")),
+        });
+        self.send(result);
+        if let Some(duplicate) = duplicate {
+            if !self.send(duplicate) {
+                counters.duplicate_completions.increment();
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct ReadAheadWindow {
+    last_read: Option<PageId>,
+    // consecutive reads which led to the last one
+    sequential_reads: usize,
+    pages: HashMap<PageId, Vec<u8>>,
+}
+
+/// Once reads go through consecutive pages, read of the next page brings following
+/// pages as well in a single disk read. They are kept until read or written.
+#[derive(Debug)]
+struct ReadAhead {
+    // 0 turns read ahead off
+    pages: AtomicUsize,
+    window: Mutex<ReadAheadWindow>,
+    // bumped by every write, pages read ahead meanwhile may be stale and are dropped
+    writes: AtomicU64,
+}
+
+impl ReadAhead {
+    fn new(pages: usize) -> Self {
+        Self {
+            pages: AtomicUsize::new(pages),
+            window: Mutex::new(ReadAheadWindow::default()),
+            writes: AtomicU64::new(0),
+        }
+    }
+
+    // page from window or read with pages after it, none if read isn't sequential
+    fn read(
+        &self,
+        disk_manager: &DiskManager,
+        counters: &SchedulerCounters,
+        page_id: PageId,
+    ) -> Option<Result<Vec<u8>>> {
+        let pages = self.pages.load(Ordering::Relaxed);
+        if pages == 0 {
+            return None;
+        }
+        let mut window = self.window.lock();
+        window.sequential_reads = match window.last_read {
+            Some(last_read) if last_read + 1 == page_id => window.sequential_reads + 1,
+            _ => 1,
+        };
+        window.last_read = Some(page_id);
+        if let Some(data) = window.pages.remove(&page_id) {
+            counters.read_ahead_hits.increment();
+            return Some(Ok(data));
+        }
+        if window.sequential_reads < SEQUENTIAL_READS {
+            return None;
+        }
+        drop(window);
+
+        let writes = self.writes.load(Ordering::SeqCst);
+        let page_ids = iter_page_ids(page_id..page_id + pages).collect::<Vec<_>>();
+        let mut data = match disk_manager.read_pages(&page_ids) {
+            Ok(data) => data.into_iter(),
+            Err(error) => return Some(Err(error)),
+        };
+        let page = data.next();
+        let mut window = self.window.lock();
+        if self.writes.load(Ordering::SeqCst) == writes {
+            window.pages = page_ids[1..].iter().copied().zip(data).collect();
+        }
+
+        page.map(Ok)
+    }
+
+    // like `DiskManager::serve_batch`, reads are served by read ahead when it can
+    fn serve_batch(
+        &self,
+        disk_manager: &DiskManager,
+        counters: &SchedulerCounters,
+        batch: &[PageIo<'_>],
+    ) -> Vec<Result<Vec<u8>>> {
+        let read_ahead = batch
+            .iter()
+            .map(|page_io| match page_io {
+                PageIo::Read(page_id) => self.read(disk_manager, counters, *page_id),
+                PageIo::Write(..) => None,
+            })
+            .collect::<Vec<_>>();
+        let rest = batch
+            .iter()
+            .zip(&read_ahead)
+            .filter(|(_, result)| result.is_none())
+            .map(|(page_io, _)| *page_io)
+            .collect::<Vec<_>>();
+        let mut served = disk_manager.serve_batch(&rest).into_iter();
+
+        read_ahead
+            .into_iter()
+            .map(|result| result.unwrap_or_else(|| served.next().unwrap()))
+            .collect()
+    }
+
+    // called once pages are written and before their requests are finished, so reads
+    // queued after writes don't get pages read ahead before them
+    fn invalidate(&self, page_ids: Range<PageId>) {
+        self.writes.fetch_add(1, Ordering::SeqCst);
+        let mut window = self.window.lock();
+        for page_id in iter_page_ids(page_ids) {
+            window.pages.remove(&page_id);
+        }
+    }
+
+    // pages may have been changed on disk by another process
+    fn clear(&self) {
+        self.writes.fetch_add(1, Ordering::SeqCst);
+        *self.window.lock() = ReadAheadWindow::default();
+    }
+}
+
+/// Requests queued by their first page. Requests touching the same page are served one
+/// at a time in order they were queued, whatever their kind, so read queued after write
+/// of the page sees written data. Run of pages is ordered on each page it touches.
 #[derive(Debug)]
 struct DiskRequestQueue {
-    queues: HashMap<PageId, VecDeque<DiskRequest>>,
-    in_processing_ids: HashSet<PageId>,
+    queues: HashMap<PageId, VecDeque<(u64, DiskRequest)>>,
+    // sequence numbers of queued and processed requests touching page, oldest first
+    page_order: HashMap<PageId, VecDeque<u64>>,
+    next_seq: u64,
 }
 
 impl DiskRequestQueue {
     pub fn new() -> Self {
         Self {
             queues: HashMap::new(),
-            in_processing_ids: HashSet::new(),
+            page_order: HashMap::new(),
+            next_seq: 0,
         }
     }
 
     pub fn push(&mut self, disk_request: DiskRequest) {
-        let page = &disk_request.page;
-        let page_id = page.0;
-        let queue = self.queues.entry(page_id).or_default();
-        queue.push_back(disk_request);
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        for page_id in iter_page_ids(disk_request.page_ids()) {
+            self.page_order.entry(page_id).or_default().push_back(seq);
+        }
+        let queue = self.queues.entry(disk_request.page_id).or_default();
+        queue.push_back((seq, disk_request));
     }
 
-    pub fn start_processing(&mut self) -> Option<DiskRequest> {
-        for (&page_id, queue) in &mut self.queues {
-            if !self.in_processing_ids.contains(&page_id) {
-                self.in_processing_ids.insert(page_id);
-                return queue.pop_front();
-            }
+    // request is taken only if it is the oldest one on each of its pages, with chaos any
+    // such request may be taken instead of the first one found
+    pub fn start_processing(&mut self, chaos: &Chaos) -> Option<DiskRequest> {
+        let mut ready = self.queues.iter().filter_map(|(page_id, queue)| {
+            let (seq, disk_request) = queue.front()?;
+            iter_page_ids(disk_request.page_ids())
+                .all(|page_id| self.page_order[&page_id].front() == Some(seq))
+                .then_some((disk_request.enqueued_at, *page_id))
+        });
+        let page_id = if chaos.is_enabled() {
+            let mut ready = ready.collect::<Vec<_>>();
+            ready.sort_unstable();
+            ready.get(chaos.pick(ready.len()))?.1
+        } else {
+            ready.next()?.1
+        };
+
+        let queue = self.queues.get_mut(&page_id)?;
+        let (_, disk_request) = queue.pop_front()?;
+        if queue.is_empty() {
+            self.queues.remove(&page_id);
         }
-        None
+
+        Some(disk_request)
     }
 
-    pub fn end_processing(&mut self, page_id: &PageId) {
-        self.in_processing_ids.remove(page_id);
-        if let Some(queue) = self.queues.get_mut(page_id) {
-            if queue.is_empty() {
-                self.queues.remove(page_id);
+    pub fn end_processing(&mut self, page_ids: Range<PageId>) {
+        // processed request is the oldest one on each of its pages
+        for page_id in iter_page_ids(page_ids) {
+            if let Some(order) = self.page_order.get_mut(&page_id) {
+                order.pop_front();
+                if order.is_empty() {
+                    self.page_order.remove(&page_id);
+                }
             }
         }
     }
@@ -64,40 +337,138 @@ struct Worker {
 
 impl Worker {
     fn new(
-        id: usize,
-        queue: Arc<Mutex<DiskRequestQueue>>,
+        queue: Arc<(Mutex<DiskRequestQueue>, Condvar)>,
         disk_manager: Arc<DiskManager>,
         stop_flag: Arc<AtomicBool>,
+        slow_request_threshold: Arc<AtomicU64>,
+        counters: Arc<SchedulerCounters>,
+        read_ahead: Arc<ReadAhead>,
+        chaos: Arc<Chaos>,
     ) -> Self {
-        let queue = Arc::clone(&queue);
+        let batch_size = disk_manager.max_batch_size();
+        let sync_period = match disk_manager.durability_policy() {
+            DurabilityPolicy::Periodic(period) => Some(period),
+            _ => None,
+        };
         let thread = thread::spawn(move || {
-            let queue = Arc::clone(&queue);
-            while !stop_flag.load(Ordering::Relaxed) {
+            let (queue, has_requests) = &*queue;
+            loop {
                 let mut pop_queue = queue.lock();
-                let disk_request = pop_queue.start_processing();
+                let disk_requests = loop {
+                    let disk_requests = iter::from_fn(|| pop_queue.start_processing(&chaos))
+                        .take(batch_size)
+                        .collect::<Vec<_>>();
+                    // queued requests are served before worker stops
+                    if !disk_requests.is_empty() {
+                        break disk_requests;
+                    }
+                    if stop_flag.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    let Some(sync_period) = sync_period else {
+                        has_requests.wait(&mut pop_queue);
+                        continue;
+                    };
+                    // writes made before disk went idle are synced once period passes
+                    if has_requests
+                        .wait_for(&mut pop_queue, sync_period)
+                        .timed_out()
+                    {
+                        MutexGuard::unlocked(&mut pop_queue, || {
+                            if let Err(error) = disk_manager.sync_if_due() {
+                                tracing::warn!(%error, "periodic sync failed");
+                            }
+                        });
+                    }
+                };
                 drop(pop_queue);
-                if let Some(disk_request) = disk_request {
-                    let page_id = disk_request.page.0;
-                    println!(
-                        "start processing page {} with write {:?}",
-                        &page_id, &disk_request.is_write
-                    );
-                    let page_data = &disk_request.page.1;
+                thread::sleep(chaos.delay());
 
-                    if disk_request.is_write {
-                        disk_manager.write_page(page_data);
-                    } else {
-                        disk_manager.read_page(page_data);
+                let started_at = Instant::now();
+                let batch = disk_requests
+                    .iter()
+                    .map(DiskRequest::page_io)
+                    .collect::<Vec<_>>();
+                let mut results = read_ahead.serve_batch(&disk_manager, &counters, &batch);
+                drop(batch);
+                for disk_request in disk_requests.iter().filter(|request| request.is_write()) {
+                    read_ahead.invalidate(disk_request.page_ids());
+                }
+                let written = disk_requests
+                    .iter()
+                    .zip(&results)
+                    .filter(|(disk_request, result)| disk_request.is_write() && result.is_ok())
+                    .count();
+                if written > 0 {
+                    // writes aren't as durable as policy promises, so they fail
+                    if let Err(error) = disk_manager.note_writes(written) {
+                        for (disk_request, result) in disk_requests.iter().zip(&mut results) {
+                            if disk_request.is_write() && result.is_ok() {
+                                *result = Err(anyhow!("Can't sync written page: {error:#}."));
+                            }
+                        }
                     }
-                    println!(
-                        "end processing page {} with write {:?}",
-                        &page_id, &disk_request.is_write
-                    );
+                }
+                let service_time = started_at.elapsed();
+
+                let mut page_ids = Vec::with_capacity(disk_requests.len());
+                for (disk_request, result) in disk_requests.into_iter().zip(results) {
+                    let page_id = disk_request.page_id;
+                    page_ids.push(disk_request.page_ids());
+                    let (operation, bytes) = match disk_request.kind {
+                        DiskRequestKind::Read { callback } => {
+                            let bytes = result.as_ref().map_or(0, |data| data.len());
+                            callback.complete(result, &chaos, &counters);
+
+                            ("read", bytes)
+                        }
+                        DiskRequestKind::Prefetch { callback } => {
+                            let bytes = result.as_ref().map_or(0, |data| data.len());
+                            callback.complete(result, &chaos, &counters);
+
+                            ("prefetch", bytes)
+                        }
+                        DiskRequestKind::Write { data, callback } => {
+                            callback.complete(result.map(|_| ()), &chaos, &counters);
 
-                    disk_request.callback_sender.send(Ok(())).unwrap();
-                    let mut end_queue = queue.lock();
-                    end_queue.end_processing(&page_id);
+                            ("write", data.len())
+                        }
+                        DiskRequestKind::WriteRun { data, callback } => {
+                            callback.complete(result.map(|_| ()), &chaos, &counters);
+
+                            ("write", data.len())
+                        }
+                    };
+                    let bytes_counter = match operation {
+                        "write" => &counters.bytes_written,
+                        _ => &counters.bytes_read,
+                    };
+                    bytes_counter.add(bytes as u64);
+
+                    let queue_wait = started_at - disk_request.enqueued_at;
+                    disk_manager.note_queue_wait(queue_wait);
+                    let threshold =
+                        Duration::from_micros(slow_request_threshold.load(Ordering::Relaxed));
+                    if queue_wait.max(service_time) > threshold {
+                        counters.slow_requests.increment();
+                        tracing::warn!(
+                            %page_id,
+                            operation,
+                            bytes,
+                            queue_wait_ms = queue_wait.as_millis() as u64,
+                            service_time_ms = service_time.as_millis() as u64,
+                            "slow disk request"
+                        );
+                    }
                 }
+
+                let mut end_queue = queue.lock();
+                for page_ids in page_ids {
+                    end_queue.end_processing(page_ids);
+                }
+                drop(end_queue);
+                // other requests for the same page could wait for this one to finish
+                has_requests.notify_all();
             }
         });
         Self { thread }
@@ -106,88 +477,383 @@ impl Worker {
 
 #[derive(Debug)]
 struct WorkerPool {
-    workers: Vec<Worker>,
-    queue: Arc<Mutex<DiskRequestQueue>>,
+    workers: Mutex<Vec<Worker>>,
+    queue: Arc<(Mutex<DiskRequestQueue>, Condvar)>,
     stop_flag: Arc<AtomicBool>,
 }
 
 impl WorkerPool {
-    fn new(size: usize, disk_manager: DiskManager) -> Self {
-        let queue: Arc<Mutex<DiskRequestQueue>> = Arc::new(Mutex::new(DiskRequestQueue::new()));
-        let disk_manager = Arc::new(disk_manager);
+    fn new(
+        size: usize,
+        disk_manager: Arc<DiskManager>,
+        slow_request_threshold: Arc<AtomicU64>,
+        counters: Arc<SchedulerCounters>,
+        read_ahead: Arc<ReadAhead>,
+        chaos: Arc<Chaos>,
+    ) -> Self {
+        let queue = Arc::new((Mutex::new(DiskRequestQueue::new()), Condvar::new()));
         let mut workers = Vec::with_capacity(size);
         let stop_flag = Arc::new(AtomicBool::new(false));
 
-        for id in 0..size {
+        for _ in 0..size {
             let queue = Arc::clone(&queue);
             let disk_manager = Arc::clone(&disk_manager);
             let stop_flag = Arc::clone(&stop_flag);
-            workers.push(Worker::new(id, queue, disk_manager, stop_flag));
+            let slow_request_threshold = Arc::clone(&slow_request_threshold);
+            let counters = Arc::clone(&counters);
+            let read_ahead = Arc::clone(&read_ahead);
+            let chaos = Arc::clone(&chaos);
+            workers.push(Worker::new(
+                queue,
+                disk_manager,
+                stop_flag,
+                slow_request_threshold,
+                counters,
+                read_ahead,
+                chaos,
+            ));
         }
         Self {
-            workers,
+            workers: Mutex::new(workers),
             queue,
             stop_flag,
         }
     }
 
+    // request dropped after shutdown closes its callback channel, so caller gets error
+    // instead of waiting forever
     fn execute(&self, disk_request: DiskRequest) {
-        let mut queue = self.queue.lock();
+        let (queue, has_requests) = &*self.queue;
+        let mut queue = queue.lock();
+        if self.stop_flag.load(Ordering::Relaxed) {
+            return;
+        }
         queue.push(disk_request);
+        has_requests.notify_one();
     }
-}
 
-impl Drop for WorkerPool {
-    fn drop(&mut self) {
+    fn execute_all(&self, disk_requests: Vec<DiskRequest>) {
+        let (queue, has_requests) = &*self.queue;
+        let mut queue = queue.lock();
+        if self.stop_flag.load(Ordering::Relaxed) {
+            return;
+        }
+        for disk_request in disk_requests {
+            queue.push(disk_request);
+        }
+        has_requests.notify_all();
+    }
+
+    fn shutdown(&self) {
+        let (queue, has_requests) = &*self.queue;
+        // flag is set under queue lock, so no worker misses wake up between check and wait
+        let queue = queue.lock();
         self.stop_flag.store(true, Ordering::Relaxed);
-        for worker in mem::take(&mut self.workers) {
+        has_requests.notify_all();
+        drop(queue);
+        for worker in mem::take(&mut *self.workers.lock()) {
             worker.thread.join().unwrap();
         }
     }
 }
 
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[derive(Debug)]
+enum DiskRequestKind {
+    Read {
+        callback: Completion<Vec<u8>>,
+    },
+    // read nobody waits for yet, its result may never be received
+    Prefetch {
+        callback: Completion<Vec<u8>>,
+    },
+    Write {
+        data: Arc<Vec<u8>>,
+        callback: Completion<()>,
+    },
+    // consecutive pages starting at request page written at once
+    WriteRun {
+        data: Vec<u8>,
+        callback: Completion<()>,
+    },
+}
+
 #[derive(Debug)]
 struct DiskRequest {
-    is_write: bool,
-    page: Arc<(PageId, Vec<u8>)>,
-    callback_sender: Sender<Result<()>>,
+    page_id: PageId,
+    kind: DiskRequestKind,
+    enqueued_at: Instant,
+}
+
+impl DiskRequest {
+    fn page_io(&self) -> PageIo<'_> {
+        match &self.kind {
+            DiskRequestKind::Read { .. } | DiskRequestKind::Prefetch { .. } => {
+                PageIo::Read(self.page_id)
+            }
+            DiskRequestKind::Write { data, .. } => PageIo::Write(self.page_id, data),
+            DiskRequestKind::WriteRun { data, .. } => PageIo::Write(self.page_id, data),
+        }
+    }
+
+    fn is_write(&self) -> bool {
+        matches!(
+            self.kind,
+            DiskRequestKind::Write { .. } | DiskRequestKind::WriteRun { .. }
+        )
+    }
+
+    fn page_ids(&self) -> Range<PageId> {
+        match &self.kind {
+            DiskRequestKind::WriteRun { data, .. } => {
+                self.page_id..self.page_id + data.len().div_ceil(PAGE_SIZE).max(1)
+            }
+            _ => self.page_id..self.page_id + 1,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct DiskScheduler {
     pool: WorkerPool,
+    disk_manager: Arc<DiskManager>,
+    // in microseconds
+    slow_request_threshold: Arc<AtomicU64>,
+    counters: Arc<SchedulerCounters>,
+    read_ahead: Arc<ReadAhead>,
+    chaos: Arc<Chaos>,
 }
 
 impl DiskScheduler {
     pub fn new(disk_manager: DiskManager) -> Self {
-        let pool = WorkerPool::new(4, disk_manager);
+        let disk_manager = Arc::new(disk_manager);
+        let slow_request_threshold = Arc::new(AtomicU64::new(
+            DEFAULT_SLOW_REQUEST_THRESHOLD.as_micros() as u64,
+        ));
+        let counters = Arc::new(SchedulerCounters::default());
+        let read_ahead = Arc::new(ReadAhead::new(DEFAULT_READ_AHEAD_PAGES));
+        let chaos = Arc::new(Chaos::default());
+        // storage serving batches keeps requests in flight from a single thread
+        let workers = match disk_manager.max_batch_size() {
+            1 => 4,
+            _ => 1,
+        };
+        let pool = WorkerPool::new(
+            workers,
+            Arc::clone(&disk_manager),
+            Arc::clone(&slow_request_threshold),
+            Arc::clone(&counters),
+            Arc::clone(&read_ahead),
+            Arc::clone(&chaos),
+        );
+
+        Self {
+            pool,
+            disk_manager,
+            slow_request_threshold,
+            counters,
+            read_ahead,
+            chaos,
+        }
+    }
+
+    /// Log requests which wait in queue or are served longer than threshold,
+    /// `None` turns logging off
+    pub fn set_slow_request_threshold(&self, threshold: Option<Duration>) {
+        let threshold = threshold.map_or(u64::MAX, |threshold| threshold.as_micros() as u64);
+        self.slow_request_threshold
+            .store(threshold, Ordering::Relaxed);
+    }
+
+    /// Pages read at once when reads go through consecutive pages, 0 turns read ahead off
+    pub fn set_read_ahead_pages(&self, pages: usize) {
+        self.read_ahead.pages.store(pages, Ordering::Relaxed);
+    }
+
+    /// Reorder, delay and duplicate completions of requests as configured, `None` turns
+    /// chaos off. Callers get every result once in any case.
+    pub fn set_chaos(&self, config: Option<ChaosConfig>) -> Result<()> {
+        self.chaos.set(config)
+    }
+
+    /// Drop pages read ahead, they may be stale if another process writes data file
+    pub fn clear_read_ahead(&self) {
+        self.read_ahead.clear();
+    }
 
-        Self { pool }
+    pub fn disk_manager(&self) -> &Arc<DiskManager> {
+        &self.disk_manager
     }
 
-    pub fn schedule_read(&self, page: Arc<(PageId, Vec<u8>)>, callback_sender: Sender<Result<()>>) {
+    pub(crate) fn counters(&self) -> &SchedulerCounters {
+        &self.counters
+    }
+
+    pub fn schedule_read(&self, page_id: PageId, callback_sender: Sender<Result<Vec<u8>>>) {
+        self.pool.execute(DiskRequest {
+            page_id,
+            kind: DiskRequestKind::Read {
+                callback: Completion::new(callback_sender),
+            },
+            enqueued_at: Instant::now(),
+        });
+    }
+
+    /// Read page ahead of its use, request is served like read and reported as prefetch
+    pub fn schedule_prefetch(&self, page_id: PageId, callback_sender: Sender<Result<Vec<u8>>>) {
         self.pool.execute(DiskRequest {
-            is_write: false,
-            page,
-            callback_sender,
+            page_id,
+            kind: DiskRequestKind::Prefetch {
+                callback: Completion::new(callback_sender),
+            },
+            enqueued_at: Instant::now(),
         });
     }
 
     pub fn schedule_write(
         &self,
-        page: Arc<(PageId, Vec<u8>)>,
+        page_id: PageId,
+        data: Arc<Vec<u8>>,
         callback_sender: Sender<Result<()>>,
     ) {
         self.pool.execute(DiskRequest {
-            is_write: true,
-            page,
-            callback_sender,
+            page_id,
+            kind: DiskRequestKind::Write {
+                data,
+                callback: Completion::new(callback_sender),
+            },
+            enqueued_at: Instant::now(),
         });
     }
+
+    /// Write runs of consecutive pages, each run given by its first page id and data of
+    /// whole pages is written at once. Runs are queued together and result of every run
+    /// is sent to `callback_sender`.
+    pub fn schedule_write_batch(
+        &self,
+        runs: Vec<(PageId, Vec<u8>)>,
+        callback_sender: Sender<Result<()>>,
+    ) {
+        let enqueued_at = Instant::now();
+        let disk_requests = runs
+            .into_iter()
+            .map(|(page_id, data)| DiskRequest {
+                page_id,
+                kind: DiskRequestKind::WriteRun {
+                    data,
+                    callback: Completion::new(callback_sender.clone()),
+                },
+                enqueued_at,
+            })
+            .collect();
+
+        self.pool.execute_all(disk_requests);
+    }
+
+    /// Flush completed writes to durable storage
+    pub fn sync(&self) -> Result<()> {
+        self.disk_manager.sync()
+    }
+
+    /// Serve requests queued so far and join workers, requests scheduled afterwards fail
+    pub fn shutdown(&self) {
+        self.pool.shutdown();
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+    use crate::buffer_pool_manager::BufferPoolManager;
+    use crate::disk_manager::DiskLatencyProfile;
+
+    #[test]
+    fn test_read_sees_write_queued_before_it() {
+        let mut disk_manager = DiskManager::new();
+        disk_manager.set_latency_profile(DiskLatencyProfile::NONE);
+        let scheduler = DiskScheduler::new(disk_manager);
+        scheduler
+            .set_chaos(Some(ChaosConfig {
+                reorder_probability: 1.0,
+                max_delay: Duration::from_micros(100),
+                duplicate_probability: 0.0,
+            }))
+            .unwrap();
+
+        let scheduler = &scheduler;
+        for round in 0..50u8 {
+            let first_page_id = PageId::new(round as usize % 4 * 3);
+            let (scheduled_sender, scheduled_receiver) = mpsc::channel();
+            thread::scope(|scope| {
+                scope.spawn(move || {
+                    let (write_sender, write_receiver) = mpsc::channel();
+                    scheduler.schedule_write_batch(
+                        vec![(first_page_id, vec![round; 3 * PAGE_SIZE])],
+                        write_sender.clone(),
+                    );
+                    scheduler.schedule_write(
+                        first_page_id + 3,
+                        Arc::new(vec![round; PAGE_SIZE]),
+                        write_sender,
+                    );
+                    // reads are scheduled by another thread once writes are queued
+                    scheduled_sender.send(()).unwrap();
+                    for result in write_receiver.iter().take(2) {
+                        result.unwrap();
+                    }
+                });
+                scope.spawn(move || {
+                    scheduled_receiver.recv().unwrap();
+                    let (read_sender, read_receiver) = mpsc::channel();
+                    for page_id in iter_page_ids(first_page_id + 1..first_page_id + 4) {
+                        scheduler.schedule_read(page_id, read_sender.clone());
+                    }
+                    for data in read_receiver.iter().take(3) {
+                        assert!(data.unwrap().iter().all(|byte| *byte == round));
+                    }
+                });
+            });
+        }
+    }
+
+    #[test]
+    fn test_slow_requests_are_counted() {
+        let mut disk_manager = DiskManager::new();
+        disk_manager.set_latency_profile(DiskLatencyProfile {
+            read: Duration::from_millis(5),
+            write: Duration::from_millis(5),
+        });
+        let buffer_pool_manager = BufferPoolManager::new(disk_manager, 2, 2);
+        let slow_requests = || buffer_pool_manager.stats().unwrap().slow_disk_requests;
+        let write_page = |page_id| {
+            buffer_pool_manager.fetch_page_write(page_id).unwrap()[0] += 1;
+            buffer_pool_manager.flush_page(page_id).unwrap();
+        };
+        let (page_id, page) = buffer_pool_manager.new_page().unwrap();
+        drop(page);
+
+        // request is counted after its result is delivered
+        buffer_pool_manager.set_slow_io_threshold(Some(Duration::from_micros(100)));
+        write_page(page_id);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while slow_requests() == 0 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(slow_requests(), 1);
+
+        buffer_pool_manager.set_slow_io_threshold(None);
+        write_page(page_id);
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(slow_requests(), 1);
+    }
+
     //use std::{
     //    sync::{mpsc, RwLock},
     //    thread::JoinHandle,
@@ -0,0 +1,92 @@
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use anyhow::Result;
+use parking_lot::Mutex;
+
+use crate::disk_manager::open_data_file;
+use crate::page::{PageId, PAGE_SIZE};
+
+// little endian page id and crc32 of page id and data, 4 reserved bytes, then page data
+const ENTRY_HEADER_SIZE: usize = 16;
+const ENTRY_SIZE: usize = ENTRY_HEADER_SIZE + PAGE_SIZE;
+
+/// Scratch file pages are appended to and synced before they are written in place, so
+/// page torn by crash in the middle of in-place write is restored from its copy on open.
+/// Copies are dropped once data file is synced.
+#[derive(Debug)]
+pub(crate) struct DoubleWriteBuffer {
+    file: Mutex<File>,
+}
+
+impl DoubleWriteBuffer {
+    pub fn open(path: &Path) -> Result<Self> {
+        Ok(Self {
+            file: Mutex::new(open_data_file(path)?),
+        })
+    }
+
+    /// Append copies of consecutive whole pages and wait until they are durable
+    pub fn stage(&self, first_page_id: PageId, data: &[u8]) -> Result<()> {
+        let mut entries = Vec::with_capacity(data.len() / PAGE_SIZE * ENTRY_SIZE);
+        for (index, page) in data.chunks(PAGE_SIZE).enumerate() {
+            let page_id = (first_page_id + index).as_usize() as u64;
+            entries.extend_from_slice(&page_id.to_le_bytes());
+            entries.extend_from_slice(&entry_checksum(page_id, page).to_le_bytes());
+            entries.extend_from_slice(&[0; 4]);
+            entries.extend_from_slice(page);
+        }
+
+        let mut file = self.file.lock();
+        file.seek(SeekFrom::End(0))?;
+        file.write_all(&entries)?;
+        file.sync_data()?;
+
+        Ok(())
+    }
+
+    /// Hand every complete copy to `restore` in the order pages were staged, copy torn by
+    /// crash was never written in place and ends the buffer. Returns number of copies.
+    pub fn recover(&self, mut restore: impl FnMut(PageId, &[u8]) -> Result<()>) -> Result<usize> {
+        let mut entries = vec![];
+        {
+            let mut file = self.file.lock();
+            file.seek(SeekFrom::Start(0))?;
+            file.read_to_end(&mut entries)?;
+        }
+
+        let mut restored = 0;
+        for entry in entries.chunks_exact(ENTRY_SIZE) {
+            let (header, page) = entry.split_at(ENTRY_HEADER_SIZE);
+            let page_id = u64::from_le_bytes(header[0..8].try_into().unwrap());
+            let checksum = u32::from_le_bytes(header[8..12].try_into().unwrap());
+            if entry_checksum(page_id, page) != checksum {
+                break;
+            }
+            restore(PageId::new(page_id as usize), page)?;
+            restored += 1;
+        }
+
+        Ok(restored)
+    }
+
+    /// Drop copies, pages they hold must be durable in data file
+    pub fn clear(&self) -> Result<()> {
+        let file = self.file.lock();
+        file.set_len(0)?;
+        file.sync_all()?;
+
+        Ok(())
+    }
+}
+
+fn entry_checksum(page_id: u64, page: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&page_id.to_le_bytes());
+    hasher.update(page);
+
+    hasher.finalize()
+}
@@ -0,0 +1,377 @@
+//! C API of the key-value store, see `include/cmu_db.h`.
+//!
+//! Keys are UTF-8 strings and values are arbitrary bytes, both passed as pointer and length.
+//! Functions return `CMUDB_OK` or error code, message of the last error on calling thread
+//! is available through `cmudb_last_error`. Panic inside a call is returned as
+//! `CMUDB_ERROR`.
+
+use std::{
+    cell::RefCell,
+    ffi::{c_char, c_int, CStr, CString},
+    panic::{self, AssertUnwindSafe},
+    ptr, slice,
+};
+
+use anyhow::Result;
+
+use crate::kv::Kv;
+
+pub const CMUDB_OK: c_int = 0;
+/// Key is absent
+pub const CMUDB_NOT_FOUND: c_int = 1;
+/// Iterator has no more entries
+pub const CMUDB_DONE: c_int = 2;
+pub const CMUDB_INVALID_ARGUMENT: c_int = -1;
+pub const CMUDB_ERROR: c_int = -2;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Opaque handle of opened store
+pub struct CmuDb {
+    kv: Kv<String, Vec<u8>>,
+}
+
+/// Opaque handle of iterator over entries
+pub struct CmuDbIter {
+    entries: Vec<(String, Vec<u8>)>,
+    position: usize,
+}
+
+fn set_last_error(error: impl ToString) {
+    let message = CString::new(error.to_string().replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+}
+
+// panic must not unwind into C, it is reported like error and fallback is returned
+fn catch_panic<T>(fallback: T, body: impl FnOnce() -> T) -> T {
+    match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(result) => result,
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            set_last_error(format!("Panicked: {}", message));
+            fallback
+        }
+    }
+}
+
+fn result_code(result: Result<c_int>) -> c_int {
+    match result {
+        Ok(code) => code,
+        Err(error) => {
+            set_last_error(&error);
+            CMUDB_ERROR
+        }
+    }
+}
+
+unsafe fn bytes<'a>(data: *const u8, len: usize) -> Option<&'a [u8]> {
+    if data.is_null() {
+        return (len == 0).then_some(&[]);
+    }
+
+    Some(slice::from_raw_parts(data, len))
+}
+
+unsafe fn key(key: *const u8, key_len: usize) -> Option<String> {
+    let key = bytes(key, key_len)?;
+
+    String::from_utf8(key.to_vec()).ok()
+}
+
+fn invalid_argument(message: &str) -> c_int {
+    set_last_error(message);
+    CMUDB_INVALID_ARGUMENT
+}
+
+// hands buffer over to caller, it is freed by `cmudb_free`
+unsafe fn into_raw_buffer(data: Vec<u8>, out: *mut *mut u8, out_len: *mut usize) {
+    let data = data.into_boxed_slice();
+    *out_len = data.len();
+    *out = Box::into_raw(data) as *mut u8;
+}
+
+/// Message of the last error on calling thread, NULL if there was none.
+/// String is valid until the next failed call on the same thread
+#[no_mangle]
+pub extern "C" fn cmudb_last_error() -> *const c_char {
+    catch_panic(ptr::null(), || {
+        LAST_ERROR.with(|last_error| {
+            last_error
+                .borrow()
+                .as_ref()
+                .map_or(ptr::null(), |message| message.as_ptr())
+        })
+    })
+}
+
+/// Open store at path, data file is created if it doesn't exist
+///
+/// # Safety
+/// `path` is NUL terminated string, `db` is valid pointer to write handle to
+#[no_mangle]
+pub unsafe extern "C" fn cmudb_open(path: *const c_char, db: *mut *mut CmuDb) -> c_int {
+    catch_panic(CMUDB_ERROR, || {
+        if path.is_null() || db.is_null() {
+            return invalid_argument("Path and handle must not be NULL.");
+        }
+        let Ok(path) = CStr::from_ptr(path).to_str() else {
+            return invalid_argument("Path is not valid UTF-8.");
+        };
+
+        result_code(Kv::open(path).map(|kv| {
+            *db = Box::into_raw(Box::new(CmuDb { kv }));
+            CMUDB_OK
+        }))
+    })
+}
+
+/// Flush and close store, NULL is ignored
+///
+/// # Safety
+/// `db` is handle returned by `cmudb_open` which is not closed yet
+#[no_mangle]
+pub unsafe extern "C" fn cmudb_close(db: *mut CmuDb) -> c_int {
+    catch_panic(CMUDB_ERROR, || {
+        if db.is_null() {
+            return CMUDB_OK;
+        }
+        let db = Box::from_raw(db);
+
+        result_code(db.kv.flush().map(|_| CMUDB_OK))
+    })
+}
+
+/// Read value of key, returns `CMUDB_NOT_FOUND` if key is absent.
+/// Value buffer is owned by caller and released by `cmudb_free`
+///
+/// # Safety
+/// `db` is open handle, `key` points to `key_len` bytes,
+/// `value` and `value_len` are valid pointers
+#[no_mangle]
+pub unsafe extern "C" fn cmudb_get(
+    db: *const CmuDb,
+    key: *const u8,
+    key_len: usize,
+    value: *mut *mut u8,
+    value_len: *mut usize,
+) -> c_int {
+    catch_panic(CMUDB_ERROR, || {
+        if db.is_null() || value.is_null() || value_len.is_null() {
+            return invalid_argument("Handle and output pointers must not be NULL.");
+        }
+        let Some(key) = self::key(key, key_len) else {
+            return invalid_argument("Key is not valid UTF-8.");
+        };
+
+        result_code((*db).kv.get(key).map(|data| match data {
+            Some(data) => {
+                into_raw_buffer(data, value, value_len);
+                CMUDB_OK
+            }
+            None => CMUDB_NOT_FOUND,
+        }))
+    })
+}
+
+/// Insert or replace value of key
+///
+/// # Safety
+/// `db` is open handle, `key` and `value` point to `key_len` and `value_len` bytes
+#[no_mangle]
+pub unsafe extern "C" fn cmudb_put(
+    db: *const CmuDb,
+    key: *const u8,
+    key_len: usize,
+    value: *const u8,
+    value_len: usize,
+) -> c_int {
+    catch_panic(CMUDB_ERROR, || {
+        if db.is_null() {
+            return invalid_argument("Handle must not be NULL.");
+        }
+        let Some(key) = self::key(key, key_len) else {
+            return invalid_argument("Key is not valid UTF-8.");
+        };
+        let Some(value) = bytes(value, value_len) else {
+            return invalid_argument("Value must not be NULL.");
+        };
+
+        result_code((*db).kv.insert(key, value.to_vec()).map(|_| CMUDB_OK))
+    })
+}
+
+/// Remove key, returns `CMUDB_NOT_FOUND` if key is absent
+///
+/// # Safety
+/// `db` is open handle, `key` points to `key_len` bytes
+#[no_mangle]
+pub unsafe extern "C" fn cmudb_delete(db: *const CmuDb, key: *const u8, key_len: usize) -> c_int {
+    catch_panic(CMUDB_ERROR, || {
+        if db.is_null() {
+            return invalid_argument("Handle must not be NULL.");
+        }
+        let Some(key) = self::key(key, key_len) else {
+            return invalid_argument("Key is not valid UTF-8.");
+        };
+
+        result_code((*db).kv.remove(key).map(|value| match value {
+            Some(_) => CMUDB_OK,
+            None => CMUDB_NOT_FOUND,
+        }))
+    })
+}
+
+/// Release buffer returned by `cmudb_get`
+///
+/// # Safety
+/// `data` and `len` are exactly as returned by `cmudb_get`, buffer is not freed yet
+#[no_mangle]
+pub unsafe extern "C" fn cmudb_free(data: *mut u8, len: usize) {
+    catch_panic((), || {
+        if !data.is_null() {
+            drop(Box::from_raw(ptr::slice_from_raw_parts_mut(data, len)));
+        }
+    })
+}
+
+/// Start iteration over entries as of this moment, entries come in no particular order
+///
+/// # Safety
+/// `db` is open handle, `iter` is valid pointer to write handle to
+#[no_mangle]
+pub unsafe extern "C" fn cmudb_iter_new(db: *const CmuDb, iter: *mut *mut CmuDbIter) -> c_int {
+    catch_panic(CMUDB_ERROR, || {
+        if db.is_null() || iter.is_null() {
+            return invalid_argument("Handles must not be NULL.");
+        }
+
+        result_code((*db).kv.snapshot().scan().map(|entries| {
+            *iter = Box::into_raw(Box::new(CmuDbIter {
+                entries,
+                position: 0,
+            }));
+            CMUDB_OK
+        }))
+    })
+}
+
+/// Move to the next entry, returns `CMUDB_DONE` when there are no more entries.
+/// Key and value stay owned by iterator and are valid until the next call
+///
+/// # Safety
+/// `iter` is handle returned by `cmudb_iter_new`, output pointers are valid
+#[no_mangle]
+pub unsafe extern "C" fn cmudb_iter_next(
+    iter: *mut CmuDbIter,
+    key: *mut *const u8,
+    key_len: *mut usize,
+    value: *mut *const u8,
+    value_len: *mut usize,
+) -> c_int {
+    catch_panic(CMUDB_ERROR, || {
+        if iter.is_null()
+            || key.is_null()
+            || key_len.is_null()
+            || value.is_null()
+            || value_len.is_null()
+        {
+            return invalid_argument("Handle and output pointers must not be NULL.");
+        }
+        let iter = &mut *iter;
+        let Some((entry_key, entry_value)) = iter.entries.get(iter.position) else {
+            return CMUDB_DONE;
+        };
+        iter.position += 1;
+
+        *key = entry_key.as_ptr();
+        *key_len = entry_key.len();
+        *value = entry_value.as_ptr();
+        *value_len = entry_value.len();
+
+        CMUDB_OK
+    })
+}
+
+/// Release iterator, NULL is ignored
+///
+/// # Safety
+/// `iter` is handle returned by `cmudb_iter_new` which is not freed yet
+#[no_mangle]
+pub unsafe extern "C" fn cmudb_iter_free(iter: *mut CmuDbIter) {
+    catch_panic((), || {
+        if !iter.is_null() {
+            drop(Box::from_raw(iter));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_c_api() {
+        let dir = TempDir::new().unwrap();
+        let path = CString::new(dir.path().join("test.db").to_str().unwrap()).unwrap();
+        let mut db = ptr::null_mut();
+
+        unsafe {
+            assert_eq!(cmudb_open(path.as_ptr(), &mut db), CMUDB_OK);
+            assert_eq!(
+                cmudb_put(db, b"key".as_ptr(), 3, b"value".as_ptr(), 5),
+                CMUDB_OK
+            );
+
+            let mut value = ptr::null_mut();
+            let mut value_len = 0;
+            assert_eq!(
+                cmudb_get(db, b"key".as_ptr(), 3, &mut value, &mut value_len),
+                CMUDB_OK
+            );
+            assert_eq!(slice::from_raw_parts(value, value_len), b"value");
+            cmudb_free(value, value_len);
+
+            let mut iter = ptr::null_mut();
+            let (mut key, mut key_len) = (ptr::null(), 0);
+            let (mut value, mut value_len) = (ptr::null(), 0);
+            assert_eq!(cmudb_iter_new(db, &mut iter), CMUDB_OK);
+            assert_eq!(
+                cmudb_iter_next(iter, &mut key, &mut key_len, &mut value, &mut value_len),
+                CMUDB_OK
+            );
+            assert_eq!(slice::from_raw_parts(key, key_len), b"key");
+            assert_eq!(
+                cmudb_iter_next(iter, &mut key, &mut key_len, &mut value, &mut value_len),
+                CMUDB_DONE
+            );
+            cmudb_iter_free(iter);
+
+            assert_eq!(cmudb_delete(db, b"key".as_ptr(), 3), CMUDB_OK);
+            assert_eq!(cmudb_delete(db, b"key".as_ptr(), 3), CMUDB_NOT_FOUND);
+            assert_eq!(
+                cmudb_put(db, [0xff].as_ptr(), 1, ptr::null(), 0),
+                CMUDB_INVALID_ARGUMENT
+            );
+            assert!(!cmudb_last_error().is_null());
+            assert_eq!(cmudb_close(db), CMUDB_OK);
+        }
+    }
+
+    #[test]
+    fn test_panic_is_returned_as_error() {
+        assert_eq!(
+            catch_panic(CMUDB_ERROR, || panic!("broken page")),
+            CMUDB_ERROR
+        );
+        let message = unsafe { CStr::from_ptr(cmudb_last_error()) };
+        assert_eq!(message.to_str().unwrap(), "Panicked: broken page");
+    }
+}
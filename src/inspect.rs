@@ -0,0 +1,302 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+};
+
+use anyhow::Result;
+
+use crate::{
+    disk_manager::DiskManager,
+    page::PageId,
+    storage::{
+        extendible_hash_table::{
+            extendible_hash_table_bucket_page::decode_occupancy,
+            extendible_hash_table_directory_page::ExtendibleHTableDirectoryPage,
+            extendible_hash_table_header_page::ExtendibleHTableHeaderPage,
+        },
+        metadata_page::{MetadataPage, METADATA_PAGE_ID},
+    },
+};
+
+/// What page holds, found by walking hash tables from catalog on metadata page
+#[derive(Debug, Clone, PartialEq)]
+pub enum PageKind {
+    Metadata {
+        hash_tables: Vec<(String, PageId)>,
+    },
+    Header {
+        hash_table: String,
+        directories: usize,
+    },
+    Directory {
+        hash_table: String,
+        global_depth: u32,
+        local_depths: Vec<u32>,
+    },
+    Bucket {
+        hash_table: String,
+        local_depth: u32,
+        len: usize,
+        max_size: usize,
+    },
+    KeyDictionary {
+        hash_table: String,
+    },
+    /// Page is referenced, but its data can't be decoded
+    Corrupted {
+        hash_table: String,
+        expected: &'static str,
+    },
+    /// Page is not referenced and is on free list or contains only zeroes
+    Free,
+    /// Page is not referenced, but contains data
+    Unreferenced,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageReport {
+    pub page_id: PageId,
+    pub kind: PageKind,
+}
+
+impl fmt::Display for PageReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "page {}: ", self.page_id)?;
+        match &self.kind {
+            PageKind::Metadata { hash_tables } => {
+                write!(f, "metadata, hash tables:")?;
+                for (name, header_page_id) in hash_tables {
+                    write!(f, " {:?} -> {}", name, header_page_id)?;
+                }
+                Ok(())
+            }
+            PageKind::Header {
+                hash_table,
+                directories,
+            } => write!(f, "header of {:?}, {} directories", hash_table, directories),
+            PageKind::Directory {
+                hash_table,
+                global_depth,
+                local_depths,
+            } => write!(
+                f,
+                "directory of {:?}, global depth {}, local depths {:?}",
+                hash_table, global_depth, local_depths
+            ),
+            PageKind::Bucket {
+                hash_table,
+                local_depth,
+                len,
+                max_size,
+            } => write!(
+                f,
+                "bucket of {:?}, local depth {}, {}/{} entries",
+                hash_table, local_depth, len, max_size
+            ),
+            PageKind::KeyDictionary { hash_table } => {
+                write!(f, "key dictionary of {:?}", hash_table)
+            }
+            PageKind::Corrupted {
+                hash_table,
+                expected,
+            } => write!(f, "CORRUPTED {} of {:?}", expected, hash_table),
+            PageKind::Free => write!(f, "free"),
+            PageKind::Unreferenced => write!(f, "unreferenced, not zeroed"),
+        }
+    }
+}
+
+/// Describe every page of disk, pages are read directly without buffer pool,
+/// so disk must not be in use by running database
+pub fn inspect(disk_manager: &DiskManager) -> Result<Vec<PageReport>> {
+    let mut kinds: BTreeMap<PageId, PageKind> = BTreeMap::new();
+
+    let metadata = MetadataPage::from_bytes(&disk_manager.read_page(METADATA_PAGE_ID)?);
+    match metadata {
+        Ok(metadata) => {
+            let hash_tables = metadata
+                .get_names()
+                .into_iter()
+                .filter_map(|name| {
+                    let header_page_id = metadata.get_header_page_id(&name)?;
+                    Some((name, header_page_id))
+                })
+                .collect::<Vec<(String, PageId)>>();
+            kinds.insert(
+                METADATA_PAGE_ID,
+                PageKind::Metadata {
+                    hash_tables: hash_tables.clone(),
+                },
+            );
+
+            for (name, header_page_id) in hash_tables {
+                inspect_hash_table(disk_manager, &name, header_page_id, &mut kinds)?;
+            }
+        }
+        Err(_) => {
+            kinds.insert(
+                METADATA_PAGE_ID,
+                PageKind::Corrupted {
+                    hash_table: String::new(),
+                    expected: "metadata",
+                },
+            );
+        }
+    }
+
+    for page_id in (0..disk_manager.num_pages()?).map(PageId::new) {
+        if kinds.contains_key(&page_id) {
+            continue;
+        }
+        let kind = if disk_manager.is_free_page(page_id)
+            || disk_manager
+                .read_page(page_id)?
+                .iter()
+                .all(|byte| *byte == 0)
+        {
+            PageKind::Free
+        } else {
+            PageKind::Unreferenced
+        };
+        kinds.insert(page_id, kind);
+    }
+
+    Ok(kinds
+        .into_iter()
+        .map(|(page_id, kind)| PageReport { page_id, kind })
+        .collect())
+}
+
+fn inspect_hash_table(
+    disk_manager: &DiskManager,
+    name: &str,
+    header_page_id: PageId,
+    kinds: &mut BTreeMap<PageId, PageKind>,
+) -> Result<()> {
+    let corrupted = |expected| PageKind::Corrupted {
+        hash_table: name.to_string(),
+        expected,
+    };
+
+    let Ok(header) =
+        ExtendibleHTableHeaderPage::from_bytes(&disk_manager.read_page(header_page_id)?)
+    else {
+        kinds.insert(header_page_id, corrupted("header"));
+        return Ok(());
+    };
+    let directory_page_ids = (0..header.get_max_size())
+        .filter_map(|directory_index| header.get_directory_page_id(directory_index).copied())
+        .collect::<Vec<PageId>>();
+    kinds.insert(
+        header_page_id,
+        PageKind::Header {
+            hash_table: name.to_string(),
+            directories: directory_page_ids.len(),
+        },
+    );
+    if let Some(dictionary_page_id) = header.get_key_dictionary_page_id() {
+        kinds
+            .entry(dictionary_page_id)
+            .or_insert(PageKind::KeyDictionary {
+                hash_table: name.to_string(),
+            });
+    }
+
+    for directory_page_id in directory_page_ids {
+        // page referenced twice is reported by its first role
+        if kinds.contains_key(&directory_page_id) {
+            continue;
+        }
+        let Ok(mut directory) =
+            ExtendibleHTableDirectoryPage::from_bytes(&disk_manager.read_page(directory_page_id)?)
+        else {
+            kinds.insert(directory_page_id, corrupted("directory"));
+            continue;
+        };
+
+        // several directory slots point to the same bucket when its local depth is lower
+        let mut buckets = BTreeSet::new();
+        let mut local_depths = vec![];
+        for bucket_index in 0..directory.get_size() {
+            let local_depth = directory.get_local_depth(bucket_index).unwrap_or_default();
+            local_depths.push(local_depth);
+            if let Some(bucket_page_id) = directory.get_bucket_page_id(bucket_index) {
+                buckets.insert((*bucket_page_id, local_depth));
+            }
+        }
+        kinds.insert(
+            directory_page_id,
+            PageKind::Directory {
+                hash_table: name.to_string(),
+                global_depth: directory.get_global_depth(),
+                local_depths,
+            },
+        );
+
+        for (bucket_page_id, local_depth) in buckets {
+            if kinds.contains_key(&bucket_page_id) {
+                continue;
+            }
+            let kind = match decode_occupancy(&disk_manager.read_page(bucket_page_id)?) {
+                Ok((max_size, len)) => PageKind::Bucket {
+                    hash_table: name.to_string(),
+                    local_depth,
+                    len,
+                    max_size,
+                },
+                Err(_) => corrupted("bucket"),
+            };
+            kinds.insert(bucket_page_id, kind);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::db_instance::DbInstance;
+
+    #[test]
+    fn test_inspect() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("test.db");
+        let db = DbInstance::open(&path).unwrap();
+        let hash_table = db.open_hash_table::<String, u32>("table", 4, 2).unwrap();
+        for i in 0..10 {
+            hash_table.insert(format!("key{i}"), i).unwrap();
+        }
+        drop(hash_table);
+        drop(db);
+
+        let reports = inspect(&DiskManager::open(&path).unwrap()).unwrap();
+
+        assert_eq!(
+            reports[0].kind,
+            PageKind::Metadata {
+                hash_tables: vec![("table".to_string(), PageId::new(1))]
+            }
+        );
+        assert!(matches!(
+            reports[1].kind,
+            PageKind::Header { directories: 1, .. }
+        ));
+        // directory is moved to a new page whenever it doubles
+        let directories = reports
+            .iter()
+            .filter(|report| matches!(report.kind, PageKind::Directory { .. }))
+            .count();
+        assert_eq!(directories, 1);
+        let entries = reports
+            .iter()
+            .map(|report| match report.kind {
+                PageKind::Bucket { len, .. } => len,
+                _ => 0,
+            })
+            .sum::<usize>();
+        assert_eq!(entries, 10);
+    }
+}
@@ -0,0 +1,433 @@
+#[cfg(not(target_os = "linux"))]
+compile_error!("io-uring feature is supported on Linux only.");
+
+use std::{
+    borrow::Cow,
+    fs::File,
+    io, mem,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    path::Path,
+    ptr,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use anyhow::{bail, Context, Result};
+use parking_lot::Mutex;
+
+use crate::{
+    disk_manager::{open_data_file, PageIo},
+    page::{PageId, PAGE_SIZE},
+};
+
+/// Operations submitted to the ring at once, disk scheduler hands at most this many
+/// requests to `serve_batch`
+pub(crate) const RING_ENTRIES: usize = 64;
+
+// see linux/io_uring.h, structs mirror kernel ABI and some of their fields are never read
+const IORING_OFF_SQ_RING: libc::off_t = 0;
+const IORING_OFF_CQ_RING: libc::off_t = 0x8000000;
+const IORING_OFF_SQES: libc::off_t = 0x10000000;
+const IORING_ENTER_GETEVENTS: u32 = 1;
+const IORING_OP_READ: u8 = 22;
+const IORING_OP_WRITE: u8 = 23;
+
+#[repr(C)]
+#[allow(dead_code)]
+#[derive(Debug, Default)]
+struct SqRingOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    flags: u32,
+    dropped: u32,
+    array: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[allow(dead_code)]
+#[derive(Debug, Default)]
+struct CqRingOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    overflow: u32,
+    cqes: u32,
+    flags: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[allow(dead_code)]
+#[derive(Debug, Default)]
+struct Params {
+    sq_entries: u32,
+    cq_entries: u32,
+    flags: u32,
+    sq_thread_cpu: u32,
+    sq_thread_idle: u32,
+    features: u32,
+    wq_fd: u32,
+    resv: [u32; 3],
+    sq_off: SqRingOffsets,
+    cq_off: CqRingOffsets,
+}
+
+#[repr(C)]
+#[allow(dead_code)]
+#[derive(Debug, Default, Clone, Copy)]
+struct Sqe {
+    opcode: u8,
+    flags: u8,
+    ioprio: u16,
+    fd: i32,
+    off: u64,
+    addr: u64,
+    len: u32,
+    rw_flags: u32,
+    user_data: u64,
+    buf_index: u16,
+    personality: u16,
+    splice_fd_in: i32,
+    addr3: u64,
+    pad: u64,
+}
+
+#[repr(C)]
+#[allow(dead_code)]
+#[derive(Debug)]
+struct Cqe {
+    user_data: u64,
+    res: i32,
+    flags: u32,
+}
+
+// memory shared with kernel, unmapped on drop
+#[derive(Debug)]
+struct Mapping {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl Mapping {
+    fn new(fd: &OwnedFd, len: usize, offset: libc::off_t) -> io::Result<Self> {
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_POPULATE,
+                fd.as_raw_fd(),
+                offset,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            ptr: ptr.cast(),
+            len,
+        })
+    }
+
+    // offsets come from kernel and stay within mapping
+    fn at<T>(&self, offset: u32) -> *mut T {
+        unsafe { self.ptr.add(offset as usize).cast() }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr.cast(), self.len) };
+    }
+}
+
+// mappings are accessed only through `&mut Ring`, which is behind a mutex
+unsafe impl Send for Mapping {}
+
+#[derive(Debug)]
+struct Ring {
+    fd: OwnedFd,
+    params: Params,
+    sq: Mapping,
+    cq: Mapping,
+    sqes: Mapping,
+}
+
+impl Ring {
+    fn new(entries: u32) -> io::Result<Self> {
+        let mut params = Params::default();
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_io_uring_setup,
+                entries,
+                &mut params as *mut Params,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd as i32) };
+
+        let sq_len = params.sq_off.array as usize + params.sq_entries as usize * 4;
+        let cq_len =
+            params.cq_off.cqes as usize + params.cq_entries as usize * mem::size_of::<Cqe>();
+        let sqes_len = params.sq_entries as usize * mem::size_of::<Sqe>();
+
+        Ok(Self {
+            sq: Mapping::new(&fd, sq_len, IORING_OFF_SQ_RING)?,
+            cq: Mapping::new(&fd, cq_len, IORING_OFF_CQ_RING)?,
+            sqes: Mapping::new(&fd, sqes_len, IORING_OFF_SQES)?,
+            fd,
+            params,
+        })
+    }
+
+    // submit entries and wait until all of them complete, results are in order of entries.
+    // Buffers entries point to must stay alive until it returns.
+    fn submit_and_wait(&mut self, entries: &[Sqe]) -> io::Result<Vec<i32>> {
+        assert!(entries.len() <= self.params.sq_entries as usize);
+        let sq_off = &self.params.sq_off;
+        let cq_off = &self.params.cq_off;
+        let (sq_tail, sq_mask, sq_array) = unsafe {
+            (
+                &*self.sq.at::<AtomicU32>(sq_off.tail),
+                *self.sq.at::<u32>(sq_off.ring_mask),
+                self.sq.at::<u32>(sq_off.array),
+            )
+        };
+        let (cq_head, cq_tail, cq_mask, cqes) = unsafe {
+            (
+                &*self.cq.at::<AtomicU32>(cq_off.head),
+                &*self.cq.at::<AtomicU32>(cq_off.tail),
+                *self.cq.at::<u32>(cq_off.ring_mask),
+                self.cq.at::<Cqe>(cq_off.cqes),
+            )
+        };
+
+        // only this thread moves submission tail
+        let mut tail = sq_tail.load(Ordering::Relaxed);
+        for (index, entry) in entries.iter().enumerate() {
+            let slot = tail & sq_mask;
+            unsafe {
+                self.sqes.at::<Sqe>(0).add(slot as usize).write(Sqe {
+                    user_data: index as u64,
+                    ..*entry
+                });
+                sq_array.add(slot as usize).write(slot);
+            }
+            tail = tail.wrapping_add(1);
+        }
+        sq_tail.store(tail, Ordering::Release);
+
+        let mut results = vec![0; entries.len()];
+        let mut to_submit = entries.len();
+        let mut completed = 0;
+        while completed < entries.len() {
+            let submitted = unsafe {
+                libc::syscall(
+                    libc::SYS_io_uring_enter,
+                    self.fd.as_raw_fd(),
+                    to_submit as u32,
+                    1u32,
+                    IORING_ENTER_GETEVENTS,
+                    ptr::null::<libc::sigset_t>(),
+                    0usize,
+                )
+            };
+            if submitted < 0 {
+                let error = io::Error::last_os_error();
+                if error.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                // submitted entries may still write to buffers, so error is returned only
+                // when none of them is in flight
+                if entries.len() - to_submit == completed {
+                    sq_tail.store(tail.wrapping_sub(to_submit as u32), Ordering::Release);
+                    return Err(error);
+                }
+                continue;
+            }
+            to_submit -= submitted as usize;
+
+            let mut head = cq_head.load(Ordering::Relaxed);
+            let completed_tail = cq_tail.load(Ordering::Acquire);
+            while head != completed_tail {
+                let cqe = unsafe { &*cqes.add((head & cq_mask) as usize) };
+                results[cqe.user_data as usize] = cqe.res;
+                head = head.wrapping_add(1);
+                completed += 1;
+            }
+            cq_head.store(head, Ordering::Release);
+        }
+
+        Ok(results)
+    }
+}
+
+/// Pages are stored like by file storage, reads and writes are submitted to io_uring.
+/// Batch of disk scheduler requests is submitted at once and served by kernel
+/// concurrently, so a single scheduler thread keeps many requests in flight.
+#[derive(Debug)]
+pub(crate) struct IoUringBackend {
+    file: File,
+    ring: Mutex<Ring>,
+}
+
+impl IoUringBackend {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = open_data_file(path)?;
+        let ring = Ring::new(RING_ENTRIES as u32).context("Can't set up io_uring.")?;
+
+        Ok(Self {
+            file,
+            ring: Mutex::new(ring),
+        })
+    }
+
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+
+    pub fn read_page(&self, page_id: PageId) -> Result<Vec<u8>> {
+        self.serve_batch(&[PageIo::Read(page_id)]).remove(0)
+    }
+
+    pub fn write_pages(&self, first_page_id: PageId, data: &[u8]) -> Result<()> {
+        self.serve_batch(&[PageIo::Write(first_page_id, data)])
+            .remove(0)
+            .map(|_| ())
+    }
+
+    /// Serve operations of the batch with one submission per ring full of them, reads
+    /// return page data and writes no data
+    pub fn serve_batch(&self, batch: &[PageIo<'_>]) -> Vec<Result<Vec<u8>>> {
+        // buffers are prepared before entries point to them, reads past end of file leave
+        // zeroes
+        let mut buffers = Vec::with_capacity(batch.len());
+        let mut results = Vec::with_capacity(batch.len());
+        for page_io in batch {
+            let buffer = match page_io {
+                PageIo::Read(_) => Ok(Cow::Owned(vec![0; PAGE_SIZE])),
+                PageIo::Write(page_id, data) => write_buffer(*page_id, data),
+            };
+            match buffer {
+                Ok(buffer) => {
+                    buffers.push(Some(buffer));
+                    results.push(None);
+                }
+                Err(error) => {
+                    buffers.push(None);
+                    results.push(Some(Err(error)));
+                }
+            }
+        }
+
+        let pending = (0..batch.len())
+            .filter(|index| buffers[*index].is_some())
+            .collect::<Vec<_>>();
+        let mut ring = self.ring.lock();
+        for chunk in pending.chunks(RING_ENTRIES) {
+            let entries = chunk
+                .iter()
+                .map(|index| {
+                    let buffer = buffers[*index].as_ref().unwrap();
+                    let (opcode, page_id) = match batch[*index] {
+                        PageIo::Read(page_id) => (IORING_OP_READ, page_id),
+                        PageIo::Write(page_id, _) => (IORING_OP_WRITE, page_id),
+                    };
+
+                    Sqe {
+                        opcode,
+                        fd: self.file.as_raw_fd(),
+                        off: page_id.offset(),
+                        addr: buffer.as_ptr() as u64,
+                        len: buffer.len() as u32,
+                        ..Sqe::default()
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            match ring.submit_and_wait(&entries) {
+                Ok(chunk_results) => {
+                    for (index, res) in chunk.iter().zip(chunk_results) {
+                        let len = buffers[*index].as_ref().unwrap().len();
+                        results[*index] = Some(operation_result(&batch[*index], len, res));
+                    }
+                }
+                Err(error) => {
+                    for index in chunk {
+                        results[*index] = Some(
+                            Err(io::Error::new(error.kind(), error.to_string()))
+                                .context("Can't submit to io_uring."),
+                        );
+                    }
+                }
+            }
+        }
+        drop(ring);
+
+        results
+            .into_iter()
+            .zip(buffers)
+            .zip(batch)
+            .map(|((result, buffer), page_io)| {
+                result.unwrap()?;
+
+                Ok(match (page_io, buffer) {
+                    (PageIo::Read(_), Some(buffer)) => buffer.into_owned(),
+                    _ => vec![],
+                })
+            })
+            .collect()
+    }
+
+    pub fn sync(&self) -> Result<()> {
+        self.file.sync_all()?;
+
+        Ok(())
+    }
+
+    pub fn num_pages(&self) -> Result<usize> {
+        let len = self.file.metadata()?.len() as usize;
+
+        Ok(len.div_ceil(PAGE_SIZE))
+    }
+}
+
+fn operation_result(page_io: &PageIo<'_>, len: usize, res: i32) -> Result<()> {
+    if res < 0 {
+        return Err(io::Error::from_raw_os_error(-res)).context("Page I/O failed.");
+    }
+    // read may stop at end of file, write must not stop short
+    if let PageIo::Write(page_id, _) = page_io {
+        if (res as usize) < len {
+            bail!("Write of pages from {} was cut short.", page_id);
+        }
+    }
+
+    Ok(())
+}
+
+// data of a single page is padded, data of several pages must consist of whole pages
+fn write_buffer<'a>(page_id: PageId, data: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+    if data.len() < PAGE_SIZE {
+        let mut page = data.to_vec();
+        page.resize(PAGE_SIZE, 0);
+        return Ok(Cow::Owned(page));
+    }
+    if !data.len().is_multiple_of(PAGE_SIZE) {
+        bail!(
+            "Data of pages from {} is {} bytes and doesn't consist of whole pages.",
+            page_id,
+            data.len()
+        );
+    }
+
+    Ok(Cow::Borrowed(data))
+}
@@ -0,0 +1,43 @@
+use std::borrow::Cow;
+
+use serde_derive::{Deserialize, Serialize};
+
+/// How keys are normalized before they are hashed and compared. It is chosen when table
+/// is created and stored in catalog, so reopened table finds keys written before.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyNormalization {
+    /// Keys are compared as they are
+    #[default]
+    Exact,
+    /// Keys which differ only in letter case are the same key
+    CaseInsensitive,
+}
+
+impl KeyNormalization {
+    /// Normalized form of string representation of the key
+    pub fn normalize<'a>(&self, key: &'a str) -> Cow<'a, str> {
+        match self {
+            KeyNormalization::Exact => Cow::Borrowed(key),
+            KeyNormalization::CaseInsensitive => Cow::Owned(key.to_lowercase()),
+        }
+    }
+
+    /// Whether keys are the same key once normalized
+    pub fn equals(&self, left: &str, right: &str) -> bool {
+        self.normalize(left) == self.normalize(right)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_case_insensitive() {
+        let normalization = KeyNormalization::CaseInsensitive;
+
+        assert_eq!(normalization.normalize("Straße"), "straße");
+        assert!(normalization.equals("Key", "kEY"));
+        assert!(!KeyNormalization::Exact.equals("Key", "kEY"));
+    }
+}
@@ -0,0 +1,1044 @@
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    hash::Hash,
+    io::ErrorKind,
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    path::Path,
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Context, Result};
+use parking_lot::{Mutex, RwLock};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    cancellation::CancellationToken,
+    db_instance::DbInstance,
+    log_archive::{read_restore_point, LogArchive, RestorePoint},
+    operation_policy::{is_transient, OperationPolicy},
+    replication::LogShipper,
+    snapshot::{Snapshot, Snapshots},
+    watch::{ChangeEvent, Watchers},
+    write_batch::{BatchLog, BatchRecord, WriteBatch},
+    ExtendibleHashTable, JobFuture, ThreadPool,
+};
+
+pub(crate) const DEFAULT_NAMESPACE: &str = "default";
+const DIRECTORY_MAX_DEPTH: u32 = 8;
+const BUCKET_MAX_SIZE: usize = 16;
+// pending merge deltas are folded into the stored value once there are this many
+const MAX_MERGE_DELTAS: usize = 8;
+// how often log shipping listener checks for new replicas
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Stored value of the key: base value, merge deltas not folded into it yet
+/// and expiration time in milliseconds since unix epoch
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct KvEntry<V> {
+    value: Option<V>,
+    deltas: Vec<V>,
+    expires_at: Option<u64>,
+}
+
+impl<V> KvEntry<V> {
+    pub fn new(value: V) -> Self {
+        Self {
+            value: Some(value),
+            deltas: Vec::new(),
+            expires_at: None,
+        }
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+}
+
+impl<V> Default for KvEntry<V> {
+    fn default() -> Self {
+        Self {
+            value: None,
+            deltas: Vec::new(),
+            expires_at: None,
+        }
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_millis() as u64)
+}
+
+type MergeFn<K, V> = dyn Fn(&K, Option<V>, &[V]) -> V + Send + Sync;
+
+struct MergeOperator<K, V>(Box<MergeFn<K, V>>);
+
+impl<K, V> Debug for MergeOperator<K, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("MergeOperator")
+    }
+}
+
+/// Persistent typed key-value store backed by extendible hash table.
+/// Database can hold several isolated namespaces, each one is a separate hash table.
+///
+/// ```no_run
+/// use cmu_db_rs::Kv;
+///
+/// let kv = Kv::<String, u64>::open("my.db")?;
+/// kv.insert("visits".into(), 1)?;
+/// assert_eq!(kv.get("visits".into())?, Some(1));
+///
+/// let users = kv.namespace::<u64, String>("users")?;
+/// users.insert(1, "alice".into())?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct Kv<K, V> {
+    hash_table: ExtendibleHashTable<K, KvEntry<V>>,
+    batch_log: BatchLog,
+    merge_operator: Option<MergeOperator<K, V>>,
+    watchers: Mutex<Watchers<K, V>>,
+    log_shipper: Mutex<LogShipper>,
+    snapshots: Snapshots<K, KvEntry<V>>,
+    // single operations hold it shared, batches exclusively
+    latch: RwLock<()>,
+    // name of background job of `start_ttl_sweeper`, it is removed with the store
+    ttl_sweeper_job: String,
+    db: Arc<DbInstance>,
+}
+
+impl<K, V> Kv<K, V>
+where
+    K: Hash + Eq + Clone + Debug + Serialize + DeserializeOwned + ToString,
+    V: Clone + Debug + Serialize + DeserializeOwned,
+{
+    /// Open default namespace of store at path, data file is created if it doesn't exist
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let db = DbInstance::open(path)?;
+
+        Self::open_namespace(Arc::new(db), DEFAULT_NAMESPACE)
+    }
+
+    /// Open namespace of the database, it is created if it doesn't exist.
+    /// Batches are atomic within a single namespace.
+    pub fn open_namespace(db: Arc<DbInstance>, name: &str) -> Result<Self> {
+        let mut batch_log_path = db.path().as_os_str().to_owned();
+        batch_log_path.push(format!(".{name}.batch"));
+        let batch_log = BatchLog::open(batch_log_path)?;
+        let hash_table = db.open_hash_table(name, DIRECTORY_MAX_DEPTH, BUCKET_MAX_SIZE)?;
+
+        let kv = Self {
+            hash_table,
+            batch_log,
+            merge_operator: None,
+            watchers: Mutex::new(Watchers::new()),
+            log_shipper: Mutex::new(LogShipper::default()),
+            snapshots: Snapshots::new(),
+            latch: RwLock::new(()),
+            ttl_sweeper_job: format!("ttl-sweeper:{name}"),
+            db,
+        };
+
+        // batch committed before crash is finished (or rolled back if it can't be applied)
+        if let Some(records) = kv.batch_log.read::<K, KvEntry<V>>()? {
+            kv.apply_records(&records)?;
+        }
+
+        Ok(kv)
+    }
+
+    /// Open another namespace of the same database
+    pub fn namespace<K2, V2>(&self, name: &str) -> Result<Kv<K2, V2>>
+    where
+        K2: Hash + Eq + Clone + Debug + Serialize + DeserializeOwned + ToString,
+        V2: Clone + Debug + Serialize + DeserializeOwned,
+    {
+        Kv::open_namespace(Arc::clone(&self.db), name)
+    }
+
+    /// Register function which folds merge deltas into value of the key,
+    /// it gets current value (`None` if key is absent) and deltas in order they were merged
+    pub fn set_merge_operator<F>(&mut self, merge_operator: F)
+    where
+        F: Fn(&K, Option<V>, &[V]) -> V + Send + Sync + 'static,
+    {
+        self.merge_operator = Some(MergeOperator(Box::new(merge_operator)));
+    }
+
+    pub fn get(&self, key: K) -> Result<Option<V>> {
+        let entry = self.get_entry(key.clone())?;
+
+        self.resolve(&key, entry)
+    }
+
+    pub(crate) fn get_entry(&self, key: K) -> Result<Option<KvEntry<V>>> {
+        let _latch = self.latch.read();
+
+        Ok(self.hash_table.get(key)?)
+    }
+
+    pub(crate) fn scan_entries(&self, token: &CancellationToken) -> Result<Vec<(K, KvEntry<V>)>> {
+        let _latch = self.latch.read();
+
+        Ok(self.hash_table.scan_cancellable(token)?)
+    }
+
+    /// Take read view of the store at this point in time
+    pub fn snapshot(&self) -> Snapshot<'_, K, V> {
+        // in-flight writes finish before snapshot is registered
+        let _latch = self.latch.write();
+
+        Snapshot::new(self, self.snapshots.register())
+    }
+
+    // every write of the store goes through here, so snapshots get value key had before it
+    fn update_entry<F>(&self, key: K, f: F) -> Result<Option<KvEntry<V>>>
+    where
+        F: FnOnce(Option<&KvEntry<V>>) -> Option<KvEntry<V>>,
+    {
+        Ok(self.hash_table.update(key.clone(), |entry| {
+            self.snapshots.record(&key, entry);
+
+            f(entry)
+        })?)
+    }
+
+    pub fn insert(&self, key: K, value: V) -> Result<()> {
+        self.insert_entry(key, KvEntry::new(value))
+    }
+
+    /// Insert value which expires after `ttl`, expired value reads as absent
+    /// and is removed from storage by `purge_expired`
+    pub fn insert_with_ttl(&self, key: K, value: V, ttl: Duration) -> Result<()> {
+        let mut entry = KvEntry::new(value);
+        entry.expires_at = Some(now_millis() + ttl.as_millis() as u64);
+
+        self.insert_entry(key, entry)
+    }
+
+    fn insert_entry(&self, key: K, new_value: KvEntry<V>) -> Result<()> {
+        let _latch = self.latch.read();
+
+        self.write_and_notify(|| {
+            let old_value = self.update_entry(key.clone(), |_| Some(new_value.clone()))?;
+
+            Ok(((), vec![BatchRecord::new(key, old_value, Some(new_value))]))
+        })
+    }
+
+    pub fn remove(&self, key: K) -> Result<Option<V>> {
+        let _latch = self.latch.read();
+
+        self.write_and_notify(|| {
+            let old_value = self.update_entry(key.clone(), |_| None)?;
+            let value = self.resolve(&key, old_value.clone())?;
+
+            Ok((value, vec![BatchRecord::new(key, old_value, None)]))
+        })
+    }
+
+    /// Append delta to the key without reading its value. Deltas are folded by merge operator
+    /// on reads, and written back folded once `MAX_MERGE_DELTAS` of them are pending.
+    pub fn merge(&self, key: K, delta: V) -> Result<()> {
+        let merge_operator = self
+            .merge_operator
+            .as_ref()
+            .context("Merge operator is not set.")?;
+        let _latch = self.latch.read();
+
+        self.write_and_notify(|| {
+            let mut new_value = None;
+            let old_value = self.update_entry(key.clone(), |entry| {
+                let mut entry = entry
+                    .filter(|entry| !entry.is_expired(now_millis()))
+                    .cloned()
+                    .unwrap_or_default();
+                entry.deltas.push(delta);
+
+                if entry.deltas.len() >= MAX_MERGE_DELTAS {
+                    let value = (merge_operator.0)(&key, entry.value, &entry.deltas);
+                    entry = KvEntry {
+                        expires_at: entry.expires_at,
+                        ..KvEntry::new(value)
+                    };
+                }
+                new_value = Some(entry.clone());
+
+                Some(entry)
+            })?;
+
+            Ok(((), vec![BatchRecord::new(key, old_value, new_value)]))
+        })
+    }
+
+    /// Subscribe to changes of keys starting with prefix, so whole key subscribes to the key
+    /// (and keys it is prefix of). Subscription ends when receiver is dropped.
+    pub fn watch(&self, prefix: impl Into<String>) -> Receiver<ChangeEvent<K, V>> {
+        self.watchers.lock().subscribe(prefix.into())
+    }
+
+    // run write returning changes it made, changes are sent to watchers and replicas. Writes
+    // are serialized while somebody watches, so events of a key arrive in the order changes
+    // were made, and replicas redo writes in the order they were made
+    fn write_and_notify<R, F>(&self, write: F) -> Result<R>
+    where
+        F: FnOnce() -> Result<(R, Vec<BatchRecord<K, KvEntry<V>>>)>,
+    {
+        let mut watchers = self.watchers.lock();
+        let mut log_shipper = self.log_shipper.lock();
+        if watchers.is_empty() && log_shipper.is_empty() {
+            drop(log_shipper);
+            drop(watchers);
+            return Ok(write()?.0);
+        }
+
+        let (result, changes) = write()?;
+        log_shipper.ship(&changes);
+        drop(log_shipper);
+        for change in changes {
+            // value which can't be resolved without merge operator is reported as absent
+            let old_value = self.resolve(&change.key, change.old_value).ok().flatten();
+            let new_value = self.resolve(&change.key, change.new_value).ok().flatten();
+
+            if let Some(event) = ChangeEvent::new(change.key, old_value, new_value) {
+                watchers.notify(event);
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Remove expired entries from storage, returns number of removed entries
+    pub fn purge_expired(&self) -> Result<usize> {
+        let _latch = self.latch.read();
+        let now = now_millis();
+        let mut purged = 0;
+
+        for (key, entry) in self.hash_table.scan()? {
+            if !entry.is_expired(now) {
+                continue;
+            }
+
+            // entry could be overwritten since scan
+            self.update_entry(key, |entry| match entry {
+                Some(entry) if entry.is_expired(now) => {
+                    purged += 1;
+                    None
+                }
+                entry => entry.cloned(),
+            })?;
+        }
+
+        Ok(purged)
+    }
+
+    /// Compress stored keys with dictionary of their frequent prefixes, so buckets hold more
+    /// entries when keys are long and repetitive. Returns number of prefixes in dictionary.
+    pub fn build_key_dictionary(&self) -> Result<usize> {
+        Ok(self.hash_table.build_key_dictionary()?)
+    }
+
+    /// Run `purge_expired` every `interval` as background job of the database until store
+    /// is dropped, see `DbInstance::background_jobs`
+    pub fn start_ttl_sweeper(self: &Arc<Self>, interval: Duration)
+    where
+        K: Send + Sync + 'static,
+        V: Send + Sync + 'static,
+    {
+        let kv = Arc::downgrade(self);
+
+        self.db
+            .background_jobs()
+            .register(self.ttl_sweeper_job.clone(), interval, move || {
+                let Some(kv) = kv.upgrade() else {
+                    return Ok(());
+                };
+                let result = kv.purge_expired().map(|_| ());
+                // store dropped meanwhile isn't dropped on thread pool, database it owns
+                // would wait for its own thread on shutdown
+                if let Some(kv) = Arc::into_inner(kv) {
+                    thread::spawn(move || drop(kv));
+                }
+
+                result
+            });
+    }
+
+    /// Like `get`, but page I/O is done on database thread pool, so async tasks don't block
+    /// their runtime while waiting for it
+    pub fn get_async(self: &Arc<Self>, key: K) -> JobFuture<Result<Option<V>>>
+    where
+        K: Send + Sync + 'static,
+        V: Send + Sync + 'static,
+    {
+        let kv = Arc::clone(self);
+
+        self.db.thread_pool().spawn_future(move || kv.get(key))
+    }
+
+    /// Like `insert`, with page I/O done on database thread pool
+    pub fn insert_async(self: &Arc<Self>, key: K, value: V) -> JobFuture<Result<()>>
+    where
+        K: Send + Sync + 'static,
+        V: Send + Sync + 'static,
+    {
+        let kv = Arc::clone(self);
+
+        self.db
+            .thread_pool()
+            .spawn_future(move || kv.insert(key, value))
+    }
+
+    /// Like `remove`, with page I/O done on database thread pool
+    pub fn remove_async(self: &Arc<Self>, key: K) -> JobFuture<Result<Option<V>>>
+    where
+        K: Send + Sync + 'static,
+        V: Send + Sync + 'static,
+    {
+        let kv = Arc::clone(self);
+
+        self.db.thread_pool().spawn_future(move || kv.remove(key))
+    }
+
+    /// Run operation on database thread pool, retrying it on transient errors (no free
+    /// buffer pool frame, interrupted I/O) and giving up once policy timeout passes.
+    /// Final error tells how many attempts were made and how long they took.
+    pub fn call<T, F>(self: &Arc<Self>, policy: OperationPolicy, operation: F) -> Result<T>
+    where
+        K: Send + Sync + 'static,
+        V: Send + Sync + 'static,
+        T: Send + 'static,
+        F: Fn(&Self) -> Result<T> + Send + Sync + 'static,
+    {
+        let started_at = Instant::now();
+        let operation = Arc::new(operation);
+        let timed_out = |attempts: u32| {
+            format!(
+                "Operation timed out after {:?}, {} attempts were made.",
+                started_at.elapsed(),
+                attempts
+            )
+        };
+
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let (sender, receiver) = mpsc::channel();
+            let kv = Arc::clone(self);
+            let attempt = Arc::clone(&operation);
+            self.db.thread_pool().spawn(move || {
+                let _ = sender.send(attempt(&kv));
+            });
+
+            let remaining = policy
+                .timeout
+                .map(|timeout| timeout.saturating_sub(started_at.elapsed()));
+            let result = match remaining.map_or_else(
+                || receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+                |remaining| receiver.recv_timeout(remaining),
+            ) {
+                Ok(result) => result,
+                Err(RecvTimeoutError::Timeout) => bail!(timed_out(attempts)),
+                Err(RecvTimeoutError::Disconnected) => {
+                    bail!("Operation didn't finish, it panicked or thread pool is shut down.")
+                }
+            };
+            let error = match result {
+                Ok(value) => return Ok(value),
+                Err(error) => error,
+            };
+
+            if attempts > policy.max_retries || !is_transient(&error) {
+                return Err(error.context(format!(
+                    "Operation failed after {} attempts in {:?}.",
+                    attempts,
+                    started_at.elapsed()
+                )));
+            }
+            let backoff = policy.backoff(attempts - 1);
+            if remaining.is_some_and(|remaining| remaining <= backoff) {
+                return Err(error.context(timed_out(attempts)));
+            }
+            thread::sleep(backoff);
+        }
+    }
+
+    pub(crate) fn thread_pool(&self) -> &ThreadPool {
+        self.db.thread_pool()
+    }
+
+    // fold pending deltas of the entry into its value, expired entry is absent
+    pub(crate) fn resolve(&self, key: &K, entry: Option<KvEntry<V>>) -> Result<Option<V>> {
+        let Some(entry) = entry.filter(|entry| !entry.is_expired(now_millis())) else {
+            return Ok(None);
+        };
+        if entry.deltas.is_empty() {
+            return Ok(entry.value);
+        }
+
+        let merge_operator = self
+            .merge_operator
+            .as_ref()
+            .context("Merge operator is not set.")?;
+
+        Ok(Some((merge_operator.0)(key, entry.value, &entry.deltas)))
+    }
+
+    /// Replace value only if current one equals `expected`, `None` stands for absent key.
+    /// On mismatch current value is returned as error.
+    pub fn compare_and_swap(
+        &self,
+        key: K,
+        expected: Option<V>,
+        new: Option<V>,
+    ) -> Result<Result<(), Option<V>>>
+    where
+        V: PartialEq,
+    {
+        let _latch = self.latch.read();
+
+        // stored entry can hold pending deltas, so it is swapped only if it didn't change
+        // since its folded value was compared
+        self.write_and_notify(|| loop {
+            let entry = self.hash_table.get(key.clone())?;
+            let current = self.resolve(&key, entry.clone())?;
+            if current != expected {
+                return Ok((Err(current), vec![]));
+            }
+
+            let new = new.clone().map(KvEntry::new);
+            let mut swapped = false;
+            self.update_entry(key.clone(), |current| {
+                // entry changed since it was compared, it is left as is
+                if current != entry.as_ref() {
+                    return current.cloned();
+                }
+                swapped = true;
+
+                new.clone()
+            })?;
+
+            if swapped {
+                return Ok((Ok(()), vec![BatchRecord::new(key, entry, new)]));
+            }
+        })
+    }
+
+    /// Apply all batch operations or none of them. Batch is durable once this returns,
+    /// concurrent readers never observe partially applied batch.
+    pub fn apply_batch(&self, batch: WriteBatch<K, V>) -> Result<()> {
+        self.apply_changes(
+            batch
+                .into_changes()
+                .map(|(key, value)| (key, value.map(KvEntry::new))),
+        )
+    }
+
+    // atomically set keys to new entries, `None` removes the key
+    fn apply_changes(
+        &self,
+        changes: impl IntoIterator<Item = (K, Option<KvEntry<V>>)>,
+    ) -> Result<()> {
+        let _latch = self.latch.write();
+
+        // values before batch are resolved up front, so batch can be undone after crash too
+        let mut changed: HashMap<K, Option<KvEntry<V>>> = HashMap::new();
+        let mut records = Vec::new();
+        for (key, new_value) in changes {
+            let old_value = match changed.get(&key) {
+                Some(value) => value.clone(),
+                None => self.hash_table.get(key.clone())?,
+            };
+            changed.insert(key.clone(), new_value.clone());
+            records.push(BatchRecord::new(key, old_value, new_value));
+        }
+
+        self.batch_log.write(&records)?;
+        self.write_and_notify(|| {
+            self.apply_records(&records)?;
+
+            Ok(((), records))
+        })
+    }
+
+    fn apply_records(&self, records: &[BatchRecord<K, KvEntry<V>>]) -> Result<()> {
+        for (index, record) in records.iter().enumerate() {
+            if let Err(error) = self.set(record.key.clone(), record.new_value.clone()) {
+                for record in records[..=index].iter().rev() {
+                    let _ = self.set(record.key.clone(), record.old_value.clone());
+                }
+                self.db.flush()?;
+                self.batch_log.clear()?;
+
+                return Err(error.context("Write batch is rolled back."));
+            }
+        }
+
+        self.db.flush()?;
+        self.batch_log.clear()
+    }
+
+    fn set(&self, key: K, value: Option<KvEntry<V>>) -> Result<()> {
+        self.update_entry(key, |_| value)?;
+
+        Ok(())
+    }
+
+    /// Ship writes of the namespace to replicas connecting to `address`, see `Replica`.
+    /// Every new replica gets copy of the whole namespace first, writes wait meanwhile.
+    /// Returns address listener is bound to, it listens until store is dropped.
+    pub fn start_log_shipping(self: &Arc<Self>, address: impl ToSocketAddrs) -> Result<SocketAddr>
+    where
+        K: Send + Sync + 'static,
+        V: Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind(address).context("Can't bind log shipping address.")?;
+        listener.set_nonblocking(true)?;
+        let local_address = listener.local_addr()?;
+        let kv = Arc::downgrade(self);
+
+        // own thread, listener would occupy one of few thread pool workers for good
+        thread::spawn(move || loop {
+            let Some(kv) = kv.upgrade() else {
+                break;
+            };
+            match listener.accept() {
+                Ok((stream, peer)) => {
+                    if let Err(error) = kv.add_replica(stream) {
+                        let error = format!("{:#}", error);
+                        tracing::warn!(error, %peer, "can't start shipping to replica");
+                    }
+                }
+                Err(error) if error.kind() == ErrorKind::WouldBlock => {
+                    drop(kv);
+                    thread::sleep(ACCEPT_INTERVAL);
+                }
+                Err(error) => {
+                    tracing::warn!(%error, "can't accept replica");
+                    drop(kv);
+                    thread::sleep(ACCEPT_INTERVAL);
+                }
+            }
+        });
+
+        Ok(local_address)
+    }
+
+    fn add_replica(&self, stream: TcpStream) -> Result<()> {
+        stream.set_nonblocking(false)?;
+        // no write runs until replica is added, so it gets every write after its base copy
+        let _latch = self.latch.write();
+        let entries = self.hash_table.scan()?;
+
+        self.log_shipper.lock().add_replica(stream, entries)
+    }
+
+    /// Archive writes of the namespace to directory for `restore_to`, archiving starts with
+    /// base backup of the whole namespace. Returns sequence number of the backup.
+    ///
+    /// Archived writes are durable once store is flushed. Call it again to take fresh
+    /// base backup, so restore has fewer writes to replay.
+    pub fn start_log_archiving(&self, dir: impl AsRef<Path>) -> Result<u64> {
+        let (archive, archived_sequence) = LogArchive::open::<K, KvEntry<V>>(dir)?;
+        // no write runs until archive is started, so it gets every write after base backup
+        let _latch = self.latch.write();
+        let entries = self.hash_table.scan()?;
+
+        self.log_shipper
+            .lock()
+            .start_archiving(archive, archived_sequence, &entries)
+    }
+
+    /// Replace content of the namespace with its state at `point` in archive written by
+    /// `start_log_archiving`. Archived writes are replayed on top of the latest base backup
+    /// before the point, returns sequence number of the last replayed write.
+    pub fn restore_to(&self, dir: impl AsRef<Path>, point: RestorePoint) -> Result<u64> {
+        let (entries, sequence) = read_restore_point::<K, KvEntry<V>>(dir, point)?;
+        self.restore(entries.into_iter().collect())?;
+
+        Ok(sequence)
+    }
+
+    /// Replace content of the namespace with given entries
+    pub(crate) fn restore(&self, entries: Vec<(K, KvEntry<V>)>) -> Result<()> {
+        let mut changes: HashMap<K, Option<KvEntry<V>>> = self
+            .scan_entries(&CancellationToken::new())?
+            .into_iter()
+            .map(|(key, _)| (key, None))
+            .collect();
+        changes.extend(entries.into_iter().map(|(key, entry)| (key, Some(entry))));
+
+        self.apply_changes(changes)
+    }
+
+    /// Redo write shipped by primary
+    pub(crate) fn redo(&self, records: Vec<BatchRecord<K, KvEntry<V>>>) -> Result<()> {
+        self.apply_changes(
+            records
+                .into_iter()
+                .map(|record| (record.key, record.new_value)),
+        )
+    }
+
+    /// Write all changes to disk, also done when store is dropped
+    pub fn flush(&self) -> Result<()> {
+        self.log_shipper.lock().sync()?;
+        self.db.flush()
+    }
+}
+
+impl<K, V> Drop for Kv<K, V> {
+    fn drop(&mut self) {
+        self.db.background_jobs().remove(&self.ttl_sweeper_job);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{page::PAGE_SIZE, ExtendibleHashTableError};
+
+    #[test]
+    fn test_reopen() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("kv.db");
+
+        let kv = Kv::<String, String>::open(&path).unwrap();
+        for i in 0..200 {
+            kv.insert(format!("key{i}"), format!("value{i}")).unwrap();
+        }
+        for i in 0..100 {
+            assert_eq!(
+                kv.remove(format!("key{i}")).unwrap(),
+                Some(format!("value{i}"))
+            );
+        }
+        drop(kv);
+
+        let kv = Kv::<String, String>::open(&path).unwrap();
+        for i in 0..100 {
+            assert_eq!(kv.get(format!("key{i}")).unwrap(), None);
+        }
+        for i in 100..200 {
+            assert_eq!(
+                kv.get(format!("key{i}")).unwrap(),
+                Some(format!("value{i}"))
+            );
+        }
+    }
+
+    #[test]
+    fn test_apply_batch() {
+        let dir = TempDir::new().unwrap();
+        let kv = Kv::<String, String>::open(dir.path().join("kv.db")).unwrap();
+        kv.insert("a".into(), "1".into()).unwrap();
+
+        let mut batch = WriteBatch::new();
+        batch.insert("b".into(), "2".into());
+        batch.remove("a".into());
+        batch.insert("c".into(), "3".into());
+        kv.apply_batch(batch).unwrap();
+
+        assert_eq!(kv.get("a".into()).unwrap(), None);
+        assert_eq!(kv.get("b".into()).unwrap(), Some("2".into()));
+        assert_eq!(kv.get("c".into()).unwrap(), Some("3".into()));
+    }
+
+    #[test]
+    fn test_failed_batch_is_rolled_back() {
+        let dir = TempDir::new().unwrap();
+        let kv = Kv::<String, String>::open(dir.path().join("kv.db")).unwrap();
+        kv.insert("a".into(), "1".into()).unwrap();
+
+        let mut batch = WriteBatch::new();
+        batch.insert("a".into(), "2".into());
+        batch.insert("b".into(), "2".into());
+        // value doesn't fit into bucket page
+        batch.insert("c".into(), "x".repeat(PAGE_SIZE));
+
+        assert!(kv.apply_batch(batch).is_err());
+        assert_eq!(kv.get("a".into()).unwrap(), Some("1".into()));
+        assert_eq!(kv.get("b".into()).unwrap(), None);
+        assert_eq!(kv.get("c".into()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_committed_batch_is_replayed_on_open() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("kv.db");
+        let kv = Kv::<String, String>::open(&path).unwrap();
+        kv.insert("a".into(), "1".into()).unwrap();
+        drop(kv);
+
+        // batch was logged but crash happened before it reached data file
+        let batch_log = BatchLog::open(dir.path().join("kv.db.default.batch")).unwrap();
+        batch_log
+            .write(&[
+                BatchRecord {
+                    key: "a".to_string(),
+                    old_value: Some(KvEntry::new("1".to_string())),
+                    new_value: None,
+                },
+                BatchRecord {
+                    key: "b".to_string(),
+                    old_value: None,
+                    new_value: Some(KvEntry::new("2".to_string())),
+                },
+            ])
+            .unwrap();
+        drop(batch_log);
+
+        let kv = Kv::<String, String>::open(&path).unwrap();
+        assert_eq!(kv.get("a".into()).unwrap(), None);
+        assert_eq!(kv.get("b".into()).unwrap(), Some("2".into()));
+    }
+
+    #[test]
+    fn test_call_retries_transient_errors() {
+        let dir = TempDir::new().unwrap();
+        let kv = Arc::new(Kv::<String, String>::open(dir.path().join("kv.db")).unwrap());
+        let policy = OperationPolicy {
+            timeout: Some(Duration::from_secs(10)),
+            max_retries: 3,
+            backoff: Duration::from_millis(1),
+        };
+
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&attempts);
+        let value = kv
+            .call(policy, move |kv| {
+                if counter.fetch_add(1, Ordering::SeqCst) < 2 {
+                    return Err(ExtendibleHashTableError::PageNotAvailable.into());
+                }
+                kv.insert("a".into(), "1".into())?;
+                kv.get("a".into())
+            })
+            .unwrap();
+        assert_eq!(value, Some("1".into()));
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+        let error = kv
+            .call(policy, |_| -> Result<()> { bail!("broken") })
+            .unwrap_err();
+        assert!(format!("{error:#}").contains("after 1 attempts"));
+
+        let policy = OperationPolicy {
+            timeout: Some(Duration::from_millis(20)),
+            ..policy
+        };
+        let error = kv
+            .call(policy, |_| {
+                thread::sleep(Duration::from_millis(200));
+                Ok(())
+            })
+            .unwrap_err();
+        assert!(error.to_string().contains("timed out"));
+    }
+
+    #[test]
+    fn test_merge() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("kv.db");
+        let mut kv = Kv::<String, u64>::open(&path).unwrap();
+        assert!(kv.merge("counter".into(), 1).is_err());
+
+        kv.set_merge_operator(|_, value, deltas| value.unwrap_or(0) + deltas.iter().sum::<u64>());
+        for _ in 0..(MAX_MERGE_DELTAS + 3) {
+            kv.merge("counter".into(), 1).unwrap();
+        }
+        assert_eq!(kv.get("counter".into()).unwrap(), Some(11));
+
+        kv.insert("other".into(), 10).unwrap();
+        kv.merge("other".into(), 5).unwrap();
+        assert_eq!(
+            kv.compare_and_swap("other".into(), Some(10), Some(0))
+                .unwrap(),
+            Err(Some(15))
+        );
+        drop(kv);
+
+        // pending deltas are stored, but folding them needs merge operator
+        let mut kv = Kv::<String, u64>::open(&path).unwrap();
+        assert!(kv.get("other".into()).is_err());
+        kv.set_merge_operator(|_, value, deltas| value.unwrap_or(0) + deltas.iter().sum::<u64>());
+        assert_eq!(kv.get("other".into()).unwrap(), Some(15));
+        assert_eq!(kv.get("counter".into()).unwrap(), Some(11));
+    }
+
+    #[test]
+    fn test_namespaces() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("kv.db");
+        let kv = Kv::<String, String>::open(&path).unwrap();
+        let numbers = kv.namespace::<String, u64>("numbers").unwrap();
+
+        kv.insert("a".into(), "1".into()).unwrap();
+        numbers.insert("a".into(), 2).unwrap();
+        numbers.insert("b".into(), 3).unwrap();
+        assert_eq!(kv.get("b".into()).unwrap(), None);
+        drop(kv);
+        drop(numbers);
+
+        let db = Arc::new(DbInstance::open(&path).unwrap());
+        assert_eq!(db.hash_table_names().unwrap(), vec!["default", "numbers"]);
+
+        let kv = Kv::<String, String>::open_namespace(Arc::clone(&db), "default").unwrap();
+        let numbers = Kv::<String, u64>::open_namespace(db, "numbers").unwrap();
+        assert_eq!(kv.get("a".into()).unwrap(), Some("1".into()));
+        assert_eq!(numbers.get("a".into()).unwrap(), Some(2));
+        assert_eq!(numbers.get("b".into()).unwrap(), Some(3));
+    }
+
+    #[test]
+    fn test_watch() {
+        let dir = TempDir::new().unwrap();
+        let kv = Kv::<String, String>::open(dir.path().join("kv.db")).unwrap();
+        let users = kv.watch("user:");
+
+        kv.insert("user:1".into(), "a".into()).unwrap();
+        kv.insert("group:1".into(), "g".into()).unwrap();
+        kv.insert("user:1".into(), "b".into()).unwrap();
+        kv.remove("user:1".into()).unwrap();
+
+        let mut batch = WriteBatch::new();
+        batch.insert("user:2".into(), "c".into());
+        kv.apply_batch(batch).unwrap();
+
+        let events = users.try_iter().collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![
+                ChangeEvent::Insert {
+                    key: "user:1".into(),
+                    value: "a".into()
+                },
+                ChangeEvent::Update {
+                    key: "user:1".into(),
+                    old_value: "a".into(),
+                    new_value: "b".into()
+                },
+                ChangeEvent::Remove {
+                    key: "user:1".into(),
+                    old_value: "b".into()
+                },
+                ChangeEvent::Insert {
+                    key: "user:2".into(),
+                    value: "c".into()
+                },
+            ]
+        );
+
+        drop(users);
+        kv.insert("user:3".into(), "d".into()).unwrap();
+        assert!(kv.watchers.lock().is_empty());
+    }
+
+    #[test]
+    fn test_ttl() {
+        let dir = TempDir::new().unwrap();
+        let kv = Arc::new(Kv::<String, String>::open(dir.path().join("kv.db")).unwrap());
+        let ttl = Duration::from_millis(50);
+
+        kv.insert_with_ttl("a".into(), "1".into(), ttl).unwrap();
+        kv.insert_with_ttl("b".into(), "2".into(), ttl).unwrap();
+        kv.insert("c".into(), "3".into()).unwrap();
+        assert_eq!(kv.get("a".into()).unwrap(), Some("1".into()));
+
+        thread::sleep(ttl);
+        assert_eq!(kv.get("a".into()).unwrap(), None);
+        assert_eq!(kv.purge_expired().unwrap(), 2);
+        assert_eq!(kv.hash_table.get("b".into()).unwrap(), None);
+        assert_eq!(kv.get("c".into()).unwrap(), Some("3".into()));
+
+        kv.insert_with_ttl("d".into(), "4".into(), ttl).unwrap();
+        kv.start_ttl_sweeper(Duration::from_millis(10));
+        thread::sleep(ttl * 3);
+        assert_eq!(kv.hash_table.get("d".into()).unwrap(), None);
+
+        // sweeper is a background job of the database, it is removed with the store
+        let db = Arc::clone(&kv.db);
+        let job = db
+            .background_jobs()
+            .job_status("ttl-sweeper:default")
+            .unwrap();
+        assert!(job.runs > 0);
+        drop(kv);
+        assert!(db
+            .background_jobs()
+            .job_status("ttl-sweeper:default")
+            .is_none());
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let dir = TempDir::new().unwrap();
+        let kv = Kv::<String, String>::open(dir.path().join("kv.db")).unwrap();
+        kv.insert("a".into(), "1".into()).unwrap();
+        kv.insert("b".into(), "2".into()).unwrap();
+
+        let snapshot = kv.snapshot();
+        kv.insert("a".into(), "3".into()).unwrap();
+        kv.insert("a".into(), "4".into()).unwrap();
+        kv.remove("b".into()).unwrap();
+        kv.insert("c".into(), "5".into()).unwrap();
+
+        assert_eq!(snapshot.get("a".into()).unwrap(), Some("1".into()));
+        assert_eq!(snapshot.get("b".into()).unwrap(), Some("2".into()));
+        assert_eq!(snapshot.get("c".into()).unwrap(), None);
+        let mut entries = snapshot.scan().unwrap();
+        entries.sort();
+        assert_eq!(
+            entries,
+            vec![("a".into(), "1".into()), ("b".into(), "2".into())]
+        );
+        assert_eq!(kv.get("a".into()).unwrap(), Some("4".into()));
+    }
+
+    // minimal executor, store doesn't depend on async runtime
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        struct ThreadWaker(thread::Thread);
+
+        impl std::task::Wake for ThreadWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let waker = Arc::new(ThreadWaker(thread::current())).into();
+        let mut cx = std::task::Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+        loop {
+            if let std::task::Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            thread::park();
+        }
+    }
+
+    #[test]
+    fn test_async() {
+        let dir = TempDir::new().unwrap();
+        let kv = Arc::new(Kv::<String, u64>::open(dir.path().join("kv.db")).unwrap());
+
+        block_on(async {
+            kv.insert_async("a".into(), 1).await.unwrap();
+            kv.insert_async("b".into(), 2).await.unwrap();
+            assert_eq!(kv.get_async("a".into()).await.unwrap(), Some(1));
+            assert_eq!(kv.remove_async("b".into()).await.unwrap(), Some(2));
+            assert_eq!(kv.get_async("b".into()).await.unwrap(), None);
+        });
+        assert_eq!(kv.get("a".into()).unwrap(), Some(1));
+    }
+}
@@ -0,0 +1,277 @@
+//! Debug build bookkeeping of page latches held by page guards. Thread which is about to
+//! wait for a latch checks that it doesn't close a cycle of threads waiting for each other
+//! and panics with report of the cycle otherwise. Release builds track nothing.
+
+use crate::{buffer_pool_manager::BufferPoolManager, page::PageId};
+
+/// Page latch of a particular buffer pool, pools are told apart by address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct LatchId {
+    pool: usize,
+    page_id: PageId,
+}
+
+impl LatchId {
+    pub fn new(buffer_pool_manager: &BufferPoolManager, page_id: PageId) -> Self {
+        Self {
+            pool: buffer_pool_manager as *const BufferPoolManager as usize,
+            page_id,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum LatchMode {
+    Read,
+    Write,
+}
+
+#[cfg(debug_assertions)]
+mod tracker {
+    use std::{
+        collections::HashMap,
+        fmt::Write,
+        sync::Mutex,
+        thread::{self, ThreadId},
+    };
+
+    use super::{LatchId, LatchMode};
+
+    #[derive(Debug, Default)]
+    struct LatchTracker {
+        holders: HashMap<LatchId, Vec<(ThreadId, LatchMode)>>,
+        waiting: HashMap<ThreadId, (LatchId, LatchMode)>,
+        thread_names: HashMap<ThreadId, String>,
+    }
+
+    impl LatchTracker {
+        // threads holding the latch which request in `mode` has to wait for
+        fn blockers(&self, latch: LatchId, mode: LatchMode) -> Vec<ThreadId> {
+            self.holders
+                .get(&latch)
+                .into_iter()
+                .flatten()
+                .filter(|(_, held_mode)| mode == LatchMode::Write || *held_mode == LatchMode::Write)
+                .map(|(thread_id, _)| *thread_id)
+                .collect()
+        }
+
+        // chain of waiting threads from `from` back to `to`, if there is one
+        fn find_cycle(&self, from: ThreadId, to: ThreadId, path: &mut Vec<ThreadId>) -> bool {
+            if from == to {
+                return true;
+            }
+            if path.contains(&from) {
+                return false;
+            }
+            let Some((latch, mode)) = self.waiting.get(&from) else {
+                return false;
+            };
+
+            path.push(from);
+            for blocker in self.blockers(*latch, *mode) {
+                if self.find_cycle(blocker, to, path) {
+                    return true;
+                }
+            }
+            path.pop();
+
+            false
+        }
+
+        fn describe(&self, thread_id: ThreadId) -> String {
+            let name = self
+                .thread_names
+                .get(&thread_id)
+                .map_or("unnamed", String::as_str);
+            let held = self
+                .holders
+                .iter()
+                .flat_map(|(latch, holders)| {
+                    holders
+                        .iter()
+                        .filter(move |(holder, _)| *holder == thread_id)
+                        .map(move |(_, mode)| format!("{:?} {}", mode, latch.page_id))
+                })
+                .collect::<Vec<String>>();
+
+            format!("{:?} ({}) holding [{}]", thread_id, name, held.join(", "))
+        }
+
+        fn report(&self, cycle: &[ThreadId]) -> String {
+            let mut report = String::from("Page latch deadlock detected:\n");
+            for thread_id in cycle {
+                let (latch, mode) = self.waiting[thread_id];
+                let _ = writeln!(
+                    report,
+                    "  thread {} waits for {:?} latch of page {}",
+                    self.describe(*thread_id),
+                    mode,
+                    latch.page_id
+                );
+            }
+
+            report
+        }
+    }
+
+    static TRACKER: Mutex<Option<LatchTracker>> = Mutex::new(None);
+
+    pub fn wait(latch: LatchId, mode: LatchMode) {
+        let thread = thread::current();
+        let thread_id = thread.id();
+        let mut guard = TRACKER.lock().unwrap_or_else(|error| error.into_inner());
+        let tracker = guard.get_or_insert_with(LatchTracker::default);
+
+        let held_mode = tracker
+            .holders
+            .get(&latch)
+            .into_iter()
+            .flatten()
+            .find(|(holder, _)| *holder == thread_id)
+            .map(|(_, mode)| *mode);
+        if let Some(held_mode) = held_mode {
+            let report = format!(
+                "Thread {} requests {:?} latch of page {} it already holds in {:?} mode.",
+                tracker.describe(thread_id),
+                mode,
+                latch.page_id,
+                held_mode
+            );
+            drop(guard);
+            panic!("{}", report);
+        }
+
+        tracker.waiting.insert(thread_id, (latch, mode));
+        if let Some(name) = thread.name() {
+            tracker.thread_names.insert(thread_id, name.to_string());
+        }
+
+        let mut cycle = vec![thread_id];
+        for blocker in tracker.blockers(latch, mode) {
+            if tracker.find_cycle(blocker, thread_id, &mut cycle) {
+                let report = tracker.report(&cycle);
+                tracker.waiting.remove(&thread_id);
+                drop(guard);
+                panic!("{}", report);
+            }
+        }
+    }
+
+    pub fn acquired(latch: LatchId, mode: LatchMode) {
+        let thread_id = thread::current().id();
+        let mut tracker = TRACKER.lock().unwrap_or_else(|error| error.into_inner());
+        let tracker = tracker.get_or_insert_with(LatchTracker::default);
+
+        tracker.waiting.remove(&thread_id);
+        tracker
+            .holders
+            .entry(latch)
+            .or_default()
+            .push((thread_id, mode));
+    }
+
+    pub fn released(latch: LatchId, mode: LatchMode) {
+        let thread_id = thread::current().id();
+        let mut tracker = TRACKER.lock().unwrap_or_else(|error| error.into_inner());
+        let Some(tracker) = tracker.as_mut() else {
+            return;
+        };
+        let Some(holders) = tracker.holders.get_mut(&latch) else {
+            return;
+        };
+
+        // guard could be sent to another thread, then any holder in the same mode is released
+        let position = holders
+            .iter()
+            .position(|holder| *holder == (thread_id, mode))
+            .or_else(|| holders.iter().position(|(_, held_mode)| *held_mode == mode));
+        if let Some(position) = position {
+            holders.swap_remove(position);
+        }
+        if holders.is_empty() {
+            tracker.holders.remove(&latch);
+        }
+    }
+}
+
+/// Register that calling thread is about to wait for page latch,
+/// panics if waiting would deadlock
+#[inline]
+pub(crate) fn wait(latch: LatchId, mode: LatchMode) {
+    #[cfg(debug_assertions)]
+    tracker::wait(latch, mode);
+    #[cfg(not(debug_assertions))]
+    let _ = (latch, mode);
+}
+
+#[inline]
+pub(crate) fn acquired(latch: LatchId, mode: LatchMode) {
+    #[cfg(debug_assertions)]
+    tracker::acquired(latch, mode);
+    #[cfg(not(debug_assertions))]
+    let _ = (latch, mode);
+}
+
+#[inline]
+pub(crate) fn released(latch: LatchId, mode: LatchMode) {
+    #[cfg(debug_assertions)]
+    tracker::released(latch, mode);
+    #[cfg(not(debug_assertions))]
+    let _ = (latch, mode);
+}
+
+#[cfg(all(test, debug_assertions))]
+mod tests {
+    use std::{
+        sync::{Arc, Barrier},
+        thread,
+        time::Duration,
+    };
+
+    use crate::{buffer_pool_manager::BufferPoolManager, disk_manager::DiskManager};
+
+    #[test]
+    fn test_relatching_same_page_panics() {
+        let buffer_pool_manager = BufferPoolManager::new(DiskManager::new(), 4, 2);
+        let (page_id, page) = buffer_pool_manager.new_page().unwrap();
+        drop(page);
+
+        let page = buffer_pool_manager.fetch_page_read(page_id).unwrap();
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            buffer_pool_manager.fetch_page_write(page_id).map(drop)
+        }));
+        assert!(result.is_err());
+        drop(page);
+    }
+
+    #[test]
+    fn test_deadlock_is_detected() {
+        let buffer_pool_manager = Arc::new(BufferPoolManager::new(DiskManager::new(), 4, 2));
+        let (first, page) = buffer_pool_manager.new_page().unwrap();
+        drop(page);
+        let (second, page) = buffer_pool_manager.new_page().unwrap();
+        drop(page);
+        let barrier = Arc::new(Barrier::new(2));
+
+        // threads latch pages in opposite order, one of them closes the cycle and panics
+        let handles = [(first, second), (second, first)].map(|(held, wanted)| {
+            let buffer_pool_manager = Arc::clone(&buffer_pool_manager);
+            let barrier = Arc::clone(&barrier);
+
+            thread::spawn(move || {
+                let _held = buffer_pool_manager.fetch_page_write(held).unwrap();
+                barrier.wait();
+                thread::sleep(Duration::from_millis(50));
+                let _wanted = buffer_pool_manager.fetch_page_write(wanted);
+            })
+        });
+        let panicked = handles
+            .into_iter()
+            .map(|handle| handle.join())
+            .filter(Result::is_err)
+            .count();
+
+        assert_eq!(panicked, 1);
+    }
+}
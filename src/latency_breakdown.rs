@@ -0,0 +1,93 @@
+use std::{
+    cell::Cell,
+    fmt,
+    time::{Duration, Instant},
+};
+
+/// Where time of hash table operation went, see `ExtendibleHashTable::with_latency_breakdown`.
+/// Time not in any phase, like waiting for latches, is only part of `total`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LatencyBreakdown {
+    pub total: Duration,
+    pub hash: Duration,
+    pub header_fetch: Duration,
+    pub directory_fetch: Duration,
+    pub bucket_fetch: Duration,
+    /// Decoding and encoding of pages
+    pub serialize: Duration,
+    /// Waiting for page reads, it is included in fetch phases as well
+    pub disk_wait: Duration,
+}
+
+impl fmt::Display for LatencyBreakdown {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "total {:?}: hash {:?}, header fetch {:?}, directory fetch {:?}, bucket fetch {:?}, \
+             serialize {:?}, disk wait {:?}",
+            self.total,
+            self.hash,
+            self.header_fetch,
+            self.directory_fetch,
+            self.bucket_fetch,
+            self.serialize,
+            self.disk_wait
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Phase {
+    Hash,
+    HeaderFetch,
+    DirectoryFetch,
+    BucketFetch,
+    Serialize,
+    DiskWait,
+}
+
+impl LatencyBreakdown {
+    fn phase_mut(&mut self, phase: Phase) -> &mut Duration {
+        match phase {
+            Phase::Hash => &mut self.hash,
+            Phase::HeaderFetch => &mut self.header_fetch,
+            Phase::DirectoryFetch => &mut self.directory_fetch,
+            Phase::BucketFetch => &mut self.bucket_fetch,
+            Phase::Serialize => &mut self.serialize,
+            Phase::DiskWait => &mut self.disk_wait,
+        }
+    }
+}
+
+thread_local! {
+    // breakdown of operation this thread is measuring, phases aren't timed without it
+    static CURRENT: Cell<Option<LatencyBreakdown>> = const { Cell::new(None) };
+}
+
+/// Run operation and time phases it goes through
+pub(crate) fn measure<T>(operation: impl FnOnce() -> T) -> (T, LatencyBreakdown) {
+    let outer = CURRENT.replace(Some(LatencyBreakdown::default()));
+    let started_at = Instant::now();
+    let result = operation();
+    let mut breakdown = CURRENT.replace(outer).unwrap_or_default();
+    breakdown.total = started_at.elapsed();
+
+    (result, breakdown)
+}
+
+/// Time phase of operation measured by this thread, it just runs otherwise
+pub(crate) fn phase<T>(phase: Phase, f: impl FnOnce() -> T) -> T {
+    if CURRENT.get().is_none() {
+        return f();
+    }
+
+    let started_at = Instant::now();
+    let result = f();
+    let elapsed = started_at.elapsed();
+    CURRENT.set(CURRENT.get().map(|mut breakdown| {
+        *breakdown.phase_mut(phase) += elapsed;
+        breakdown
+    }));
+
+    result
+}
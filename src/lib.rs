@@ -1,12 +1,109 @@
-pub use crate::buffer_pool_manager::BufferPoolManager;
-pub use crate::disk_manager::DiskManager;
-pub use crate::storage::extendible_hash_table::extendible_hash_table::ExtendibleHashTable;
-pub use crate::thread_pool::ThreadPool;
+pub use crate::access_trace::{read_access_trace, replay_access_trace, AccessRecord, ReplayStats};
+#[cfg(feature = "grpc")]
+pub use crate::admin_service::{AdminServer, AdminService};
+pub use crate::arc_replacer::ArcReplacer;
+pub use crate::background_jobs::{BackgroundJobs, JobStatus};
+pub use crate::buffer_pool_manager::{
+    BufferPoolManager, BufferPoolStats, FrameMemory, FrameWaitPolicy,
+};
+pub use crate::cancellation::CancellationToken;
+pub use crate::check::{CheckProblem, CheckReport};
+pub use crate::clock_replacer::ClockReplacer;
+pub use crate::compressed_backend::PageCompression;
+pub use crate::db_instance::{DbInstance, RecoveryPath};
+pub use crate::disk_log::Lsn;
+pub use crate::disk_manager::{
+    AlreadyInUse, CorruptPage, DiskLatencyProfile, DiskManager, DiskStats, DurabilityPolicy,
+    InjectedFault, PageView,
+};
+pub use crate::disk_scheduler::ChaosConfig;
+pub use crate::inspect::{inspect, PageKind, PageReport};
+pub use crate::key_normalization::KeyNormalization;
+pub use crate::kv::Kv;
+pub use crate::latency_breakdown::LatencyBreakdown;
+pub use crate::log_archive::RestorePoint;
+pub use crate::lru_k_replacer::{AccessType, FrameId, LruKReplacer};
+pub use crate::memtable::Memtable;
+pub use crate::operation_policy::OperationPolicy;
+pub use crate::owner_quotas::OwnerId;
+pub use crate::page::PageId;
+pub use crate::page_guard::{ReadPageGuard, WritePageGuard};
+pub use crate::replacer::Replacer;
+pub use crate::replication::Replica;
+pub use crate::rng::{rng_seed, seeded_rng, SEED_ENV};
+pub use crate::salvage::{read_salvage_dump, salvage, SalvageReport, SalvagedEntry};
+pub use crate::slru_replacer::SlruReplacer;
+pub use crate::snapshot::Snapshot;
+pub use crate::space_report::{SpaceReport, TableSpace};
+pub use crate::storage::extendible_hash_table::error::ExtendibleHashTableError;
+pub use crate::storage::extendible_hash_table::extendible_hash_table::{
+    CompactionPolicy, ExtendibleHashTable, HashTableStats,
+};
+pub use crate::storage::extendible_hash_table::extendible_hash_table_header_page::{
+    DirectoryHashBits, KeyHasher,
+};
+pub use crate::storage::extendible_hash_table::partitioned_hash_table::PartitionedHashTable;
+pub use crate::storage::extendible_hash_table::value_codec::ValueCodec;
+pub use crate::tablespace_backend::TablespaceLayout;
+pub use crate::temp_page_allocator::TempPageAllocator;
+pub use crate::thread_pool::{JobFuture, ThreadPool};
+pub use crate::two_q_replacer::TwoQReplacer;
+pub use crate::watch::ChangeEvent;
+pub use crate::write_batch::WriteBatch;
 
+mod access_trace;
+#[cfg(feature = "grpc")]
+mod admin_service;
+mod arc_replacer;
+mod background_jobs;
 mod buffer_pool_manager;
+mod cancellation;
+mod check;
+mod checksummed_backend;
+mod clock_replacer;
+mod compressed_backend;
+mod db_instance;
+mod disk_log;
 mod disk_manager;
 mod disk_scheduler;
+mod double_write_buffer;
+pub mod ffi;
+mod inspect;
+#[cfg(feature = "io-uring")]
+mod io_uring_backend;
+mod key_normalization;
+mod kv;
+mod latch_tracker;
+mod latency_breakdown;
+mod log_archive;
 mod lru_k_replacer;
+mod memtable;
+mod mirrored_backend;
+#[cfg(feature = "mmap")]
+mod mmap_backend;
+#[cfg(feature = "object-store")]
+mod object_store_backend;
+mod operation_policy;
+mod owner_quotas;
 mod page;
+mod page_allocator;
+mod page_guard;
+mod page_scrubber;
+mod replacer;
+mod replication;
+mod rng;
+mod salvage;
+mod sharded_counter;
+mod slru_replacer;
+mod snapshot;
+mod space_report;
 mod storage;
+mod structure_admission;
+mod structure_log;
+mod tablespace_backend;
+mod temp_page_allocator;
 mod thread_pool;
+mod tiered_backend;
+mod two_q_replacer;
+mod watch;
+mod write_batch;
@@ -0,0 +1,321 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    hash::Hash,
+    io::{BufReader, Read, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::write_batch::BatchRecord;
+
+// segment is finished once it gets this large, next write starts a new one
+const SEGMENT_SIZE: u64 = 16 * 1024 * 1024;
+const SEGMENT_EXTENSION: &str = "log";
+const BASE_BACKUP_EXTENSION: &str = "base";
+
+/// Point in history of archived namespace to restore, see `Kv::restore_to`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RestorePoint {
+    /// State right after write with this sequence number
+    Sequence(u64),
+    /// State with every write made at or before this time
+    Time(SystemTime),
+}
+
+impl RestorePoint {
+    fn includes(&self, sequence: u64, timestamp: u64) -> bool {
+        match self {
+            Self::Sequence(point) => sequence <= *point,
+            Self::Time(point) => timestamp <= millis(*point),
+        }
+    }
+}
+
+fn millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_millis() as u64
+}
+
+/// Write of the namespace as stored in log segment, records are stored as little endian
+/// length followed by bincode payload
+#[derive(Serialize, Deserialize, Debug)]
+struct ArchivedWrite<K, E> {
+    sequence: u64,
+    // milliseconds since unix epoch
+    timestamp: u64,
+    records: Vec<BatchRecord<K, E>>,
+}
+
+// segment is named by sequence of its first write, base backup by sequence of the last write
+// it includes and its time, so zero padded names sort in log order
+fn segment_name(sequence: u64) -> String {
+    format!("{:020}.{}", sequence, SEGMENT_EXTENSION)
+}
+
+fn base_backup_name(sequence: u64, timestamp: u64) -> String {
+    format!("{:020}-{}.{}", sequence, timestamp, BASE_BACKUP_EXTENSION)
+}
+
+fn parse_base_backup_name(path: &Path) -> Option<(u64, u64)> {
+    let (sequence, timestamp) = path.file_stem()?.to_str()?.split_once('-')?;
+
+    Some((sequence.parse().ok()?, timestamp.parse().ok()?))
+}
+
+// files of the archive with extension, sorted by name
+fn archive_files(dir: &Path, extension: &str) -> Result<Vec<PathBuf>> {
+    let mut paths = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == extension) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    Ok(paths)
+}
+
+// writes of segment in order, partially written last write is ignored
+fn read_segment<K, E>(path: &Path) -> Result<Vec<ArchivedWrite<K, E>>>
+where
+    K: DeserializeOwned,
+    E: DeserializeOwned,
+{
+    let mut data = vec![];
+    BufReader::new(File::open(path)?).read_to_end(&mut data)?;
+
+    let mut writes = vec![];
+    let mut rest = data.as_slice();
+    while let Some((len, payload)) = rest.split_first_chunk::<8>() {
+        let len = u64::from_le_bytes(*len) as usize;
+        if payload.len() < len {
+            break;
+        }
+        writes.push(
+            bincode::deserialize(&payload[..len])
+                .with_context(|| format!("Log segment {} is corrupted.", path.display()))?,
+        );
+        rest = &payload[len..];
+    }
+
+    Ok(writes)
+}
+
+/// Directory with base backups of namespace and log segments of writes made after them
+#[derive(Debug)]
+pub(crate) struct LogArchive {
+    dir: PathBuf,
+    segment: Option<File>,
+    segment_len: u64,
+}
+
+impl LogArchive {
+    /// Open archive, directory is created if it doesn't exist.
+    /// Returns it with sequence of the last write archived so far.
+    pub fn open<K, E>(dir: impl AsRef<Path>) -> Result<(Self, u64)>
+    where
+        K: DeserializeOwned,
+        E: DeserializeOwned,
+    {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Can't create log archive {}.", dir.display()))?;
+
+        let mut sequence = archive_files(&dir, BASE_BACKUP_EXTENSION)?
+            .iter()
+            .filter_map(|path| parse_base_backup_name(path))
+            .map(|(sequence, _)| sequence)
+            .max()
+            .unwrap_or(0);
+        if let Some(segment) = archive_files(&dir, SEGMENT_EXTENSION)?.last() {
+            if let Some(write) = read_segment::<K, E>(segment)?.last() {
+                sequence = sequence.max(write.sequence);
+            }
+        }
+
+        let archive = Self {
+            dir,
+            segment: None,
+            segment_len: 0,
+        };
+
+        Ok((archive, sequence))
+    }
+
+    /// Durably store whole namespace as of write with `sequence`
+    pub fn write_base_backup<K, E>(&self, sequence: u64, entries: &[(K, E)]) -> Result<()>
+    where
+        K: Serialize,
+        E: Serialize,
+    {
+        let name = base_backup_name(sequence, millis(SystemTime::now()));
+        // backup becomes visible only once it is complete
+        let temp_path = self.dir.join(format!("{name}.tmp"));
+        let mut file = File::create(&temp_path)?;
+        file.write_all(&bincode::serialize(entries)?)?;
+        file.sync_all()?;
+        fs::rename(temp_path, self.dir.join(name))?;
+
+        Ok(())
+    }
+
+    /// Append write to the current segment
+    pub fn append<K, E>(&mut self, sequence: u64, records: &[BatchRecord<K, E>]) -> Result<()>
+    where
+        K: Clone + Serialize,
+        E: Clone + Serialize,
+    {
+        let write = ArchivedWrite {
+            sequence,
+            timestamp: millis(SystemTime::now()),
+            records: records.to_vec(),
+        };
+        let payload = bincode::serialize(&write)?;
+
+        let segment = match &mut self.segment {
+            Some(segment) => segment,
+            None => {
+                let path = self.dir.join(segment_name(sequence));
+                let segment = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .with_context(|| format!("Can't create log segment {}.", path.display()))?;
+                self.segment_len = 0;
+                self.segment.insert(segment)
+            }
+        };
+        segment.write_all(&(payload.len() as u64).to_le_bytes())?;
+        segment.write_all(&payload)?;
+        self.segment_len += 8 + payload.len() as u64;
+
+        if self.segment_len >= SEGMENT_SIZE {
+            self.sync()?;
+            self.segment = None;
+        }
+
+        Ok(())
+    }
+
+    /// Wait until appended writes are durable
+    pub fn sync(&self) -> Result<()> {
+        if let Some(segment) = &self.segment {
+            segment.sync_data()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Entries of namespace at restore point, built from the latest base backup before it and
+/// writes archived after the backup. Returns them with sequence of the last included write.
+pub(crate) fn read_restore_point<K, E>(
+    dir: impl AsRef<Path>,
+    point: RestorePoint,
+) -> Result<(HashMap<K, E>, u64)>
+where
+    K: Hash + Eq + DeserializeOwned,
+    E: DeserializeOwned,
+{
+    let dir = dir.as_ref();
+    let (base_backup, mut sequence) = archive_files(dir, BASE_BACKUP_EXTENSION)?
+        .into_iter()
+        .rev()
+        .filter_map(|path| {
+            let (sequence, timestamp) = parse_base_backup_name(&path)?;
+            point
+                .includes(sequence, timestamp)
+                .then_some((path, sequence))
+        })
+        .next()
+        .context("Log archive has no base backup before restore point.")?;
+
+    let data = fs::read(&base_backup)?;
+    let entries: Vec<(K, E)> = bincode::deserialize(&data)
+        .with_context(|| format!("Base backup {} is corrupted.", base_backup.display()))?;
+    let mut entries: HashMap<K, E> = entries.into_iter().collect();
+
+    'segments: for segment in archive_files(dir, SEGMENT_EXTENSION)? {
+        for write in read_segment::<K, E>(&segment)? {
+            if write.sequence <= sequence {
+                continue;
+            }
+            if !point.includes(write.sequence, write.timestamp) {
+                break 'segments;
+            }
+
+            for record in write.records {
+                match record.new_value {
+                    Some(value) => entries.insert(record.key, value),
+                    None => entries.remove(&record.key),
+                };
+            }
+            sequence = write.sequence;
+        }
+    }
+
+    Ok((entries, sequence))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::Kv;
+
+    #[test]
+    fn test_restore_to() {
+        let dir = TempDir::new().unwrap();
+        let archive = dir.path().join("archive");
+        let kv = Kv::<String, u64>::open(dir.path().join("test.db")).unwrap();
+        kv.insert("a".into(), 1).unwrap();
+        assert_eq!(kv.start_log_archiving(&archive).unwrap(), 0);
+
+        kv.insert("b".into(), 2).unwrap();
+        kv.insert("a".into(), 3).unwrap();
+        thread::sleep(Duration::from_millis(5));
+        let time = SystemTime::now();
+        thread::sleep(Duration::from_millis(5));
+        kv.remove("b".into()).unwrap();
+        drop(kv);
+
+        let restored = Kv::<String, u64>::open(dir.path().join("restored.db")).unwrap();
+        assert_eq!(
+            restored
+                .restore_to(&archive, RestorePoint::Sequence(1))
+                .unwrap(),
+            1
+        );
+        assert_eq!(restored.get("a".into()).unwrap(), Some(1));
+        assert_eq!(restored.get("b".into()).unwrap(), Some(2));
+
+        assert_eq!(
+            restored
+                .restore_to(&archive, RestorePoint::Time(time))
+                .unwrap(),
+            2
+        );
+        assert_eq!(restored.get("a".into()).unwrap(), Some(3));
+
+        assert_eq!(
+            restored
+                .restore_to(&archive, RestorePoint::Sequence(u64::MAX))
+                .unwrap(),
+            3
+        );
+        assert_eq!(restored.get("b".into()).unwrap(), None);
+
+        // sequence goes on after restart
+        let kv = Kv::<String, u64>::open(dir.path().join("test.db")).unwrap();
+        assert_eq!(kv.start_log_archiving(&archive).unwrap(), 3);
+    }
+}
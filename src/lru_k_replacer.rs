@@ -1,7 +1,45 @@
 use std::collections::{HashMap, VecDeque};
+use std::fmt;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-pub type FrameId = usize;
+/// Id of buffer pool frame, not to be confused with `PageId` of page it holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FrameId(usize);
+
+impl FrameId {
+    /// Id no frame has, for slots which don't point to a frame
+    pub const INVALID: Self = Self(usize::MAX);
+
+    pub const fn new(id: usize) -> Self {
+        Self(id)
+    }
+
+    pub const fn as_usize(self) -> usize {
+        self.0
+    }
+
+    pub fn is_valid(self) -> bool {
+        self != Self::INVALID
+    }
+}
+
+impl fmt::Display for FrameId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl From<usize> for FrameId {
+    fn from(id: usize) -> Self {
+        Self(id)
+    }
+}
+
+impl From<FrameId> for usize {
+    fn from(frame_id: FrameId) -> Self {
+        frame_id.0
+    }
+}
 pub type Timestamp = u128;
 
 fn get_now_ts() -> Timestamp {
@@ -14,20 +52,18 @@ fn get_now_ts() -> Timestamp {
 #[derive(Debug)]
 struct LruKNode {
     k: usize,
-    frame_id: FrameId,
     is_evictable: bool,
     history: VecDeque<Timestamp>,
 }
 
 impl LruKNode {
-    fn new(frame_id: FrameId, k: usize) -> Self {
+    fn new(k: usize) -> Self {
         assert!(k > 0);
         let mut history: VecDeque<Timestamp> = VecDeque::with_capacity(k);
         history.push_front(get_now_ts());
 
         Self {
             k,
-            frame_id,
             history,
             is_evictable: false,
         }
@@ -41,13 +77,18 @@ impl LruKNode {
         }
     }
 
+    #[cfg(test)]
     fn k_distance(&self) -> Option<usize> {
+        self.k_distance_at(get_now_ts())
+    }
+
+    fn k_distance_at(&self, now: Timestamp) -> Option<usize> {
         if self.history.len() < self.k {
             return None;
         }
         let kth_history_entry = self.history[self.history.len() - 1];
 
-        Some((get_now_ts() - kth_history_entry) as usize)
+        Some((now - kth_history_entry) as usize)
     }
 
     fn least_recent_access(&self) -> Timestamp {
@@ -61,11 +102,11 @@ impl LruKNode {
 
 #[derive(Debug)]
 pub struct LruKReplacer {
-    num_of_frames: usize,
     k: usize,
     node_store: HashMap<FrameId, LruKNode>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum AccessType {
     Unknown,
     Lookup,
@@ -75,24 +116,27 @@ pub enum AccessType {
 
 impl LruKReplacer {
     pub fn new(num_of_frames: usize, k: usize) -> Self {
-        let node_store = HashMap::default();
-        Self {
-            num_of_frames,
-            k,
-            node_store,
-        }
+        let node_store = HashMap::with_capacity(num_of_frames);
+
+        Self { k, node_store }
     }
 
     pub fn evict(&self) -> Option<FrameId> {
-        let longest_k_distance_node = self.node_store.iter().max_by(|x, y| {
-            x.1.k_distance()
-                .unwrap_or(usize::MAX)
-                .cmp(&y.1.k_distance().unwrap_or(usize::MAX))
-        });
+        // distances are only comparable when measured from the same moment
+        let now = get_now_ts();
+        let longest_k_distance_node = self
+            .node_store
+            .iter()
+            .filter(|(_, value)| value.get_is_evictable())
+            .max_by(|x, y| {
+                x.1.k_distance_at(now)
+                    .unwrap_or(usize::MAX)
+                    .cmp(&y.1.k_distance_at(now).unwrap_or(usize::MAX))
+            });
 
         let longest_k_distance = longest_k_distance_node?
             .1
-            .k_distance()
+            .k_distance_at(now)
             .unwrap_or(usize::MAX);
 
         // it is possible to have multiple nodes with same longest k distance
@@ -101,7 +145,7 @@ impl LruKReplacer {
             .iter()
             .filter(|(_, value)| {
                 value.get_is_evictable()
-                    && value.k_distance().unwrap_or(usize::MAX) == longest_k_distance
+                    && value.k_distance_at(now).unwrap_or(usize::MAX) == longest_k_distance
             })
             .collect::<Vec<(&FrameId, &LruKNode)>>();
 
@@ -123,7 +167,7 @@ impl LruKReplacer {
         match node {
             Some(node) => node.record_access(),
             _ => {
-                let new_node = LruKNode::new(frame_id, self.k);
+                let new_node = LruKNode::new(self.k);
                 self.node_store.insert(frame_id, new_node);
             }
         };
@@ -154,7 +198,7 @@ mod tests {
     #[test]
     fn test_init_node() {
         let now = get_now_ts();
-        let node = LruKNode::new(10, 2);
+        let node = LruKNode::new(2);
 
         // TODO: rework
         assert!(node.least_recent_access() - now < 1000000);
@@ -163,7 +207,7 @@ mod tests {
 
     #[test]
     fn test_history() {
-        let mut node = LruKNode::new(10, 3);
+        let mut node = LruKNode::new(3);
         node.record_access();
         node.record_access();
 
@@ -181,15 +225,15 @@ mod tests {
     fn test_size_after_record_access() {
         let mut replacer = LruKReplacer::new(10, 2);
 
-        replacer.record_access(12, AccessType::Unknown);
-        replacer.record_access(13, AccessType::Unknown);
+        replacer.record_access(FrameId::new(12), AccessType::Unknown);
+        replacer.record_access(FrameId::new(13), AccessType::Unknown);
 
         assert_eq!(replacer.size(), 0);
     }
 
     #[test]
     fn test_size_after_set_evictable() {
-        let frame_id = 12;
+        let frame_id = FrameId::new(12);
         let mut replacer = LruKReplacer::new(10, 2);
 
         replacer.record_access(frame_id, AccessType::Unknown);
@@ -203,9 +247,9 @@ mod tests {
     #[test]
     fn test_eviction_1() {
         let mut replacer = LruKReplacer::new(10, 2);
-        let first_frame_id = 10;
-        let second_frame_id = 11;
-        let third_frame_id = 12;
+        let first_frame_id = FrameId::new(10);
+        let second_frame_id = FrameId::new(11);
+        let third_frame_id = FrameId::new(12);
         replacer.record_access(first_frame_id, AccessType::Unknown);
         replacer.record_access(second_frame_id, AccessType::Unknown);
         replacer.record_access(third_frame_id, AccessType::Unknown);
@@ -225,9 +269,9 @@ mod tests {
     #[test]
     fn test_eviction_2() {
         let mut replacer = LruKReplacer::new(10, 3);
-        let first_frame_id = 10;
-        let second_frame_id = 11;
-        let third_frame_id = 12;
+        let first_frame_id = FrameId::new(10);
+        let second_frame_id = FrameId::new(11);
+        let third_frame_id = FrameId::new(12);
         replacer.record_access(first_frame_id, AccessType::Unknown);
         replacer.record_access(second_frame_id, AccessType::Unknown);
         replacer.record_access(second_frame_id, AccessType::Unknown);
@@ -248,8 +292,8 @@ mod tests {
     #[test]
     fn test_eviction_3() {
         let mut replacer = LruKReplacer::new(10, 2);
-        let first_frame_id = 10;
-        let second_frame_id = 11;
+        let first_frame_id = FrameId::new(10);
+        let second_frame_id = FrameId::new(11);
         replacer.record_access(first_frame_id, AccessType::Unknown);
         replacer.record_access(second_frame_id, AccessType::Unknown);
 
@@ -266,9 +310,9 @@ mod tests {
     #[test]
     fn test_eviction_4() {
         let mut replacer = LruKReplacer::new(10, 3);
-        let first_frame_id = 10;
-        let second_frame_id = 11;
-        let third_frame_id = 12;
+        let first_frame_id = FrameId::new(10);
+        let second_frame_id = FrameId::new(11);
+        let third_frame_id = FrameId::new(12);
         replacer.record_access(first_frame_id, AccessType::Unknown);
         replacer.record_access(second_frame_id, AccessType::Unknown);
         replacer.record_access(second_frame_id, AccessType::Unknown);
@@ -288,7 +332,7 @@ mod tests {
     #[test]
     fn test_eviction_5() {
         let mut replacer = LruKReplacer::new(10, 3);
-        let first_frame_id = 10;
+        let first_frame_id = FrameId::new(10);
         replacer.record_access(first_frame_id, AccessType::Unknown);
 
         replacer.set_evictable(first_frame_id, true);
@@ -298,4 +342,21 @@ mod tests {
 
         assert_eq!(frame_id, None);
     }
+
+    // frame with longest k distance is not evictable, next evictable frame should be evicted
+    #[test]
+    fn test_eviction_6() {
+        let mut replacer = LruKReplacer::new(10, 2);
+        let first_frame_id = FrameId::new(10);
+        let second_frame_id = FrameId::new(11);
+        replacer.record_access(first_frame_id, AccessType::Unknown);
+        replacer.record_access(second_frame_id, AccessType::Unknown);
+        replacer.record_access(second_frame_id, AccessType::Unknown);
+
+        replacer.set_evictable(second_frame_id, true);
+
+        let frame_id = replacer.evict();
+
+        assert_eq!(frame_id, Some(second_frame_id));
+    }
 }
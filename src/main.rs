@@ -1,13 +1,25 @@
-use crate::lru_k_replacer::LruKReplacer;
+use anyhow::{bail, Result};
+use cmu_db_rs::Kv;
 
-mod buffer_pool_manager;
-mod disk_manager;
-mod disk_scheduler;
-mod lru_k_replacer;
-mod page;
-mod storage;
+fn main() -> Result<()> {
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    let Some(path) = args.first() else {
+        bail!("Usage: cmu-db-rs <path> get <key> | insert <key> <value> | remove <key>");
+    };
+    let kv = Kv::<String, String>::open(path)?;
 
-fn main() {
-    let lru_k_replacer = LruKReplacer::new(5, 5);
-    lru_k_replacer.evict();
+    match &args[1..] {
+        [command, key] if command == "get" => {
+            println!("{:?}", kv.get(key.clone())?);
+        }
+        [command, key, value] if command == "insert" => {
+            kv.insert(key.clone(), value.clone())?;
+        }
+        [command, key] if command == "remove" => {
+            println!("{:?}", kv.remove(key.clone())?);
+        }
+        _ => bail!("Unknown command."),
+    }
+
+    Ok(())
 }
@@ -0,0 +1,238 @@
+use std::{
+    fmt::Debug,
+    hash::Hash,
+    mem,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use anyhow::Result;
+use crossbeam_skiplist::SkipMap;
+use parking_lot::{Mutex, RwLock};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{kv::Kv, write_batch::WriteBatch};
+
+// buffered writes ordered by encoded key, `None` value is buffered remove
+type Table<K, V> = SkipMap<Vec<u8>, (K, Option<V>)>;
+
+#[derive(Debug)]
+struct Tables<K, V> {
+    // takes new writes
+    active: Arc<Table<K, V>>,
+    // being applied to the store, it stays here until applied successfully
+    immutable: Option<Arc<Table<K, V>>>,
+}
+
+#[derive(Debug)]
+struct MemtableState<K, V> {
+    kv: Arc<Kv<K, V>>,
+    // writes hold it shared, so table is never rotated in the middle of write
+    tables: RwLock<Tables<K, V>>,
+    capacity: usize,
+    flush_scheduled: AtomicBool,
+    // one flush at a time
+    flush_latch: Mutex<()>,
+}
+
+impl<K, V> MemtableState<K, V>
+where
+    K: Hash + Eq + Clone + Debug + Serialize + DeserializeOwned + ToString + Send + Sync + 'static,
+    V: Clone + Debug + Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    fn flush(&self) -> Result<()> {
+        let _flush_latch = self.flush_latch.lock();
+
+        let table = {
+            let mut tables = self.tables.write();
+            // table left by failed flush goes first
+            if tables.immutable.is_none() {
+                let active = mem::replace(&mut tables.active, Arc::new(SkipMap::new()));
+                tables.immutable = Some(active);
+            }
+            Arc::clone(tables.immutable.as_ref().unwrap())
+        };
+        if table.is_empty() {
+            self.tables.write().immutable = None;
+            return Ok(());
+        }
+
+        let mut batch = WriteBatch::new();
+        for entry in table.iter() {
+            match entry.value().clone() {
+                (key, Some(value)) => batch.insert(key, value),
+                (key, None) => batch.remove(key),
+            }
+        }
+        self.kv.apply_batch(batch)?;
+        self.tables.write().immutable = None;
+
+        Ok(())
+    }
+}
+
+/// Write buffer in front of `Kv`. Writes land in concurrent in-memory skip list, background
+/// job on database thread pool applies them to the store as a single batch once `capacity`
+/// of them are buffered. Reads see buffered writes first.
+///
+/// Buffered writes are lost on crash until they are flushed, they are flushed when memtable
+/// is dropped as well. Writers apply the buffer themselves if it reaches twice its capacity
+/// while the previous one is still being applied.
+#[derive(Debug)]
+pub struct Memtable<K, V>
+where
+    K: Hash + Eq + Clone + Debug + Serialize + DeserializeOwned + ToString + Send + Sync + 'static,
+    V: Clone + Debug + Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    state: Arc<MemtableState<K, V>>,
+}
+
+impl<K, V> Memtable<K, V>
+where
+    K: Hash + Eq + Clone + Debug + Serialize + DeserializeOwned + ToString + Send + Sync + 'static,
+    V: Clone + Debug + Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    pub fn new(kv: Arc<Kv<K, V>>, capacity: usize) -> Self {
+        let tables = Tables {
+            active: Arc::new(SkipMap::new()),
+            immutable: None,
+        };
+
+        Self {
+            state: Arc::new(MemtableState {
+                kv,
+                tables: RwLock::new(tables),
+                capacity,
+                flush_scheduled: AtomicBool::new(false),
+                flush_latch: Mutex::new(()),
+            }),
+        }
+    }
+
+    pub fn get(&self, key: K) -> Result<Option<V>> {
+        let encoded_key = bincode::serialize(&key)?;
+        {
+            let tables = self.state.tables.read();
+            let buffered = tables.active.get(&encoded_key).or_else(|| {
+                tables
+                    .immutable
+                    .as_ref()
+                    .and_then(|table| table.get(&encoded_key))
+            });
+            if let Some(entry) = buffered {
+                return Ok(entry.value().1.clone());
+            }
+        }
+
+        self.state.kv.get(key)
+    }
+
+    pub fn insert(&self, key: K, value: V) -> Result<()> {
+        self.write(key, Some(value))
+    }
+
+    pub fn remove(&self, key: K) -> Result<()> {
+        self.write(key, None)
+    }
+
+    fn write(&self, key: K, value: Option<V>) -> Result<()> {
+        let encoded_key = bincode::serialize(&key)?;
+        let buffered = {
+            let tables = self.state.tables.read();
+            tables.active.insert(encoded_key, (key, value));
+            tables.active.len()
+        };
+        if buffered < self.state.capacity {
+            return Ok(());
+        }
+
+        // background flush can't keep up, writer stalls until buffer is applied
+        if buffered >= self.state.capacity * 2 {
+            return self.state.flush();
+        }
+        if !self.state.flush_scheduled.swap(true, Ordering::AcqRel) {
+            let state = Arc::downgrade(&self.state);
+            self.state.kv.thread_pool().spawn(move || {
+                let Some(state) = state.upgrade() else {
+                    return;
+                };
+                state.flush_scheduled.store(false, Ordering::Release);
+                if let Err(error) = state.flush() {
+                    let error = format!("{:#}", error);
+                    tracing::warn!(error, "memtable flush failed");
+                }
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Number of buffered writes not applied to the store yet
+    pub fn len(&self) -> usize {
+        let tables = self.state.tables.read();
+
+        tables.active.len() + tables.immutable.as_ref().map_or(0, |table| table.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Apply buffered writes to the store and make them durable
+    pub fn flush(&self) -> Result<()> {
+        self.state.flush()?;
+        self.state.kv.flush()
+    }
+}
+
+impl<K, V> Drop for Memtable<K, V>
+where
+    K: Hash + Eq + Clone + Debug + Serialize + DeserializeOwned + ToString + Send + Sync + 'static,
+    V: Clone + Debug + Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    fn drop(&mut self) {
+        let _ = self.state.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_memtable() {
+        let dir = TempDir::new().unwrap();
+        let kv = Arc::new(Kv::<String, u64>::open(dir.path().join("test.db")).unwrap());
+        kv.insert("stored".into(), 1).unwrap();
+        let memtable = Memtable::new(Arc::clone(&kv), 4);
+
+        memtable.insert("a".into(), 1).unwrap();
+        memtable.remove("stored".into()).unwrap();
+        assert_eq!(memtable.get("a".into()).unwrap(), Some(1));
+        assert_eq!(memtable.get("stored".into()).unwrap(), None);
+        assert_eq!(kv.get("stored".into()).unwrap(), Some(1));
+
+        // reaching capacity schedules background flush
+        memtable.insert("b".into(), 2).unwrap();
+        memtable.insert("c".into(), 3).unwrap();
+        for _ in 0..100 {
+            if memtable.is_empty() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(memtable.is_empty());
+        assert_eq!(kv.get("c".into()).unwrap(), Some(3));
+        assert_eq!(kv.get("stored".into()).unwrap(), None);
+
+        memtable.insert("d".into(), 4).unwrap();
+        memtable.flush().unwrap();
+        assert_eq!(kv.get("d".into()).unwrap(), Some(4));
+    }
+}
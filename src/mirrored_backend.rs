@@ -0,0 +1,172 @@
+use std::{fs::File, path::Path};
+
+use anyhow::{Context, Result};
+use parking_lot::Mutex;
+
+use crate::checksummed_backend::{encode_frame, read_frame, verify_frame, write_frame, FRAME_SIZE};
+use crate::disk_manager::{open_data_file, CorruptPage};
+use crate::page::{PageId, PAGE_SIZE};
+
+/// Every page is written to two files, page which fails checksum on read is taken from
+/// the other file and repaired. Files store page data followed by its checksum.
+/// Primary frame of zeroes passes checksum as never written page, so it is taken from
+/// mirror as well if mirror holds the page.
+#[derive(Debug)]
+pub(crate) struct MirroredBackend {
+    primary: Mutex<File>,
+    mirror: Mutex<File>,
+}
+
+impl MirroredBackend {
+    pub fn open(primary_path: &Path, mirror_path: &Path) -> Result<Self> {
+        Ok(Self {
+            primary: Mutex::new(open_data_file(primary_path)?),
+            mirror: Mutex::new(open_data_file(mirror_path)?),
+        })
+    }
+
+    pub fn read_page(&self, page_id: PageId) -> Result<Vec<u8>> {
+        let mut primary = self.primary.lock();
+        let frame = read_frame(&mut primary, page_id)?;
+        if holds_page(&frame) {
+            return Ok(frame[..PAGE_SIZE].to_vec());
+        }
+
+        let mirror_frame = read_frame(&mut self.mirror.lock(), page_id)?;
+        if !holds_page(&mirror_frame) {
+            // page was never written
+            if is_blank(&frame) && verify_frame(&mirror_frame).is_some() {
+                return Ok(vec![0; PAGE_SIZE]);
+            }
+            // page is corrupted in both mirrors
+            return Err(CorruptPage { page_id }.into());
+        }
+        write_frame(&mut primary, page_id, &mirror_frame)
+            .with_context(|| format!("Can't repair page {} from mirror.", page_id))?;
+
+        Ok(mirror_frame[..PAGE_SIZE].to_vec())
+    }
+
+    /// Write page data of page size to both files
+    pub fn write_page(&self, page_id: PageId, data: &[u8]) -> Result<()> {
+        let frame = encode_frame(data);
+
+        write_frame(&mut self.primary.lock(), page_id, &frame)?;
+        write_frame(&mut self.mirror.lock(), page_id, &frame)
+    }
+
+    /// Copies of page which fail checksum or are blank while the other one holds page,
+    /// nothing is repaired
+    pub fn corrupted_copies(&self, page_id: PageId) -> Result<Vec<&'static str>> {
+        let primary = read_frame(&mut self.primary.lock(), page_id)?;
+        let mirror = read_frame(&mut self.mirror.lock(), page_id)?;
+        let is_corrupted = |frame: &[u8], other: &[u8]| {
+            verify_frame(frame).is_none() || (is_blank(frame) && holds_page(other))
+        };
+
+        let mut copies = vec![];
+        if is_corrupted(&primary, &mirror) {
+            copies.push("primary");
+        }
+        if is_corrupted(&mirror, &primary) {
+            copies.push("mirror");
+        }
+
+        Ok(copies)
+    }
+
+    pub fn sync(&self) -> Result<()> {
+        self.primary.lock().sync_all()?;
+        self.mirror.lock().sync_all()?;
+
+        Ok(())
+    }
+
+    pub fn num_pages(&self) -> Result<usize> {
+        let primary_len = self.primary.lock().metadata()?.len() as usize;
+        let mirror_len = self.mirror.lock().metadata()?.len() as usize;
+
+        Ok(primary_len.max(mirror_len).div_ceil(FRAME_SIZE))
+    }
+}
+
+fn is_blank(frame: &[u8]) -> bool {
+    frame.iter().all(|byte| *byte == 0)
+}
+
+// frame of written page which matches its checksum
+fn holds_page(frame: &[u8]) -> bool {
+    !is_blank(frame) && verify_frame(frame).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_corrupted_page_is_repaired_from_mirror() {
+        let dir = TempDir::new().unwrap();
+        let primary_path = dir.path().join("primary.db");
+        let mirror_path = dir.path().join("mirror.db");
+        let backend = MirroredBackend::open(&primary_path, &mirror_path).unwrap();
+
+        backend.write_page(PageId::new(1), &[7; PAGE_SIZE]).unwrap();
+        assert_eq!(
+            backend.read_page(PageId::new(0)).unwrap(),
+            vec![0; PAGE_SIZE]
+        );
+
+        let corrupt = |path: &Path| {
+            let mut data = fs::read(path).unwrap();
+            data[FRAME_SIZE + 10] = 0;
+            fs::write(path, data).unwrap();
+        };
+        corrupt(&primary_path);
+        assert_eq!(
+            backend.read_page(PageId::new(1)).unwrap(),
+            vec![7; PAGE_SIZE]
+        );
+        assert_eq!(
+            fs::read(&primary_path).unwrap(),
+            fs::read(&mirror_path).unwrap()
+        );
+
+        corrupt(&primary_path);
+        corrupt(&mirror_path);
+        let error = backend.read_page(PageId::new(1)).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<CorruptPage>().unwrap().page_id,
+            PageId::new(1)
+        );
+    }
+
+    #[test]
+    fn test_zeroed_primary_page_is_repaired_from_mirror() {
+        let dir = TempDir::new().unwrap();
+        let primary_path = dir.path().join("primary.db");
+        let mirror_path = dir.path().join("mirror.db");
+        let backend = MirroredBackend::open(&primary_path, &mirror_path).unwrap();
+        backend.write_page(PageId::new(1), &[7; PAGE_SIZE]).unwrap();
+
+        let mut data = fs::read(&primary_path).unwrap();
+        data[FRAME_SIZE..2 * FRAME_SIZE].fill(0);
+        fs::write(&primary_path, data).unwrap();
+        assert_eq!(
+            backend.corrupted_copies(PageId::new(1)).unwrap(),
+            vec!["primary"]
+        );
+        assert_eq!(
+            backend.read_page(PageId::new(1)).unwrap(),
+            vec![7; PAGE_SIZE]
+        );
+        assert_eq!(
+            fs::read(&primary_path).unwrap(),
+            fs::read(&mirror_path).unwrap()
+        );
+        assert!(backend.corrupted_copies(PageId::new(1)).unwrap().is_empty());
+    }
+}
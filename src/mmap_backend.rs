@@ -0,0 +1,104 @@
+use std::{fs::File, ops::Range, path::Path};
+
+use anyhow::{Context, Result};
+use memmap2::MmapMut;
+use parking_lot::{MappedRwLockReadGuard, RwLock, RwLockReadGuard};
+
+use crate::disk_manager::open_data_file;
+use crate::page::{PageId, PAGE_SIZE};
+
+/// Data file with the layout of `DiskManager::open` served from its memory mapping.
+/// Reads copy page from mapping or borrow it without system calls, writes are copied into
+/// mapping and synced by `msync`. Mapping is remapped when writes grow the file.
+#[derive(Debug)]
+pub(crate) struct MmapBackend {
+    file: File,
+    // empty file can't be mapped
+    map: RwLock<Option<MmapMut>>,
+}
+
+impl MmapBackend {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = open_data_file(path)?;
+        let map = map_file(&file)?;
+
+        Ok(Self {
+            file,
+            map: RwLock::new(map),
+        })
+    }
+
+    /// Page data borrowed from mapping, none if page is past the end of file
+    pub fn page(&self, page_id: PageId) -> Option<MappedRwLockReadGuard<'_, [u8]>> {
+        let range = page_range(page_id, 1);
+        RwLockReadGuard::try_map(self.map.read(), |map| map.as_ref()?.get(range)).ok()
+    }
+
+    pub fn read_page(&self, page_id: PageId) -> Vec<u8> {
+        self.page(page_id)
+            .map_or_else(|| vec![0; PAGE_SIZE], |page| page.to_vec())
+    }
+
+    /// Write consecutive whole pages and wait until `msync` of them is done
+    pub fn write_pages(&self, first_page_id: PageId, data: &[u8]) -> Result<()> {
+        let range = page_range(first_page_id, data.len() / PAGE_SIZE);
+        self.grow(range.end)?;
+
+        let mut map = self.map.write();
+        let map = map.as_mut().context("Data file is not mapped.")?;
+        map[range.clone()].copy_from_slice(data);
+        map.flush_range(range.start, range.len())
+            .context("Can't sync mapped pages.")?;
+
+        Ok(())
+    }
+
+    pub fn sync(&self) -> Result<()> {
+        if let Some(map) = &*self.map.read() {
+            map.flush()?;
+        }
+        self.file.sync_all()?;
+
+        Ok(())
+    }
+
+    pub fn num_pages(&self) -> usize {
+        self.map
+            .read()
+            .as_ref()
+            .map_or(0, |map| map.len().div_ceil(PAGE_SIZE))
+    }
+
+    // file is extended to `len` bytes and mapped again, pages borrowed meanwhile keep
+    // the old mapping read latched
+    fn grow(&self, len: usize) -> Result<()> {
+        if self.map.read().as_ref().is_some_and(|map| map.len() >= len) {
+            return Ok(());
+        }
+
+        let mut map = self.map.write();
+        if map.as_ref().is_some_and(|map| map.len() >= len) {
+            return Ok(());
+        }
+        self.file.set_len(len as u64)?;
+        *map = map_file(&self.file)?;
+
+        Ok(())
+    }
+}
+
+fn map_file(file: &File) -> Result<Option<MmapMut>> {
+    if file.metadata()?.len() == 0 {
+        return Ok(None);
+    }
+    // data file is locked exclusively, so nobody else changes its size under the mapping
+    let map = unsafe { MmapMut::map_mut(file) }.context("Can't map data file.")?;
+
+    Ok(Some(map))
+}
+
+fn page_range(first_page_id: PageId, pages: usize) -> Range<usize> {
+    let start = first_page_id.as_usize() * PAGE_SIZE;
+
+    start..start + pages * PAGE_SIZE
+}
@@ -0,0 +1,304 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{mpsc, Arc},
+};
+
+use anyhow::{anyhow, Context, Result};
+use futures::TryStreamExt;
+use object_store::{path::Path, ObjectStore, PutPayload};
+use parking_lot::{Mutex, MutexGuard};
+use tokio::runtime::Runtime;
+
+use crate::page::{PageId, PAGE_SIZE};
+
+#[derive(Debug)]
+struct CachedPage {
+    data: Vec<u8>,
+    is_dirty: bool,
+    // changed by every write, upload marks page clean only if it uploaded this write
+    version: u64,
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct PageCache {
+    pages: HashMap<PageId, CachedPage>,
+    num_pages: usize,
+    // advanced by every access and write, versions and last uses come from it
+    clock: u64,
+    writes: u64,
+}
+
+impl PageCache {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+}
+
+/// Pages are stored as objects `<prefix>/<page_id>` of object store.
+/// Written pages stay in local write-back cache until `sync`, or until they are evicted
+/// from it, so object store sees only full page uploads. The least recently used clean
+/// page is evicted first.
+/// Object store is async, its calls run on a private runtime and the blocking disk
+/// scheduler workers wait for them, so callers may be inside another runtime. Cache
+/// isn't latched while requests are in flight.
+#[derive(Debug)]
+pub(crate) struct ObjectStoreBackend {
+    store: Arc<dyn ObjectStore>,
+    prefix: Path,
+    cache_capacity: usize,
+    cache: Mutex<PageCache>,
+    // uploads run one at a time, so an older copy of page never overwrites a newer one
+    upload: Mutex<()>,
+    // taken out on drop, runtime can't be dropped inside another one
+    runtime: Option<Runtime>,
+}
+
+impl ObjectStoreBackend {
+    pub fn open(store: Arc<dyn ObjectStore>, prefix: &str, cache_capacity: usize) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()?;
+        let mut backend = Self {
+            store,
+            prefix: Path::from(prefix),
+            cache_capacity: cache_capacity.max(1),
+            cache: Mutex::new(PageCache::default()),
+            upload: Mutex::new(()),
+            runtime: Some(runtime),
+        };
+
+        let store = Arc::clone(&backend.store);
+        let prefix = backend.prefix.clone();
+        let objects = backend
+            .run(async move { store.list(Some(&prefix)).try_collect::<Vec<_>>().await })?
+            .context("Can't list pages in object store.")?;
+        backend.cache.get_mut().num_pages = objects
+            .iter()
+            .filter_map(|object| object.location.filename()?.parse::<usize>().ok())
+            .max()
+            .map_or(0, |page_id| page_id + 1);
+
+        Ok(backend)
+    }
+
+    pub fn read_page(&self, page_id: PageId) -> Result<Vec<u8>> {
+        loop {
+            let mut cache = self.cache.lock();
+            let clock = cache.tick();
+            if let Some(page) = cache.pages.get_mut(&page_id) {
+                page.last_used = clock;
+                return Ok(page.data.clone());
+            }
+            let writes = cache.writes;
+            drop(cache);
+
+            let store = Arc::clone(&self.store);
+            let location = self.location(page_id);
+            let data = self.run(async move {
+                match store.get(&location).await {
+                    Ok(object) => Ok(object.bytes().await?.to_vec()),
+                    Err(object_store::Error::NotFound { .. }) => Ok(vec![0; PAGE_SIZE]),
+                    Err(error) => Err(error),
+                }
+            })??;
+
+            let mut cache = self.cache.lock();
+            self.make_room(&mut cache)?;
+            // page written while it was downloaded is read again, object may be older
+            if cache.writes != writes {
+                continue;
+            }
+            let last_used = cache.tick();
+            cache.pages.insert(
+                page_id,
+                CachedPage {
+                    data: data.clone(),
+                    is_dirty: false,
+                    version: 0,
+                    last_used,
+                },
+            );
+
+            return Ok(data);
+        }
+    }
+
+    pub fn write_page(&self, page_id: PageId, data: Vec<u8>) -> Result<()> {
+        let mut cache = self.cache.lock();
+        if !cache.pages.contains_key(&page_id) {
+            self.make_room(&mut cache)?;
+        }
+
+        cache.num_pages = cache.num_pages.max(page_id.as_usize() + 1);
+        cache.writes += 1;
+        let version = cache.tick();
+        cache.pages.insert(
+            page_id,
+            CachedPage {
+                data,
+                is_dirty: true,
+                version,
+                last_used: version,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Upload all dirty pages
+    pub fn sync(&self) -> Result<()> {
+        self.upload_dirty_pages()
+    }
+
+    pub fn num_pages(&self) -> usize {
+        self.cache.lock().num_pages
+    }
+
+    // drop the least recently used clean page if cache is full, dirty pages are uploaded
+    // first if there is none
+    fn make_room(&self, cache: &mut MutexGuard<'_, PageCache>) -> Result<()> {
+        while cache.pages.len() >= self.cache_capacity {
+            let page_id = cache
+                .pages
+                .iter()
+                .filter(|(_, page)| !page.is_dirty)
+                .min_by_key(|(_, page)| page.last_used)
+                .map(|(page_id, _)| *page_id);
+            match page_id {
+                Some(page_id) => {
+                    cache.pages.remove(&page_id);
+                }
+                None => MutexGuard::unlocked(cache, || self.upload_dirty_pages())?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn upload_dirty_pages(&self) -> Result<()> {
+        let _upload = self.upload.lock();
+        let dirty_pages = self
+            .cache
+            .lock()
+            .pages
+            .iter()
+            .filter(|(_, page)| page.is_dirty)
+            .map(|(page_id, page)| (*page_id, page.version, page.data.clone()))
+            .collect::<Vec<_>>();
+
+        for (page_id, version, data) in dirty_pages {
+            let store = Arc::clone(&self.store);
+            let location = self.location(page_id);
+            self.run(async move { store.put(&location, PutPayload::from(data)).await })?
+                .with_context(|| format!("Can't upload page {}.", page_id))?;
+            // page written meanwhile stays dirty
+            if let Some(page) = self.cache.lock().pages.get_mut(&page_id) {
+                if page.version == version {
+                    page.is_dirty = false;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // wait for future spawned on private runtime, calling thread may be inside another
+    // runtime, which can't block on futures
+    fn run<F>(&self, future: F) -> Result<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        self.runtime
+            .as_ref()
+            .expect("Runtime is dropped only with backend.")
+            .spawn(async move {
+                let _ = sender.send(future.await);
+            });
+
+        receiver
+            .recv()
+            .map_err(|_| anyhow!("Object store request didn't finish."))
+    }
+
+    fn location(&self, page_id: PageId) -> Path {
+        self.prefix.child(page_id.to_string())
+    }
+}
+
+impl Drop for ObjectStoreBackend {
+    fn drop(&mut self) {
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use object_store::memory::InMemory;
+
+    use super::*;
+
+    #[test]
+    fn test_write_back_cache() {
+        let store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        let backend = ObjectStoreBackend::open(Arc::clone(&store), "db", 2).unwrap();
+
+        for page_id in 0..5 {
+            backend
+                .write_page(PageId::new(page_id), vec![page_id as u8; PAGE_SIZE])
+                .unwrap();
+        }
+        for page_id in 0..5 {
+            assert_eq!(
+                backend.read_page(PageId::new(page_id)).unwrap()[0],
+                page_id as u8
+            );
+        }
+        backend.sync().unwrap();
+        drop(backend);
+
+        let backend = ObjectStoreBackend::open(store, "db", 2).unwrap();
+        assert_eq!(backend.num_pages(), 5);
+        assert_eq!(backend.read_page(PageId::new(3)).unwrap()[0], 3);
+        assert_eq!(
+            backend.read_page(PageId::new(7)).unwrap(),
+            vec![0; PAGE_SIZE]
+        );
+
+        // the least recently used clean page is evicted
+        backend.read_page(PageId::new(3)).unwrap();
+        backend.read_page(PageId::new(1)).unwrap();
+        let cache = backend.cache.lock();
+        assert!(cache.pages.contains_key(&PageId::new(3)));
+        assert!(!cache.pages.contains_key(&PageId::new(7)));
+    }
+
+    #[test]
+    fn test_sync_inside_runtime() {
+        let store: Arc<dyn ObjectStore> = Arc::new(InMemory::new());
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async {
+            let backend = ObjectStoreBackend::open(Arc::clone(&store), "db", 2).unwrap();
+            for page_id in 0..3 {
+                backend
+                    .write_page(PageId::new(page_id), vec![page_id as u8; PAGE_SIZE])
+                    .unwrap();
+            }
+            backend.sync().unwrap();
+            assert_eq!(backend.read_page(PageId::new(0)).unwrap()[0], 0);
+        });
+
+        let backend = ObjectStoreBackend::open(store, "db", 2).unwrap();
+        assert_eq!(backend.read_page(PageId::new(2)).unwrap()[0], 2);
+    }
+}
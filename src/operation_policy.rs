@@ -0,0 +1,47 @@
+use std::{io, time::Duration};
+
+use crate::ExtendibleHashTableError;
+
+/// Timeout and retries of operation run by `Kv::call`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OperationPolicy {
+    /// Time all attempts may take. Attempt in flight when it passes is not interrupted,
+    /// so timed out write may still take effect.
+    pub timeout: Option<Duration>,
+    /// Attempts made after the first one failed with transient error
+    pub max_retries: u32,
+    /// Wait before the first retry, it doubles with every next one
+    pub backoff: Duration,
+}
+
+impl Default for OperationPolicy {
+    fn default() -> Self {
+        Self {
+            timeout: None,
+            max_retries: 3,
+            backoff: Duration::from_millis(10),
+        }
+    }
+}
+
+impl OperationPolicy {
+    pub(crate) fn backoff(&self, retry: u32) -> Duration {
+        self.backoff.saturating_mul(1 << retry.min(16))
+    }
+}
+
+/// Whether operation failed with error which may go away on its own: buffer pool had
+/// no frame to give or I/O was interrupted or timed out
+pub(crate) fn is_transient(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        if let Some(error) = cause.downcast_ref::<ExtendibleHashTableError>() {
+            return matches!(error, ExtendibleHashTableError::PageNotAvailable);
+        }
+        cause.downcast_ref::<io::Error>().is_some_and(|error| {
+            matches!(
+                error.kind(),
+                io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            )
+        })
+    })
+}
@@ -0,0 +1,50 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::lru_k_replacer::FrameId;
+
+/// Tag of page fetches made on behalf of query, operation or component
+pub type OwnerId = u64;
+
+/// Frames loaded by owners and limits of how many frames each owner may hold
+#[derive(Debug, Default)]
+pub(crate) struct OwnerQuotas {
+    quotas: HashMap<OwnerId, usize>,
+    // frames of every owner in the order they were loaded
+    frames: HashMap<OwnerId, VecDeque<FrameId>>,
+}
+
+impl OwnerQuotas {
+    pub fn set_quota(&mut self, owner: OwnerId, max_frames: Option<usize>) {
+        match max_frames {
+            Some(max_frames) => self.quotas.insert(owner, max_frames),
+            None => self.quotas.remove(&owner),
+        };
+    }
+
+    pub fn is_at_quota(&self, owner: OwnerId) -> bool {
+        self.quotas
+            .get(&owner)
+            .is_some_and(|quota| self.resident_frames(owner) >= *quota)
+    }
+
+    pub fn resident_frames(&self, owner: OwnerId) -> usize {
+        self.frames.get(&owner).map_or(0, |frames| frames.len())
+    }
+
+    /// Frames of the owner, the earliest loaded first
+    pub fn frames(&self, owner: OwnerId) -> impl Iterator<Item = FrameId> + '_ {
+        self.frames.get(&owner).into_iter().flatten().copied()
+    }
+
+    pub fn record_load(&mut self, owner: OwnerId, frame_id: FrameId) {
+        self.frames.entry(owner).or_default().push_back(frame_id);
+    }
+
+    /// Forget frame which is evicted or freed
+    pub fn release(&mut self, frame_id: FrameId) {
+        self.frames.retain(|_, frames| {
+            frames.retain(|id| *id != frame_id);
+            !frames.is_empty()
+        });
+    }
+}
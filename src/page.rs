@@ -1,10 +1,80 @@
-use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::{
+    fmt,
+    ops::{Add, Range},
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+};
 
+use anyhow::Result;
 use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use serde::{Deserialize, Serialize};
 
-pub type PageId = usize;
+/// Id of page on disk, not to be confused with `FrameId` of buffer pool frame holding it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PageId(usize);
 
-const PAGE_SIZE: usize = 4096;
+impl PageId {
+    /// Id no page has, for slots which don't point to a page
+    pub const INVALID: Self = Self(usize::MAX);
+
+    pub const fn new(id: usize) -> Self {
+        Self(id)
+    }
+
+    pub const fn as_usize(self) -> usize {
+        self.0
+    }
+
+    pub fn is_valid(self) -> bool {
+        self != Self::INVALID
+    }
+
+    /// Offset of page in data file which stores pages one after another
+    pub const fn offset(self) -> u64 {
+        (self.0 * PAGE_SIZE) as u64
+    }
+}
+
+impl fmt::Display for PageId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl From<usize> for PageId {
+    fn from(id: usize) -> Self {
+        Self(id)
+    }
+}
+
+impl From<PageId> for usize {
+    fn from(page_id: PageId) -> Self {
+        page_id.0
+    }
+}
+
+/// Ids of pages within range, which can't be iterated itself
+pub(crate) fn iter_page_ids(range: Range<PageId>) -> impl Iterator<Item = PageId> + Clone {
+    (range.start.0..range.end.0).map(PageId)
+}
+
+/// Page `count` pages after this one
+impl Add<usize> for PageId {
+    type Output = Self;
+
+    fn add(self, count: usize) -> Self {
+        Self(self.0 + count)
+    }
+}
+
+pub const PAGE_SIZE: usize = 4096;
+
+// source of page versions, versions are even and unique across all frames of all pools
+static VERSION_CLOCK: AtomicU64 = AtomicU64::new(0);
+
+fn next_version() -> u64 {
+    VERSION_CLOCK.fetch_add(2, Ordering::SeqCst) + 2
+}
 
 #[derive(Debug)]
 pub struct Page {
@@ -12,6 +82,11 @@ pub struct Page {
     data: RwLock<Vec<u8>>,
     pin_count: AtomicUsize,
     is_dirty: AtomicBool,
+    // sequence of frame data, odd while data is write latched
+    version: AtomicU64,
+    // page is read into the frame, data stays write latched meanwhile
+    loading: AtomicBool,
+    load_failed: AtomicBool,
 }
 
 impl Page {
@@ -21,25 +96,30 @@ impl Page {
             pin_count: AtomicUsize::new(0),
             is_dirty: AtomicBool::new(false),
             id: RwLock::new(None),
+            version: AtomicU64::new(next_version()),
+            loading: AtomicBool::new(false),
+            load_failed: AtomicBool::new(false),
         }
     }
 
-    pub fn new_with_id(id: PageId) -> Self {
-        Page {
-            data: RwLock::new(vec![0; PAGE_SIZE]),
-            pin_count: AtomicUsize::new(0),
-            is_dirty: AtomicBool::new(false),
-            id: RwLock::new(Some(id)),
-        }
+    /// Forget page held by the frame and zero its data, buffer is reused
+    pub fn reset(&self) {
+        self.reset_metadata();
+        let mut data = self.data.write();
+        data.clear();
+        data.resize(PAGE_SIZE, 0);
     }
 
-    pub fn reset(&self) {
+    /// Forget page held by the frame but leave its data as is, for frames which data
+    /// is about to be overwritten anyway
+    pub fn reset_metadata(&self) {
         let mut id = self.id.write();
         *id = None;
         self.pin_count.store(0, Ordering::SeqCst);
         self.is_dirty.store(false, Ordering::SeqCst);
-        let mut data = self.data.write();
-        *data = vec![0; PAGE_SIZE];
+        self.version.store(next_version(), Ordering::SeqCst);
+        self.loading.store(false, Ordering::SeqCst);
+        self.load_failed.store(false, Ordering::SeqCst);
     }
 
     pub fn get_data_read(&self) -> RwLockReadGuard<'_, Vec<u8>> {
@@ -50,6 +130,47 @@ impl Page {
         self.data.write()
     }
 
+    /// Version of frame data, it changes with every write and when frame gets another
+    /// page and is never repeated. Odd version means data is being written.
+    pub fn version(&self) -> u64 {
+        self.version.load(Ordering::SeqCst)
+    }
+
+    /// Mark data as being written, must be called with data write latched
+    pub fn begin_write(&self) {
+        self.version.fetch_or(1, Ordering::SeqCst);
+    }
+
+    /// Give written data new version, must be called before write latch is released
+    pub fn end_write(&self) {
+        self.version.store(next_version(), Ordering::SeqCst);
+    }
+
+    /// Mark page as being read into the frame, must be called with data write latched
+    /// until `end_load`
+    pub fn begin_load(&self) {
+        self.loading.store(true, Ordering::SeqCst);
+        self.begin_write();
+    }
+
+    /// Finish reading page into the frame, must be called before data write latch is
+    /// released
+    pub fn end_load(&self, loaded: bool) {
+        self.load_failed.store(!loaded, Ordering::SeqCst);
+        self.end_write();
+        self.loading.store(false, Ordering::SeqCst);
+    }
+
+    /// Wait until page pinned while it was read into the frame is loaded, returns whether
+    /// read succeeded
+    pub fn wait_loaded(&self) -> bool {
+        if self.loading.load(Ordering::SeqCst) {
+            drop(self.data.read());
+        }
+
+        !self.load_failed.load(Ordering::SeqCst)
+    }
+
     pub fn pin(&self) {
         self.pin_count.fetch_add(1, Ordering::SeqCst);
     }
@@ -79,4 +200,48 @@ impl Page {
         let mut old_id = self.id.write();
         *old_id = Some(id);
     }
+
+    /// Lock frame data against swapping, data is copied into the same buffer from then
+    /// on, see `overwrite_data`
+    #[cfg(target_os = "linux")]
+    pub fn lock_memory(&self) -> Result<()> {
+        use anyhow::Context;
+
+        let data = self.data.read();
+        let result = unsafe { libc::mlock(data.as_ptr().cast(), data.capacity()) };
+        if result != 0 {
+            return Err(std::io::Error::last_os_error()).context("Can't lock buffer pool memory.");
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn lock_memory(&self) -> Result<()> {
+        anyhow::bail!("Locking buffer pool memory is supported on Linux only.")
+    }
+}
+
+/// Replace frame data by copying into its buffer, so buffer of page size is never
+/// reallocated and locked memory stays locked
+pub(crate) fn overwrite_data(buffer: &mut Vec<u8>, data: &[u8]) {
+    buffer.clear();
+    buffer.extend_from_slice(data);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_id_is_encoded_as_bare_id() {
+        // pages written before page ids were typed must decode the same
+        let page_id = PageId::new(42);
+        assert_eq!(
+            bincode::serialize(&page_id).unwrap(),
+            bincode::serialize(&42_usize).unwrap()
+        );
+        assert_eq!(page_id.to_string(), "42");
+        assert!(!PageId::INVALID.is_valid());
+    }
 }
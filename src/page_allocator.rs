@@ -0,0 +1,195 @@
+use std::{
+    collections::BTreeSet,
+    fs::{self, File},
+    io::Write,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use anyhow::{Context, Result};
+use parking_lot::Mutex;
+
+use crate::page::PageId;
+
+/// Page ids of disk manager: pages freed by `deallocate` are handed out again before
+/// new ones past the end of data file. Free pages of data file are stored in bitmap file
+/// next to it, bit `i % 8` of byte `i / 8` is set if page `i` is free. Free pages of other
+/// storages are kept in memory only.
+#[derive(Debug)]
+pub(crate) struct PageAllocator {
+    // the lowest page id which was never handed out, page 0 is reserved for metadata
+    next_page_id: AtomicUsize,
+    free_pages: Mutex<BTreeSet<PageId>>,
+    // checked before taking the lock, so allocation without free pages doesn't contend
+    free_count: AtomicUsize,
+    path: Option<PathBuf>,
+    // whether free pages changed since bitmap was written
+    dirty: AtomicBool,
+}
+
+impl PageAllocator {
+    pub fn new(num_pages: usize) -> Self {
+        Self::with_free_pages(num_pages, BTreeSet::new(), None)
+    }
+
+    /// Read bitmap of free pages at path, missing bitmap means no page is free
+    pub fn open(path: PathBuf, num_pages: usize) -> Result<Self> {
+        let bitmap = match fs::read(&path) {
+            Ok(bitmap) => bitmap,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => vec![],
+            Err(error) => {
+                return Err(error)
+                    .with_context(|| format!("Can't read free pages {}.", path.display()))
+            }
+        };
+        let free_pages = bitmap
+            .iter()
+            .enumerate()
+            .flat_map(|(index, byte)| {
+                (0..8)
+                    .filter(move |bit| byte & (1 << bit) != 0)
+                    .map(move |bit| PageId::new(index * 8 + bit))
+            })
+            .collect();
+
+        Ok(Self::with_free_pages(num_pages, free_pages, Some(path)))
+    }
+
+    fn with_free_pages(
+        num_pages: usize,
+        free_pages: BTreeSet<PageId>,
+        path: Option<PathBuf>,
+    ) -> Self {
+        // free page is never handed out as a new one as well
+        let next_page_id = free_pages
+            .last()
+            .map_or(num_pages, |page_id| num_pages.max(page_id.as_usize() + 1))
+            .max(1);
+
+        Self {
+            next_page_id: AtomicUsize::new(next_page_id),
+            free_count: AtomicUsize::new(free_pages.len()),
+            free_pages: Mutex::new(free_pages),
+            path,
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    pub fn allocate(&self) -> PageId {
+        self.take_free().unwrap_or_else(|| self.reserve(1).start)
+    }
+
+    /// The lowest free page, if any
+    pub fn take_free(&self) -> Option<PageId> {
+        if self.free_count.load(Ordering::Relaxed) == 0 {
+            return None;
+        }
+
+        let mut free_pages = self.free_pages.lock();
+        let page_id = free_pages.pop_first()?;
+        self.free_count.store(free_pages.len(), Ordering::Relaxed);
+        self.dirty.store(true, Ordering::Relaxed);
+
+        Some(page_id)
+    }
+
+    /// Hand out `count` new pages at once, free pages are not reused
+    pub fn reserve(&self, count: usize) -> Range<PageId> {
+        let first = PageId::new(self.next_page_id.fetch_add(count, Ordering::Relaxed));
+
+        first..first + count
+    }
+
+    /// Give back reserved pages which were never used, pages at the end are handed out
+    /// as new ones again, others are freed
+    pub fn release(&self, page_ids: Range<PageId>) {
+        if page_ids.start == page_ids.end {
+            return;
+        }
+        let released_at_end = self
+            .next_page_id
+            .compare_exchange(
+                page_ids.end.as_usize(),
+                page_ids.start.as_usize(),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            )
+            .is_ok();
+        if !released_at_end {
+            for page_id in (page_ids.start.as_usize()..page_ids.end.as_usize()).map(PageId::new) {
+                self.deallocate(page_id);
+            }
+        }
+    }
+
+    /// Page written by other means than allocation, like redo of a log, is in use
+    pub fn mark_allocated(&self, page_id: PageId) {
+        self.next_page_id
+            .fetch_max(page_id.as_usize() + 1, Ordering::Relaxed);
+        let mut free_pages = self.free_pages.lock();
+        if free_pages.remove(&page_id) {
+            self.free_count.store(free_pages.len(), Ordering::Relaxed);
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn deallocate(&self, page_id: PageId) {
+        let mut free_pages = self.free_pages.lock();
+        if free_pages.insert(page_id) {
+            self.free_count.store(free_pages.len(), Ordering::Relaxed);
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn is_free(&self, page_id: PageId) -> bool {
+        self.free_pages.lock().contains(&page_id)
+    }
+
+    /// Forget all free pages, returns how many there were
+    pub fn discard_free(&self) -> usize {
+        let mut free_pages = self.free_pages.lock();
+        let discarded = free_pages.len();
+        if discarded > 0 {
+            free_pages.clear();
+            self.free_count.store(0, Ordering::Relaxed);
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+
+        discarded
+    }
+
+    /// Write bitmap if free pages changed, it replaces the previous one at once
+    pub fn persist(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        let mut bitmap = vec![];
+        for page_id in self
+            .free_pages
+            .lock()
+            .iter()
+            .map(|page_id| page_id.as_usize())
+        {
+            if bitmap.len() <= page_id / 8 {
+                bitmap.resize(page_id / 8 + 1, 0);
+            }
+            bitmap[page_id / 8] |= 1 << (page_id % 8);
+        }
+        write_atomically(path, &bitmap).inspect_err(|_| self.dirty.store(true, Ordering::Relaxed))
+    }
+}
+
+fn write_atomically(path: &Path, data: &[u8]) -> Result<()> {
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    let mut file = File::create(&temp_path)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    fs::rename(&temp_path, path)
+        .with_context(|| format!("Can't write free pages {}.", path.display()))
+}
@@ -0,0 +1,132 @@
+use std::ops::{Deref, DerefMut};
+
+use parking_lot::{RwLockReadGuard, RwLockWriteGuard};
+
+use crate::{
+    buffer_pool_manager::BufferPoolManager,
+    latch_tracker::{self, LatchId, LatchMode},
+    page::{overwrite_data, Page, PageId},
+};
+
+/// Shared access to page data, page is unpinned when guard is dropped
+#[derive(Debug)]
+pub struct ReadPageGuard<'a> {
+    buffer_pool_manager: &'a BufferPoolManager,
+    page_id: PageId,
+    version: u64,
+    guard: Option<RwLockReadGuard<'a, Vec<u8>>>,
+}
+
+impl<'a> ReadPageGuard<'a> {
+    /// Latch pinned page for reading
+    pub(crate) fn new(
+        buffer_pool_manager: &'a BufferPoolManager,
+        page_id: PageId,
+        page: &'a Page,
+    ) -> Self {
+        let latch = LatchId::new(buffer_pool_manager, page_id);
+        latch_tracker::wait(latch, LatchMode::Read);
+        let guard = page.get_data_read();
+        latch_tracker::acquired(latch, LatchMode::Read);
+
+        Self {
+            buffer_pool_manager,
+            page_id,
+            version: page.version(),
+            guard: Some(guard),
+        }
+    }
+
+    pub fn page_id(&self) -> PageId {
+        self.page_id
+    }
+
+    /// Version of data read through the guard, see `BufferPoolManager::is_page_unchanged`
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+}
+
+impl Deref for ReadPageGuard<'_> {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Self::Target {
+        self.guard.as_ref().unwrap()
+    }
+}
+
+impl Drop for ReadPageGuard<'_> {
+    fn drop(&mut self) {
+        // latch has to be released before unpin, unpinned page can be evicted right away
+        self.guard.take();
+        let latch = LatchId::new(self.buffer_pool_manager, self.page_id);
+        latch_tracker::released(latch, LatchMode::Read);
+        let _ = self.buffer_pool_manager.unpin_page(self.page_id, false);
+    }
+}
+
+/// Exclusive access to page data, page is unpinned and marked dirty when guard is dropped
+#[derive(Debug)]
+pub struct WritePageGuard<'a> {
+    buffer_pool_manager: &'a BufferPoolManager,
+    page_id: PageId,
+    page: &'a Page,
+    guard: Option<RwLockWriteGuard<'a, Vec<u8>>>,
+}
+
+impl<'a> WritePageGuard<'a> {
+    /// Latch pinned page for writing
+    pub(crate) fn new(
+        buffer_pool_manager: &'a BufferPoolManager,
+        page_id: PageId,
+        page: &'a Page,
+    ) -> Self {
+        let latch = LatchId::new(buffer_pool_manager, page_id);
+        latch_tracker::wait(latch, LatchMode::Write);
+        let guard = page.get_data_write();
+        page.begin_write();
+        latch_tracker::acquired(latch, LatchMode::Write);
+
+        Self {
+            buffer_pool_manager,
+            page_id,
+            page,
+            guard: Some(guard),
+        }
+    }
+
+    pub fn page_id(&self) -> PageId {
+        self.page_id
+    }
+
+    /// Replace page data, buffer of frame is reused
+    pub fn overwrite(&mut self, data: &[u8]) {
+        overwrite_data(self.guard.as_mut().unwrap(), data);
+    }
+}
+
+// data is changed in place or replaced by `overwrite`, so buffer of frame is never swapped
+// for another allocation
+impl Deref for WritePageGuard<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        self.guard.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for WritePageGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.guard.as_mut().unwrap()
+    }
+}
+
+impl Drop for WritePageGuard<'_> {
+    fn drop(&mut self) {
+        self.page.end_write();
+        self.guard.take();
+        let latch = LatchId::new(self.buffer_pool_manager, self.page_id);
+        latch_tracker::released(latch, LatchMode::Write);
+        let _ = self.buffer_pool_manager.unpin_page(self.page_id, true);
+    }
+}
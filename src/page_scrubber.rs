@@ -0,0 +1,95 @@
+use std::{
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Weak,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::disk_manager::DiskManager;
+use crate::page::PageId;
+
+/// Thread of `DiskManager::start_scrubber`, it holds no strong reference to disk manager,
+/// so it stops once disk manager is dropped
+#[derive(Debug)]
+pub(crate) struct PageScrubber {
+    // dropped to stop thread
+    stop_sender: Sender<()>,
+    thread: thread::JoinHandle<()>,
+}
+
+impl PageScrubber {
+    pub fn start(
+        disk_manager: Weak<DiskManager>,
+        page_interval: Duration,
+        corrupted_sender: Sender<PageId>,
+    ) -> Self {
+        let (stop_sender, stop_receiver) = mpsc::channel();
+        let thread = thread::spawn(move || {
+            scrub(
+                &disk_manager,
+                page_interval,
+                &stop_receiver,
+                &corrupted_sender,
+            )
+        });
+
+        Self {
+            stop_sender,
+            thread,
+        }
+    }
+
+    /// Stop thread and wait until page it checks is done
+    pub fn stop(self) {
+        drop(self.stop_sender);
+        let _ = self.thread.join();
+    }
+}
+
+// pages are checked one per interval from the first to the last one and over again,
+// until scrubber is stopped, disk manager is dropped or nobody receives corrupted pages
+fn scrub(
+    disk_manager: &Weak<DiskManager>,
+    page_interval: Duration,
+    stop_receiver: &Receiver<()>,
+    corrupted_sender: &Sender<PageId>,
+) {
+    let mut page_id = PageId::new(0);
+    loop {
+        if stop_receiver.recv_timeout(page_interval) != Err(RecvTimeoutError::Timeout) {
+            return;
+        }
+        let Some(disk_manager) = disk_manager.upgrade() else {
+            return;
+        };
+        let num_pages = match disk_manager.num_pages() {
+            Ok(num_pages) => num_pages,
+            Err(error) => {
+                tracing::warn!(%error, "page scrub failed");
+                continue;
+            }
+        };
+        if page_id.as_usize() >= num_pages {
+            page_id = PageId::new(0);
+            if num_pages == 0 {
+                continue;
+            }
+        }
+
+        match disk_manager.is_page_corrupted(page_id) {
+            Ok(false) => {}
+            Ok(true) => {
+                tracing::warn!(%page_id, "scrubber found corrupted page");
+                if corrupted_sender.send(page_id).is_err() {
+                    return;
+                }
+            }
+            Err(error) => {
+                tracing::warn!(%page_id, %error, "page scrub failed");
+            }
+        }
+        page_id = page_id + 1;
+    }
+}
@@ -0,0 +1,50 @@
+use std::fmt::Debug;
+
+use crate::{
+    lru_k_replacer::{AccessType, FrameId, LruKReplacer},
+    page::PageId,
+};
+
+/// Page replacement policy of buffer pool: tracks frames and picks victim for eviction
+pub trait Replacer: Debug + Send {
+    /// Page `page_id` is loaded into frame, called before the first `record_access` of the frame.
+    /// Policies remembering recently evicted pages use it
+    fn record_load(&mut self, _frame_id: FrameId, _page_id: PageId) {}
+
+    fn record_access(&mut self, frame_id: FrameId, access_type: AccessType);
+
+    fn set_evictable(&mut self, frame_id: FrameId, is_evictable: bool);
+
+    /// Pick evictable frame and stop tracking it
+    fn evict(&mut self) -> Option<FrameId>;
+
+    fn remove(&mut self, frame_id: FrameId);
+
+    /// Number of evictable frames
+    fn size(&self) -> usize;
+}
+
+impl Replacer for LruKReplacer {
+    fn record_access(&mut self, frame_id: FrameId, access_type: AccessType) {
+        LruKReplacer::record_access(self, frame_id, access_type);
+    }
+
+    fn set_evictable(&mut self, frame_id: FrameId, is_evictable: bool) {
+        LruKReplacer::set_evictable(self, frame_id, is_evictable);
+    }
+
+    fn evict(&mut self) -> Option<FrameId> {
+        let frame_id = LruKReplacer::evict(self)?;
+        LruKReplacer::remove(self, frame_id);
+
+        Some(frame_id)
+    }
+
+    fn remove(&mut self, frame_id: FrameId) {
+        LruKReplacer::remove(self, frame_id);
+    }
+
+    fn size(&self) -> usize {
+        LruKReplacer::size(self)
+    }
+}
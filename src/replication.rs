@@ -0,0 +1,345 @@
+use std::{
+    fmt::Debug,
+    hash::Hash,
+    io::{BufReader, Read, Write},
+    net::{Shutdown, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::Receiver,
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    kv::Kv, log_archive::LogArchive, snapshot::Snapshot, watch::ChangeEvent,
+    write_batch::BatchRecord,
+};
+
+// replica which doesn't take a write within this time is dropped, so it can't stall primary
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Message from primary to replica, stored as little endian length followed by bincode payload
+#[derive(Serialize, Deserialize, Debug)]
+enum ReplicationMessage<K, E> {
+    /// Whole namespace, replica replaces its content with it
+    Snapshot { sequence: u64, entries: Vec<(K, E)> },
+    /// Changes made by a single write of primary
+    Records {
+        sequence: u64,
+        records: Vec<BatchRecord<K, E>>,
+    },
+}
+
+fn encode_message<K, E>(message: &ReplicationMessage<K, E>) -> Result<Vec<u8>>
+where
+    K: Serialize,
+    E: Serialize,
+{
+    let payload = bincode::serialize(message)?;
+    let mut data = Vec::with_capacity(8 + payload.len());
+    data.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    data.extend_from_slice(&payload);
+
+    Ok(data)
+}
+
+fn read_message<K, E>(reader: &mut impl Read) -> Result<ReplicationMessage<K, E>>
+where
+    K: DeserializeOwned,
+    E: DeserializeOwned,
+{
+    let mut len = [0; 8];
+    reader.read_exact(&mut len)?;
+    let mut payload = vec![0; u64::from_le_bytes(len) as usize];
+    reader.read_exact(&mut payload)?;
+
+    Ok(bincode::deserialize(&payload)?)
+}
+
+/// Connections of replicas following writes of `Kv` and archive of its writes,
+/// see `Kv::start_log_shipping` and `Kv::start_log_archiving`
+#[derive(Debug, Default)]
+pub(crate) struct LogShipper {
+    replicas: Vec<TcpStream>,
+    archive: Option<LogArchive>,
+    // sequence number of the last shipped write
+    sequence: u64,
+}
+
+impl LogShipper {
+    pub fn is_empty(&self) -> bool {
+        self.replicas.is_empty() && self.archive.is_none()
+    }
+
+    /// Start archiving writes, archive gets `entries` as its base backup first.
+    /// No write may happen until archive is started. Returns sequence of the backup.
+    pub fn start_archiving<K, E>(
+        &mut self,
+        archive: LogArchive,
+        archived_sequence: u64,
+        entries: &[(K, E)],
+    ) -> Result<u64>
+    where
+        K: Serialize,
+        E: Serialize,
+    {
+        // sequence keeps growing across restarts of the database
+        self.sequence = self.sequence.max(archived_sequence);
+        archive.write_base_backup(self.sequence, entries)?;
+        self.archive = Some(archive);
+
+        Ok(self.sequence)
+    }
+
+    /// Wait until archived writes are durable
+    pub fn sync(&self) -> Result<()> {
+        match &self.archive {
+            Some(archive) => archive.sync(),
+            None => Ok(()),
+        }
+    }
+
+    /// Start shipping writes to replica, it gets `entries` as its base copy first.
+    /// No write may happen until replica is added.
+    pub fn add_replica<K, E>(&mut self, mut stream: TcpStream, entries: Vec<(K, E)>) -> Result<()>
+    where
+        K: Serialize,
+        E: Serialize,
+    {
+        stream.set_write_timeout(Some(SEND_TIMEOUT))?;
+        stream.set_nodelay(true)?;
+        let message = ReplicationMessage::Snapshot {
+            sequence: self.sequence,
+            entries,
+        };
+        stream.write_all(&encode_message(&message)?)?;
+        self.replicas.push(stream);
+
+        Ok(())
+    }
+
+    /// Send changes made by write to archive and replicas, replicas which fail to take them
+    /// are dropped. Archiving stops if write can't be archived.
+    pub fn ship<K, E>(&mut self, records: &[BatchRecord<K, E>])
+    where
+        K: Clone + Serialize,
+        E: Clone + Serialize,
+    {
+        if self.is_empty() || records.is_empty() {
+            return;
+        }
+
+        self.sequence += 1;
+        if let Some(archive) = &mut self.archive {
+            if let Err(error) = archive.append(self.sequence, records) {
+                tracing::warn!(error = format!("{:#}", error), "log archiving stopped");
+                self.archive = None;
+            }
+        }
+        if self.replicas.is_empty() {
+            return;
+        }
+
+        let message = ReplicationMessage::Records {
+            sequence: self.sequence,
+            records: records.to_vec(),
+        };
+        let data = match encode_message(&message) {
+            Ok(data) => data,
+            Err(error) => {
+                tracing::warn!(%error, "can't encode write, all replicas are dropped");
+                self.replicas.clear();
+                return;
+            }
+        };
+
+        self.replicas
+            .retain_mut(|stream| match stream.write_all(&data) {
+                Ok(()) => true,
+                Err(error) => {
+                    tracing::warn!(%error, peer = ?stream.peer_addr().ok(), "replica is dropped");
+                    false
+                }
+            });
+    }
+}
+
+/// Read-only copy of `Kv` namespace which redoes writes shipped by primary,
+/// see `Kv::start_log_shipping`. Replica stops following when connection to primary drops,
+/// it can be promoted to writable store then (or any time before, as warm standby).
+///
+/// ```no_run
+/// use std::sync::Arc;
+/// use cmu_db_rs::{Kv, Replica};
+///
+/// let primary = Arc::new(Kv::<String, u64>::open("primary.db")?);
+/// let address = primary.start_log_shipping("127.0.0.1:0")?;
+///
+/// let replica = Replica::follow(Kv::<String, u64>::open("replica.db")?, address)?;
+/// primary.insert("visits".into(), 1)?;
+/// let visits = replica.get("visits".into())?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct Replica<K, V> {
+    kv: Arc<Kv<K, V>>,
+    stream: TcpStream,
+    applied_sequence: Arc<AtomicU64>,
+    follower: Option<JoinHandle<()>>,
+}
+
+impl<K, V> Replica<K, V>
+where
+    K: Hash + Eq + Clone + Debug + Serialize + DeserializeOwned + ToString + Send + Sync + 'static,
+    V: Clone + Debug + Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    /// Follow primary at `address`. Content of `kv` is replaced by base copy of primary
+    /// before this returns, then writes of primary are redone on background thread.
+    pub fn follow(kv: Kv<K, V>, address: impl ToSocketAddrs) -> Result<Self> {
+        let stream = TcpStream::connect(address).context("Can't connect to primary.")?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+
+        let ReplicationMessage::Snapshot { sequence, entries } = read_message(&mut reader)? else {
+            bail!("Primary didn't send base copy.");
+        };
+        kv.restore(entries)?;
+
+        let kv = Arc::new(kv);
+        let applied_sequence = Arc::new(AtomicU64::new(sequence));
+        let follower = {
+            let kv = Arc::clone(&kv);
+            let applied_sequence = Arc::clone(&applied_sequence);
+
+            thread::spawn(move || {
+                if let Err(error) = Self::redo_shipped(&kv, &mut reader, &applied_sequence) {
+                    tracing::warn!(error = format!("{:#}", error), "replica stopped following");
+                }
+            })
+        };
+
+        Ok(Self {
+            kv,
+            stream,
+            applied_sequence,
+            follower: Some(follower),
+        })
+    }
+
+    fn redo_shipped(
+        kv: &Kv<K, V>,
+        reader: &mut impl Read,
+        applied_sequence: &AtomicU64,
+    ) -> Result<()> {
+        loop {
+            let sequence = match read_message(reader)? {
+                ReplicationMessage::Snapshot { sequence, entries } => {
+                    kv.restore(entries)?;
+                    sequence
+                }
+                ReplicationMessage::Records { sequence, records } => {
+                    kv.redo(records)?;
+                    sequence
+                }
+            };
+            applied_sequence.store(sequence, Ordering::Release);
+        }
+    }
+
+    pub fn get(&self, key: K) -> Result<Option<V>> {
+        self.kv.get(key)
+    }
+
+    /// Take read view of the replica at this point in time
+    pub fn snapshot(&self) -> Snapshot<'_, K, V> {
+        self.kv.snapshot()
+    }
+
+    /// Subscribe to changes of keys starting with prefix as they are redone
+    pub fn watch(&self, prefix: impl Into<String>) -> Receiver<ChangeEvent<K, V>> {
+        self.kv.watch(prefix)
+    }
+
+    /// Sequence number of the last primary write applied by replica
+    pub fn applied_sequence(&self) -> u64 {
+        self.applied_sequence.load(Ordering::Acquire)
+    }
+
+    pub fn is_following(&self) -> bool {
+        self.follower
+            .as_ref()
+            .is_some_and(|follower| !follower.is_finished())
+    }
+
+    /// Stop following primary and return store for reads and writes
+    pub fn promote(self) -> Result<Kv<K, V>> {
+        let kv = Arc::clone(&self.kv);
+        // follower thread is stopped and releases store
+        drop(self);
+
+        Arc::into_inner(kv).context("Replica store is still in use.")
+    }
+}
+
+impl<K, V> Drop for Replica<K, V> {
+    fn drop(&mut self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+        if let Some(follower) = self.follower.take() {
+            let _ = follower.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::WriteBatch;
+
+    fn wait_for_sequence(replica: &Replica<String, u64>, sequence: u64) {
+        let started_at = Instant::now();
+        while replica.applied_sequence() < sequence {
+            assert!(started_at.elapsed() < Duration::from_secs(10));
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_log_shipping() {
+        let dir = TempDir::new().unwrap();
+        let primary = Arc::new(Kv::<String, u64>::open(dir.path().join("primary.db")).unwrap());
+        let address = primary.start_log_shipping("127.0.0.1:0").unwrap();
+        primary.insert("base".into(), 1).unwrap();
+
+        let replica_kv = Kv::<String, u64>::open(dir.path().join("replica.db")).unwrap();
+        replica_kv.insert("stale".into(), 1).unwrap();
+        let replica = Replica::follow(replica_kv, address).unwrap();
+        assert_eq!(replica.get("base".into()).unwrap(), Some(1));
+        assert_eq!(replica.get("stale".into()).unwrap(), None);
+
+        primary.insert("key".into(), 2).unwrap();
+        primary.remove("base".into()).unwrap();
+        let mut batch = WriteBatch::new();
+        batch.insert("a".into(), 3);
+        batch.insert("b".into(), 4);
+        primary.apply_batch(batch).unwrap();
+        wait_for_sequence(&replica, 3);
+
+        assert_eq!(replica.get("key".into()).unwrap(), Some(2));
+        assert_eq!(replica.get("base".into()).unwrap(), None);
+        assert_eq!(replica.get("b".into()).unwrap(), Some(4));
+        assert!(replica.is_following());
+
+        let promoted = replica.promote().unwrap();
+        promoted.insert("key".into(), 5).unwrap();
+        assert_eq!(promoted.get("key".into()).unwrap(), Some(5));
+        assert_eq!(primary.get("key".into()).unwrap(), Some(2));
+    }
+}
@@ -0,0 +1,57 @@
+use std::sync::OnceLock;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// Environment variable with seed of randomized tests, benches and workloads
+pub const SEED_ENV: &str = "CMU_DB_SEED";
+
+/// Seed shared by all random streams of the process: `CMU_DB_SEED` if it is set, random one
+/// otherwise. Seed is logged once, so a failing run can be repeated with the same values.
+pub fn rng_seed() -> u64 {
+    static SEED: OnceLock<u64> = OnceLock::new();
+
+    *SEED.get_or_init(|| {
+        let seed = match std::env::var(SEED_ENV).ok().map(|seed| seed.parse()) {
+            Some(Ok(seed)) => seed,
+            Some(Err(error)) => {
+                tracing::warn!(%error, "invalid {}, random seed is used", SEED_ENV);
+                rand::thread_rng().gen()
+            }
+            None => rand::thread_rng().gen(),
+        };
+        tracing::info!(seed, "random seed, set {} to repeat", SEED_ENV);
+
+        seed
+    })
+}
+
+/// Generator of named stream of random values. Streams with the same name get the same
+/// values for the same seed, independent of how much other streams consumed.
+pub fn seeded_rng(stream: &str) -> StdRng {
+    // FNV-1a, stable across platforms and compiler versions unlike std hashers
+    let stream_hash = stream
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        });
+
+    StdRng::seed_from_u64(rng_seed() ^ stream_hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_streams_are_reproducible() {
+        let values = |stream| {
+            seeded_rng(stream)
+                .sample_iter(rand::distributions::Standard)
+                .take(4)
+                .collect::<Vec<u64>>()
+        };
+
+        assert_eq!(values("workload"), values("workload"));
+        assert_ne!(values("workload"), values("faults"));
+    }
+}
@@ -0,0 +1,290 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt,
+    fs::File,
+    hash::Hash,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    disk_manager::DiskManager,
+    page::PageId,
+    storage::{
+        extendible_hash_table::{
+            extendible_hash_table_bucket_page::ExtendibleHTableBucketPage,
+            extendible_hash_table_directory_page::ExtendibleHTableDirectoryPage,
+            extendible_hash_table_header_page::ExtendibleHTableHeaderPage,
+            extendible_hash_table_key_dictionary_page::ExtendibleHTableKeyDictionaryPage,
+        },
+        metadata_page::{MetadataPage, METADATA_PAGE_ID},
+    },
+};
+
+/// Key and value read from bucket page by `salvage`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SalvagedEntry<K, V> {
+    pub page_id: PageId,
+    /// Table whose directory refers to the bucket, none if no readable directory does
+    pub hash_table: Option<String>,
+    pub key: K,
+    pub value: V,
+}
+
+/// Result of `salvage`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SalvageReport {
+    /// Pages in data file
+    pub pages: usize,
+    /// Bucket pages entries were exported from
+    pub buckets: usize,
+    pub entries: usize,
+    /// Pages which hold data, but can't be read or decoded as bucket, like directories
+    /// of tables catalog doesn't lead to anymore
+    pub skipped_pages: Vec<PageId>,
+}
+
+impl fmt::Display for SalvageReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} pages, {} entries exported from {} buckets, {} pages skipped",
+            self.pages,
+            self.entries,
+            self.buckets,
+            self.skipped_pages.len()
+        )
+    }
+}
+
+// pages which are known not to be buckets and what is known about buckets, gathered from
+// whatever part of catalog and table structure is readable
+#[derive(Default)]
+struct Structure {
+    pages: BTreeSet<PageId>,
+    bucket_tables: HashMap<PageId, String>,
+    dictionaries: BTreeMap<String, ExtendibleHTableKeyDictionaryPage>,
+}
+
+/// Export every key and value of data file which can still be read to dump at `path`,
+/// for recovery of database which doesn't open or fails check. Pages are scanned one by
+/// one directly from disk, pages failing checksum or decoding as bucket of `K` and `V`
+/// are skipped. Key may be exported more than once if stale copy of its bucket survived.
+pub fn salvage<K, V>(disk_manager: &DiskManager, path: impl AsRef<Path>) -> Result<SalvageReport>
+where
+    K: Hash + Eq + Clone + fmt::Debug + Serialize + DeserializeOwned,
+    V: Clone + fmt::Debug + Serialize + DeserializeOwned,
+{
+    let path = path.as_ref();
+    let file = File::create(path)
+        .with_context(|| format!("Can't create salvage dump {}.", path.display()))?;
+    let mut dump = BufWriter::new(file);
+    let structure = read_structure(disk_manager)?;
+    let mut report = SalvageReport {
+        pages: disk_manager.num_pages()?,
+        ..Default::default()
+    };
+
+    for page_id in (0..report.pages).map(PageId::new) {
+        if structure.pages.contains(&page_id) || disk_manager.is_free_page(page_id) {
+            continue;
+        }
+        let Ok(data) = disk_manager.read_page(page_id) else {
+            report.skipped_pages.push(page_id);
+            continue;
+        };
+        if data.iter().all(|byte| *byte == 0) {
+            continue;
+        }
+
+        let hash_table = structure.bucket_tables.get(&page_id);
+        // bucket of unknown table may have keys compressed by any dictionary
+        let dictionaries = match hash_table.and_then(|name| structure.dictionaries.get(name)) {
+            Some(dictionary) => vec![Some(dictionary)],
+            None => all_dictionaries(&structure),
+        };
+        // page of other kind may decode as bucket too, but then leaves data after it
+        let Some(mut bucket) = dictionaries.into_iter().find_map(|dictionary| {
+            let bucket =
+                ExtendibleHTableBucketPage::<K, V>::from_bytes_with(&data, dictionary).ok()?;
+            let len = bucket.to_bytes_with(dictionary).len();
+            data[len..].iter().all(|byte| *byte == 0).then_some(bucket)
+        }) else {
+            report.skipped_pages.push(page_id);
+            continue;
+        };
+
+        report.buckets += 1;
+        for (key, value) in bucket.get_entries() {
+            let entry = SalvagedEntry {
+                page_id,
+                hash_table: hash_table.cloned(),
+                key,
+                value,
+            };
+            let payload = bincode::serialize(&entry)?;
+            dump.write_all(&(payload.len() as u64).to_le_bytes())?;
+            dump.write_all(&payload)?;
+            report.entries += 1;
+        }
+    }
+    dump.into_inner()?.sync_all()?;
+
+    Ok(report)
+}
+
+/// Read dump written by `salvage`, partially written last entry is ignored
+pub fn read_salvage_dump<K, V>(path: impl AsRef<Path>) -> Result<Vec<SalvagedEntry<K, V>>>
+where
+    K: DeserializeOwned,
+    V: DeserializeOwned,
+{
+    let path = path.as_ref();
+    let file =
+        File::open(path).with_context(|| format!("Can't open salvage dump {}.", path.display()))?;
+    let mut data = vec![];
+    BufReader::new(file).read_to_end(&mut data)?;
+
+    let mut entries = vec![];
+    let mut rest = data.as_slice();
+    while rest.len() >= 8 {
+        let len = u64::from_le_bytes(rest[..8].try_into().unwrap()) as usize;
+        let Some(payload) = rest[8..].get(..len) else {
+            break;
+        };
+        entries.push(bincode::deserialize(payload)?);
+        rest = &rest[8 + len..];
+    }
+
+    Ok(entries)
+}
+
+// plain encoding first, then keys compressed by every known dictionary
+fn all_dictionaries(structure: &Structure) -> Vec<Option<&ExtendibleHTableKeyDictionaryPage>> {
+    std::iter::once(None)
+        .chain(structure.dictionaries.values().map(Some))
+        .collect()
+}
+
+// pages which can't be read or decoded are left to page scan
+fn read_structure(disk_manager: &DiskManager) -> Result<Structure> {
+    let mut structure = Structure::default();
+    structure.pages.insert(METADATA_PAGE_ID);
+    let Some(metadata) = disk_manager
+        .read_page(METADATA_PAGE_ID)
+        .ok()
+        .and_then(|data| MetadataPage::from_bytes(&data).ok())
+    else {
+        return Ok(structure);
+    };
+
+    for name in metadata.get_names() {
+        let Some(header_page_id) = metadata.get_header_page_id(&name) else {
+            continue;
+        };
+        let Some(header) = disk_manager
+            .read_page(header_page_id)
+            .ok()
+            .and_then(|data| ExtendibleHTableHeaderPage::from_bytes(&data).ok())
+        else {
+            continue;
+        };
+        structure.pages.insert(header_page_id);
+
+        if let Some(dictionary_page_id) = header.get_key_dictionary_page_id() {
+            structure.pages.insert(dictionary_page_id);
+            if let Some(dictionary) = disk_manager
+                .read_page(dictionary_page_id)
+                .ok()
+                .and_then(|data| ExtendibleHTableKeyDictionaryPage::from_bytes(&data).ok())
+            {
+                structure.dictionaries.insert(name.clone(), dictionary);
+            }
+        }
+
+        for directory_index in 0..header.get_max_size() {
+            let Some(directory_page_id) = header.get_directory_page_id(directory_index).copied()
+            else {
+                continue;
+            };
+            let Some(directory) = disk_manager
+                .read_page(directory_page_id)
+                .ok()
+                .and_then(|data| ExtendibleHTableDirectoryPage::from_bytes(&data).ok())
+            else {
+                continue;
+            };
+            structure.pages.insert(directory_page_id);
+            for bucket_index in 0..directory.get_size() {
+                if let Some(bucket_page_id) = directory.get_bucket_page_id(bucket_index) {
+                    structure
+                        .bucket_tables
+                        .insert(*bucket_page_id, name.clone());
+                }
+            }
+        }
+    }
+
+    Ok(structure)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::OpenOptions;
+    use std::io::{Seek, SeekFrom};
+
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{
+        db_instance::DbInstance,
+        inspect::{inspect, PageKind},
+    };
+
+    #[test]
+    fn test_salvage_skips_corrupted_pages() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("test.db");
+        let db = DbInstance::open(&path).unwrap();
+        let hash_table = db.open_hash_table::<u32, u32>("numbers", 4, 4).unwrap();
+        for i in 0..20 {
+            hash_table.insert(i, i * 10).unwrap();
+        }
+        drop(hash_table);
+        db.close().unwrap();
+
+        // catalog and one of buckets are overwritten with garbage
+        let (bucket_page_id, lost) = inspect(&DiskManager::open(&path).unwrap())
+            .unwrap()
+            .into_iter()
+            .find_map(|report| match report.kind {
+                PageKind::Bucket { len, .. } if len > 0 => Some((report.page_id, len)),
+                _ => None,
+            })
+            .unwrap();
+        let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.write_all(&[0xff; 64]).unwrap();
+        file.seek(SeekFrom::Start(bucket_page_id.offset())).unwrap();
+        file.write_all(&[0xff; 64]).unwrap();
+        drop(file);
+
+        let dump_path = dir.path().join("test.dump");
+        let report = salvage::<u32, u32>(&DiskManager::open(&path).unwrap(), &dump_path).unwrap();
+        let entries = read_salvage_dump::<u32, u32>(&dump_path).unwrap();
+
+        assert_eq!(report.entries, entries.len());
+        assert!(report.skipped_pages.contains(&bucket_page_id));
+        // stale copies of buckets may hold keys of the lost one
+        let keys = entries
+            .iter()
+            .map(|entry| entry.key)
+            .collect::<BTreeSet<_>>();
+        assert!(keys.len() >= 20 - lost);
+        for entry in entries {
+            assert_eq!(entry.value, entry.key * 10);
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+// threads pick shards round robin, so a few busy threads rarely share one
+pub(crate) const SHARDS: usize = 16;
+
+static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static SHARD: usize = NEXT_SHARD.fetch_add(1, Ordering::Relaxed) % SHARDS;
+}
+
+// each shard takes its own cache line, so threads adding to different shards don't
+// invalidate each other's
+#[derive(Debug, Default)]
+#[repr(align(64))]
+struct Shard(AtomicU64);
+
+/// Counter of hot path events. Every thread adds to its own shard without taking a latch
+/// and shards are summed on read, so reads are slower than adds and may miss adds made
+/// while they run.
+#[derive(Debug, Default)]
+pub(crate) struct ShardedCounter {
+    shards: [Shard; SHARDS],
+}
+
+impl ShardedCounter {
+    pub fn increment(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, value: u64) {
+        self.shards[thread_shard()]
+            .0
+            .fetch_add(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.shards
+            .iter()
+            .map(|shard| shard.0.load(Ordering::Relaxed))
+            .sum()
+    }
+}
+
+/// Shard of calling thread, other per thread state can be sharded the same way
+pub(crate) fn thread_shard() -> usize {
+    SHARD.with(|shard| *shard)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, thread};
+
+    use super::*;
+
+    #[test]
+    fn test_adds_of_all_threads_are_counted() {
+        let counter = Arc::new(ShardedCounter::default());
+        let handles = (0..8)
+            .map(|_| {
+                let counter = Arc::clone(&counter);
+                thread::spawn(move || {
+                    for _ in 0..1000 {
+                        counter.increment();
+                    }
+                    counter.add(10);
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(counter.get(), 8 * 1010);
+    }
+}
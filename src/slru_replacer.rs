@@ -0,0 +1,156 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::{
+    lru_k_replacer::{AccessType, FrameId},
+    replacer::Replacer,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Segment {
+    // accessed once since it was loaded or demoted
+    Probationary,
+    // accessed again while probationary
+    Protected,
+}
+
+#[derive(Debug)]
+struct SlruEntry {
+    segment: Segment,
+    is_evictable: bool,
+}
+
+/// Segmented LRU policy: frames start in large probationary LRU segment and are promoted
+/// to small protected one on repeated access. Victims come from probationary segment first,
+/// so pages of skewed workload stay resident while pages touched once come and go.
+/// Protected segment which outgrows its size demotes its least recently used frame back.
+#[derive(Debug)]
+pub struct SlruReplacer {
+    // front is the least recently used end of both segments
+    probationary: VecDeque<FrameId>,
+    protected: VecDeque<FrameId>,
+    entries: HashMap<FrameId, SlruEntry>,
+    max_protected_size: usize,
+}
+
+impl SlruReplacer {
+    pub fn new(num_of_frames: usize) -> Self {
+        Self {
+            probationary: VecDeque::new(),
+            protected: VecDeque::new(),
+            entries: HashMap::new(),
+            max_protected_size: (num_of_frames / 4).max(1),
+        }
+    }
+
+    fn segment_mut(&mut self, segment: Segment) -> &mut VecDeque<FrameId> {
+        match segment {
+            Segment::Probationary => &mut self.probationary,
+            Segment::Protected => &mut self.protected,
+        }
+    }
+
+    fn evict_from(&mut self, segment: Segment) -> Option<FrameId> {
+        let entries = &self.entries;
+        let frames = match segment {
+            Segment::Probationary => &mut self.probationary,
+            Segment::Protected => &mut self.protected,
+        };
+        let position = frames
+            .iter()
+            .position(|frame_id| entries[frame_id].is_evictable)?;
+        let frame_id = frames.remove(position)?;
+        self.entries.remove(&frame_id);
+
+        Some(frame_id)
+    }
+}
+
+impl Replacer for SlruReplacer {
+    fn record_access(&mut self, frame_id: FrameId, _access_type: AccessType) {
+        let Some(entry) = self.entries.get_mut(&frame_id) else {
+            self.probationary.push_back(frame_id);
+            self.entries.insert(
+                frame_id,
+                SlruEntry {
+                    segment: Segment::Probationary,
+                    is_evictable: false,
+                },
+            );
+            return;
+        };
+
+        let segment = entry.segment;
+        entry.segment = Segment::Protected;
+        self.segment_mut(segment).retain(|id| *id != frame_id);
+        self.protected.push_back(frame_id);
+
+        if self.protected.len() > self.max_protected_size {
+            if let Some(demoted) = self.protected.pop_front() {
+                self.entries.get_mut(&demoted).unwrap().segment = Segment::Probationary;
+                self.probationary.push_back(demoted);
+            }
+        }
+    }
+
+    fn set_evictable(&mut self, frame_id: FrameId, is_evictable: bool) {
+        if let Some(entry) = self.entries.get_mut(&frame_id) {
+            entry.is_evictable = is_evictable;
+        }
+    }
+
+    fn evict(&mut self) -> Option<FrameId> {
+        self.evict_from(Segment::Probationary)
+            .or_else(|| self.evict_from(Segment::Protected))
+    }
+
+    fn remove(&mut self, frame_id: FrameId) {
+        if let Some(entry) = self.entries.remove(&frame_id) {
+            self.segment_mut(entry.segment).retain(|id| *id != frame_id);
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.entries
+            .values()
+            .filter(|entry| entry.is_evictable)
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn access(replacer: &mut SlruReplacer, frame_id: usize) {
+        let frame_id = FrameId::new(frame_id);
+        replacer.record_access(frame_id, AccessType::Unknown);
+        replacer.set_evictable(frame_id, true);
+    }
+
+    #[test]
+    fn test_repeated_access_protects_frame() {
+        let mut replacer = SlruReplacer::new(4);
+        for frame_id in 0..4 {
+            access(&mut replacer, frame_id);
+        }
+
+        // protected segment holds one frame here, promoting frame 2 demotes frame 0
+        access(&mut replacer, 0);
+        assert_eq!(
+            replacer.entries[&FrameId::new(0)].segment,
+            Segment::Protected
+        );
+        assert_eq!(replacer.evict(), Some(FrameId::new(1)));
+        access(&mut replacer, 2);
+        access(&mut replacer, 2);
+        assert_eq!(
+            replacer.entries[&FrameId::new(0)].segment,
+            Segment::Probationary
+        );
+
+        assert_eq!(replacer.evict(), Some(FrameId::new(3)));
+        assert_eq!(replacer.evict(), Some(FrameId::new(0)));
+        assert_eq!(replacer.evict(), Some(FrameId::new(2)));
+        assert_eq!(replacer.evict(), None);
+    }
+}
@@ -0,0 +1,128 @@
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    hash::Hash,
+    sync::{Arc, Weak},
+};
+
+use anyhow::Result;
+use parking_lot::Mutex;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    cancellation::CancellationToken,
+    kv::{Kv, KvEntry},
+};
+
+/// Values keys had when snapshot was taken, key is recorded on its first write after that.
+/// `None` stands for key which was absent.
+#[derive(Debug)]
+pub(crate) struct SnapshotState<K, V> {
+    old_values: Mutex<HashMap<K, Option<V>>>,
+}
+
+/// Snapshots taken from the store which are still alive
+#[derive(Debug)]
+pub(crate) struct Snapshots<K, V> {
+    snapshots: Mutex<Vec<Weak<SnapshotState<K, V>>>>,
+}
+
+impl<K, V> Snapshots<K, V>
+where
+    K: Hash + Eq + Clone,
+    V: Clone,
+{
+    pub fn new() -> Self {
+        Self {
+            snapshots: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn register(&self) -> Arc<SnapshotState<K, V>> {
+        let state = Arc::new(SnapshotState {
+            old_values: Mutex::new(HashMap::new()),
+        });
+        self.snapshots.lock().push(Arc::downgrade(&state));
+
+        state
+    }
+
+    /// Remember value the key has before write, must be called before write is visible
+    /// to readers. Dropped snapshots are unregistered.
+    pub fn record(&self, key: &K, old_value: Option<&V>) {
+        let mut snapshots = self.snapshots.lock();
+
+        snapshots.retain(|snapshot| {
+            let Some(snapshot) = snapshot.upgrade() else {
+                return false;
+            };
+            snapshot
+                .old_values
+                .lock()
+                .entry(key.clone())
+                .or_insert_with(|| old_value.cloned());
+
+            true
+        });
+    }
+}
+
+/// Read view of `Kv` at the point in time it was taken, writes made after that are not visible.
+/// Values overwritten since the snapshot are kept in memory until it is dropped.
+#[derive(Debug)]
+pub struct Snapshot<'a, K, V> {
+    kv: &'a Kv<K, V>,
+    state: Arc<SnapshotState<K, KvEntry<V>>>,
+}
+
+impl<'a, K, V> Snapshot<'a, K, V>
+where
+    K: Hash + Eq + Clone + Debug + Serialize + DeserializeOwned + ToString,
+    V: Clone + Debug + Serialize + DeserializeOwned,
+{
+    pub(crate) fn new(kv: &'a Kv<K, V>, state: Arc<SnapshotState<K, KvEntry<V>>>) -> Self {
+        Self { kv, state }
+    }
+
+    pub fn get(&self, key: K) -> Result<Option<V>> {
+        // live value is read first: if key is written after that, its old value is recorded
+        // by the time it is looked up below
+        let entry = self.kv.get_entry(key.clone())?;
+        let entry = match self.state.old_values.lock().get(&key) {
+            Some(old_value) => old_value.clone(),
+            None => entry,
+        };
+
+        self.kv.resolve(&key, entry)
+    }
+
+    /// All entries of the store as of the snapshot
+    pub fn scan(&self) -> Result<Vec<(K, V)>> {
+        self.scan_cancellable(&CancellationToken::new())
+    }
+
+    /// Like `scan`, but aborted with `ExtendibleHashTableError::Cancelled` once token
+    /// is cancelled
+    pub fn scan_cancellable(&self, token: &CancellationToken) -> Result<Vec<(K, V)>> {
+        let mut entries = self
+            .kv
+            .scan_entries(token)?
+            .into_iter()
+            .collect::<HashMap<K, KvEntry<V>>>();
+        for (key, old_value) in self.state.old_values.lock().iter() {
+            match old_value {
+                Some(old_value) => entries.insert(key.clone(), old_value.clone()),
+                None => entries.remove(key),
+            };
+        }
+
+        let mut values = Vec::with_capacity(entries.len());
+        for (key, entry) in entries {
+            if let Some(value) = self.kv.resolve(&key, Some(entry))? {
+                values.push((key, value));
+            }
+        }
+
+        Ok(values)
+    }
+}
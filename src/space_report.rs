@@ -0,0 +1,146 @@
+use std::{collections::BTreeMap, fmt};
+
+use anyhow::Result;
+
+use crate::{
+    disk_manager::DiskManager,
+    inspect::{inspect, PageKind},
+    page::PAGE_SIZE,
+};
+
+/// Pages taken by hash table
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TableSpace {
+    pub name: String,
+    /// Header, directory, key dictionary and bucket pages
+    pub pages: usize,
+    pub bucket_pages: usize,
+    pub entries: usize,
+    /// Entries buckets may hold
+    pub bucket_capacity: usize,
+}
+
+impl TableSpace {
+    /// Share of bucket capacity taken by entries
+    pub fn fill(&self) -> f64 {
+        fill(self.entries, self.bucket_capacity)
+    }
+}
+
+/// Where pages of data file went, see `DbInstance::space_report`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SpaceReport {
+    /// Pages in data file
+    pub pages: usize,
+    pub metadata_pages: usize,
+    /// Pages on free list or holding only zeroes, like never written ones
+    pub free_pages: usize,
+    /// Pages no table refers to which still hold data, like pages of structure change
+    /// interrupted by crash
+    pub unreferenced_pages: usize,
+    pub corrupted_pages: usize,
+    /// Tables ordered by name
+    pub tables: Vec<TableSpace>,
+}
+
+impl SpaceReport {
+    pub fn bytes(&self) -> usize {
+        self.pages * PAGE_SIZE
+    }
+
+    /// Share of bucket capacity taken by entries over all tables
+    pub fn fill(&self) -> f64 {
+        fill(
+            self.tables.iter().map(|table| table.entries).sum(),
+            self.tables.iter().map(|table| table.bucket_capacity).sum(),
+        )
+    }
+}
+
+impl fmt::Display for SpaceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} pages ({} bytes): {} metadata, {} free, {} unreferenced, {} corrupted, fill {:.0}%",
+            self.pages,
+            self.bytes(),
+            self.metadata_pages,
+            self.free_pages,
+            self.unreferenced_pages,
+            self.corrupted_pages,
+            self.fill() * 100.0
+        )?;
+        for table in &self.tables {
+            writeln!(
+                f,
+                "{:?}: {} pages, {} buckets, {} entries, fill {:.0}%",
+                table.name,
+                table.pages,
+                table.bucket_pages,
+                table.entries,
+                table.fill() * 100.0
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+fn table(tables: &mut BTreeMap<String, TableSpace>, name: String) -> &mut TableSpace {
+    tables.entry(name.clone()).or_insert_with(|| TableSpace {
+        name,
+        ..TableSpace::default()
+    })
+}
+
+fn fill(entries: usize, capacity: usize) -> f64 {
+    if capacity == 0 {
+        return 0.0;
+    }
+
+    entries as f64 / capacity as f64
+}
+
+/// Sum up pages of data file by what they hold, hash tables are walked from catalog
+pub(crate) fn space_report(disk_manager: &DiskManager) -> Result<SpaceReport> {
+    let mut report = SpaceReport::default();
+    let mut tables: BTreeMap<String, TableSpace> = BTreeMap::new();
+
+    for page in inspect(disk_manager)? {
+        report.pages += 1;
+        match page.kind {
+            PageKind::Metadata { hash_tables } => {
+                report.metadata_pages += 1;
+                for (name, _) in hash_tables {
+                    table(&mut tables, name);
+                }
+            }
+            PageKind::Header { hash_table, .. }
+            | PageKind::Directory { hash_table, .. }
+            | PageKind::KeyDictionary { hash_table } => table(&mut tables, hash_table).pages += 1,
+            PageKind::Bucket {
+                hash_table,
+                len,
+                max_size,
+                ..
+            } => {
+                let table = table(&mut tables, hash_table);
+                table.pages += 1;
+                table.bucket_pages += 1;
+                table.entries += len;
+                table.bucket_capacity += max_size;
+            }
+            PageKind::Corrupted { hash_table, .. } => {
+                report.corrupted_pages += 1;
+                if !hash_table.is_empty() {
+                    table(&mut tables, hash_table).pages += 1;
+                }
+            }
+            PageKind::Free => report.free_pages += 1,
+            PageKind::Unreferenced => report.unreferenced_pages += 1,
+        }
+    }
+    report.tables = tables.into_values().collect();
+
+    Ok(report)
+}
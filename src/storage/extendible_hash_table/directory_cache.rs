@@ -0,0 +1,124 @@
+use std::{collections::HashMap, sync::Arc};
+
+use parking_lot::Mutex;
+
+use super::extendible_hash_table_directory_page::ExtendibleHTableDirectoryPage;
+use crate::page::PageId;
+
+#[derive(Debug)]
+struct CachedDirectory {
+    version: u64,
+    directory: Arc<ExtendibleHTableDirectoryPage>,
+    size: usize,
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct Directories {
+    entries: HashMap<PageId, CachedDirectory>,
+    // sum of sizes of cached directories
+    size: usize,
+    // advanced by every use, the least recently used directory has the lowest one
+    clock: u64,
+}
+
+/// Decoded directories of hash table shared by all its threads, with versions of pages
+/// they were decoded from. Directory is used only while its page keeps the version, page
+/// written since is decoded again. The least recently used directories are dropped once
+/// they take more than budget bytes.
+#[derive(Debug)]
+pub(crate) struct DirectoryCache {
+    budget: usize,
+    directories: Mutex<Directories>,
+}
+
+impl DirectoryCache {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            directories: Mutex::new(Directories::default()),
+        }
+    }
+
+    /// Directory decoded from page at given version, directory of other version is dropped
+    pub fn get(&self, page_id: PageId, version: u64) -> Option<Arc<ExtendibleHTableDirectoryPage>> {
+        let mut directories = self.directories.lock();
+        directories.clock += 1;
+        let clock = directories.clock;
+        let cached = directories.entries.get_mut(&page_id)?;
+        if cached.version == version {
+            cached.last_used = clock;
+            return Some(Arc::clone(&cached.directory));
+        }
+
+        let size = cached.size;
+        directories.entries.remove(&page_id);
+        directories.size -= size;
+
+        None
+    }
+
+    pub fn insert(
+        &self,
+        page_id: PageId,
+        version: u64,
+        directory: Arc<ExtendibleHTableDirectoryPage>,
+    ) {
+        let size = directory.memory_size();
+        if size > self.budget {
+            return;
+        }
+
+        let mut directories = self.directories.lock();
+        directories.clock += 1;
+        let cached = CachedDirectory {
+            version,
+            directory,
+            size,
+            last_used: directories.clock,
+        };
+        if let Some(replaced) = directories.entries.insert(page_id, cached) {
+            directories.size -= replaced.size;
+        }
+        directories.size += size;
+
+        while directories.size > self.budget {
+            let Some(evicted) = directories
+                .entries
+                .iter()
+                .filter(|(evicted, _)| **evicted != page_id)
+                .min_by_key(|(_, cached)| cached.last_used)
+                .map(|(evicted, _)| *evicted)
+            else {
+                break;
+            };
+            let evicted = directories.entries.remove(&evicted).unwrap();
+            directories.size -= evicted.size;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_directory_cache() {
+        let directory = Arc::new(ExtendibleHTableDirectoryPage::new(2));
+        let size = directory.memory_size();
+        let cache = DirectoryCache::new(2 * size);
+
+        cache.insert(PageId::new(1), 2, Arc::clone(&directory));
+        cache.insert(PageId::new(2), 2, Arc::clone(&directory));
+        assert!(cache.get(PageId::new(1), 2).is_some());
+
+        // page 2 is the least recently used one
+        cache.insert(PageId::new(3), 2, Arc::clone(&directory));
+        assert!(cache.get(PageId::new(2), 2).is_none());
+        assert!(cache.get(PageId::new(1), 2).is_some());
+
+        // written page has new version
+        assert!(cache.get(PageId::new(3), 4).is_none());
+        assert!(cache.get(PageId::new(3), 2).is_none());
+    }
+}
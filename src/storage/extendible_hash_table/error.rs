@@ -8,6 +8,28 @@ pub enum ExtendibleHashTableError {
     NoDirectoryForPageId,
     #[error("Can't load bucket by page id.")]
     NoBucketForPageId,
+    #[error("Can't get page from buffer pool.")]
+    PageNotAvailable,
+    #[error("Bucket data doesn't fit into page.")]
+    PageOverflow,
+    #[error("Page data is corrupted.")]
+    CorruptedPage,
+    #[error("Operation is cancelled.")]
+    Cancelled,
+    #[error("Can't log structure change: {0}")]
+    StructureLog(String),
+    #[error("Hash table {0} isn't in catalog.")]
+    NotInCatalog(String),
+    #[error("Hash table {name} holds {stored}, not {requested}.")]
+    TypeMismatch {
+        name: String,
+        stored: String,
+        requested: String,
+    },
+    #[error("Hash table keys are hashed with {stored}, not {requested}.")]
+    KeyHasherMismatch { stored: String, requested: String },
+    #[error("Hash function {0} hashes keys differently than when hash table was written.")]
+    KeyHasherChanged(String),
     #[error("unknown database error")]
     Unknown,
-}
\ No newline at end of file
+}
@@ -1,32 +1,129 @@
+use super::directory_cache::DirectoryCache;
 use super::error::ExtendibleHashTableError;
-use super::extendible_hash_table_bucket_page::ExtendibleHTableBucketPage;
+use super::extendible_hash_table_bucket_page::{
+    decode_occupancy, find_value, ExtendibleHTableBucketPage,
+};
 use super::extendible_hash_table_directory_page::ExtendibleHTableDirectoryPage;
-use super::extendible_hash_table_header_page::ExtendibleHTableHeaderPage;
-use crate::{buffer_pool_manager::BufferPoolManager, page::PageId};
-use parking_lot::RwLockWriteGuard;
+use super::extendible_hash_table_header_page::{
+    DirectoryHashBits, ExtendibleHTableHeaderPage, KeyHasher,
+};
+use super::extendible_hash_table_key_dictionary_page::ExtendibleHTableKeyDictionaryPage;
+use super::snapshot_cache::SnapshotCache;
+use super::value_codec::ValueCodec;
+use super::write_coalescer::{PendingInsert, WriteCoalescer};
+use crate::{
+    buffer_pool_manager::BufferPoolManager,
+    cancellation::CancellationToken,
+    key_normalization::KeyNormalization,
+    latency_breakdown::{self, LatencyBreakdown, Phase},
+    lru_k_replacer::AccessType,
+    page::{PageId, PAGE_SIZE},
+    page_guard::{ReadPageGuard, WritePageGuard},
+    sharded_counter::ShardedCounter,
+    storage::metadata_page::{MetadataPage, METADATA_PAGE_ID},
+    structure_log::StructureChange,
+    thread_pool::ThreadPool,
+};
+use parking_lot::Mutex;
 use serde::{de::DeserializeOwned, Serialize};
 use std::{
+    collections::{hash_map::Entry, BTreeMap, BTreeSet, HashMap},
     fmt::Debug,
-    hash::{DefaultHasher, Hash, Hasher},
+    hash::Hash,
     marker::PhantomData,
-    sync::{Arc, Mutex},
+    sync::{mpsc, Arc},
+    time::Duration,
 };
 
-fn hash_string(s: String) -> u32 {
-    let mut hasher = DefaultHasher::new();
-    s.hash(&mut hasher);
-    let hash = hasher.finish();
+pub(crate) fn hash_string(s: String) -> u32 {
+    KeyHasher::Std.hash(&s)
+}
+
+// what read-modify-write does with the key
+enum Modification<V> {
+    Keep,
+    Set(V),
+    Remove,
+}
+
+impl<V> From<Option<V>> for Modification<V> {
+    fn from(value: Option<V>) -> Self {
+        match value {
+            Some(value) => Modification::Set(value),
+            None => Modification::Remove,
+        }
+    }
+}
+
+// outcome of inserting key into directory
+enum Insertion<'a, K, V> {
+    Inserted,
+    // directory has to double before key fits, full bucket stays latched
+    NeedsGrowth(K, V, WritePageGuard<'a>),
+}
+
+// bytes bucket page encoding takes besides its entries
+const BUCKET_OVERHEAD: usize = 64;
+
+// optimistic reads which may be retried before reader latches directory until it reads bucket
+const MAX_OPTIMISTIC_READS: usize = 3;
+
+// buckets scan starts reading ahead of the one it processes
+const SCAN_READAHEAD: usize = 4;
+
+/// Cumulative counters of hash table operations since it was opened
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct HashTableStats {
+    /// Lookups, every key of multi-get counts
+    pub gets: u64,
+    /// Inserts, removes and other writes, coalesced ones included
+    pub writes: u64,
+    /// Optimistic lookups started over because header or directory changed meanwhile
+    pub read_retries: u64,
+    /// Inserts written as part of a coalesced batch
+    pub coalesced_inserts: u64,
+    /// Headers and directories of lookups taken from snapshot cache of the thread
+    pub snapshot_cache_hits: u64,
+    /// Directories of lookups taken decoded from directory cache of the table
+    pub directory_cache_hits: u64,
+}
+
+/// When sibling buckets are merged by `compact_buckets` and how often background
+/// compaction runs, see `DbInstance::schedule_bucket_compaction`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompactionPolicy {
+    /// Most of bucket capacity (entries and page size) merged bucket may fill,
+    /// so it doesn't split again right after merge
+    pub max_merged_fill: f64,
+    pub interval: Duration,
+}
+
+impl Default for CompactionPolicy {
+    fn default() -> Self {
+        Self {
+            max_merged_fill: 0.5,
+            interval: Duration::from_secs(60),
+        }
+    }
+}
 
-    (hash % u32::MAX as u64) as u32
+// counted on hot paths, so they are sharded instead of shared atomics
+#[derive(Debug, Default)]
+struct Counters {
+    gets: ShardedCounter,
+    writes: ShardedCounter,
+    read_retries: ShardedCounter,
+    coalesced_inserts: ShardedCounter,
+    snapshot_cache_hits: ShardedCounter,
+    directory_cache_hits: ShardedCounter,
 }
 
 /*
     TODO:
-    1. Unwraps -> Result
-    2. Review pages locking on insert: page should be locked while inserting
-    3. Get rid of recursive calls
-    4. `Get` should return reference to value
-    5. Process keys collision
+    1. Review pages locking on insert: page should be locked while inserting
+    2. Get rid of recursive calls
+    3. `Get` should return reference to value
+    4. Process keys collision
 */
 #[derive(Debug)]
 pub struct ExtendibleHashTable<K, V> {
@@ -35,6 +132,22 @@ pub struct ExtendibleHashTable<K, V> {
     bucket_max_size: usize,
     header_page_id: PageId,
     buffer_pool_manager: Arc<BufferPoolManager>,
+    // dictionary of bucket keys with its page, page ids are never reused
+    key_dictionary: Mutex<Option<(PageId, Arc<ExtendibleHTableKeyDictionaryPage>)>>,
+    key_normalization: KeyNormalization,
+    key_hasher: KeyHasher,
+    keep_resident: bool,
+    latency_breakdown: bool,
+    write_coalescer: Option<WriteCoalescer<K, V>>,
+    snapshot_cache: Option<SnapshotCache>,
+    directory_cache: Option<DirectoryCache>,
+    // lookups latch directory until they read bucket after this many failed optimistic reads
+    max_optimistic_reads: usize,
+    counters: Counters,
+    // serializes writers which change header or directories. Writer which changes a single
+    // bucket only shares directory latch, so writers of different buckets don't wait for
+    // each other.
+    structure: Mutex<()>,
     phantom_key: PhantomData<K>,
     phantom_value: PhantomData<V>,
 }
@@ -42,105 +155,685 @@ pub struct ExtendibleHashTable<K, V> {
 impl<K, V> ExtendibleHashTable<K, V>
 where
     K: Hash + Eq + Clone + Debug + Serialize + DeserializeOwned + ToString,
-    V: Copy + Clone + Debug + Serialize + DeserializeOwned,
+    V: Clone + Debug + Serialize + DeserializeOwned,
 {
+    /// Create table with new header page, panics if buffer pool has no frame for it
     pub fn new(
         name: String,
         buffer_pool_manager: Arc<BufferPoolManager>,
         directory_max_depth: u32,
         bucket_max_size: usize,
     ) -> Self {
-        let header_max_size = 0;
+        Self::new_with_header(
+            name,
+            buffer_pool_manager,
+            0,
+            DirectoryHashBits::High,
+            directory_max_depth,
+            bucket_max_size,
+        )
+    }
+
+    /// Like `new`, header picks one of `2^header_max_depth` directories by given bits of
+    /// key hash. Directories use low bits, so high bits keep keys of every directory
+    /// spread over all of its buckets.
+    pub fn new_with_header(
+        name: String,
+        buffer_pool_manager: Arc<BufferPoolManager>,
+        header_max_depth: u32,
+        directory_hash_bits: DirectoryHashBits,
+        directory_max_depth: u32,
+        bucket_max_size: usize,
+    ) -> Self {
+        Self::create(
+            name,
+            buffer_pool_manager,
+            header_max_depth,
+            directory_hash_bits,
+            KeyHasher::default(),
+            directory_max_depth,
+            bucket_max_size,
+        )
+        .expect("Buffer pool has no frame for header page.")
+    }
+
+    /// Like `new`, keys are hashed with given hasher instead of std one, which may hash
+    /// differently once built by another Rust version
+    pub fn new_with_hasher(
+        name: String,
+        buffer_pool_manager: Arc<BufferPoolManager>,
+        key_hasher: KeyHasher,
+        directory_max_depth: u32,
+        bucket_max_size: usize,
+    ) -> Self {
+        Self::create(
+            name,
+            buffer_pool_manager,
+            0,
+            DirectoryHashBits::High,
+            key_hasher,
+            directory_max_depth,
+            bucket_max_size,
+        )
+        .expect("Buffer pool has no frame for header page.")
+    }
 
-        // TODO: what if BPM is not able to create new page
-        let buf = Arc::clone(&buffer_pool_manager);
-        let (page_id, mut header_page) = buf.new_page().unwrap();
-        let header = ExtendibleHTableHeaderPage::new(header_max_size);
+    fn create(
+        name: String,
+        buffer_pool_manager: Arc<BufferPoolManager>,
+        header_max_depth: u32,
+        directory_hash_bits: DirectoryHashBits,
+        key_hasher: KeyHasher,
+        directory_max_depth: u32,
+        bucket_max_size: usize,
+    ) -> Result<Self, ExtendibleHashTableError> {
+        let (page_id, mut header_page) = buffer_pool_manager
+            .new_page()
+            .ok_or(ExtendibleHashTableError::PageNotAvailable)?;
+        let mut header =
+            ExtendibleHTableHeaderPage::with_hash_bits(header_max_depth, directory_hash_bits);
+        header.set_key_hasher(key_hasher);
         let header_data = header.to_bytes();
-        *header_page = header_data;
+        header_page.overwrite(&header_data);
+        drop(header_page);
+
+        Ok(Self::open(
+            name,
+            buffer_pool_manager,
+            page_id,
+            directory_max_depth,
+            bucket_max_size,
+        ))
+    }
+
+    /// Open hash table which header page was previously created by `new`, keys are hashed
+    /// with hasher stored in header
+    pub fn open(
+        name: String,
+        buffer_pool_manager: Arc<BufferPoolManager>,
+        header_page_id: PageId,
+        directory_max_depth: u32,
+        bucket_max_size: usize,
+    ) -> Self {
+        // header which can't be read fails first operation anyway
+        let key_hasher = buffer_pool_manager
+            .fetch_page_read(header_page_id)
+            .and_then(|header_page| ExtendibleHTableHeaderPage::try_from(&header_page).ok())
+            .map_or_else(KeyHasher::default, |header| header.get_key_hasher());
 
         Self {
             name,
             directory_max_depth,
             bucket_max_size,
-            // TODO: for now we assume that BPM will return page with initialized PageId
-            // consider have Frame and Page entities, where Page always have PageId
-            header_page_id: page_id,
+            header_page_id,
             buffer_pool_manager,
+            key_dictionary: Mutex::new(None),
+            key_normalization: KeyNormalization::Exact,
+            key_hasher,
+            keep_resident: false,
+            latency_breakdown: false,
+            write_coalescer: None,
+            snapshot_cache: None,
+            directory_cache: None,
+            max_optimistic_reads: MAX_OPTIMISTIC_READS,
+            counters: Counters::default(),
+            structure: Mutex::new(()),
             phantom_key: PhantomData,
             phantom_value: PhantomData,
         }
     }
 
+    /// Open hash table registered in catalog of the database, like by
+    /// `DbInstance::open_hash_table`, with parameters and key normalization it was
+    /// created with. Table has to hold keys and values of types it was created with.
+    pub fn open_by_name(
+        name: &str,
+        buffer_pool_manager: Arc<BufferPoolManager>,
+    ) -> Result<Self, ExtendibleHashTableError> {
+        let metadata_page = buffer_pool_manager
+            .fetch_page_read(METADATA_PAGE_ID)
+            .ok_or(ExtendibleHashTableError::PageNotAvailable)?;
+        let metadata = MetadataPage::try_from(&metadata_page)
+            .map_err(|_| ExtendibleHashTableError::CorruptedPage)?;
+        drop(metadata_page);
+
+        // tables created before parameters were stored can't be opened by name alone
+        let (Some(header_page_id), Some(params)) = (
+            metadata.get_header_page_id(name),
+            metadata.get_table_params(name),
+        ) else {
+            return Err(ExtendibleHashTableError::NotInCatalog(name.to_string()));
+        };
+        if !params.has_types::<K, V>() {
+            return Err(ExtendibleHashTableError::TypeMismatch {
+                name: name.to_string(),
+                stored: format!("{} => {}", params.key_type, params.value_type),
+                requested: format!(
+                    "{} => {}",
+                    std::any::type_name::<K>(),
+                    std::any::type_name::<V>()
+                ),
+            });
+        }
+
+        let table = Self::open(
+            name.to_string(),
+            buffer_pool_manager,
+            header_page_id,
+            params.directory_max_depth,
+            params.bucket_max_size,
+        )
+        .with_key_normalization(metadata.get_key_normalization(name));
+        // hasher has to hash the same as when table was written
+        table.read_header()?;
+
+        Ok(table)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn header_page_id(&self) -> PageId {
+        self.header_page_id
+    }
+
+    /// Normalize keys before they are hashed and compared, table has to be opened with
+    /// the same normalization it was written with
+    pub fn with_key_normalization(mut self, key_normalization: KeyNormalization) -> Self {
+        self.key_normalization = key_normalization;
+        self
+    }
+
+    pub fn key_normalization(&self) -> KeyNormalization {
+        self.key_normalization
+    }
+
+    /// Hash keys with given hasher instead of one stored in header, operations fail
+    /// with `KeyHasherMismatch` unless they are the same
+    pub fn with_key_hasher(mut self, key_hasher: KeyHasher) -> Self {
+        self.key_hasher = key_hasher;
+        self
+    }
+
+    pub fn key_hasher(&self) -> KeyHasher {
+        self.key_hasher
+    }
+
+    pub fn stats(&self) -> HashTableStats {
+        HashTableStats {
+            gets: self.counters.gets.get(),
+            writes: self.counters.writes.get(),
+            read_retries: self.counters.read_retries.get(),
+            coalesced_inserts: self.counters.coalesced_inserts.get(),
+            snapshot_cache_hits: self.counters.snapshot_cache_hits.get(),
+            directory_cache_hits: self.counters.directory_cache_hits.get(),
+        }
+    }
+
+    /// Hint buffer pool to keep header and directory pages resident, they are touched by
+    /// every operation. Directories created later are kept resident as well.
+    pub fn with_keep_resident(mut self) -> Self {
+        self.keep_resident = true;
+        self.buffer_pool_manager
+            .set_keep_resident(self.header_page_id, true);
+        // hint is best effort, table which can't be read fails on first operation anyway
+        if let Ok((_, header)) = self.read_header() {
+            for directory_index in 0..header.get_max_size() {
+                if let Some(directory_page_id) = header.get_directory_page_id(directory_index) {
+                    self.buffer_pool_manager
+                        .set_keep_resident(*directory_page_id, true);
+                }
+            }
+        }
+
+        self
+    }
+
+    /// Emit latency breakdown of every lookup and write as debug tracing event
+    pub fn with_latency_breakdown(mut self) -> Self {
+        self.latency_breakdown = true;
+        self
+    }
+
+    /// Keep decoded header and directories every thread read last, lookups of the thread
+    /// reuse them as long as their pages are unchanged instead of fetching and decoding
+    /// them again. Pays off for threads which look up the same table over and over.
+    pub fn with_snapshot_cache(mut self) -> Self {
+        self.snapshot_cache = Some(SnapshotCache::new());
+        self
+    }
+
+    /// Keep directories decoded by lookups of all threads, up to `budget` bytes of the
+    /// least recently used ones. Directory is decoded again once its page is written.
+    pub fn with_directory_cache(mut self, budget: usize) -> Self {
+        self.directory_cache = Some(DirectoryCache::new(budget));
+        self
+    }
+
+    /// Group inserts which arrive for the same bucket within `window`, each group is
+    /// written under one bucket latch with one page write. Every insert waits at least
+    /// the window, so it pays off only when many writers hit the same buckets.
+    pub fn with_write_coalescing(mut self, window: Duration) -> Self {
+        self.write_coalescer = Some(WriteCoalescer::new(window));
+        self
+    }
+
+    // runs operation, its latency breakdown is emitted if table opted in
+    fn measured<T>(&self, operation: &'static str, f: impl FnOnce() -> T) -> T {
+        if !self.latency_breakdown {
+            return f();
+        }
+
+        let (result, breakdown) = latency_breakdown::measure(f);
+        tracing::debug!(table = %self.name, operation, %breakdown, "operation latency");
+
+        result
+    }
+
+    fn hash_key(&self, key: &K) -> u32 {
+        latency_breakdown::phase(Phase::Hash, || {
+            self.key_hasher
+                .hash(&self.key_normalization.normalize(&key.to_string()))
+        })
+    }
+
+    // max size of buckets split from bucket with entries of given sizes. Entries as large
+    // as 90% of them are fit into a page, so a few large values don't shrink the bucket
+    // much, and bucket never takes more than table's bucket max size.
+    fn adaptive_max_size(&self, mut entry_sizes: Vec<usize>) -> usize {
+        if entry_sizes.is_empty() {
+            return self.bucket_max_size;
+        }
+        entry_sizes.sort_unstable();
+        let typical_size = entry_sizes[(entry_sizes.len() - 1) * 9 / 10].max(1);
+
+        ((PAGE_SIZE - BUCKET_OVERHEAD) / typical_size).clamp(1, self.bucket_max_size)
+    }
+
+    // key as it is stored in the bucket, keys equal once normalized are the same key and
+    // the key keeps spelling it was first written with
+    fn stored_key(&self, bucket: &ExtendibleHTableBucketPage<K, V>, key: K) -> K {
+        if self.key_normalization == KeyNormalization::Exact {
+            return key;
+        }
+
+        let key_string = key.to_string();
+        bucket
+            .keys()
+            .find(|stored| {
+                self.key_normalization
+                    .equals(&stored.to_string(), &key_string)
+            })
+            .cloned()
+            .unwrap_or(key)
+    }
+
     pub fn insert(&self, key: K, value: V) -> Result<(), ExtendibleHashTableError> {
+        let Some(write_coalescer) = &self.write_coalescer else {
+            return self.modify(key, |_| (Modification::Set(value), ()));
+        };
+
+        let hash = self.hash_key(&key);
+        // key without bucket yet changes structure anyway
+        let Some(bucket_page_id) = self.route_to_bucket(hash)? else {
+            return self.modify(key, |_| (Modification::Set(value), ()));
+        };
+        write_coalescer.submit(bucket_page_id, (key, value, hash), |batch| {
+            self.insert_batch(bucket_page_id, batch)
+        })
+    }
+
+    // bucket the hash maps to now, it may be split or merged right after
+    fn route_to_bucket(&self, hash: u32) -> Result<Option<PageId>, ExtendibleHashTableError> {
+        let (_, header) = self.read_header()?;
+        let Some(directory_page_id) = header
+            .get_directory_page_id(header.hash_to_directory_index(hash))
+            .copied()
+        else {
+            return Ok(None);
+        };
+        let directory_page = self
+            .buffer_pool_manager
+            .fetch_page_read(directory_page_id)
+            .ok_or(ExtendibleHashTableError::NoDirectoryForPageId)?;
+        let directory = self.decode_directory(&directory_page)?;
+
+        Ok(directory
+            .get_bucket_page_id(directory.hash_to_bucket_index(hash))
+            .copied())
+    }
+
+    // inserts of batch which still map to the bucket and fit into it are written at once,
+    // the rest is inserted one by one
+    fn insert_batch(&self, bucket_page_id: PageId, batch: Vec<PendingInsert<K, V>>) {
+        let written = self
+            .measured("write batch", || self.write_batch(bucket_page_id, &batch))
+            .unwrap_or_else(|_| vec![false; batch.len()]);
+
+        for (insert, written) in batch.into_iter().zip(written) {
+            let result = if written {
+                self.counters.writes.increment();
+                self.counters.coalesced_inserts.increment();
+                Ok(())
+            } else {
+                let value = insert.value;
+                self.modify(insert.key, |_| (Modification::Set(value), ()))
+            };
+            insert.completer.complete(result);
+        }
+    }
+
+    // which inserts of the batch were written, bucket stays write latched and directory
+    // read latched like in `modify_bucket`
+    fn write_batch(
+        &self,
+        bucket_page_id: PageId,
+        batch: &[PendingInsert<K, V>],
+    ) -> Result<Vec<bool>, ExtendibleHashTableError> {
+        let mut written = vec![false; batch.len()];
+        let Some(first) = batch.first() else {
+            return Ok(written);
+        };
+        let _write_gate = self.buffer_pool_manager.write_gate();
+        let (header_version, header) = self.read_header()?;
+        let dictionary = self.key_dictionary(&header)?;
+        let dictionary = dictionary.as_deref();
+        // buckets of different directories never share a page
+        let directory_index = header.hash_to_directory_index(first.hash);
+        let Some(directory_page_id) = header.get_directory_page_id(directory_index).copied() else {
+            return Ok(written);
+        };
+        let directory_page = self
+            .buffer_pool_manager
+            .fetch_page_read(directory_page_id)
+            .ok_or(ExtendibleHashTableError::NoDirectoryForPageId)?;
+        if !self.is_header_unchanged(header_version) {
+            return Ok(written);
+        }
+        let directory = ExtendibleHTableDirectoryPage::try_from(&directory_page)?;
+        // page of bucket merged meanwhile may be reused by anything, so it is fetched only
+        // if directory still points to it
+        let maps_to_bucket = |hash| {
+            directory.get_bucket_page_id(directory.hash_to_bucket_index(hash))
+                == Some(&bucket_page_id)
+        };
+        if !batch.iter().any(|insert| maps_to_bucket(insert.hash)) {
+            return Ok(written);
+        }
+        let mut bucket_page = self
+            .buffer_pool_manager
+            .fetch_page_write(bucket_page_id)
+            .ok_or(ExtendibleHashTableError::NoBucketForPageId)?;
+        if !self.is_header_unchanged(header_version) {
+            return Ok(written);
+        }
+
+        let mut bucket =
+            ExtendibleHTableBucketPage::<K, V>::from_bytes_with(&bucket_page, dictionary)?;
+        for (insert, written) in batch.iter().zip(written.iter_mut()) {
+            if !maps_to_bucket(insert.hash) {
+                continue;
+            }
+            let key = self.stored_key(&bucket, insert.key.clone());
+            // same room check as `modify_bucket`, inserts which may split go one by one
+            let fits = bucket.get(key.clone()).is_some() || {
+                let bucket_size = bucket.to_bytes_with(dictionary).len();
+                let average_entry_size = bucket_size / bucket.get_size().max(1);
+
+                !bucket.is_full() && bucket_size + 2 * average_entry_size <= PAGE_SIZE
+            };
+            if fits {
+                bucket.insert(key, insert.value.clone());
+                *written = true;
+            }
+        }
+        let bucket_data = bucket.to_bytes_with(dictionary);
+        if bucket_data.len() > PAGE_SIZE {
+            return Ok(vec![false; batch.len()]);
+        }
+        if written.contains(&true) {
+            bucket_page.overwrite(&bucket_data);
+        }
+
+        Ok(written)
+    }
+
+    // must be called with structure lock, returns page id of the new directory
+    fn create_directory(&self, directory_index: usize) -> Result<PageId, ExtendibleHashTableError> {
         let mut header_page = self
             .buffer_pool_manager
             .fetch_page_write(self.header_page_id)
-            .unwrap();
-        let mut header = ExtendibleHTableHeaderPage::from(&header_page);
+            .ok_or(ExtendibleHashTableError::PageNotAvailable)?;
+        let mut header = ExtendibleHTableHeaderPage::try_from(&header_page)?;
+        let (directory_page_id, mut directory_page) = self
+            .buffer_pool_manager
+            .new_page()
+            .ok_or(ExtendibleHashTableError::PageNotAvailable)?;
+        let directory = ExtendibleHTableDirectoryPage::new(self.directory_max_depth);
+        if self.keep_resident {
+            self.buffer_pool_manager
+                .set_keep_resident(directory_page_id, true);
+        }
+        header.set_directory_page_id(directory_index, directory_page_id);
+        header_page.overwrite(&header.to_bytes());
+        directory_page.overwrite(&directory.to_bytes());
+        self.log_structure_change(
+            StructureChange::NewDirectory,
+            &[&header_page, &directory_page],
+        )?;
 
-        let insertion_key_hash = hash_string(key.to_string());
+        Ok(directory_page_id)
+    }
 
-        let directory_index = header.hash_to_directory_index(insertion_key_hash);
-        let (mut directory, mut directory_page) =
-            match header.get_directory_page_id(directory_index) {
-                Some(directory_page_id) => {
-                    let directory_page = self
-                        .buffer_pool_manager
-                        .fetch_page_write(*directory_page_id)
-                        .unwrap();
+    // must be called with structure lock, directory which has to double for the key is
+    // replaced by its doubled copy
+    fn insert_growing(
+        &self,
+        directory_index: usize,
+        mut directory: ExtendibleHTableDirectoryPage,
+        mut directory_page: WritePageGuard<'_>,
+        key: K,
+        value: V,
+        dictionary: Option<&ExtendibleHTableKeyDictionaryPage>,
+    ) -> Result<(), ExtendibleHashTableError> {
+        match self.insert_internal(key, value, &mut directory, &mut directory_page, dictionary)? {
+            Insertion::Inserted => Ok(()),
+            Insertion::NeedsGrowth(key, value, bucket_page) => {
+                // writers of other buckets go on with the old directory while it is copied
+                let directory_page_id = directory_page.page_id();
+                drop(directory_page);
 
-                    (
-                        ExtendibleHTableDirectoryPage::from(&directory_page),
-                        directory_page,
-                    )
+                self.grow_directory(
+                    directory_index,
+                    directory_page_id,
+                    directory,
+                    bucket_page,
+                    (key, value),
+                    dictionary,
+                )
+            }
+        }
+    }
+
+    // must be called with structure lock. Doubled copy of directory with the full bucket
+    // split into two new pages is prepared while readers keep using the old ones, then
+    // header is pointed to the copy. Key goes into its half before the copy is visible,
+    // the half is split again if every entry went there.
+    fn grow_directory<'a>(
+        &'a self,
+        directory_index: usize,
+        mut directory_page_id: PageId,
+        mut directory: ExtendibleHTableDirectoryPage,
+        mut bucket_page: WritePageGuard<'a>,
+        (key, value): (K, V),
+        dictionary: Option<&ExtendibleHTableKeyDictionaryPage>,
+    ) -> Result<(), ExtendibleHashTableError> {
+        let hash = self.hash_key(&key);
+        let mut entry = Some((key, value));
+
+        loop {
+            if directory.is_full() {
+                return Err(ExtendibleHashTableError::DirectoryMaxSizeReached);
+            }
+            // bucket stays write latched, so writers of other buckets can't change it
+            let mut bucket =
+                ExtendibleHTableBucketPage::<K, V>::from_bytes_with(&bucket_page, dictionary)?;
+            let bucket_page_id = bucket_page.page_id();
+            let bucket_index = directory.hash_to_bucket_index(hash);
+
+            directory.increment_global_depth()?;
+            let (low_page_id, mut low_page) = self
+                .buffer_pool_manager
+                .new_page()
+                .ok_or(ExtendibleHashTableError::PageNotAvailable)?;
+            let Some((high_page_id, mut high_page)) = self.buffer_pool_manager.new_page() else {
+                drop(low_page);
+                let _ = self.buffer_pool_manager.delete_page(low_page_id);
+                return Err(ExtendibleHashTableError::PageNotAvailable);
+            };
+            directory.increment_local_depth(bucket_index);
+            let split_image_index = directory.get_split_image_index(bucket_index);
+            directory.increment_local_depth(split_image_index);
+            directory.set_bucket_page_id(bucket_index, low_page_id);
+            directory.set_bucket_page_id(split_image_index, high_page_id);
+
+            let max_size = self.adaptive_max_size(bucket.entry_sizes());
+            let mut low = ExtendibleHTableBucketPage::<K, V>::new(max_size);
+            let mut high = ExtendibleHTableBucketPage::<K, V>::new(max_size);
+            for (key, value) in bucket.get_entries() {
+                let index = directory.hash_to_bucket_index(self.hash_key(&key));
+                if directory.get_bucket_page_id(index) == Some(&high_page_id) {
+                    high.insert(key, value);
+                } else {
+                    low.insert(key, value);
                 }
-                None => {
-                    let (page_id, new_page) = self.buffer_pool_manager.new_page().unwrap();
-                    let directory_page_id = page_id;
-                    //let header_page = self.fetch_page(self.header_page_id).unwrap();
-                    //let mut header_page = header_page.lock().unwrap();
-                    //let mut header = ExtendibleHTableHeaderPage::from(&header_page);
-                    header.set_directory_page_id(directory_index, directory_page_id);
-                    *header_page = header.to_bytes();
-                    //drop(header_page);
+            }
+            let key_goes_high = directory.get_bucket_page_id(directory.hash_to_bucket_index(hash))
+                == Some(&high_page_id);
+            let half = if key_goes_high { &mut high } else { &mut low };
+            if !half.is_full() {
+                let (key, value) = entry.take().unwrap();
+                half.insert(key.clone(), value);
+                // key too large for the half is kept for its next split
+                if half.get_size() > 1 && half.to_bytes_with(dictionary).len() > PAGE_SIZE {
+                    let value = half.delete(key.clone()).unwrap();
+                    half.set_max_size(half.get_size());
+                    entry = Some((key, value));
+                }
+            }
 
-                    (
-                        ExtendibleHTableDirectoryPage::new(self.directory_max_depth),
-                        new_page,
-                    )
+            low_page.overwrite(&low.to_bytes_with(dictionary));
+            high_page.overwrite(&high.to_bytes_with(dictionary));
+            if low_page.len() > PAGE_SIZE || high_page.len() > PAGE_SIZE {
+                drop((low_page, high_page));
+                let _ = self.buffer_pool_manager.delete_page(low_page_id);
+                let _ = self.buffer_pool_manager.delete_page(high_page_id);
+                return Err(ExtendibleHashTableError::PageOverflow);
+            }
+            let Some((new_directory_page_id, mut new_directory_page)) =
+                self.buffer_pool_manager.new_page()
+            else {
+                drop((low_page, high_page));
+                let _ = self.buffer_pool_manager.delete_page(low_page_id);
+                let _ = self.buffer_pool_manager.delete_page(high_page_id);
+                return Err(ExtendibleHashTableError::PageNotAvailable);
+            };
+            new_directory_page.overwrite(&directory.to_bytes());
+            if self.keep_resident {
+                self.buffer_pool_manager
+                    .set_keep_resident(new_directory_page_id, true);
+            }
+
+            // nobody waits for header latch while holding another one, so header can be
+            // latched with the bucket held
+            let Some(mut header_page) = self
+                .buffer_pool_manager
+                .fetch_page_write(self.header_page_id)
+            else {
+                drop((low_page, high_page, new_directory_page));
+                for page_id in [low_page_id, high_page_id, new_directory_page_id] {
+                    let _ = self.buffer_pool_manager.delete_page(page_id);
                 }
+                return Err(ExtendibleHashTableError::PageNotAvailable);
             };
-        //drop(header_page);
+            let mut header = ExtendibleHTableHeaderPage::try_from(&header_page)?;
+            header.set_directory_page_id(directory_index, new_directory_page_id);
+            header_page.overwrite(&header.to_bytes());
+            self.log_structure_change(
+                StructureChange::DirectoryGrowth,
+                &[&header_page, &new_directory_page, &low_page, &high_page],
+            )?;
+            drop((header_page, new_directory_page));
+
+            // writers waiting for the old bucket see header changed and start over. Pages
+            // still pinned by readers are deleted once readers unpin them, readers which
+            // pin them before fail validation.
+            drop(bucket_page);
+            self.buffer_pool_manager
+                .set_keep_resident(directory_page_id, false);
+            let _ = self
+                .buffer_pool_manager
+                .delete_page_when_unpinned(directory_page_id);
+            let _ = self
+                .buffer_pool_manager
+                .delete_page_when_unpinned(bucket_page_id);
 
-        self.insert_internal(key, value, &mut directory, &mut directory_page)?;
+            if entry.is_none() {
+                return Ok(());
+            }
+            (directory_page_id, bucket_page) = if key_goes_high {
+                drop(low_page);
+                (new_directory_page_id, high_page)
+            } else {
+                drop(high_page);
+                (new_directory_page_id, low_page)
+            };
+        }
+    }
 
-        Ok(())
+    // pages changed together reach disk together, so header or directory never point
+    // to page which wasn't written
+    fn log_structure_change(
+        &self,
+        change: StructureChange,
+        pages: &[&WritePageGuard<'_>],
+    ) -> Result<(), ExtendibleHashTableError> {
+        self.buffer_pool_manager
+            .log_structure_change(change, pages)
+            .map_err(|error| ExtendibleHashTableError::StructureLog(format!("{:#}", error)))
     }
 
+    // insert key into directory, bucket which is full is split in place unless directory
+    // has to double, key is handed back then
     fn insert_internal(
         &self,
         key: K,
         value: V,
         directory: &mut ExtendibleHTableDirectoryPage,
-        directory_page: &mut RwLockWriteGuard<'_, Vec<u8>>,
-    ) -> Result<(), ExtendibleHashTableError> {
-        let insertion_key_hash = hash_string(key.to_string());
+        directory_page: &mut WritePageGuard<'_>,
+        dictionary: Option<&ExtendibleHTableKeyDictionaryPage>,
+    ) -> Result<Insertion<'_, K, V>, ExtendibleHashTableError> {
+        let insertion_key_hash = self.hash_key(&key);
         let bucket_index = directory.hash_to_bucket_index(insertion_key_hash);
+        let is_new_bucket = directory.get_bucket_page_id(bucket_index).is_none();
         let (mut bucket, mut bucket_page) = match directory.get_bucket_page_id(bucket_index) {
             Some(bucket_page_id) => {
                 let bucket_page = self
                     .buffer_pool_manager
                     .fetch_page_write(*bucket_page_id)
-                    .unwrap();
+                    .ok_or(ExtendibleHashTableError::NoBucketForPageId)?;
 
                 (
-                    ExtendibleHTableBucketPage::from_bytes(&bucket_page),
+                    ExtendibleHTableBucketPage::from_bytes_with(&bucket_page, dictionary)?,
                     bucket_page,
                 )
             }
             None => {
-                let (page_id, new_page) = self.buffer_pool_manager.new_page().unwrap();
+                let (page_id, new_page) = self
+                    .buffer_pool_manager
+                    .new_page()
+                    .ok_or(ExtendibleHashTableError::PageNotAvailable)?;
                 let bucket_page_id = page_id;
                 directory.set_bucket_page_id(bucket_index, bucket_page_id);
 
@@ -151,294 +844,2225 @@ where
             }
         };
 
-        if !bucket.is_full() {
-            bucket.insert(key, value);
+        // existing key is updated in place, even if bucket is full
+        let key = self.stored_key(&bucket, key);
+        let exists = bucket.get(key.clone()).is_some();
+        let (key, value) = if !bucket.is_full() || exists {
+            bucket.insert(key.clone(), value);
+
+            let bucket_data = bucket.to_bytes_with(dictionary);
+            if bucket_data.len() <= PAGE_SIZE {
+                bucket_page.overwrite(&bucket_data);
+                directory_page.overwrite(&directory.to_bytes());
+                if is_new_bucket {
+                    self.log_structure_change(
+                        StructureChange::NewBucket,
+                        &[directory_page, &bucket_page],
+                    )?;
+                }
 
-            *bucket_page = bucket.to_bytes();
-            **directory_page = directory.to_bytes();
+                return Ok(Insertion::Inserted);
+            }
+            if exists || bucket.get_size() == 1 {
+                // page of the new bucket isn't referenced by directory on disk, it is freed
+                // instead of leaking as formatted but unused page
+                if is_new_bucket {
+                    let page_id = bucket_page.page_id();
+                    drop(bucket_page);
+                    let _ = self.buffer_pool_manager.delete_page(page_id);
+                }
+                return Err(ExtendibleHashTableError::PageOverflow);
+            }
 
-            Ok(())
+            // entries are larger than max size of the bucket assumes, so it is split
+            // before it is full
+            let value = bucket.delete(key.clone()).unwrap();
+            bucket.set_max_size(bucket.get_size());
+            (key, value)
         } else {
-            let local_depth = directory.get_local_depth(bucket_index).unwrap();
-            let global_depth = directory.get_global_depth();
-            let should_double_size = local_depth == global_depth;
-
-            let new_bucket = ExtendibleHTableBucketPage::<K, V>::new(self.bucket_max_size);
-            let (page_id, mut new_page) = self.buffer_pool_manager.new_page().unwrap();
-            *new_page = new_bucket.to_bytes();
-            let new_page_id = page_id;
-            drop(new_page);
-
-            let bucket_next_local_depth = directory.get_local_depth(bucket_index).unwrap() + 1;
-            let local_depth_mask = (1 << bucket_next_local_depth) - 1;
-            let aligned_bucket_index = bucket_index & local_depth_mask;
-
-            if should_double_size {
-                directory.increment_local_depth(bucket_index);
-                directory.increment_global_depth()?;
-                let split_image_index = directory.get_split_image_index(bucket_index);
+            (key, value)
+        };
+
+        let local_depth = directory.get_local_depth(bucket_index).unwrap();
+        let global_depth = directory.get_global_depth();
+        let should_double_size = local_depth == global_depth;
+
+        if should_double_size && directory.is_full() {
+            return Err(ExtendibleHashTableError::DirectoryMaxSizeReached);
+        }
+        if should_double_size {
+            return Ok(Insertion::NeedsGrowth(key, value, bucket_page));
+        }
+
+        let max_size = self.adaptive_max_size(bucket.entry_sizes());
+        bucket.set_max_size(max_size);
+        let mut new_bucket = ExtendibleHTableBucketPage::<K, V>::new(max_size);
+        let (new_page_id, mut new_page) = self
+            .buffer_pool_manager
+            .new_page()
+            .ok_or(ExtendibleHashTableError::PageNotAvailable)?;
+
+        let bucket_next_local_depth = local_depth + 1;
+        let local_depth_mask = (1 << bucket_next_local_depth) - 1;
+        let aligned_bucket_index = bucket_index & local_depth_mask;
+
+        for index in 0..directory.get_size() {
+            let other_bucket_index = index & local_depth_mask;
+            if aligned_bucket_index == other_bucket_index {
+                directory.increment_local_depth(index);
+
+                let split_image_index = directory.get_split_image_index(index);
+                directory.increment_local_depth(split_image_index);
                 directory.set_bucket_page_id(split_image_index, new_page_id);
+            }
+        }
+
+        // entries which now hash to split image move to the new bucket
+        for (key, value) in bucket.get_entries() {
+            let index = directory.hash_to_bucket_index(self.hash_key(&key));
+            if directory.get_bucket_page_id(index) == Some(&new_page_id) {
+                new_bucket.insert(key, value);
             } else {
-                for index in 0..directory.get_size() {
-                    let other_bucket_index = index & local_depth_mask;
-                    if aligned_bucket_index == other_bucket_index {
-                        directory.increment_local_depth(index);
-
-                        let split_image_index = directory.get_split_image_index(index);
-                        directory.increment_local_depth(split_image_index);
-                        directory.set_bucket_page_id(split_image_index, new_page_id);
-                    }
-                }
+                bucket.insert(key, value);
             }
+        }
 
-            // drain all entries from current bucket
-            let mut all_entries = bucket.get_entries();
+        // split is written as a whole before the key, which may need another split
+        directory_page.overwrite(&directory.to_bytes());
+        bucket_page.overwrite(&bucket.to_bytes_with(dictionary));
+        new_page.overwrite(&new_bucket.to_bytes_with(dictionary));
+        self.log_structure_change(
+            StructureChange::BucketSplit,
+            &[directory_page, &bucket_page, &new_page],
+        )?;
+        drop(bucket_page);
+        drop(new_page);
 
-            // write data to pages
-            **directory_page = directory.to_bytes();
-            *bucket_page = bucket.to_bytes();
-            drop(bucket_page);
+        self.insert_internal(key, value, directory, directory_page, dictionary)
+    }
 
-            all_entries.push((key, value));
-            for entry in all_entries {
-                let key = entry.0;
-                let value = entry.1;
-                self.insert_internal(key, value, directory, directory_page)?
-            }
-
-            Ok(())
-        }
-    }
-
-    // TODO: remove empty directories
-    //pub fn remove(&self, key: K) -> Result<(), ExtendibleHashTableError> {
-    //    let insertion_key_hash = hash_string(key.to_string());
-    //    let mut buffer_pool_manager = self.buffer_pool_manager.lock().unwrap();
-    //
-    //    // header
-    //    let header_page = buffer_pool_manager
-    //        .fetch_page_read(self.header_page_id)
-    //        .map(|p| Arc::clone(&p))
-    //        .unwrap();
-    //    let header = ExtendibleHTableHeaderPage::from(&header_page);
-    //
-    //    // directory
-    //    let directory_index = header.hash_to_directory_index(insertion_key_hash);
-    //    let directory_page_id = *header
-    //        .get_directory_page_id(directory_index)
-    //        .ok_or(ExtendibleHashTableError::NoDirectoryForPageId)?;
-    //    let directory_page = buffer_pool_manager
-    //        .fetch_page_write(directory_page_id)
-    //        .unwrap();
-    //    let mut directory = ExtendibleHTableDirectoryPage::from(&directory_page);
-    //
-    //    //bucket
-    //    let bucket_index = directory.hash_to_bucket_index(insertion_key_hash);
-    //    let bucket_page_id = *directory
-    //        .get_bucket_page_id(bucket_index)
-    //        .ok_or(ExtendibleHashTableError::NoBucketForPageId)?;
-    //    let mut buffer_pool_manager = self.buffer_pool_manager.lock().unwrap();
-    //    let bucket_page = buffer_pool_manager
-    //        .fetch_page_write(bucket_page_id)
-    //        .unwrap();
-    //    let mut bucket = ExtendibleHTableBucketPage::<K, V>::from(&bucket_page);
-    //    let value = bucket.delete(key);
-    //    drop(buffer_pool_manager);
-    //
-    //    if value.is_some() && bucket.is_empty() {
-    //        let local_depth_mask = (1 << directory.get_local_depth(bucket_index).unwrap()) - 1;
-    //        let aligned_bucket_index = bucket_index & local_depth_mask;
-    //
-    //        for index in 0..directory.get_size() {
-    //            let other_bucket_index = index & local_depth_mask;
-    //            if aligned_bucket_index == other_bucket_index {
-    //                let bucket_current_local_depth = directory.get_local_depth(index).unwrap();
-    //                let split_image_index = directory.get_split_image_index(index);
-    //                let split_image_bucket_local_depth =
-    //                    directory.get_local_depth(split_image_index).unwrap();
-    //
-    //                if bucket_current_local_depth != split_image_bucket_local_depth {
-    //                    continue;
-    //                }
-    //
-    //                let split_image_page_id =
-    //                    directory.get_bucket_page_id(split_image_index).unwrap();
-    //                directory.set_bucket_page_id(index, *split_image_page_id);
-    //
-    //                directory.decrement_local_depth(index);
-    //                directory.decrement_local_depth(split_image_index);
-    //            }
-    //        }
-    //
-    //        let mut should_shrink = true;
-    //        let global_depth = directory.get_global_depth();
-    //        for bucket_index in 0..directory.get_size() {
-    //            let local_depth = directory.get_local_depth(bucket_index).unwrap();
-    //
-    //            if local_depth == global_depth {
-    //                should_shrink = false;
-    //            }
-    //        }
-    //
-    //        if should_shrink {
-    //            directory.decrement_global_depth();
-    //        }
-    //    }
-    //
-    //    directory_page.set_data(directory.to_bytes());
-    //    bucket_page.set_data(bucket.to_bytes());
-    //
-    //    Ok(())
-    //}
-
-    pub fn get(&self, key: K) -> Option<V> {
-        let hash = hash_string(key.to_string());
+    /// Remove key from hash table and return its value,
+    /// bucket left empty is merged with its split image
+    pub fn remove(&self, key: K) -> Result<Option<V>, ExtendibleHashTableError> {
+        self.modify(key, |current| {
+            let modification = match current {
+                Some(_) => Modification::Remove,
+                None => Modification::Keep,
+            };
 
-        let header_page = self
+            (modification, current)
+        })
+    }
+
+    fn remove_internal(
+        &self,
+        key: K,
+        directory: &mut ExtendibleHTableDirectoryPage,
+        mut directory_page: WritePageGuard<'_>,
+        dictionary: Option<&ExtendibleHTableKeyDictionaryPage>,
+    ) -> Result<Option<V>, ExtendibleHashTableError> {
+        let hash = self.hash_key(&key);
+        let bucket_index = directory.hash_to_bucket_index(hash);
+        let Some(bucket_page_id) = directory.get_bucket_page_id(bucket_index).copied() else {
+            return Ok(None);
+        };
+        let mut bucket_page = self
             .buffer_pool_manager
-            .fetch_page_read(self.header_page_id)
-            .unwrap();
-        let header = ExtendibleHTableHeaderPage::from(&header_page);
-        drop(header_page);
+            .fetch_page_write(bucket_page_id)
+            .ok_or(ExtendibleHashTableError::NoBucketForPageId)?;
+        let mut bucket =
+            ExtendibleHTableBucketPage::<K, V>::from_bytes_with(&bucket_page, dictionary)?;
 
-        let directory_index = header.hash_to_directory_index(hash);
+        let key = self.stored_key(&bucket, key);
+        let value = bucket.delete(key);
+        if value.is_none() {
+            return Ok(None);
+        }
+        bucket_page.overwrite(&bucket.to_bytes_with(dictionary));
+        drop(bucket_page);
 
-        let directory_page_id = header.get_directory_page_id(directory_index).unwrap();
-        let directory_page = self
-            .buffer_pool_manager
-            .fetch_page_read(*directory_page_id)
-            .unwrap();
-        let directory = ExtendibleHTableDirectoryPage::from(&directory_page);
-        drop(directory_page);
+        if bucket.is_empty() && self.merge_bucket(directory, bucket_index) {
+            directory_page.overwrite(&directory.to_bytes());
+            drop(directory_page);
+            // directory doesn't point to bucket page anymore, readers which still reach it
+            // through their copy of directory fail validation. Page pinned by them is
+            // deleted once they release it.
+            let _ = self
+                .buffer_pool_manager
+                .delete_page_when_unpinned(bucket_page_id);
+        }
 
-        let bucket_index = directory.hash_to_bucket_index(hash);
+        Ok(value)
+    }
 
-        let bucket_page_id = directory.get_bucket_page_id(bucket_index).unwrap();
-        let bucket_page = self
-            .buffer_pool_manager
-            .fetch_page_read(*bucket_page_id)
-            .unwrap();
-        let bucket = ExtendibleHTableBucketPage::<K, V>::from(&bucket_page);
+    /// Replace value of the key with `new` only if current value equals `expected`,
+    /// `None` stands for absent key. On mismatch current value is returned as error.
+    /// Key's bucket stays write latched until compare and swap is done, so no other
+    /// writer of the key can interleave.
+    pub fn compare_and_swap(
+        &self,
+        key: K,
+        expected: Option<V>,
+        new: Option<V>,
+    ) -> Result<Result<(), Option<V>>, ExtendibleHashTableError>
+    where
+        V: PartialEq,
+    {
+        self.modify(key, |current| {
+            if current != expected {
+                return (Modification::Keep, Err(current));
+            }
 
-        bucket.get(key).copied()
+            (Modification::from(new), Ok(()))
+        })
     }
 
-    pub fn verify_integrity(&self) {
-        //let header_page = self.fetch_page(self.header_page_id).unwrap();
-        //let header_page = header_page.lock().unwrap();
-        //let header = ExtendibleHTableHeaderPage::from(&header_page);
-        //
-        //for index in 0..header.get_max_size() {
-        //    let directory_page_id = header.get_directory_page_id(index);
-        //
-        //    if let Some(directory_page_id) = directory_page_id {
-        //        let directory_page = self.fetch_page(*directory_page_id).unwrap();
-        //        let directory_page = directory_page.lock().unwrap();
-        //        let directory = ExtendibleHTableDirectoryPage::from(&directory_page);
-        //
-        //        directory.verify_integrity();
-        //    }
-        //}
+    /// Replace value of the key with result of `f`, `None` stands for absent key.
+    /// Like compare and swap, no other writer can interleave. Previous value is returned.
+    pub fn update<F>(&self, key: K, f: F) -> Result<Option<V>, ExtendibleHashTableError>
+    where
+        F: FnOnce(Option<&V>) -> Option<V>,
+    {
+        self.modify(key, |current| {
+            let new = f(current.as_ref());
+
+            (Modification::from(new), current)
+        })
+    }
+
+    // read-modify-write of a single key, `f` decides what to do with current value
+    fn modify<F, R>(&self, key: K, f: F) -> Result<R, ExtendibleHashTableError>
+    where
+        F: FnOnce(Option<V>) -> (Modification<V>, R),
+    {
+        self.counters.writes.increment();
+        self.measured("write", || {
+            let _write_gate = self.buffer_pool_manager.write_gate();
+            let hash = self.hash_key(&key);
+            let f = match self.modify_bucket(&key, hash, f)? {
+                Ok(result) => return Ok(result),
+                Err(f) => f,
+            };
+
+            self.modify_structure(key, hash, f)
+        })
+    }
+
+    // write which neither splits nor empties the bucket, directory latch is shared with
+    // writers of other buckets. `f` is handed back if the write may change structure.
+    fn modify_bucket<F, R>(
+        &self,
+        key: &K,
+        hash: u32,
+        f: F,
+    ) -> Result<Result<R, F>, ExtendibleHashTableError>
+    where
+        F: FnOnce(Option<V>) -> (Modification<V>, R),
+    {
+        let (header_version, header) = self.read_header()?;
+        let dictionary = self.key_dictionary(&header)?;
+        let dictionary = dictionary.as_deref();
+        let directory_index = header.hash_to_directory_index(hash);
+        let Some(directory_page_id) = header.get_directory_page_id(directory_index).copied() else {
+            return Ok(Err(f));
+        };
+        let directory_page = latency_breakdown::phase(Phase::DirectoryFetch, || {
+            self.buffer_pool_manager.fetch_page_read(directory_page_id)
+        })
+        .ok_or(ExtendibleHashTableError::NoDirectoryForPageId)?;
+        // directory replaced by its doubled copy before it was pinned may be deleted already
+        if !self.is_header_unchanged(header_version) {
+            return Ok(Err(f));
+        }
+        let directory = latency_breakdown::phase(Phase::Serialize, || {
+            ExtendibleHTableDirectoryPage::try_from(&directory_page)
+        })?;
+        let Some(bucket_page_id) = directory
+            .get_bucket_page_id(directory.hash_to_bucket_index(hash))
+            .copied()
+        else {
+            return Ok(Err(f));
+        };
+        let mut bucket_page = latency_breakdown::phase(Phase::BucketFetch, || {
+            self.buffer_pool_manager.fetch_page_write(bucket_page_id)
+        })
+        .ok_or(ExtendibleHashTableError::NoBucketForPageId)?;
+        // bucket split by directory growth is write latched until header points to the copy
+        if !self.is_header_unchanged(header_version) {
+            return Ok(Err(f));
+        }
+
+        let mut bucket = latency_breakdown::phase(Phase::Serialize, || {
+            ExtendibleHTableBucketPage::<K, V>::from_bytes_with(&bucket_page, dictionary)
+        })?;
+        let key = self.stored_key(&bucket, key.clone());
+        let current = bucket.get(key.clone()).cloned();
+        // new key goes through structure path unless there is room for entry twice as large
+        // as average one, value which is much larger still fails with page overflow
+        let changes_structure = match current {
+            Some(_) => bucket.get_size() == 1,
+            None => {
+                let bucket_size = bucket.to_bytes_with(dictionary).len();
+                let average_entry_size = bucket_size / bucket.get_size().max(1);
+
+                bucket.is_full() || bucket_size + 2 * average_entry_size > PAGE_SIZE
+            }
+        };
+        if changes_structure {
+            return Ok(Err(f));
+        }
+
+        let (modification, result) = f(current);
+        match modification {
+            Modification::Keep => return Ok(Ok(result)),
+            Modification::Set(value) => {
+                bucket.insert(key, value);
+            }
+            Modification::Remove => {
+                bucket.delete(key);
+            }
+        }
+        let bucket_data =
+            latency_breakdown::phase(Phase::Serialize, || bucket.to_bytes_with(dictionary));
+        if bucket_data.len() > PAGE_SIZE {
+            return Err(ExtendibleHashTableError::PageOverflow);
+        }
+        bucket_page.overwrite(&bucket_data);
+
+        Ok(Ok(result))
+    }
+
+    // write which may split or merge buckets or create directory, directory is write
+    // latched so writers of its buckets wait
+    fn modify_structure<F, R>(&self, key: K, hash: u32, f: F) -> Result<R, ExtendibleHashTableError>
+    where
+        F: FnOnce(Option<V>) -> (Modification<V>, R),
+    {
+        let _structure = self.structure.lock();
+        let _permit = self.buffer_pool_manager.admit_structure_change();
+        let (_, header) = self.read_header()?;
+        let dictionary = self.key_dictionary(&header)?;
+        let dictionary = dictionary.as_deref();
+        let directory_index = header.hash_to_directory_index(hash);
+
+        let Some(directory_page_id) = header.get_directory_page_id(directory_index).copied() else {
+            let (modification, result) = f(None);
+            if let Modification::Set(value) = modification {
+                let directory_page_id = self.create_directory(directory_index)?;
+                let directory_page = self
+                    .buffer_pool_manager
+                    .fetch_page_write(directory_page_id)
+                    .ok_or(ExtendibleHashTableError::NoDirectoryForPageId)?;
+                let directory = ExtendibleHTableDirectoryPage::try_from(&directory_page)?;
+                self.insert_growing(
+                    directory_index,
+                    directory,
+                    directory_page,
+                    key,
+                    value,
+                    dictionary,
+                )?;
+            }
+
+            return Ok(result);
+        };
+        let directory_page = self
+            .buffer_pool_manager
+            .fetch_page_write(directory_page_id)
+            .ok_or(ExtendibleHashTableError::NoDirectoryForPageId)?;
+        let mut directory = ExtendibleHTableDirectoryPage::try_from(&directory_page)?;
+        let current = match directory.get_bucket_page_id(directory.hash_to_bucket_index(hash)) {
+            Some(bucket_page_id) => {
+                let bucket_page = self
+                    .buffer_pool_manager
+                    .fetch_page_read(*bucket_page_id)
+                    .ok_or(ExtendibleHashTableError::NoBucketForPageId)?;
+                let bucket =
+                    ExtendibleHTableBucketPage::<K, V>::from_bytes_with(&bucket_page, dictionary)?;
+
+                bucket.get(self.stored_key(&bucket, key.clone())).cloned()
+            }
+            None => None,
+        };
+        let (modification, result) = f(current);
+
+        match modification {
+            Modification::Keep => {}
+            Modification::Set(value) => {
+                self.insert_growing(
+                    directory_index,
+                    directory,
+                    directory_page,
+                    key,
+                    value,
+                    dictionary,
+                )?;
+            }
+            Modification::Remove => {
+                self.remove_internal(key, &mut directory, directory_page, dictionary)?;
+            }
+        }
+
+        Ok(result)
+    }
+
+    // merge empty bucket into its split image, returns false if depths don't allow merge
+    fn merge_bucket(
+        &self,
+        directory: &mut ExtendibleHTableDirectoryPage,
+        bucket_index: usize,
+    ) -> bool {
+        let local_depth = directory.get_local_depth(bucket_index).unwrap();
+        if local_depth == 0 {
+            return false;
+        }
+
+        let split_image_index = directory.get_split_image_index(bucket_index);
+        if directory.get_local_depth(split_image_index) != Some(local_depth) {
+            return false;
+        }
+
+        let empty_page_id = *directory.get_bucket_page_id(bucket_index).unwrap();
+        let split_image_page_id = *directory.get_bucket_page_id(split_image_index).unwrap();
+        for index in 0..directory.get_size() {
+            let page_id = *directory.get_bucket_page_id(index).unwrap();
+            if page_id == empty_page_id || page_id == split_image_page_id {
+                directory.set_bucket_page_id(index, split_image_page_id);
+                directory.set_local_depth(index, local_depth - 1);
+            }
+        }
+
+        while directory.can_shrink() {
+            directory.decrement_global_depth();
+        }
+
+        true
+    }
+
+    /// Merge sibling buckets left sparse by removes, if their local depths allow it and
+    /// merged bucket fills at most `max_merged_fill` of capacity. Pages of merged buckets
+    /// are freed and directories shrink when they can. Returns number of merges.
+    pub fn compact_buckets(&self, max_merged_fill: f64) -> Result<usize, ExtendibleHashTableError> {
+        let _write_gate = self.buffer_pool_manager.write_gate();
+        let _structure = self.structure.lock();
+        let _permit = self.buffer_pool_manager.admit_structure_change();
+        let (_, header) = self.read_header()?;
+        let dictionary = self.key_dictionary(&header)?;
+        let dictionary = dictionary.as_deref();
+
+        let mut merges = 0;
+        for directory_index in 0..header.get_max_size() {
+            let Some(directory_page_id) = header.get_directory_page_id(directory_index).copied()
+            else {
+                continue;
+            };
+            let mut directory_page = self
+                .buffer_pool_manager
+                .fetch_page_write(directory_page_id)
+                .ok_or(ExtendibleHashTableError::NoDirectoryForPageId)?;
+            let mut directory = ExtendibleHTableDirectoryPage::try_from(&directory_page)?;
+
+            // merged bucket may merge again with its new split image
+            let mut bucket_index = 0;
+            while bucket_index < directory.get_size() {
+                if self.merge_sparse_bucket(
+                    &mut directory,
+                    &mut directory_page,
+                    bucket_index,
+                    dictionary,
+                    max_merged_fill,
+                )? {
+                    merges += 1;
+                } else {
+                    bucket_index += 1;
+                }
+            }
+        }
+
+        Ok(merges)
+    }
+
+    // move entries of bucket into its split image if merged bucket is sparse enough,
+    // returns false if it isn't or depths don't allow merge
+    fn merge_sparse_bucket(
+        &self,
+        directory: &mut ExtendibleHTableDirectoryPage,
+        directory_page: &mut WritePageGuard<'_>,
+        bucket_index: usize,
+        dictionary: Option<&ExtendibleHTableKeyDictionaryPage>,
+        max_merged_fill: f64,
+    ) -> Result<bool, ExtendibleHashTableError> {
+        let local_depth = directory.get_local_depth(bucket_index).unwrap();
+        let split_image_index = directory.get_split_image_index(bucket_index);
+        if local_depth == 0 || directory.get_local_depth(split_image_index) != Some(local_depth) {
+            return Ok(false);
+        }
+        let bucket_page_id = *directory.get_bucket_page_id(bucket_index).unwrap();
+        let split_image_page_id = *directory.get_bucket_page_id(split_image_index).unwrap();
+
+        let bucket_page = self
+            .buffer_pool_manager
+            .fetch_page_write(bucket_page_id)
+            .ok_or(ExtendibleHashTableError::NoBucketForPageId)?;
+        let mut split_image_page = self
+            .buffer_pool_manager
+            .fetch_page_write(split_image_page_id)
+            .ok_or(ExtendibleHashTableError::NoBucketForPageId)?;
+        let mut bucket =
+            ExtendibleHTableBucketPage::<K, V>::from_bytes_with(&bucket_page, dictionary)?;
+        let mut merged =
+            ExtendibleHTableBucketPage::<K, V>::from_bytes_with(&split_image_page, dictionary)?;
+        for (key, value) in bucket.get_entries() {
+            merged.insert(key, value);
+        }
+        let bytes = merged.to_bytes_with(dictionary);
+        if merged.get_size() as f64 > merged.get_max_size() as f64 * max_merged_fill
+            || bytes.len() as f64 > PAGE_SIZE as f64 * max_merged_fill
+        {
+            return Ok(false);
+        }
+
+        split_image_page.overwrite(&bytes);
+        self.merge_bucket(directory, bucket_index);
+        directory_page.overwrite(&directory.to_bytes());
+        self.log_structure_change(
+            StructureChange::BucketMerge,
+            &[directory_page, &split_image_page],
+        )?;
+        drop((bucket_page, split_image_page));
+        // readers which still reach bucket through their copy of directory fail validation
+        let _ = self
+            .buffer_pool_manager
+            .delete_page_when_unpinned(bucket_page_id);
+
+        Ok(true)
+    }
+
+    /// Hash keys with `key_hasher` from now on, like when hash function of table changed
+    /// since it was written. Entries are inserted into new directories and buckets, then
+    /// header is switched to them at once and old pages are freed, so crash meanwhile
+    /// leaves the table as it was. Buckets are written without key dictionary, see
+    /// `build_key_dictionary`. Returns number of entries moved.
+    pub fn rehash_to(&mut self, key_hasher: KeyHasher) -> Result<usize, ExtendibleHashTableError> {
+        let buffer_pool_manager = Arc::clone(&self.buffer_pool_manager);
+        let _write_gate = buffer_pool_manager.write_gate();
+        let header_page = buffer_pool_manager
+            .fetch_page_read(self.header_page_id)
+            .ok_or(ExtendibleHashTableError::PageNotAvailable)?;
+        // stored hasher isn't checked, entries are read without hashing their keys
+        let header = ExtendibleHTableHeaderPage::try_from(&header_page)?;
+        drop(header_page);
+        let (entries, old_page_ids) = self.read_all_entries(&header)?;
+
+        let rehashed = Self::create(
+            self.name.clone(),
+            Arc::clone(&buffer_pool_manager),
+            header.get_max_depth(),
+            header.get_directory_hash_bits(),
+            key_hasher,
+            self.directory_max_depth,
+            self.bucket_max_size,
+        )?
+        .with_key_normalization(self.key_normalization);
+        let moved = entries.len();
+        let rehashed_header = entries
+            .into_iter()
+            .try_for_each(|(key, value)| rehashed.insert(key, value))
+            .and_then(|_| rehashed.read_header());
+        let rehashed_header = match rehashed_header {
+            Ok((_, rehashed_header)) => rehashed_header,
+            Err(error) => {
+                // half built table is dropped, the old one stays in place
+                if let Ok((_, rehashed_header)) = rehashed.read_header() {
+                    if let Ok((_, page_ids)) = rehashed.read_all_entries(&rehashed_header) {
+                        for page_id in page_ids {
+                            let _ = buffer_pool_manager.delete_page_when_unpinned(page_id);
+                        }
+                    }
+                }
+                let _ = buffer_pool_manager.delete_page_when_unpinned(rehashed.header_page_id);
+
+                return Err(error);
+            }
+        };
+
+        let mut header_page = buffer_pool_manager
+            .fetch_page_write(self.header_page_id)
+            .ok_or(ExtendibleHashTableError::PageNotAvailable)?;
+        header_page.overwrite(&rehashed_header.to_bytes());
+        self.log_structure_change(StructureChange::Rehash, &[&header_page])?;
+        drop(header_page);
+        self.key_hasher = key_hasher;
+        *self.key_dictionary.lock() = None;
+
+        for page_id in old_page_ids.into_iter().chain([rehashed.header_page_id]) {
+            let _ = buffer_pool_manager.delete_page_when_unpinned(page_id);
+        }
+        if self.keep_resident {
+            for directory_index in 0..rehashed_header.get_max_size() {
+                if let Some(page_id) = rehashed_header.get_directory_page_id(directory_index) {
+                    buffer_pool_manager.set_keep_resident(*page_id, true);
+                }
+            }
+        }
+
+        Ok(moved)
+    }
+
+    // entries of all buckets with pages of directories, buckets and key dictionary
+    #[allow(clippy::type_complexity)]
+    fn read_all_entries(
+        &self,
+        header: &ExtendibleHTableHeaderPage,
+    ) -> Result<(Vec<(K, V)>, Vec<PageId>), ExtendibleHashTableError> {
+        let dictionary = self.key_dictionary(header)?;
+        let mut entries = vec![];
+        let mut page_ids = header
+            .get_key_dictionary_page_id()
+            .into_iter()
+            .collect::<Vec<_>>();
+
+        for directory_index in 0..header.get_max_size() {
+            let Some(directory_page_id) = header.get_directory_page_id(directory_index).copied()
+            else {
+                continue;
+            };
+            let directory_page = self
+                .buffer_pool_manager
+                .fetch_page_read(directory_page_id)
+                .ok_or(ExtendibleHashTableError::NoDirectoryForPageId)?;
+            let directory = ExtendibleHTableDirectoryPage::try_from(&directory_page)?;
+            drop(directory_page);
+            page_ids.push(directory_page_id);
+
+            let bucket_page_ids = (0..directory.get_size())
+                .filter_map(|bucket_index| directory.get_bucket_page_id(bucket_index).copied())
+                .collect::<BTreeSet<PageId>>();
+            for bucket_page_id in bucket_page_ids {
+                let bucket_page = self
+                    .buffer_pool_manager
+                    .fetch_page_read_for(AccessType::Scan, bucket_page_id)
+                    .ok_or(ExtendibleHashTableError::NoBucketForPageId)?;
+                let mut bucket = ExtendibleHTableBucketPage::<K, V>::from_bytes_with(
+                    &bucket_page,
+                    dictionary.as_deref(),
+                )?;
+                entries.extend(bucket.get_entries());
+                page_ids.push(bucket_page_id);
+            }
+        }
+
+        Ok((entries, page_ids))
+    }
+
+    // decoded directory of latched page, taken from directory cache while page keeps version
+    fn decode_directory(
+        &self,
+        directory_page: &ReadPageGuard<'_>,
+    ) -> Result<Arc<ExtendibleHTableDirectoryPage>, ExtendibleHashTableError> {
+        let Some(cache) = &self.directory_cache else {
+            return Ok(Arc::new(ExtendibleHTableDirectoryPage::try_from(
+                directory_page,
+            )?));
+        };
+        let (page_id, version) = (directory_page.page_id(), directory_page.version());
+        if let Some(directory) = cache.get(page_id, version) {
+            self.counters.directory_cache_hits.increment();
+            return Ok(directory);
+        }
+        let directory = Arc::new(ExtendibleHTableDirectoryPage::try_from(directory_page)?);
+        cache.insert(page_id, version, Arc::clone(&directory));
+
+        Ok(directory)
+    }
+
+    // copy of header with version it was read at
+    fn read_header(&self) -> Result<(u64, ExtendibleHTableHeaderPage), ExtendibleHashTableError> {
+        let header_page = latency_breakdown::phase(Phase::HeaderFetch, || {
+            self.buffer_pool_manager
+                .fetch_page_read(self.header_page_id)
+        })
+        .ok_or(ExtendibleHashTableError::PageNotAvailable)?;
+        let header = latency_breakdown::phase(Phase::Serialize, || {
+            ExtendibleHTableHeaderPage::try_from(&header_page)
+        })?;
+        header.check_key_hasher(self.key_hasher)?;
+
+        Ok((header_page.version(), header))
+    }
+
+    // like `read_header`, header snapshot of the thread is taken if it is current
+    fn read_header_snapshot(
+        &self,
+    ) -> Result<(u64, Arc<ExtendibleHTableHeaderPage>), ExtendibleHashTableError> {
+        let Some(cache) = &self.snapshot_cache else {
+            let (version, header) = self.read_header()?;
+            return Ok((version, Arc::new(header)));
+        };
+        if let Some(snapshot) = cache.header(|version| self.is_header_unchanged(version)) {
+            self.counters.snapshot_cache_hits.increment();
+            return Ok(snapshot);
+        }
+
+        let (version, header) = self.read_header()?;
+        let header = Arc::new(header);
+        cache.set_header(version, Arc::clone(&header));
+
+        Ok((version, header))
+    }
+
+    fn is_header_unchanged(&self, version: u64) -> bool {
+        self.buffer_pool_manager
+            .is_page_unchanged(self.header_page_id, version)
+    }
+
+    /// Value of the key. Header, directory and bucket are latched one at a time and read
+    /// is retried if header or directory changed meanwhile, so readers don't hold latches
+    /// writers wait for. After a few retries directory stays latched until bucket is read.
+    pub fn get(&self, key: K) -> Result<Option<V>, ExtendibleHashTableError> {
+        self.counters.gets.increment();
+        self.measured("get", || self.get_internal(key))
+    }
+
+    /// Like `get`, latency breakdown of the lookup is returned with value whether table
+    /// opted in or not
+    pub fn get_with_latency(
+        &self,
+        key: K,
+    ) -> Result<(Option<V>, LatencyBreakdown), ExtendibleHashTableError> {
+        self.counters.gets.increment();
+        let (value, breakdown) = latency_breakdown::measure(|| self.get_internal(key));
+
+        Ok((value?, breakdown))
+    }
+
+    /// Call `f` with view of value of the key borrowed from bucket page, neither the value
+    /// nor the rest of bucket is decoded into owned data. Directory and bucket stay read
+    /// latched while `f` runs, so writers of the bucket wait for it.
+    pub fn get_with<F, R>(&self, key: K, f: F) -> Result<R, ExtendibleHashTableError>
+    where
+        V: ValueCodec,
+        F: FnOnce(Option<V::View<'_>>) -> R,
+    {
+        self.counters.gets.increment();
+        self.measured("get", || {
+            let hash = self.hash_key(&key);
+            let key_string = key.to_string();
+            let matches = |stored: &K| match self.key_normalization {
+                KeyNormalization::Exact => *stored == key,
+                normalization => normalization.equals(&stored.to_string(), &key_string),
+            };
+
+            loop {
+                let (header_version, header) = self.read_header()?;
+                let dictionary = self.key_dictionary(&header)?;
+                let directory_index = header.hash_to_directory_index(hash);
+                let Some(directory_page_id) =
+                    header.get_directory_page_id(directory_index).copied()
+                else {
+                    return Ok(f(None));
+                };
+                let directory_page = self
+                    .buffer_pool_manager
+                    .fetch_page_read(directory_page_id)
+                    .ok_or(ExtendibleHashTableError::NoDirectoryForPageId)?;
+                // directory replaced by its doubled copy before it was pinned may be deleted
+                // already
+                if !self.is_header_unchanged(header_version) {
+                    continue;
+                }
+                let directory = self.decode_directory(&directory_page)?;
+                let Some(bucket_page_id) = directory
+                    .get_bucket_page_id(directory.hash_to_bucket_index(hash))
+                    .copied()
+                else {
+                    return Ok(f(None));
+                };
+                let bucket_page = self.buffer_pool_manager.fetch_page_read(bucket_page_id);
+                // bucket replaced by directory growth before it was pinned may be deleted and
+                // its page reused already
+                if !self.is_header_unchanged(header_version) {
+                    continue;
+                }
+                let bucket_page = bucket_page.ok_or(ExtendibleHashTableError::NoBucketForPageId)?;
+                let value = find_value::<K, V>(&bucket_page, dictionary.as_deref(), matches)?;
+
+                return Ok(f(value));
+            }
+        })
+    }
+
+    fn get_internal(&self, key: K) -> Result<Option<V>, ExtendibleHashTableError> {
+        let hash = self.hash_key(&key);
+        let mut attempt = 0;
+
+        loop {
+            let optimistic = attempt < self.max_optimistic_reads;
+            if attempt > 0 {
+                self.counters.read_retries.increment();
+            }
+            attempt += 1;
+
+            let (header_version, header) = self.read_header_snapshot()?;
+            let dictionary = self.key_dictionary(&header)?;
+            let directory_index = header.hash_to_directory_index(hash);
+            let Some(directory_page_id) = header.get_directory_page_id(directory_index).copied()
+            else {
+                return Ok(None);
+            };
+            // snapshot is good for optimistic read only, pessimistic one latches directory
+            let snapshot = self
+                .snapshot_cache
+                .as_ref()
+                .filter(|_| optimistic)
+                .and_then(|cache| {
+                    cache.directory(directory_page_id, |version| {
+                        self.buffer_pool_manager
+                            .is_page_unchanged(directory_page_id, version)
+                    })
+                });
+            let (directory_version, directory, directory_page) = match snapshot {
+                Some((directory_version, directory)) => {
+                    self.counters.snapshot_cache_hits.increment();
+                    (directory_version, directory, None)
+                }
+                None => {
+                    let directory_page = latency_breakdown::phase(Phase::DirectoryFetch, || {
+                        self.buffer_pool_manager.fetch_page_read(directory_page_id)
+                    })
+                    .ok_or(ExtendibleHashTableError::NoDirectoryForPageId)?;
+                    // directory replaced by its doubled copy before it was pinned may be
+                    // deleted already
+                    if !self.is_header_unchanged(header_version) {
+                        continue;
+                    }
+                    let directory_version = directory_page.version();
+                    let directory = latency_breakdown::phase(Phase::Serialize, || {
+                        self.decode_directory(&directory_page)
+                    })?;
+                    if let Some(cache) = &self.snapshot_cache {
+                        cache.set_directory(
+                            directory_page_id,
+                            directory_version,
+                            Arc::clone(&directory),
+                        );
+                    }
+
+                    (
+                        directory_version,
+                        directory,
+                        (!optimistic).then_some(directory_page),
+                    )
+                }
+            };
+            let Some(bucket_page_id) = directory
+                .get_bucket_page_id(directory.hash_to_bucket_index(hash))
+                .copied()
+            else {
+                return Ok(None);
+            };
+
+            // bucket may be split, merged or deleted once directory is released, its data
+            // is trusted only if directory and header weren't changed since
+            let bucket_page = latency_breakdown::phase(Phase::BucketFetch, || {
+                self.buffer_pool_manager.fetch_page_read(bucket_page_id)
+            });
+            // directory growth doesn't wait for latched old directory, bucket it replaced
+            // before being pinned may be deleted and its page reused already
+            if !optimistic && !self.is_header_unchanged(header_version) {
+                continue;
+            }
+            let value = bucket_page
+                .ok_or(ExtendibleHashTableError::NoBucketForPageId)
+                .and_then(|bucket_page| {
+                    let bucket = latency_breakdown::phase(Phase::Serialize, || {
+                        ExtendibleHTableBucketPage::<K, V>::from_bytes_with(
+                            &bucket_page,
+                            dictionary.as_deref(),
+                        )
+                    })?;
+
+                    Ok(bucket.get(self.stored_key(&bucket, key.clone())).cloned())
+                });
+            drop(directory_page);
+            if optimistic
+                && !(self
+                    .buffer_pool_manager
+                    .is_page_unchanged(directory_page_id, directory_version)
+                    && self.is_header_unchanged(header_version))
+            {
+                continue;
+            }
+
+            return value;
+        }
+    }
+
+    /// Values of the keys in their order. Keys are partitioned by the bucket they map to
+    /// and partitions are looked up concurrently on thread pool, every directory and bucket
+    /// of a partition is latched and decoded once for all of its keys.
+    pub fn get_parallel(
+        self: &Arc<Self>,
+        keys: Vec<K>,
+        thread_pool: &ThreadPool,
+    ) -> Result<Vec<Option<V>>, ExtendibleHashTableError>
+    where
+        K: Send + Sync + 'static,
+        V: Send + Sync + 'static,
+    {
+        let keys_count = keys.len();
+        self.counters.gets.add(keys_count as u64);
+        let partitions = self.partition_by_bucket(keys)?;
+        let partitions_count = partitions.len();
+
+        let (sender, receiver) = mpsc::channel();
+        for keys in partitions {
+            let hash_table = Arc::clone(self);
+            let sender = sender.clone();
+            thread_pool.spawn(move || {
+                let values = hash_table.get_partition(&keys);
+                let indexes = keys
+                    .into_iter()
+                    .map(|(index, _, _)| index)
+                    .collect::<Vec<_>>();
+                let _ = sender.send((indexes, values));
+            });
+        }
+        drop(sender);
+
+        let mut values = vec![None; keys_count];
+        for _ in 0..partitions_count {
+            // job panicked or thread pool is shut down
+            let (indexes, partition_values) = receiver
+                .recv()
+                .map_err(|_| ExtendibleHashTableError::Unknown)?;
+            for (index, value) in indexes.into_iter().zip(partition_values?) {
+                values[index] = value;
+            }
+        }
+
+        Ok(values)
+    }
+
+    // keys with their index and hash grouped by bucket they map to now. Buckets may split
+    // or merge before partitions are looked up, so it only decides which keys are read
+    // together.
+    #[allow(clippy::type_complexity)]
+    fn partition_by_bucket(
+        &self,
+        keys: Vec<K>,
+    ) -> Result<Vec<Vec<(usize, K, u32)>>, ExtendibleHashTableError> {
+        let (_, header) = self.read_header()?;
+        let mut directories = HashMap::new();
+        let mut partitions = BTreeMap::<Option<PageId>, Vec<_>>::new();
+        for (index, key) in keys.into_iter().enumerate() {
+            let hash = self.hash_key(&key);
+            let directory_index = header.hash_to_directory_index(hash);
+            let bucket_page_id = match header.get_directory_page_id(directory_index).copied() {
+                Some(directory_page_id) => {
+                    let directory = match directories.entry(directory_page_id) {
+                        Entry::Occupied(entry) => entry.into_mut(),
+                        Entry::Vacant(entry) => {
+                            let directory_page = self
+                                .buffer_pool_manager
+                                .fetch_page_read(directory_page_id)
+                                .ok_or(ExtendibleHashTableError::NoDirectoryForPageId)?;
+                            entry.insert(self.decode_directory(&directory_page)?)
+                        }
+                    };
+                    directory
+                        .get_bucket_page_id(directory.hash_to_bucket_index(hash))
+                        .copied()
+                }
+                None => None,
+            };
+            partitions
+                .entry(bucket_page_id)
+                .or_default()
+                .push((index, key, hash));
+        }
+
+        Ok(partitions.into_values().collect())
+    }
+
+    // values of keys given with their hashes, directories stay latched while their buckets
+    // are read, so keys are looked up in buckets they map to at the time
+    fn get_partition(
+        &self,
+        keys: &[(usize, K, u32)],
+    ) -> Result<Vec<Option<V>>, ExtendibleHashTableError> {
+        'retry: loop {
+            let (header_version, header) = self.read_header()?;
+            let dictionary = self.key_dictionary(&header)?;
+            let mut by_directory = BTreeMap::<PageId, Vec<usize>>::new();
+            for (position, (_, _, hash)) in keys.iter().enumerate() {
+                let directory_index = header.hash_to_directory_index(*hash);
+                if let Some(directory_page_id) = header.get_directory_page_id(directory_index) {
+                    by_directory
+                        .entry(*directory_page_id)
+                        .or_default()
+                        .push(position);
+                }
+            }
+
+            let mut values = vec![None; keys.len()];
+            for (directory_page_id, positions) in by_directory {
+                let directory_page = self
+                    .buffer_pool_manager
+                    .fetch_page_read(directory_page_id)
+                    .ok_or(ExtendibleHashTableError::NoDirectoryForPageId)?;
+                // directory replaced by its doubled copy before it was pinned may be deleted
+                // already
+                if !self.is_header_unchanged(header_version) {
+                    continue 'retry;
+                }
+                let directory = self.decode_directory(&directory_page)?;
+                let mut by_bucket = BTreeMap::<PageId, Vec<usize>>::new();
+                for position in positions {
+                    let bucket_index = directory.hash_to_bucket_index(keys[position].2);
+                    if let Some(bucket_page_id) = directory.get_bucket_page_id(bucket_index) {
+                        by_bucket.entry(*bucket_page_id).or_default().push(position);
+                    }
+                }
+
+                for (bucket_page_id, positions) in by_bucket {
+                    let bucket_page = self.buffer_pool_manager.fetch_page_read(bucket_page_id);
+                    // bucket replaced by directory growth before it was pinned may be
+                    // deleted and its page reused already
+                    if !self.is_header_unchanged(header_version) {
+                        continue 'retry;
+                    }
+                    let bucket_page =
+                        bucket_page.ok_or(ExtendibleHashTableError::NoBucketForPageId)?;
+                    let bucket = ExtendibleHTableBucketPage::<K, V>::from_bytes_with(
+                        &bucket_page,
+                        dictionary.as_deref(),
+                    )?;
+                    for position in positions {
+                        let key = self.stored_key(&bucket, keys[position].1.clone());
+                        values[position] = bucket.get(key).cloned();
+                    }
+                }
+            }
+
+            return Ok(values);
+        }
+    }
+
+    /// All entries of the table. Buckets are read one by one, so entries written concurrently
+    /// with scan may be missed, each bucket is seen consistent though.
+    pub fn scan(&self) -> Result<Vec<(K, V)>, ExtendibleHashTableError> {
+        self.scan_cancellable(&CancellationToken::new())
+    }
+
+    /// Like `scan`, token is checked before every bucket and cancelled scan fails
+    /// with `Cancelled` error
+    pub fn scan_cancellable(
+        &self,
+        token: &CancellationToken,
+    ) -> Result<Vec<(K, V)>, ExtendibleHashTableError> {
+        let (_, header) = self.read_header()?;
+        let mut entries = vec![];
+
+        'directory: for directory_index in 0..header.get_max_size() {
+            let scanned = entries.len();
+            // buckets of directory replaced by its doubled copy while they're read are read
+            // again from the copy
+            'restart: loop {
+                entries.truncate(scanned);
+                let Some((header_version, dictionary, directory_page)) =
+                    self.pin_directory(directory_index)?
+                else {
+                    continue 'directory;
+                };
+                let directory = ExtendibleHTableDirectoryPage::try_from(&directory_page)?;
+
+                // several directory slots point to the same bucket when its local depth is
+                // lower, buckets are read in page order
+                let bucket_page_ids = (0..directory.get_size())
+                    .filter_map(|bucket_index| directory.get_bucket_page_id(bucket_index).copied())
+                    .collect::<BTreeSet<PageId>>()
+                    .into_iter()
+                    .collect::<Vec<PageId>>();
+                for page_id in bucket_page_ids.iter().take(SCAN_READAHEAD) {
+                    self.buffer_pool_manager.prefetch_page(*page_id);
+                }
+                for (index, &bucket_page_id) in bucket_page_ids.iter().enumerate() {
+                    if token.is_cancelled() {
+                        return Err(ExtendibleHashTableError::Cancelled);
+                    }
+                    // next buckets are read from disk while this one is deserialized
+                    if let Some(page_id) = bucket_page_ids.get(index + SCAN_READAHEAD) {
+                        self.buffer_pool_manager.prefetch_page(*page_id);
+                    }
+                    // buckets are read once, they go through scan ring instead of evicting
+                    // pages of lookups
+                    let bucket_page = self
+                        .buffer_pool_manager
+                        .fetch_page_read_for(AccessType::Scan, bucket_page_id);
+                    // replaced bucket may be deleted and its page reused already
+                    if !self.is_header_unchanged(header_version) {
+                        continue 'restart;
+                    }
+                    let bucket_page =
+                        bucket_page.ok_or(ExtendibleHashTableError::NoBucketForPageId)?;
+                    let mut bucket = ExtendibleHTableBucketPage::<K, V>::from_bytes_with(
+                        &bucket_page,
+                        dictionary.as_deref(),
+                    )?;
+
+                    entries.extend(bucket.get_entries());
+                }
+
+                break;
+            }
+        }
+
+        Ok(entries)
+    }
+
+    // current directory with header version and dictionary of its buckets, header isn't
+    // latched meanwhile
+    #[allow(clippy::type_complexity)]
+    fn pin_directory(
+        &self,
+        directory_index: usize,
+    ) -> Result<
+        Option<(
+            u64,
+            Option<Arc<ExtendibleHTableKeyDictionaryPage>>,
+            ReadPageGuard<'_>,
+        )>,
+        ExtendibleHashTableError,
+    > {
+        loop {
+            let (header_version, header) = self.read_header()?;
+            let Some(directory_page_id) = header.get_directory_page_id(directory_index).copied()
+            else {
+                return Ok(None);
+            };
+            let directory_page = self
+                .buffer_pool_manager
+                .fetch_page_read(directory_page_id)
+                .ok_or(ExtendibleHashTableError::NoDirectoryForPageId)?;
+            if self.is_header_unchanged(header_version) {
+                return Ok(Some((
+                    header_version,
+                    self.key_dictionary(&header)?,
+                    directory_page,
+                )));
+            }
+        }
+    }
+
+    // dictionary which bucket keys are compressed with, it is cached until header points
+    // to another one
+    fn key_dictionary(
+        &self,
+        header: &ExtendibleHTableHeaderPage,
+    ) -> Result<Option<Arc<ExtendibleHTableKeyDictionaryPage>>, ExtendibleHashTableError> {
+        let Some(page_id) = header.get_key_dictionary_page_id() else {
+            return Ok(None);
+        };
+
+        let mut cached = self.key_dictionary.lock();
+        if let Some((cached_page_id, dictionary)) = cached.as_ref() {
+            if *cached_page_id == page_id {
+                return Ok(Some(Arc::clone(dictionary)));
+            }
+        }
+        let page = self
+            .buffer_pool_manager
+            .fetch_page_read(page_id)
+            .ok_or(ExtendibleHashTableError::PageNotAvailable)?;
+        let dictionary = Arc::new(ExtendibleHTableKeyDictionaryPage::from_bytes(&page)?);
+        *cached = Some((page_id, Arc::clone(&dictionary)));
+
+        Ok(Some(dictionary))
+    }
+
+    /// Compress keys of all buckets with dictionary of their frequent prefixes, which helps
+    /// tables with long repetitive keys fit more entries into a bucket page. Dictionary is
+    /// trained on keys stored now and replaces the previous one, keys written later are
+    /// compressed with it too. Returns number of prefixes in dictionary.
+    ///
+    /// Writers and readers wait while header is write latched until rebuild is done.
+    pub fn build_key_dictionary(&self) -> Result<usize, ExtendibleHashTableError> {
+        let _write_gate = self.buffer_pool_manager.write_gate();
+        let _structure = self.structure.lock();
+        let mut header_page = self
+            .buffer_pool_manager
+            .fetch_page_write(self.header_page_id)
+            .ok_or(ExtendibleHashTableError::PageNotAvailable)?;
+        let mut header = ExtendibleHTableHeaderPage::try_from(&header_page)?;
+        let old_dictionary = self.key_dictionary(&header)?;
+        let old_page_id = header.get_key_dictionary_page_id();
+
+        // readers which passed header before it was latched still go through directories,
+        // each directory is latched while its buckets are read or rewritten
+        let mut buckets = vec![];
+        for directory_index in 0..header.get_max_size() {
+            let Some(directory_page_id) = header.get_directory_page_id(directory_index) else {
+                continue;
+            };
+            let directory_page = self
+                .buffer_pool_manager
+                .fetch_page_read(*directory_page_id)
+                .ok_or(ExtendibleHashTableError::NoDirectoryForPageId)?;
+            let directory = ExtendibleHTableDirectoryPage::try_from(&directory_page)?;
+
+            let bucket_page_ids = (0..directory.get_size())
+                .filter_map(|bucket_index| directory.get_bucket_page_id(bucket_index).copied())
+                .collect::<BTreeSet<PageId>>();
+            for bucket_page_id in bucket_page_ids {
+                let bucket_page = self
+                    .buffer_pool_manager
+                    .fetch_page_read(bucket_page_id)
+                    .ok_or(ExtendibleHashTableError::NoBucketForPageId)?;
+                buckets.push(ExtendibleHTableBucketPage::<K, V>::from_bytes_with(
+                    &bucket_page,
+                    old_dictionary.as_deref(),
+                )?);
+            }
+        }
+
+        let keys = buckets
+            .iter_mut()
+            .flat_map(|bucket| bucket.get_entries())
+            .map(|(key, _)| bincode::serialize(&key).unwrap())
+            .collect::<Vec<Vec<u8>>>();
+        let dictionary = ExtendibleHTableKeyDictionaryPage::train(keys.iter().map(Vec::as_slice));
+        // nothing is changed unless every bucket fits, concurrent removes only shrink them
+        for bucket in &buckets {
+            if bucket.to_bytes_with(Some(&dictionary)).len() > PAGE_SIZE {
+                return Err(ExtendibleHashTableError::PageOverflow);
+            }
+        }
+        drop(buckets);
+
+        let (dictionary_page_id, mut dictionary_page) = self
+            .buffer_pool_manager
+            .new_page()
+            .ok_or(ExtendibleHashTableError::PageNotAvailable)?;
+        dictionary_page.overwrite(&dictionary.to_bytes());
+        drop(dictionary_page);
+
+        for directory_index in 0..header.get_max_size() {
+            let Some(directory_page_id) = header.get_directory_page_id(directory_index) else {
+                continue;
+            };
+            let directory_page = self
+                .buffer_pool_manager
+                .fetch_page_write(*directory_page_id)
+                .ok_or(ExtendibleHashTableError::NoDirectoryForPageId)?;
+            let directory = ExtendibleHTableDirectoryPage::try_from(&directory_page)?;
+
+            let bucket_page_ids = (0..directory.get_size())
+                .filter_map(|bucket_index| directory.get_bucket_page_id(bucket_index).copied())
+                .collect::<BTreeSet<PageId>>();
+            for bucket_page_id in bucket_page_ids {
+                let mut bucket_page = self
+                    .buffer_pool_manager
+                    .fetch_page_write(bucket_page_id)
+                    .ok_or(ExtendibleHashTableError::NoBucketForPageId)?;
+                let bucket = ExtendibleHTableBucketPage::<K, V>::from_bytes_with(
+                    &bucket_page,
+                    old_dictionary.as_deref(),
+                )?;
+                bucket_page.overwrite(&bucket.to_bytes_with(Some(&dictionary)));
+            }
+        }
+
+        header.set_key_dictionary_page_id(Some(dictionary_page_id));
+        header_page.overwrite(&header.to_bytes());
+        let num_prefixes = dictionary.num_prefixes();
+        *self.key_dictionary.lock() = Some((dictionary_page_id, Arc::new(dictionary)));
+        drop(header_page);
+        if let Some(old_page_id) = old_page_id {
+            let _ = self
+                .buffer_pool_manager
+                .delete_page_when_unpinned(old_page_id);
+        }
+
+        Ok(num_prefixes)
+    }
+
+    /// Graphviz graph of header, directories and buckets with their depths and sizes,
+    /// render it with `dot -Tsvg`
+    pub fn to_dot(&self) -> Result<String, ExtendibleHashTableError> {
+        // header is held while directories are read, which only writers of structure wait for
+        let _structure = self.structure.lock();
+        let header_page = self
+            .buffer_pool_manager
+            .fetch_page_read(self.header_page_id)
+            .ok_or(ExtendibleHashTableError::PageNotAvailable)?;
+        let header = ExtendibleHTableHeaderPage::try_from(&header_page)?;
+        let mut dot = format!(
+            "digraph {:?} {{\n  node [shape=record];\n  page{} [label=\"header {}\"];\n",
+            self.name, self.header_page_id, self.header_page_id
+        );
+        let mut bucket_page_ids = BTreeSet::new();
+
+        for directory_index in 0..header.get_max_size() {
+            let Some(directory_page_id) = header.get_directory_page_id(directory_index) else {
+                continue;
+            };
+            let directory_page = self
+                .buffer_pool_manager
+                .fetch_page_read(*directory_page_id)
+                .ok_or(ExtendibleHashTableError::NoDirectoryForPageId)?;
+            let mut directory = ExtendibleHTableDirectoryPage::try_from(&directory_page)?;
+
+            let slots = (0..directory.get_size())
+                .map(|bucket_index| {
+                    let local_depth = directory.get_local_depth(bucket_index).unwrap_or_default();
+                    format!("<s{bucket_index}> {bucket_index}: ld={local_depth}")
+                })
+                .collect::<Vec<String>>();
+            dot.push_str(&format!(
+                "  page{} [label=\"{{directory {} gd={}|{}}}\"];\n  page{} -> page{} [label=\"{}\"];\n",
+                directory_page_id,
+                directory_page_id,
+                directory.get_global_depth(),
+                slots.join("|"),
+                self.header_page_id,
+                directory_page_id,
+                directory_index
+            ));
+
+            for bucket_index in 0..directory.get_size() {
+                let Some(bucket_page_id) = directory.get_bucket_page_id(bucket_index) else {
+                    continue;
+                };
+                dot.push_str(&format!(
+                    "  page{}:s{} -> page{};\n",
+                    directory_page_id, bucket_index, bucket_page_id
+                ));
+                bucket_page_ids.insert(*bucket_page_id);
+            }
+        }
+
+        for bucket_page_id in bucket_page_ids {
+            let bucket_page = self
+                .buffer_pool_manager
+                .fetch_page_read(bucket_page_id)
+                .ok_or(ExtendibleHashTableError::NoBucketForPageId)?;
+            let (max_size, size) = decode_occupancy(&bucket_page)?;
+            dot.push_str(&format!(
+                "  page{} [label=\"bucket {}|{}/{}\"];\n",
+                bucket_page_id, bucket_page_id, size, max_size
+            ));
+        }
+        dot.push_str("}\n");
+
+        Ok(dot)
+    }
+
+    pub fn verify_integrity(&self) {
+        let _structure = self.structure.lock();
+        let header_page = self
+            .buffer_pool_manager
+            .fetch_page_read(self.header_page_id)
+            .unwrap();
+        let header = ExtendibleHTableHeaderPage::try_from(&header_page).unwrap();
+
+        for index in 0..header.get_max_size() {
+            let directory_page_id = header.get_directory_page_id(index);
+
+            if let Some(directory_page_id) = directory_page_id {
+                let directory_page = self
+                    .buffer_pool_manager
+                    .fetch_page_read(*directory_page_id)
+                    .unwrap();
+                let directory = ExtendibleHTableDirectoryPage::try_from(&directory_page).unwrap();
+
+                directory.verify_integrity();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::atomic::{AtomicBool, Ordering},
+        thread::{self, JoinHandle},
+        time::Duration,
+    };
+
+    use proptest::prelude::*;
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::disk_manager::DiskManager;
+
+    fn create_hash_table(
+        dir: &TempDir,
+        pool_size: usize,
+        bucket_max_size: usize,
+    ) -> ExtendibleHashTable<String, u32> {
+        let disk_manager = DiskManager::open(dir.path().join("test.db")).unwrap();
+        let buffer_pool_manager = BufferPoolManager::new(disk_manager, pool_size, 4);
+
+        ExtendibleHashTable::<String, u32>::new(
+            "Test".into(),
+            Arc::new(buffer_pool_manager),
+            6,
+            bucket_max_size,
+        )
+    }
+
+    #[test]
+    fn test_hash_table() {
+        let entry_value = 277;
+        let dir = TempDir::new().unwrap();
+        let hash_table = create_hash_table(&dir, 12, 2);
+
+        let keys: Vec<String> = vec![
+            "asdasdsas".into(),
+            "b1211212c".into(),
+            "d1211212c".into(),
+            "s1211212c".into(),
+            "w1211212c".into(),
+            "jj1211212c".into(),
+            "jf1212c".into(),
+            "jfsds1212c".into(),
+            "gfghfg1212c".into(),
+            "gfghdfsdfsdf1212c".into(),
+            "gfisdisidighfg1212c".into(),
+            "sdfs921201".into(),
+        ];
+
+        for key in keys.clone() {
+            hash_table.insert(key, entry_value).unwrap();
+        }
+
+        hash_table.verify_integrity();
+
+        for key in keys.clone() {
+            let value = hash_table.get(key).unwrap();
+            assert_eq!(value.unwrap(), entry_value);
+        }
+
+        let value = hash_table.get("absent key".into()).unwrap();
+        assert_eq!(value, None);
+
+        for key in keys.clone() {
+            assert_eq!(hash_table.remove(key).unwrap(), Some(entry_value));
+        }
+
+        for key in keys.clone() {
+            let value = hash_table.get(key).unwrap();
+            assert_eq!(value, None);
+        }
+        hash_table.verify_integrity();
+    }
+
+    #[test]
+    fn test_hash_table_with_eviction() {
+        let dir = TempDir::new().unwrap();
+        let hash_table = create_hash_table(&dir, 6, 8);
+
+        for i in 0..100 {
+            hash_table.insert(format!("key{i}"), i).unwrap();
+        }
+        hash_table.verify_integrity();
+
+        for i in 0..100 {
+            assert_eq!(hash_table.get(format!("key{i}")).unwrap(), Some(i));
+        }
+
+        let mut values = hash_table
+            .scan()
+            .unwrap()
+            .into_iter()
+            .map(|(_, value)| value)
+            .collect::<Vec<u32>>();
+        values.sort();
+        assert_eq!(values, (0..100).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn test_latency_breakdown() {
+        let dir = TempDir::new().unwrap();
+        let hash_table = create_hash_table(&dir, 6, 8);
+        for i in 0..100 {
+            hash_table.insert(format!("key{i}"), i).unwrap();
+        }
+        hash_table.buffer_pool_manager.flush_all_pages().unwrap();
+        let header_page_id = hash_table.header_page_id();
+        drop(hash_table);
+
+        // nothing is cached by the new buffer pool, so every page is read from disk
+        let disk_manager = DiskManager::open(dir.path().join("test.db")).unwrap();
+        let hash_table = ExtendibleHashTable::<String, u32>::open(
+            "Test".into(),
+            Arc::new(BufferPoolManager::new(disk_manager, 6, 4)),
+            header_page_id,
+            6,
+            8,
+        )
+        .with_latency_breakdown();
+        let (value, breakdown) = hash_table.get_with_latency("key0".into()).unwrap();
+        assert_eq!(value, Some(0));
+        assert!(breakdown.disk_wait > Duration::ZERO);
+        assert!(
+            breakdown.header_fetch + breakdown.directory_fetch + breakdown.bucket_fetch
+                >= breakdown.disk_wait
+        );
+        assert!(breakdown.total >= breakdown.disk_wait + breakdown.hash + breakdown.serialize);
+    }
+
+    #[test]
+    fn test_high_bits_spread_keys_over_directories() {
+        let dir = TempDir::new().unwrap();
+        let disk_manager = DiskManager::open(dir.path().join("test.db")).unwrap();
+        let hash_table = ExtendibleHashTable::<String, u32>::new_with_header(
+            "Test".into(),
+            Arc::new(BufferPoolManager::new(disk_manager, 32, 4)),
+            2,
+            DirectoryHashBits::High,
+            4,
+            8,
+        );
+        for i in 0..200 {
+            hash_table.insert(format!("key{i}"), i).unwrap();
+        }
+        hash_table.verify_integrity();
+        for i in 0..200 {
+            assert_eq!(hash_table.get(format!("key{i}")).unwrap(), Some(i));
+        }
+
+        // with low bits shared by header and directories, keys of a directory would all
+        // have the same two low bits and use only a quarter of its bucket indexes
+        let (_, header) = hash_table.read_header().unwrap();
+        let directory_and_low_bits = (0..200)
+            .map(|i| {
+                let hash = hash_table.hash_key(&format!("key{i}"));
+                (header.hash_to_directory_index(hash), hash & 0b11)
+            })
+            .collect::<BTreeSet<_>>();
+        assert_eq!(directory_and_low_bits.len(), 16);
+    }
+
+    #[test]
+    fn test_get_with_borrowed_value() {
+        let dir = TempDir::new().unwrap();
+        let hash_table = create_hash_table(&dir, 16, 8);
+        for i in 0..50 {
+            hash_table.insert(format!("user:{i}"), i).unwrap();
+        }
+
+        let read = |key: &str| hash_table.get_with(key.into(), |value| value).unwrap();
+        assert_eq!(read("user:7"), Some(7));
+        assert_eq!(read("user:100"), None);
+
+        // bucket values go before compressed keys
+        assert!(hash_table.build_key_dictionary().unwrap() > 0);
+        assert_eq!(read("user:42"), Some(42));
+        assert_eq!(read("user:100"), None);
+
+        let dir = TempDir::new().unwrap();
+        let disk_manager = DiskManager::open(dir.path().join("test.db")).unwrap();
+        let hash_table = ExtendibleHashTable::<u32, String>::new(
+            "Test".into(),
+            Arc::new(BufferPoolManager::new(disk_manager, 16, 4)),
+            6,
+            8,
+        );
+        hash_table.insert(1, "value".into()).unwrap();
+        let value = hash_table
+            .get_with(1, |value: Option<&str>| value.map(str::to_uppercase))
+            .unwrap();
+        assert_eq!(value, Some("VALUE".to_string()));
+    }
+
+    #[test]
+    fn test_get_parallel() {
+        let dir = TempDir::new().unwrap();
+        let hash_table = Arc::new(create_hash_table(&dir, 12, 8));
+        for i in 0..100 {
+            hash_table.insert(format!("key{i}"), i).unwrap();
+        }
+        let thread_pool = ThreadPool::new(4);
+
+        let keys = (0..120)
+            .rev()
+            .map(|i| format!("key{i}"))
+            .collect::<Vec<_>>();
+        let values = hash_table.get_parallel(keys, &thread_pool).unwrap();
+        let expected = (0..120)
+            .rev()
+            .map(|i| (i < 100).then_some(i))
+            .collect::<Vec<_>>();
+        assert_eq!(values, expected);
+        assert!(hash_table
+            .get_parallel(vec![], &thread_pool)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_stats() {
+        let dir = TempDir::new().unwrap();
+        let hash_table = Arc::new(create_hash_table(&dir, 16, 8));
+        hash_table.insert("a".into(), 1).unwrap();
+        hash_table.insert("b".into(), 2).unwrap();
+        hash_table.remove("a".into()).unwrap();
+        hash_table.get("b".into()).unwrap();
+        hash_table
+            .get_parallel(vec!["a".into(), "b".into()], &ThreadPool::new(2))
+            .unwrap();
+
+        let stats = hash_table.stats();
+        assert_eq!((stats.gets, stats.writes), (3, 3));
+        assert_eq!((stats.read_retries, stats.coalesced_inserts), (0, 0));
+    }
+
+    #[test]
+    fn test_snapshot_cache() {
+        let dir = TempDir::new().unwrap();
+        let hash_table = create_hash_table(&dir, 16, 4).with_snapshot_cache();
+        hash_table.insert("a".into(), 1).unwrap();
+        hash_table.get("a".into()).unwrap();
+        hash_table.get("a".into()).unwrap();
+        // header and directory of the second lookup come from snapshots
+        assert_eq!(hash_table.stats().snapshot_cache_hits, 2);
+
+        // splits change directory, stale snapshot isn't used
+        for i in 0..20 {
+            hash_table.insert(i.to_string(), i).unwrap();
+            for j in 0..=i {
+                assert_eq!(hash_table.get(j.to_string()).unwrap(), Some(j));
+            }
+        }
+        assert_eq!(hash_table.get("a".into()).unwrap(), Some(1));
+
+        // snapshots are kept per thread
+        let hits = hash_table.stats().snapshot_cache_hits;
+        std::thread::scope(|scope| {
+            scope.spawn(|| hash_table.get("a".into()).unwrap());
+        });
+        assert_eq!(hash_table.stats().snapshot_cache_hits, hits);
+    }
+
+    #[test]
+    fn test_directory_cache() {
+        let dir = TempDir::new().unwrap();
+        let hash_table = create_hash_table(&dir, 16, 4).with_directory_cache(64 * 1024);
+        hash_table.insert("a".into(), 1).unwrap();
+        hash_table.get("a".into()).unwrap();
+        hash_table.get("a".into()).unwrap();
+        assert_eq!(hash_table.stats().directory_cache_hits, 1);
+
+        // splits write directory, directory decoded before isn't used
+        for i in 0..20 {
+            hash_table.insert(i.to_string(), i).unwrap();
+            for j in 0..=i {
+                assert_eq!(hash_table.get(j.to_string()).unwrap(), Some(j));
+            }
+        }
+        assert_eq!(hash_table.get("a".into()).unwrap(), Some(1));
+
+        // directories are shared by threads
+        let hits = hash_table.stats().directory_cache_hits;
+        std::thread::scope(|scope| {
+            scope.spawn(|| hash_table.get("a".into()).unwrap());
+        });
+        assert_eq!(hash_table.stats().directory_cache_hits, hits + 1);
+    }
+
+    #[test]
+    fn test_structure_changes_of_shared_pool_are_admitted_one_at_a_time() {
+        let dir = TempDir::new().unwrap();
+        let disk_manager = DiskManager::open(dir.path().join("test.db")).unwrap();
+        let buffer_pool_manager = Arc::new(BufferPoolManager::new(disk_manager, 16, 4));
+        buffer_pool_manager.set_structure_change_limit(Some(1));
+
+        thread::scope(|scope| {
+            for i in 0..4 {
+                let buffer_pool_manager = Arc::clone(&buffer_pool_manager);
+                scope.spawn(move || {
+                    let hash_table = ExtendibleHashTable::<String, u32>::new(
+                        format!("Test{i}"),
+                        buffer_pool_manager,
+                        6,
+                        4,
+                    );
+                    for j in 0..40 {
+                        hash_table.insert(j.to_string(), j).unwrap();
+                    }
+                    for j in 0..40 {
+                        assert_eq!(hash_table.get(j.to_string()).unwrap(), Some(j));
+                    }
+                });
+            }
+        });
+
+        assert!(buffer_pool_manager.stats().unwrap().structure_change_waits > 0);
+    }
+
+    #[test]
+    fn test_failed_rehash_frees_its_pages() {
+        let dir = TempDir::new().unwrap();
+        let mut hash_table = create_hash_table(&dir, 16, 4);
+        for i in 0..40 {
+            hash_table.insert(i.to_string(), i).unwrap();
+        }
+
+        // two frames are left, enough for new header and directory but not for bucket
+        let buffer_pool_manager = Arc::clone(&hash_table.buffer_pool_manager);
+        let pinned = (0..14)
+            .map(|_| buffer_pool_manager.new_page().unwrap())
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            hash_table.rehash_to(KeyHasher::Fnv1a { seed: 42 }),
+            Err(ExtendibleHashTableError::PageNotAvailable)
+        ));
+        for (page_id, page) in pinned {
+            drop(page);
+            buffer_pool_manager.delete_page(page_id).unwrap();
+        }
+
+        assert_eq!(hash_table.key_hasher(), KeyHasher::Std);
+        for i in 0..40 {
+            assert_eq!(hash_table.get(i.to_string()).unwrap(), Some(i));
+        }
+        let (_, header) = hash_table.read_header().unwrap();
+        let (_, page_ids) = hash_table.read_all_entries(&header).unwrap();
+        hash_table.buffer_pool_manager.flush_all_pages().unwrap();
+        let disk_manager = hash_table.buffer_pool_manager.disk_manager();
+        let unreferenced = (1..disk_manager.num_pages().unwrap())
+            .map(PageId::new)
+            .filter(|page_id| {
+                *page_id != hash_table.header_page_id()
+                    && !page_ids.contains(page_id)
+                    && !disk_manager.is_free_page(*page_id)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(unreferenced, vec![]);
+    }
+
+    #[test]
+    fn test_rehash_to() {
+        let dir = TempDir::new().unwrap();
+        let mut hash_table = create_hash_table(&dir, 16, 4);
+        for i in 0..40 {
+            hash_table.insert(i.to_string(), i).unwrap();
+        }
+
+        let key_hasher = KeyHasher::Fnv1a { seed: 42 };
+        assert_eq!(hash_table.rehash_to(key_hasher).unwrap(), 40);
+        assert_eq!(hash_table.key_hasher(), key_hasher);
+        hash_table.verify_integrity();
+        for i in 0..40 {
+            assert_eq!(hash_table.get(i.to_string()).unwrap(), Some(i));
+        }
+        hash_table.insert("new".into(), 40).unwrap();
+        assert_eq!(hash_table.scan().unwrap().len(), 41);
+
+        // reopened table hashes with stored hasher and refuses another one
+        let buffer_pool_manager = Arc::clone(&hash_table.buffer_pool_manager);
+        let header_page_id = hash_table.header_page_id();
+        let reopened = ExtendibleHashTable::<String, u32>::open(
+            "Test".into(),
+            Arc::clone(&buffer_pool_manager),
+            header_page_id,
+            6,
+            4,
+        );
+        assert_eq!(reopened.key_hasher(), key_hasher);
+        assert_eq!(reopened.get("7".into()).unwrap(), Some(7));
+        let mismatched = ExtendibleHashTable::<String, u32>::open(
+            "Test".into(),
+            buffer_pool_manager,
+            header_page_id,
+            6,
+            4,
+        )
+        .with_key_hasher(KeyHasher::Std);
+        assert!(matches!(
+            mismatched.get("7".into()),
+            Err(ExtendibleHashTableError::KeyHasherMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_compact_buckets() {
+        let dir = TempDir::new().unwrap();
+        let hash_table = create_hash_table(&dir, 16, 4);
+        for i in 0..40 {
+            hash_table.insert(i.to_string(), i).unwrap();
+        }
+        let buckets = |hash_table: &ExtendibleHashTable<String, u32>| {
+            hash_table
+                .to_dot()
+                .unwrap()
+                .lines()
+                .filter(|line| line.contains("[label=\"bucket"))
+                .count()
+        };
+        let buckets_before = buckets(&hash_table);
+        // removes rarely empty a bucket, so it stays sparse
+        for i in (0..40).filter(|i| i % 4 != 0) {
+            hash_table.remove(i.to_string()).unwrap();
+        }
+
+        assert!(hash_table.compact_buckets(0.5).unwrap() > 0);
+        assert!(buckets(&hash_table) < buckets_before);
+        hash_table.verify_integrity();
+        for i in 0..40 {
+            let expected = (i % 4 == 0).then_some(i);
+            assert_eq!(hash_table.get(i.to_string()).unwrap(), expected);
+        }
+        // merged buckets are full enough
+        assert_eq!(hash_table.compact_buckets(0.5).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_write_coalescing() {
+        let dir = TempDir::new().unwrap();
+        let hash_table = Arc::new(
+            create_hash_table(&dir, 16, 8).with_write_coalescing(Duration::from_millis(5)),
+        );
+
+        let handles = (0..4)
+            .map(|i| {
+                let hash_table = Arc::clone(&hash_table);
+                thread::spawn(move || {
+                    for j in 0..10 {
+                        hash_table.insert(format!("key{}", j % 5), i).unwrap();
+                        hash_table.insert(format!("key{i}-{j}"), j).unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        hash_table.verify_integrity();
+        for i in 0..4 {
+            for j in 0..10 {
+                assert_eq!(hash_table.get(format!("key{i}-{j}")).unwrap(), Some(j));
+            }
+        }
+        for j in 0..5 {
+            assert!(hash_table.get(format!("key{j}")).unwrap().is_some());
+        }
+    }
+
+    #[test]
+    fn test_hash_table_concurrency() {
+        let dir = TempDir::new().unwrap();
+        let hash_table = Arc::new(create_hash_table(&dir, 12, 2));
+
+        let mut handles: Vec<JoinHandle<()>> = vec![];
+        for i in 0..8 {
+            let handle = thread::spawn({
+                let hash_table = Arc::clone(&hash_table);
+                move || {
+                    let key = format!("key{i}");
+                    hash_table.insert(key.clone(), i).unwrap();
+                    assert_eq!(hash_table.get(key.clone()).unwrap(), Some(i));
+                    assert_eq!(hash_table.remove(key).unwrap(), Some(i));
+                }
+            });
+
+            handles.push(handle);
+        }
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        hash_table.verify_integrity();
+    }
+
+    #[test]
+    fn test_readers_during_splits() {
+        let dir = TempDir::new().unwrap();
+        let hash_table = Arc::new(create_hash_table(&dir, 64, 8));
+        for i in 0..20 {
+            hash_table.insert(format!("stable{i}"), i).unwrap();
+        }
+
+        let mut handles: Vec<JoinHandle<()>> = vec![];
+        for writer in 0..4 {
+            let hash_table = Arc::clone(&hash_table);
+            handles.push(thread::spawn(move || {
+                for i in 0..25 {
+                    let key = format!("key{writer}-{i}");
+                    hash_table.insert(key.clone(), i).unwrap();
+                    hash_table
+                        .update(key, |value| value.map(|v| v + 1))
+                        .unwrap();
+                }
+            }));
+        }
+        for _ in 0..2 {
+            let hash_table = Arc::clone(&hash_table);
+            handles.push(thread::spawn(move || {
+                for _ in 0..20 {
+                    for i in 0..20 {
+                        assert_eq!(hash_table.get(format!("stable{i}")).unwrap(), Some(i));
+                    }
+                }
+            }));
+        }
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        for writer in 0..4 {
+            for i in 0..25 {
+                assert_eq!(
+                    hash_table.get(format!("key{writer}-{i}")).unwrap(),
+                    Some(i + 1)
+                );
+            }
+        }
+        hash_table.verify_integrity();
+    }
+
+    #[test]
+    fn test_replaced_pages_are_freed_once_unpinned() {
+        let dir = TempDir::new().unwrap();
+        let hash_table = Arc::new(create_hash_table(&dir, 64, 8));
+        for i in 0..10 {
+            hash_table.insert(format!("stable{i}"), i).unwrap();
+        }
+
+        // one reader keeps directories pinned, so doublings replace pinned pages, the
+        // other one looks up keys meanwhile
+        let done = Arc::new(AtomicBool::new(false));
+        let pinning = {
+            let hash_table = Arc::clone(&hash_table);
+            let done = Arc::clone(&done);
+            thread::spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    let (_, header) = hash_table.read_header().unwrap();
+                    let directory_pages = (0..header.get_max_size())
+                        .filter_map(|index| header.get_directory_page_id(index).copied())
+                        .filter_map(|page_id| {
+                            hash_table.buffer_pool_manager.fetch_page_read(page_id)
+                        })
+                        .collect::<Vec<_>>();
+                    thread::sleep(Duration::from_millis(1));
+                    drop(directory_pages);
+                }
+            })
+        };
+        let looking_up = {
+            let hash_table = Arc::clone(&hash_table);
+            let done = Arc::clone(&done);
+            thread::spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    for i in 0..10 {
+                        assert_eq!(hash_table.get(format!("stable{i}")).unwrap(), Some(i));
+                    }
+                }
+            })
+        };
+        for i in 0..100 {
+            hash_table.insert(format!("key{i}"), i).unwrap();
+        }
+        done.store(true, Ordering::Relaxed);
+        pinning.join().unwrap();
+        looking_up.join().unwrap();
+
+        for i in 0..100 {
+            assert_eq!(hash_table.get(format!("key{i}")).unwrap(), Some(i));
+        }
+        let (_, header) = hash_table.read_header().unwrap();
+        let (_, page_ids) = hash_table.read_all_entries(&header).unwrap();
+        hash_table.buffer_pool_manager.flush_all_pages().unwrap();
+        let disk_manager = hash_table.buffer_pool_manager.disk_manager();
+        let unreferenced = (1..disk_manager.num_pages().unwrap())
+            .map(PageId::new)
+            .filter(|page_id| {
+                *page_id != hash_table.header_page_id()
+                    && !page_ids.contains(page_id)
+                    && !disk_manager.is_free_page(*page_id)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(unreferenced, vec![]);
+    }
+
+    #[test]
+    fn test_pessimistic_reads_while_directories_double() {
+        let dir = TempDir::new().unwrap();
+        let mut hash_table = create_hash_table(&dir, 64, 8);
+        hash_table.max_optimistic_reads = 0;
+        let hash_table = Arc::new(hash_table);
+        for i in 0..10 {
+            hash_table.insert(format!("stable{i}"), i).unwrap();
+        }
+
+        // replaced buckets are freed and their pages reused by next doublings while
+        // lookups go on
+        let done = Arc::new(AtomicBool::new(false));
+        let readers = (0..8)
+            .map(|_| {
+                let hash_table = Arc::clone(&hash_table);
+                let done = Arc::clone(&done);
+                thread::spawn(move || {
+                    while !done.load(Ordering::Relaxed) {
+                        for i in 0..10 {
+                            assert_eq!(hash_table.get(format!("stable{i}")).unwrap(), Some(i));
+                        }
+                        let entries = hash_table.scan().unwrap();
+                        for i in 0..10 {
+                            assert!(entries.contains(&(format!("stable{i}"), i)));
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for round in 0..5 {
+            for i in 0..200 {
+                hash_table.insert(format!("key{i}"), i).unwrap();
+            }
+            if round < 4 {
+                for i in 0..200 {
+                    hash_table.remove(format!("key{i}")).unwrap();
+                }
+            }
+        }
+        done.store(true, Ordering::Relaxed);
+        for reader in readers {
+            reader.join().unwrap();
+        }
+
+        for i in 0..200 {
+            assert_eq!(hash_table.get(format!("key{i}")).unwrap(), Some(i));
+        }
+    }
+
+    #[test]
+    fn test_large_values_split_buckets_early() {
+        let dir = TempDir::new().unwrap();
+        let disk_manager = DiskManager::open(dir.path().join("test.db")).unwrap();
+        let hash_table = ExtendibleHashTable::<String, String>::new(
+            "Test".into(),
+            Arc::new(BufferPoolManager::new(disk_manager, 64, 4)),
+            6,
+            64,
+        );
+
+        // only three values fit into a page, though bucket may take 64 entries
+        for i in 0..40 {
+            hash_table
+                .insert(format!("key{i}"), "x".repeat(1000))
+                .unwrap();
+        }
+        for i in 0..40 {
+            assert_eq!(
+                hash_table.get(format!("key{i}")).unwrap(),
+                Some("x".repeat(1000))
+            );
+        }
+        hash_table.verify_integrity();
+    }
+
+    #[test]
+    fn test_compare_and_swap() {
+        let dir = TempDir::new().unwrap();
+        let hash_table = Arc::new(create_hash_table(&dir, 12, 2));
+        let key = "counter".to_string();
+
+        assert_eq!(
+            hash_table
+                .compare_and_swap(key.clone(), Some(1), Some(2))
+                .unwrap(),
+            Err(None)
+        );
+        hash_table
+            .compare_and_swap(key.clone(), None, Some(0))
+            .unwrap()
+            .unwrap();
+
+        let mut handles: Vec<JoinHandle<()>> = vec![];
+        for _ in 0..8 {
+            let handle = thread::spawn({
+                let hash_table = Arc::clone(&hash_table);
+                let key = key.clone();
+                move || {
+                    for _ in 0..10 {
+                        let mut current = hash_table.get(key.clone()).unwrap();
+                        while let Err(actual) = hash_table
+                            .compare_and_swap(key.clone(), current, current.map(|c| c + 1))
+                            .unwrap()
+                        {
+                            current = actual;
+                        }
+                    }
+                }
+            });
+
+            handles.push(handle);
+        }
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(hash_table.get(key.clone()).unwrap(), Some(80));
+        hash_table
+            .compare_and_swap(key.clone(), Some(80), None)
+            .unwrap()
+            .unwrap();
+        assert_eq!(hash_table.get(key).unwrap(), None);
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let dir = TempDir::new().unwrap();
+        let hash_table = create_hash_table(&dir, 12, 2);
+        for i in 0..6 {
+            hash_table.insert(format!("key{i}"), i).unwrap();
+        }
+
+        let dot = hash_table.to_dot().unwrap();
+        assert!(dot.starts_with("digraph \"Test\" {"));
+        assert!(dot.contains("header"));
+        assert!(dot.contains("directory"));
+        assert!(dot.ends_with("}\n"));
+    }
+
+    #[test]
+    fn test_key_dictionary() {
+        let dir = TempDir::new().unwrap();
+        let hash_table = create_hash_table(&dir, 12, 8);
+        let key = |i| format!("tenant/42/session/key{i}");
+        for i in 0..40 {
+            hash_table.insert(key(i), i).unwrap();
+        }
+
+        assert!(hash_table.build_key_dictionary().unwrap() > 0);
+        for i in 40..60 {
+            hash_table.insert(key(i), i).unwrap();
+        }
+        for i in 0..10 {
+            assert_eq!(hash_table.remove(key(i)).unwrap(), Some(i));
+        }
+        // reopened table loads dictionary from header
+        let hash_table = ExtendibleHashTable::<String, u32>::open(
+            "Test".into(),
+            Arc::clone(&hash_table.buffer_pool_manager),
+            hash_table.header_page_id(),
+            6,
+            8,
+        );
+        for i in 10..60 {
+            assert_eq!(hash_table.get(key(i)).unwrap(), Some(i));
+        }
+        assert_eq!(hash_table.scan().unwrap().len(), 50);
+
+        // dictionary is trained again on what is stored now, the old one is freed once
+        // nobody pins it
+        let (_, header) = hash_table.read_header().unwrap();
+        let old_page_id = header.get_key_dictionary_page_id().unwrap();
+        let old_page = hash_table
+            .buffer_pool_manager
+            .fetch_page_read(old_page_id)
+            .unwrap();
+        assert!(hash_table.build_key_dictionary().unwrap() > 0);
+        assert_eq!(hash_table.get(key(59)).unwrap(), Some(59));
+        let disk_manager = hash_table.buffer_pool_manager.disk_manager();
+        assert!(!disk_manager.is_free_page(old_page_id));
+        drop(old_page);
+        assert!(disk_manager.is_free_page(old_page_id));
+
+        let mut bucket = ExtendibleHTableBucketPage::<String, u32>::new(8);
+        for i in 10..18 {
+            bucket.insert(key(i), i);
+        }
+        let dictionary = hash_table.key_dictionary.lock().clone().unwrap().1;
+        let compressed = bucket.to_bytes_with(Some(&dictionary));
+        assert!(compressed.len() < bucket.to_bytes().len() / 2);
+        assert_eq!(
+            ExtendibleHTableBucketPage::from_bytes_with(&compressed, Some(&dictionary)).unwrap(),
+            bucket
+        );
+    }
+
+    #[test]
+    fn test_corrupted_page_is_error() {
+        let dir = TempDir::new().unwrap();
+        let hash_table = create_hash_table(&dir, 12, 2);
+        hash_table.insert("key".into(), 1).unwrap();
+
+        let mut header_page = hash_table
+            .buffer_pool_manager
+            .fetch_page_write(hash_table.header_page_id())
+            .unwrap();
+        header_page.fill(0xff);
+        drop(header_page);
+
+        assert!(matches!(
+            hash_table.get("key".into()),
+            Err(ExtendibleHashTableError::CorruptedPage)
+        ));
+        assert!(matches!(
+            hash_table.insert("key".into(), 2),
+            Err(ExtendibleHashTableError::CorruptedPage)
+        ));
+    }
+
+    #[derive(Debug, Clone)]
+    enum Operation {
+        Insert(u32, u32),
+        Remove(u32),
+        Get(u32),
+    }
+
+    fn operation() -> impl Strategy<Value = Operation> {
+        // small key space, so keys are removed and buckets merge as often as they split
+        prop_oneof![
+            (0..40u32, any::<u32>()).prop_map(|(key, value)| Operation::Insert(key, value)),
+            (0..40u32).prop_map(Operation::Remove),
+            (0..40u32).prop_map(Operation::Get),
+        ]
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn test_hash_table_matches_model(
+            operations in prop::collection::vec(operation(), 1..200)
+        ) {
+            let dir = TempDir::new().unwrap();
+            let hash_table = create_hash_table(&dir, 8, 4);
+            let mut model: HashMap<String, u32> = HashMap::new();
+
+            for operation in operations {
+                match operation {
+                    Operation::Insert(key, value) => {
+                        let key = format!("key{key}");
+                        match hash_table.insert(key.clone(), value) {
+                            Ok(()) => {
+                                model.insert(key, value);
+                            }
+                            // too many keys share hash prefix, table stays as it was
+                            Err(ExtendibleHashTableError::DirectoryMaxSizeReached) => {}
+                            Err(error) => panic!("{error}"),
+                        }
+                    }
+                    Operation::Remove(key) => {
+                        let key = format!("key{key}");
+                        prop_assert_eq!(hash_table.remove(key.clone()).unwrap(), model.remove(&key));
+                    }
+                    Operation::Get(key) => {
+                        let key = format!("key{key}");
+                        prop_assert_eq!(hash_table.get(key.clone()).unwrap(), model.get(&key).copied());
+                    }
+                }
+                hash_table.verify_integrity();
+            }
+
+            let entries = hash_table.scan().unwrap().into_iter().collect::<HashMap<String, u32>>();
+            prop_assert_eq!(entries, model);
+        }
     }
 }
-//#[cfg(test)]
-//mod tests {
-//    use std::{
-//        thread::{self, JoinHandle},
-//        time::Duration,
-//    };
-//
-//    use rand::Rng;
-//
-//    use super::*;
-//    use crate::disk_manager::DiskManager;
-//
-//    #[test]
-//    fn test_hash_table() {
-//        let entry_value = 277;
-//        let disk_manager = DiskManager::new();
-//        let buffer_pool_manager = BufferPoolManager::new(disk_manager, 12, 4);
-//        let hash_table = ExtendibleHashTable::<String, u32>::new(
-//            "Test".into(),
-//            Arc::new(Mutex::new(buffer_pool_manager)),
-//            6,
-//            2,
-//        );
-//
-//        let keys: Vec<String> = vec![
-//            "asdasdsas".into(),
-//            "b1211212c".into(),
-//            "d1211212c".into(),
-//            "s1211212c".into(),
-//            "w1211212c".into(),
-//            "jj1211212c".into(),
-//            "jf1212c".into(),
-//            "jfsds1212c".into(),
-//            "gfghfg1212c".into(),
-//            "gfghdfsdfsdf1212c".into(),
-//            "gfisdisidighfg1212c".into(),
-//            "sdfs921201".into(),
-//        ];
-//
-//        for key in keys.clone() {
-//            hash_table.insert(key, entry_value).unwrap();
-//        }
-//
-//        hash_table.verify_integrity();
-//
-//        for key in keys.clone() {
-//            let value = hash_table.get(key);
-//            assert_eq!(value.unwrap(), entry_value);
-//        }
-//
-//        let value = hash_table.get("absent key".into());
-//        assert_eq!(value, None);
-//
-//        for key in keys.clone() {
-//            hash_table.remove(key).unwrap();
-//        }
-//
-//        for key in keys.clone() {
-//            let value = hash_table.get(key);
-//            assert_eq!(value, None);
-//        }
-//        hash_table.verify_integrity();
-//        println!("Hash table test has passed!");
-//    }
-//
-//    #[test]
-//    fn test_hash_table_concurrency() {
-//        let disk_manager = DiskManager::new();
-//        let buffer_pool_manager = BufferPoolManager::new(disk_manager, 12, 4);
-//        let hash_table = ExtendibleHashTable::<String, u32>::new(
-//            "Test".into(),
-//            Arc::new(Mutex::new(buffer_pool_manager)),
-//            6,
-//            2,
-//        );
-//
-//        let hash_table = Arc::new(hash_table);
-//
-//        let mut handles: Vec<JoinHandle<()>> = vec![];
-//        for _ in 0..8 {
-//            let handle = thread::spawn({
-//                let hash_table = Arc::clone(&hash_table);
-//                move || {
-//                    let mut rng = rand::thread_rng();
-//                    let random_number: u32 = rng.gen_range(0..50);
-//                    thread::sleep(Duration::from_millis(random_number as u64));
-//                    hash_table.insert("key".into(), 21).unwrap();
-//                    let _ = hash_table.get("key".into());
-//                    thread::sleep(Duration::from_millis(random_number as u64));
-//                    hash_table.remove("key".into()).unwrap();
-//                }
-//            });
-//
-//            handles.push(handle);
-//        }
-//
-//        for handle in handles {
-//            handle.join().unwrap();
-//        }
-//    }
-//}
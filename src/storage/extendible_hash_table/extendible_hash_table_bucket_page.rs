@@ -1,9 +1,15 @@
 use std::{collections::HashMap, fmt::Debug, hash::Hash};
 
-use parking_lot::{RwLockReadGuard, RwLockWriteGuard};
+use bincode::Options;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use crate::page::Page;
+use crate::page_guard::{ReadPageGuard, WritePageGuard};
+
+use super::{
+    error::ExtendibleHashTableError,
+    extendible_hash_table_key_dictionary_page::ExtendibleHTableKeyDictionaryPage,
+    value_codec::ValueCodec,
+};
 
 #[derive(Serialize, Clone, Deserialize, PartialEq, Eq, Debug)]
 #[repr(C)]
@@ -28,10 +34,6 @@ where
         }
     }
 
-    pub fn lookup(&self, key: K, value: V) -> bool {
-        false
-    }
-
     // TODO: to result
     pub fn insert(&mut self, key: K, value: V) -> bool {
         self.data.insert(key, value);
@@ -46,12 +48,16 @@ where
         self.data.remove(&key)
     }
 
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.data.keys()
+    }
+
     pub fn get_entries(&mut self) -> Vec<(K, V)> {
         self.data.drain().collect::<Vec<(K, V)>>()
     }
 
     pub fn is_full(&self) -> bool {
-        self.data.len() == self.max_size
+        self.get_size() >= self.get_max_size()
     }
 
     pub fn is_empty(&self) -> bool {
@@ -62,6 +68,19 @@ where
         self.max_size
     }
 
+    /// Change number of entries bucket takes, it never drops below entries already stored
+    pub fn set_max_size(&mut self, max_size: usize) {
+        self.max_size = max_size.max(self.data.len());
+    }
+
+    /// Encoded size of every entry, keys are counted uncompressed
+    pub fn entry_sizes(&self) -> Vec<usize> {
+        self.data
+            .iter()
+            .map(|entry| bincode::serialized_size(&entry).unwrap() as usize)
+            .collect()
+    }
+
     pub fn get_size(&self) -> usize {
         self.data.len()
     }
@@ -70,27 +89,147 @@ where
         bincode::serialize(&self).unwrap()
     }
 
-    pub fn from_bytes(bytes: &[u8]) -> Self {
-        bincode::deserialize(bytes).unwrap()
+    /// Decode page, data which doesn't make a valid page is an error
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ExtendibleHashTableError> {
+        let page: Self =
+            bincode::deserialize(bytes).map_err(|_| ExtendibleHashTableError::CorruptedPage)?;
+        if page.data.len() > page.max_size {
+            return Err(ExtendibleHashTableError::CorruptedPage);
+        }
+
+        Ok(page)
+    }
+
+    /// Encode page with keys compressed by dictionary, values go first so encoding
+    /// starts with max size and number of entries like the plain one
+    pub fn to_bytes_with(&self, dictionary: Option<&ExtendibleHTableKeyDictionaryPage>) -> Vec<u8> {
+        let Some(dictionary) = dictionary else {
+            return self.to_bytes();
+        };
+
+        let mut keys = vec![];
+        let values = self
+            .data
+            .iter()
+            .map(|(key, value)| {
+                dictionary.encode_key(&bincode::serialize(key).unwrap(), &mut keys);
+                value
+            })
+            .collect::<Vec<&V>>();
+
+        bincode::serialize(&(self.max_size, values, keys)).unwrap()
+    }
+
+    /// Decode page encoded by `to_bytes_with` with the same dictionary
+    pub fn from_bytes_with(
+        bytes: &[u8],
+        dictionary: Option<&ExtendibleHTableKeyDictionaryPage>,
+    ) -> Result<Self, ExtendibleHashTableError> {
+        let Some(dictionary) = dictionary else {
+            return Self::from_bytes(bytes);
+        };
+
+        let (max_size, values, keys): (usize, Vec<V>, Vec<u8>) =
+            bincode::deserialize(bytes).map_err(|_| ExtendibleHashTableError::CorruptedPage)?;
+        if values.len() > max_size {
+            return Err(ExtendibleHashTableError::CorruptedPage);
+        }
+
+        let mut keys = keys.as_slice();
+        let mut data = HashMap::with_capacity(values.len());
+        for value in values {
+            let key = dictionary.decode_key(&mut keys)?;
+            let key =
+                bincode::deserialize(&key).map_err(|_| ExtendibleHashTableError::CorruptedPage)?;
+            data.insert(key, value);
+        }
+
+        Ok(Self { max_size, data })
+    }
+}
+
+/// Max size and number of entries of encoded bucket, plain or with compressed keys,
+/// keys and values are not decoded
+pub fn decode_occupancy(bytes: &[u8]) -> Result<(usize, usize), ExtendibleHashTableError> {
+    // bucket is encoded as max size followed by length of entries map or values vector
+    let (max_size, len): (usize, u64) =
+        bincode::deserialize(bytes).map_err(|_| ExtendibleHashTableError::CorruptedPage)?;
+    if len > max_size as u64 {
+        return Err(ExtendibleHashTableError::CorruptedPage);
     }
+
+    Ok((max_size, len as usize))
 }
 
-impl<K, V> From<&RwLockWriteGuard<'_, Vec<u8>>> for ExtendibleHTableBucketPage<K, V>
+/// View of value of the first key `matches` accepts, read from encoded bucket (plain or
+/// with compressed keys) without decoding the whole bucket
+pub fn find_value<'a, K, V>(
+    bytes: &'a [u8],
+    dictionary: Option<&ExtendibleHTableKeyDictionaryPage>,
+    matches: impl Fn(&K) -> bool,
+) -> Result<Option<V::View<'a>>, ExtendibleHashTableError>
+where
+    K: DeserializeOwned,
+    V: ValueCodec,
+{
+    let corrupted = |_| ExtendibleHashTableError::CorruptedPage;
+    // the same options `bincode::deserialize` uses
+    let options = bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes();
+    let mut deserializer = bincode::Deserializer::from_slice(bytes, options);
+    let (_, len) = decode_occupancy(bytes)?;
+    <(usize, u64)>::deserialize(&mut deserializer).map_err(corrupted)?;
+
+    let Some(dictionary) = dictionary else {
+        for _ in 0..len {
+            let key = K::deserialize(&mut deserializer).map_err(corrupted)?;
+            let value = V::View::deserialize(&mut deserializer).map_err(corrupted)?;
+            if matches(&key) {
+                return Ok(Some(value));
+            }
+        }
+
+        return Ok(None);
+    };
+
+    // values go first, keys follow in the same order
+    let values = (0..len)
+        .map(|_| V::View::deserialize(&mut deserializer))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(corrupted)?;
+    let mut keys = <&[u8]>::deserialize(&mut deserializer).map_err(corrupted)?;
+    for value in values {
+        let key = dictionary.decode_key(&mut keys)?;
+        let key = bincode::deserialize(&key).map_err(corrupted)?;
+        if matches(&key) {
+            return Ok(Some(value));
+        }
+    }
+
+    Ok(None)
+}
+
+impl<K, V> TryFrom<&WritePageGuard<'_>> for ExtendibleHTableBucketPage<K, V>
 where
     K: Hash + Eq + Clone + Debug + Serialize + DeserializeOwned,
     V: Clone + Debug + Serialize + DeserializeOwned,
 {
-    fn from(data: &RwLockWriteGuard<'_, Vec<u8>>) -> Self {
-        bincode::deserialize(data).unwrap()
+    type Error = ExtendibleHashTableError;
+
+    fn try_from(data: &WritePageGuard<'_>) -> Result<Self, Self::Error> {
+        Self::from_bytes(data)
     }
 }
 
-impl<K, V> From<&RwLockReadGuard<'_, Vec<u8>>> for ExtendibleHTableBucketPage<K, V>
+impl<K, V> TryFrom<&ReadPageGuard<'_>> for ExtendibleHTableBucketPage<K, V>
 where
     K: Hash + Eq + Clone + Debug + Serialize + DeserializeOwned,
     V: Clone + Debug + Serialize + DeserializeOwned,
 {
-    fn from(data: &RwLockReadGuard<'_, Vec<u8>>) -> Self {
-        bincode::deserialize(data).unwrap()
+    type Error = ExtendibleHashTableError;
+
+    fn try_from(data: &ReadPageGuard<'_>) -> Result<Self, Self::Error> {
+        Self::from_bytes(data)
     }
 }
@@ -1,9 +1,11 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, mem};
 
-use parking_lot::{RwLockReadGuard, RwLockWriteGuard};
 use serde::{Deserialize, Serialize};
 
-use crate::page::{Page, PageId};
+use crate::{
+    page::PageId,
+    page_guard::{ReadPageGuard, WritePageGuard},
+};
 
 use super::error::ExtendibleHashTableError;
 
@@ -29,20 +31,12 @@ impl ExtendibleHTableDirectoryPage {
         }
     }
 
-    // TODO: rework
-    pub fn init(&mut self, page_id: PageId) {
-        if self.local_depths.len() == 0 {
-            self.local_depths.push(0);
-            self.bucket_page_ids.push(page_id);
-        }
-    }
-
     pub fn hash_to_bucket_index(&self, hash: u32) -> BucketIndex {
         (hash & self.get_global_depth_mask()) as usize
     }
 
     pub fn get_bucket_page_id(&self, bucket_index: BucketIndex) -> Option<&PageId> {
-        self.bucket_page_ids.get(bucket_index as usize)
+        self.bucket_page_ids.get(bucket_index)
     }
 
     pub fn get_split_image_index(&mut self, bucket_index: BucketIndex) -> BucketIndex {
@@ -64,18 +58,12 @@ impl ExtendibleHTableDirectoryPage {
         }
     }
 
-    pub fn get_local_depth_mask(&mut self, bucket_index: BucketIndex) -> usize {
-        let local_depth = self.get_local_depth(bucket_index).unwrap();
-
-        (1 << local_depth) - 1
-    }
-
     pub fn get_global_depth(&self) -> u32 {
         self.global_depth
     }
 
     pub fn get_size(&self) -> usize {
-        2_usize.pow(self.global_depth as u32)
+        2_usize.pow(self.global_depth)
     }
 
     pub fn increment_global_depth(&mut self) -> Result<(), ExtendibleHashTableError> {
@@ -86,7 +74,7 @@ impl ExtendibleHTableDirectoryPage {
         let old_size = self.bucket_page_ids.len();
         let new_size = 2 * old_size;
 
-        let mut new_bucket_page_ids: Vec<PageId> = vec![0; new_size];
+        let mut new_bucket_page_ids: Vec<PageId> = vec![PageId::INVALID; new_size];
         let mut new_local_depths = vec![0; new_size];
 
         for i in 0..old_size {
@@ -106,12 +94,20 @@ impl ExtendibleHTableDirectoryPage {
         Ok(())
     }
 
+    pub fn can_shrink(&self) -> bool {
+        self.global_depth > 0
+            && self
+                .local_depths
+                .iter()
+                .all(|local_depth| *local_depth < self.global_depth)
+    }
+
     pub fn decrement_global_depth(&mut self) {
         let old_size = self.bucket_page_ids.len();
 
         self.global_depth -= 1;
-        self.bucket_page_ids.resize(old_size / 2, 0);
-        self.local_depths.resize(old_size / 2, 0);
+        self.bucket_page_ids.truncate(old_size / 2);
+        self.local_depths.truncate(old_size / 2);
     }
 
     pub fn get_local_depth(&mut self, bucket_index: BucketIndex) -> Option<u32> {
@@ -127,54 +123,85 @@ impl ExtendibleHTableDirectoryPage {
         self.local_depths[bucket_index] += 1;
     }
 
-    // TODO: return Result
-    pub fn decrement_local_depth(&mut self, bucket_index: BucketIndex) {
-        if self.get_local_depth(bucket_index).unwrap() > 0 {
-            self.local_depths[bucket_index] -= 1;
-        }
-    }
-
     pub fn set_bucket_page_id(&mut self, bucket_index: BucketIndex, bucket_page_id: PageId) {
         // TODO: review
         if self.bucket_page_ids.is_empty() {
-            self.bucket_page_ids.push(0);
+            self.bucket_page_ids.push(PageId::INVALID);
         }
         self.bucket_page_ids[bucket_index] = bucket_page_id;
     }
 
-    pub fn is_full(&mut self, bucket_index: BucketIndex) -> bool {
+    pub fn is_full(&self) -> bool {
         self.global_depth == self.max_depth
     }
 
+    /// Bytes decoded directory takes in memory
+    pub fn memory_size(&self) -> usize {
+        mem::size_of::<Self>()
+            + self.bucket_page_ids.capacity() * mem::size_of::<PageId>()
+            + self.local_depths.capacity() * mem::size_of::<BucketDepth>()
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         bincode::serialize(&self).unwrap()
     }
 
-    pub fn from_bytes(bytes: &[u8]) -> Self {
-        bincode::deserialize(bytes).unwrap()
+    /// Decode page, data which doesn't make a valid page is an error
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ExtendibleHashTableError> {
+        let page: Self =
+            bincode::deserialize(bytes).map_err(|_| ExtendibleHashTableError::CorruptedPage)?;
+        if !page.is_consistent() {
+            return Err(ExtendibleHashTableError::CorruptedPage);
+        }
+
+        Ok(page)
+    }
+
+    // sizes and depths of decoded page agree with each other
+    fn is_consistent(&self) -> bool {
+        let Some(size) = 2_usize.checked_pow(self.global_depth) else {
+            return false;
+        };
+
+        self.global_depth <= self.max_depth
+            && self.local_depths.len() == size
+            && (self.bucket_page_ids.is_empty() || self.bucket_page_ids.len() == size)
+            && self
+                .local_depths
+                .iter()
+                .all(|local_depth| *local_depth <= self.global_depth)
     }
 
     pub fn verify_integrity(&self) {
-        let mut page_id_to_count: HashMap<usize, u32> = HashMap::new();
-        let mut page_id_to_ld: HashMap<usize, u32> = HashMap::new();
+        let problems = self.integrity_problems();
+        assert!(problems.is_empty(), "{}", problems.join(", "));
+    }
+
+    /// Local depths which disagree with global depth or with number of slots pointing
+    /// to their bucket
+    pub fn integrity_problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+        let mut page_id_to_count: HashMap<PageId, u32> = HashMap::new();
+        let mut page_id_to_ld: HashMap<PageId, u32> = HashMap::new();
 
         for curr_idx in 0..self.bucket_page_ids.len() {
             let curr_page_id = self.bucket_page_ids[curr_idx];
             let curr_ld = self.local_depths[curr_idx];
 
-            assert!(
-                curr_ld <= self.global_depth,
-                "Local depth exceeds global depth"
-            );
+            if curr_ld > self.global_depth {
+                problems.push(format!("Local depth exceeds global depth at {}", curr_idx));
+                continue;
+            }
 
             *page_id_to_count.entry(curr_page_id).or_insert(0) += 1;
 
             if let Some(&old_ld) = page_id_to_ld.get(&curr_page_id) {
-                assert_eq!(
-                    curr_ld, old_ld,
-                    "Local depth mismatch for page_id: {}",
-                    curr_page_id
-                );
+                if curr_ld != old_ld {
+                    problems.push(format!(
+                        "Local depth mismatch for page_id: {}",
+                        curr_page_id
+                    ));
+                }
             } else {
                 page_id_to_ld.insert(curr_page_id, curr_ld);
             }
@@ -184,23 +211,27 @@ impl ExtendibleHTableDirectoryPage {
             let curr_ld = page_id_to_ld[&curr_page_id];
             let required_count = 1 << (self.global_depth - curr_ld);
 
-            assert_eq!(
-                curr_count, required_count,
-                "Count mismatch for page_id: {}",
-                curr_page_id
-            );
+            if curr_count != required_count {
+                problems.push(format!("Count mismatch for page_id: {}", curr_page_id));
+            }
         }
+
+        problems
     }
 }
 
-impl From<&RwLockWriteGuard<'_, Vec<u8>>> for ExtendibleHTableDirectoryPage {
-    fn from(data: &RwLockWriteGuard<'_, Vec<u8>>) -> Self {
-        bincode::deserialize(data).unwrap()
+impl TryFrom<&WritePageGuard<'_>> for ExtendibleHTableDirectoryPage {
+    type Error = ExtendibleHashTableError;
+
+    fn try_from(data: &WritePageGuard<'_>) -> Result<Self, Self::Error> {
+        Self::from_bytes(data)
     }
 }
 
-impl From<&RwLockReadGuard<'_, Vec<u8>>> for ExtendibleHTableDirectoryPage {
-    fn from(data: &RwLockReadGuard<'_, Vec<u8>>) -> Self {
-        bincode::deserialize(data).unwrap()
+impl TryFrom<&ReadPageGuard<'_>> for ExtendibleHTableDirectoryPage {
+    type Error = ExtendibleHashTableError;
+
+    fn try_from(data: &ReadPageGuard<'_>) -> Result<Self, Self::Error> {
+        Self::from_bytes(data)
     }
 }
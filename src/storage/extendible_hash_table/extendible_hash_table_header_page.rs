@@ -1,25 +1,142 @@
-use parking_lot::{RwLockReadGuard, RwLockWriteGuard};
+use std::hash::{DefaultHasher, Hash, Hasher};
+
 use serde_derive::{Deserialize, Serialize};
 
-use crate::page::{Page, PageId};
+use crate::{
+    page::PageId,
+    page_guard::{ReadPageGuard, WritePageGuard},
+};
+
+use super::error::ExtendibleHashTableError;
+
+/// Bits of key hash header picks directory by, directories pick buckets by low bits
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DirectoryHashBits {
+    /// The same bits directories use, so keys of a directory share their low bits and
+    /// fill only some of its buckets. Headers written before bits were stored use them.
+    #[default]
+    Low,
+    /// High bits, disjoint from bits of directories unless header and directory depths
+    /// together exceed 32
+    High,
+}
+
+// key every hasher hashes into fingerprint stored with it
+const HASHER_PROBE: &str = "extendible hash table hasher probe";
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Function keys are hashed with, it is stored in header and table can't be read with
+/// another one, see `ExtendibleHashTable::rehash_to`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeyHasher {
+    /// `DefaultHasher` of std, which may hash differently once built by another Rust
+    /// version. Headers written before hasher was stored use it.
+    #[default]
+    Std,
+    /// 64-bit FNV-1a starting from basis mixed with seed, it hashes the same everywhere
+    Fnv1a { seed: u64 },
+}
+
+impl KeyHasher {
+    pub fn hash(&self, key: &str) -> u32 {
+        match self {
+            KeyHasher::Std => {
+                let mut hasher = DefaultHasher::new();
+                key.hash(&mut hasher);
+
+                (hasher.finish() % u32::MAX as u64) as u32
+            }
+            KeyHasher::Fnv1a { seed } => {
+                let hash = key.bytes().fold(FNV_OFFSET_BASIS ^ seed, |hash, byte| {
+                    (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+                });
+
+                (hash ^ (hash >> 32)) as u32
+            }
+        }
+    }
+
+    // hash of probe key, differs when hash function changes
+    fn fingerprint(&self) -> u32 {
+        self.hash(HASHER_PROBE)
+    }
+}
 
 #[derive(Serialize, Deserialize, Debug)]
 #[repr(C)]
 pub struct ExtendibleHTableHeaderPage {
     directory_page_ids: Vec<Option<PageId>>,
     max_depth: u32,
+    // added after the other fields, pages written before it decode it from zero padding as None
+    key_dictionary_page_id: Option<PageId>,
+    // decoded from zero padding as `Low` as well
+    directory_hash_bits: DirectoryHashBits,
+    // decoded from zero padding as `Std` with fingerprint 0, which isn't checked
+    key_hasher: KeyHasher,
+    key_hasher_fingerprint: u32,
 }
 
 impl ExtendibleHTableHeaderPage {
-    pub fn new(max_depth: u32) -> Self {
+    pub fn with_hash_bits(max_depth: u32, directory_hash_bits: DirectoryHashBits) -> Self {
         Self {
             max_depth,
             directory_page_ids: vec![None; 2_usize.pow(max_depth)],
+            key_dictionary_page_id: None,
+            directory_hash_bits,
+            key_hasher: KeyHasher::Std,
+            key_hasher_fingerprint: KeyHasher::Std.fingerprint(),
         }
     }
 
     pub fn hash_to_directory_index(&self, hash: u32) -> usize {
-        (hash & (2_u32.pow(self.max_depth) - 1)) as usize
+        if self.max_depth == 0 {
+            return 0;
+        }
+
+        match self.directory_hash_bits {
+            DirectoryHashBits::Low => (hash & (2_u32.pow(self.max_depth) - 1)) as usize,
+            DirectoryHashBits::High => (hash >> (32 - self.max_depth)) as usize,
+        }
+    }
+
+    pub fn get_directory_hash_bits(&self) -> DirectoryHashBits {
+        self.directory_hash_bits
+    }
+
+    pub fn get_max_depth(&self) -> u32 {
+        self.max_depth
+    }
+
+    pub fn get_key_hasher(&self) -> KeyHasher {
+        self.key_hasher
+    }
+
+    /// Store hasher with fingerprint of how it hashes in this build
+    pub fn set_key_hasher(&mut self, key_hasher: KeyHasher) {
+        self.key_hasher = key_hasher;
+        self.key_hasher_fingerprint = key_hasher.fingerprint();
+    }
+
+    /// Error unless keys were hashed with `key_hasher` and it still hashes them the same
+    pub fn check_key_hasher(&self, key_hasher: KeyHasher) -> Result<(), ExtendibleHashTableError> {
+        if self.key_hasher != key_hasher {
+            return Err(ExtendibleHashTableError::KeyHasherMismatch {
+                stored: format!("{:?}", self.key_hasher),
+                requested: format!("{:?}", key_hasher),
+            });
+        }
+        if self.key_hasher_fingerprint != 0
+            && self.key_hasher_fingerprint != key_hasher.fingerprint()
+        {
+            return Err(ExtendibleHashTableError::KeyHasherChanged(format!(
+                "{:?}",
+                key_hasher
+            )));
+        }
+
+        Ok(())
     }
 
     pub fn get_directory_page_id(&self, directory_index: usize) -> Option<&PageId> {
@@ -32,6 +149,15 @@ impl ExtendibleHTableHeaderPage {
         self.directory_page_ids[directory_index] = Some(directory_page_id);
     }
 
+    /// Page of the dictionary bucket keys are compressed with, if any
+    pub fn get_key_dictionary_page_id(&self) -> Option<PageId> {
+        self.key_dictionary_page_id
+    }
+
+    pub fn set_key_dictionary_page_id(&mut self, page_id: Option<PageId>) {
+        self.key_dictionary_page_id = page_id;
+    }
+
     pub fn get_max_size(&self) -> usize {
         2_u32.pow(self.max_depth) as usize
     }
@@ -40,19 +166,92 @@ impl ExtendibleHTableHeaderPage {
         bincode::serialize(&self).unwrap()
     }
 
-    pub fn from_bytes(bytes: &[u8]) -> Self {
-        bincode::deserialize(bytes).unwrap()
+    /// Decode page, data which doesn't make a valid page is an error
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ExtendibleHashTableError> {
+        let page: Self =
+            bincode::deserialize(bytes).map_err(|_| ExtendibleHashTableError::CorruptedPage)?;
+        if page.max_depth > 32
+            || 2_usize.checked_pow(page.max_depth) != Some(page.directory_page_ids.len())
+        {
+            return Err(ExtendibleHashTableError::CorruptedPage);
+        }
+
+        Ok(page)
     }
 }
 
-impl From<&RwLockWriteGuard<'_, Vec<u8>>> for ExtendibleHTableHeaderPage {
-    fn from(data: &RwLockWriteGuard<'_, Vec<u8>>) -> Self {
-        bincode::deserialize(data).unwrap()
+impl TryFrom<&WritePageGuard<'_>> for ExtendibleHTableHeaderPage {
+    type Error = ExtendibleHashTableError;
+
+    fn try_from(data: &WritePageGuard<'_>) -> Result<Self, Self::Error> {
+        Self::from_bytes(data)
+    }
+}
+
+impl TryFrom<&ReadPageGuard<'_>> for ExtendibleHTableHeaderPage {
+    type Error = ExtendibleHashTableError;
+
+    fn try_from(data: &ReadPageGuard<'_>) -> Result<Self, Self::Error> {
+        Self::from_bytes(data)
     }
 }
 
-impl From<&RwLockReadGuard<'_, Vec<u8>>> for ExtendibleHTableHeaderPage {
-    fn from(data: &RwLockReadGuard<'_, Vec<u8>>) -> Self {
-        bincode::deserialize(data).unwrap()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_directory_hash_bits() {
+        let hash = 0b1010_0000_0000_0000_0000_0000_0000_0011;
+
+        let low = ExtendibleHTableHeaderPage::with_hash_bits(2, DirectoryHashBits::Low);
+        assert_eq!(low.hash_to_directory_index(hash), 0b11);
+        let high = ExtendibleHTableHeaderPage::with_hash_bits(2, DirectoryHashBits::High);
+        assert_eq!(high.hash_to_directory_index(hash), 0b10);
+        let single = ExtendibleHTableHeaderPage::with_hash_bits(0, DirectoryHashBits::High);
+        assert_eq!(single.hash_to_directory_index(hash), 0);
+
+        // header written before bits were stored is read with low bits
+        let mut bytes = low.to_bytes();
+        bytes.truncate(bytes.len() - 12);
+        bytes.resize(bytes.len() + 16, 0);
+        let page = ExtendibleHTableHeaderPage::from_bytes(&bytes).unwrap();
+        assert_eq!(page.get_directory_hash_bits(), DirectoryHashBits::Low);
+    }
+
+    #[test]
+    fn test_key_hasher() {
+        let mut header = ExtendibleHTableHeaderPage::with_hash_bits(1, DirectoryHashBits::High);
+        header.set_key_hasher(KeyHasher::Fnv1a { seed: 7 });
+        let header = ExtendibleHTableHeaderPage::from_bytes(&header.to_bytes()).unwrap();
+        assert_eq!(header.get_key_hasher(), KeyHasher::Fnv1a { seed: 7 });
+        assert!(header
+            .check_key_hasher(KeyHasher::Fnv1a { seed: 7 })
+            .is_ok());
+        assert!(matches!(
+            header.check_key_hasher(KeyHasher::Fnv1a { seed: 8 }),
+            Err(ExtendibleHashTableError::KeyHasherMismatch { .. })
+        ));
+        assert_ne!(
+            KeyHasher::Fnv1a { seed: 7 }.hash("key"),
+            KeyHasher::Fnv1a { seed: 8 }.hash("key")
+        );
+
+        // hasher which hashes differently than when header was written is refused
+        let mut changed = ExtendibleHTableHeaderPage::with_hash_bits(1, DirectoryHashBits::High);
+        changed.key_hasher_fingerprint = KeyHasher::Std.fingerprint().wrapping_add(1);
+        assert!(matches!(
+            changed.check_key_hasher(KeyHasher::Std),
+            Err(ExtendibleHashTableError::KeyHasherChanged(_))
+        ));
+
+        // header written before hasher was stored is read with std hasher
+        let mut bytes =
+            ExtendibleHTableHeaderPage::with_hash_bits(1, DirectoryHashBits::High).to_bytes();
+        bytes.truncate(bytes.len() - 8);
+        bytes.resize(bytes.len() + 16, 0);
+        let old = ExtendibleHTableHeaderPage::from_bytes(&bytes).unwrap();
+        assert_eq!(old.get_key_hasher(), KeyHasher::Std);
+        assert!(old.check_key_hasher(KeyHasher::Std).is_ok());
     }
 }
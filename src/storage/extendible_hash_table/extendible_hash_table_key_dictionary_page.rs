@@ -0,0 +1,147 @@
+use std::{cmp::Reverse, collections::HashMap};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::page::PAGE_SIZE;
+
+use super::error::ExtendibleHashTableError;
+
+// longer common prefixes are cut to this length
+const MAX_PREFIX_LEN: usize = 64;
+// code 0 stands for key stored without prefix
+const MAX_PREFIXES: usize = u8::MAX as usize;
+
+/// Frequent prefixes of encoded keys shared by all buckets of hash table. Bucket stores key
+/// as code of its longest prefix found in dictionary followed by the rest of the key.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ExtendibleHTableKeyDictionaryPage {
+    prefixes: Vec<Vec<u8>>,
+    #[serde(skip)]
+    codes: HashMap<Vec<u8>, u8>,
+}
+
+impl ExtendibleHTableKeyDictionaryPage {
+    /// Pick prefixes which save the most bytes across `keys`, up to what fits into page
+    pub fn train<'a>(keys: impl IntoIterator<Item = &'a [u8]>) -> Self {
+        let mut counts = HashMap::<&[u8], usize>::new();
+        for key in keys {
+            for len in 1..=key.len().min(MAX_PREFIX_LEN) {
+                *counts.entry(&key[..len]).or_default() += 1;
+            }
+        }
+
+        let mut candidates = counts
+            .into_iter()
+            .filter(|(_, count)| *count > 1)
+            .collect::<Vec<(&[u8], usize)>>();
+        candidates.sort_by_key(|(prefix, count)| (Reverse(prefix.len() * count), *prefix));
+
+        // prefixes are encoded as vector length followed by vectors with their own lengths
+        let mut size = 8;
+        let mut prefixes = vec![];
+        for (prefix, _) in candidates {
+            if prefixes.len() == MAX_PREFIXES {
+                break;
+            }
+            if size + 8 + prefix.len() > PAGE_SIZE {
+                continue;
+            }
+            size += 8 + prefix.len();
+            prefixes.push(prefix.to_vec());
+        }
+
+        Self::with_prefixes(prefixes)
+    }
+
+    fn with_prefixes(prefixes: Vec<Vec<u8>>) -> Self {
+        let codes = prefixes
+            .iter()
+            .enumerate()
+            .map(|(index, prefix)| (prefix.clone(), index as u8 + 1))
+            .collect();
+
+        Self { prefixes, codes }
+    }
+
+    pub fn num_prefixes(&self) -> usize {
+        self.prefixes.len()
+    }
+
+    /// Append encoded key to `out`: prefix code, length of the rest and the rest
+    pub fn encode_key(&self, key: &[u8], out: &mut Vec<u8>) {
+        let (code, prefix_len) = (1..=key.len().min(MAX_PREFIX_LEN))
+            .rev()
+            .find_map(|len| self.codes.get(&key[..len]).map(|code| (*code, len)))
+            .unwrap_or((0, 0));
+        let rest = &key[prefix_len..];
+
+        out.push(code);
+        write_varint(rest.len() as u64, out);
+        out.extend_from_slice(rest);
+    }
+
+    /// Decode key at the start of `bytes` and advance past it
+    pub fn decode_key(&self, bytes: &mut &[u8]) -> Result<Vec<u8>, ExtendibleHashTableError> {
+        let (&code, rest) = bytes
+            .split_first()
+            .ok_or(ExtendibleHashTableError::CorruptedPage)?;
+        let mut rest = rest;
+        let len = read_varint(&mut rest)? as usize;
+        if rest.len() < len {
+            return Err(ExtendibleHashTableError::CorruptedPage);
+        }
+
+        let mut key = match code {
+            0 => vec![],
+            code => self
+                .prefixes
+                .get(code as usize - 1)
+                .ok_or(ExtendibleHashTableError::CorruptedPage)?
+                .clone(),
+        };
+        key.extend_from_slice(&rest[..len]);
+        *bytes = &rest[len..];
+
+        Ok(key)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(&self).unwrap()
+    }
+
+    /// Decode page, data which doesn't make a valid page is an error
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ExtendibleHashTableError> {
+        let page: Self =
+            bincode::deserialize(bytes).map_err(|_| ExtendibleHashTableError::CorruptedPage)?;
+        if page.prefixes.len() > MAX_PREFIXES {
+            return Err(ExtendibleHashTableError::CorruptedPage);
+        }
+
+        Ok(Self::with_prefixes(page.prefixes))
+    }
+}
+
+// lengths of key rests are mostly short, so they take a byte instead of eight
+fn write_varint(mut value: u64, out: &mut Vec<u8>) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(bytes: &mut &[u8]) -> Result<u64, ExtendibleHashTableError> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = bytes
+            .split_first()
+            .ok_or(ExtendibleHashTableError::CorruptedPage)?;
+        *bytes = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(ExtendibleHashTableError::CorruptedPage)
+}
@@ -1,5 +1,12 @@
+pub(crate) mod directory_cache;
+pub mod error;
+#[allow(clippy::module_inception)]
 pub mod extendible_hash_table;
-mod error;
-mod extendible_hash_table_bucket_page;
-mod extendible_hash_table_directory_page;
-mod extendible_hash_table_header_page;
\ No newline at end of file
+pub(crate) mod extendible_hash_table_bucket_page;
+pub(crate) mod extendible_hash_table_directory_page;
+pub(crate) mod extendible_hash_table_header_page;
+pub(crate) mod extendible_hash_table_key_dictionary_page;
+pub mod partitioned_hash_table;
+pub(crate) mod snapshot_cache;
+pub(crate) mod value_codec;
+pub(crate) mod write_coalescer;
@@ -0,0 +1,189 @@
+use std::{fmt::Debug, hash::Hash, sync::Arc};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{
+    error::ExtendibleHashTableError,
+    extendible_hash_table::{hash_string, ExtendibleHashTable},
+};
+use crate::{buffer_pool_manager::BufferPoolManager, page::PageId};
+
+/// Logical hash table split into partitions by key hash, every partition is a separate
+/// extendible hash table in its own buffer pool. Pools with their own files and disk
+/// schedulers don't share latches, so threads working on different partitions never
+/// contend with each other.
+#[derive(Debug)]
+pub struct PartitionedHashTable<K, V> {
+    partitions: Vec<ExtendibleHashTable<K, V>>,
+}
+
+impl<K, V> PartitionedHashTable<K, V>
+where
+    K: Hash + Eq + Clone + Debug + Serialize + DeserializeOwned + ToString,
+    V: Clone + Debug + Serialize + DeserializeOwned,
+{
+    /// Create table with a partition in each of the buffer pools
+    pub fn new(
+        name: String,
+        buffer_pool_managers: Vec<Arc<BufferPoolManager>>,
+        directory_max_depth: u32,
+        bucket_max_size: usize,
+    ) -> Self {
+        assert!(!buffer_pool_managers.is_empty(), "Table needs a partition.");
+
+        let partitions = buffer_pool_managers
+            .into_iter()
+            .map(|buffer_pool_manager| {
+                ExtendibleHashTable::new(
+                    name.clone(),
+                    buffer_pool_manager,
+                    directory_max_depth,
+                    bucket_max_size,
+                )
+            })
+            .collect();
+
+        Self { partitions }
+    }
+
+    /// Open table previously created by `new`, pools with header pages of their partitions
+    /// go in the same order as at creation
+    pub fn open(
+        name: String,
+        partitions: Vec<(Arc<BufferPoolManager>, PageId)>,
+        directory_max_depth: u32,
+        bucket_max_size: usize,
+    ) -> Self {
+        assert!(!partitions.is_empty(), "Table needs a partition.");
+
+        let partitions = partitions
+            .into_iter()
+            .map(|(buffer_pool_manager, header_page_id)| {
+                ExtendibleHashTable::open(
+                    name.clone(),
+                    buffer_pool_manager,
+                    header_page_id,
+                    directory_max_depth,
+                    bucket_max_size,
+                )
+            })
+            .collect();
+
+        Self { partitions }
+    }
+
+    pub fn num_partitions(&self) -> usize {
+        self.partitions.len()
+    }
+
+    /// Header pages of partitions, needed to `open` table again
+    pub fn header_page_ids(&self) -> Vec<PageId> {
+        self.partitions
+            .iter()
+            .map(ExtendibleHashTable::header_page_id)
+            .collect()
+    }
+
+    /// Partition the key belongs to
+    pub fn partition_of(&self, key: &K) -> usize {
+        // high bits of the hash pick partition, low ones are used by directories inside it
+        let hash = hash_string(key.to_string()) as u64;
+
+        ((hash * self.partitions.len() as u64) >> 32) as usize
+    }
+
+    fn partition(&self, key: &K) -> &ExtendibleHashTable<K, V> {
+        &self.partitions[self.partition_of(key)]
+    }
+
+    pub fn insert(&self, key: K, value: V) -> Result<(), ExtendibleHashTableError> {
+        self.partition(&key).insert(key, value)
+    }
+
+    pub fn remove(&self, key: K) -> Result<Option<V>, ExtendibleHashTableError> {
+        self.partition(&key).remove(key)
+    }
+
+    pub fn get(&self, key: K) -> Result<Option<V>, ExtendibleHashTableError> {
+        self.partition(&key).get(key)
+    }
+
+    /// See `ExtendibleHashTable::compare_and_swap`
+    pub fn compare_and_swap(
+        &self,
+        key: K,
+        expected: Option<V>,
+        new: Option<V>,
+    ) -> Result<Result<(), Option<V>>, ExtendibleHashTableError>
+    where
+        V: PartialEq,
+    {
+        self.partition(&key).compare_and_swap(key, expected, new)
+    }
+
+    /// See `ExtendibleHashTable::update`
+    pub fn update<F>(&self, key: K, f: F) -> Result<Option<V>, ExtendibleHashTableError>
+    where
+        F: FnOnce(Option<&V>) -> Option<V>,
+    {
+        self.partition(&key).update(key, f)
+    }
+
+    /// All entries of the table, partitions are scanned one after another
+    pub fn scan(&self) -> Result<Vec<(K, V)>, ExtendibleHashTableError> {
+        let mut entries = vec![];
+        for partition in &self.partitions {
+            entries.extend(partition.scan()?);
+        }
+
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::disk_manager::DiskManager;
+
+    #[test]
+    fn test_partitioned_hash_table() {
+        let dir = TempDir::new().unwrap();
+        let buffer_pool_managers = (0..4)
+            .map(|i| {
+                let disk_manager = DiskManager::open(dir.path().join(format!("{i}.db"))).unwrap();
+                Arc::new(BufferPoolManager::new(disk_manager, 8, 2))
+            })
+            .collect::<Vec<_>>();
+        let table = PartitionedHashTable::<String, u32>::new(
+            "Test".into(),
+            buffer_pool_managers.clone(),
+            6,
+            4,
+        );
+
+        let mut used_partitions = [false; 4];
+        for i in 0..100 {
+            table.insert(format!("key{i}"), i).unwrap();
+            used_partitions[table.partition_of(&format!("key{i}"))] = true;
+        }
+        assert_eq!(used_partitions, [true; 4]);
+        assert_eq!(table.remove("key0".into()).unwrap(), Some(0));
+
+        let table = PartitionedHashTable::<String, u32>::open(
+            "Test".into(),
+            buffer_pool_managers
+                .into_iter()
+                .zip(table.header_page_ids())
+                .collect(),
+            6,
+            4,
+        );
+        assert_eq!(table.get("key0".into()).unwrap(), None);
+        for i in 1..100 {
+            assert_eq!(table.get(format!("key{i}")).unwrap(), Some(i));
+        }
+        assert_eq!(table.scan().unwrap().len(), 99);
+    }
+}
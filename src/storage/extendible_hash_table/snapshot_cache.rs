@@ -0,0 +1,125 @@
+use std::{
+    cell::RefCell,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use super::extendible_hash_table_directory_page::ExtendibleHTableDirectoryPage;
+use super::extendible_hash_table_header_page::ExtendibleHTableHeaderPage;
+use crate::page::PageId;
+
+// tables a thread keeps snapshots of and directories of each table, the least recently
+// used ones are dropped first
+const MAX_TABLES: usize = 8;
+const MAX_DIRECTORIES: usize = 8;
+
+static NEXT_TABLE_ID: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Default)]
+struct TableSnapshots {
+    table_id: u64,
+    header: Option<(u64, Arc<ExtendibleHTableHeaderPage>)>,
+    // most recently used last
+    directories: Vec<(PageId, u64, Arc<ExtendibleHTableDirectoryPage>)>,
+}
+
+thread_local! {
+    // most recently used last
+    static SNAPSHOTS: RefCell<Vec<TableSnapshots>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Decoded header and directories of hash table which a thread read last, with versions
+/// of pages they were read at. Snapshot is used only while its page keeps the version,
+/// so lookups of a thread skip fetching and decoding them until table structure changes.
+#[derive(Debug)]
+pub(crate) struct SnapshotCache {
+    table_id: u64,
+}
+
+impl SnapshotCache {
+    pub fn new() -> Self {
+        Self {
+            table_id: NEXT_TABLE_ID.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    /// Header of this thread with its version, if `is_current` confirms the version
+    pub fn header(
+        &self,
+        is_current: impl FnOnce(u64) -> bool,
+    ) -> Option<(u64, Arc<ExtendibleHTableHeaderPage>)> {
+        let (version, header) = self.with_table(|table| table.header.clone())?;
+
+        is_current(version).then_some((version, header))
+    }
+
+    pub fn set_header(&self, version: u64, header: Arc<ExtendibleHTableHeaderPage>) {
+        self.with_table(|table| table.header = Some((version, header)));
+    }
+
+    /// Directory of this thread with its version, if `is_current` confirms the version
+    pub fn directory(
+        &self,
+        page_id: PageId,
+        is_current: impl FnOnce(u64) -> bool,
+    ) -> Option<(u64, Arc<ExtendibleHTableDirectoryPage>)> {
+        let (version, directory) = self.with_table(|table| {
+            let index = table
+                .directories
+                .iter()
+                .position(|(directory_page_id, ..)| *directory_page_id == page_id)?;
+            let snapshot = table.directories.remove(index);
+            let (_, version, directory) = &snapshot;
+            let found = (*version, Arc::clone(directory));
+            table.directories.push(snapshot);
+
+            Some(found)
+        })?;
+
+        is_current(version).then_some((version, directory))
+    }
+
+    pub fn set_directory(
+        &self,
+        page_id: PageId,
+        version: u64,
+        directory: Arc<ExtendibleHTableDirectoryPage>,
+    ) {
+        self.with_table(|table| {
+            table
+                .directories
+                .retain(|(directory_page_id, ..)| *directory_page_id != page_id);
+            if table.directories.len() == MAX_DIRECTORIES {
+                table.directories.remove(0);
+            }
+            table.directories.push((page_id, version, directory));
+        });
+    }
+
+    // snapshots of this table in current thread, table becomes the most recently used one
+    fn with_table<R>(&self, f: impl FnOnce(&mut TableSnapshots) -> R) -> R {
+        SNAPSHOTS.with(|tables| {
+            let mut tables = tables.borrow_mut();
+            let table = match tables
+                .iter()
+                .position(|table| table.table_id == self.table_id)
+            {
+                Some(index) => tables.remove(index),
+                None => {
+                    if tables.len() == MAX_TABLES {
+                        tables.remove(0);
+                    }
+                    TableSnapshots {
+                        table_id: self.table_id,
+                        ..Default::default()
+                    }
+                }
+            };
+            tables.push(table);
+
+            f(tables.last_mut().unwrap())
+        })
+    }
+}
@@ -0,0 +1,28 @@
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// Value which can be read straight from encoded bucket page as borrowed view, like
+/// `&str` of `String`, see `ExtendibleHashTable::get_with`. View must decode from
+/// the same bincode encoding as the value.
+pub trait ValueCodec: Serialize + DeserializeOwned {
+    type View<'a>: Deserialize<'a>;
+}
+
+impl ValueCodec for String {
+    type View<'a> = &'a str;
+}
+
+impl ValueCodec for Vec<u8> {
+    type View<'a> = &'a [u8];
+}
+
+macro_rules! impl_value_codec_by_copy {
+    ($($ty:ty),*) => {
+        $(
+            impl ValueCodec for $ty {
+                type View<'a> = $ty;
+            }
+        )*
+    };
+}
+
+impl_value_codec_by_copy!(bool, u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
@@ -0,0 +1,136 @@
+use std::{collections::HashMap, sync::Arc, thread, time::Duration};
+
+use parking_lot::{Condvar, Mutex};
+
+use super::error::ExtendibleHashTableError;
+use crate::page::PageId;
+
+/// Insert waiting in batch of its bucket
+#[derive(Debug)]
+pub(crate) struct PendingInsert<K, V> {
+    pub key: K,
+    pub value: V,
+    pub hash: u32,
+    pub completer: Completer,
+}
+
+#[derive(Debug, Default)]
+struct Completion {
+    result: Mutex<Option<Result<(), ExtendibleHashTableError>>>,
+    done: Condvar,
+}
+
+/// Hands result of queued insert to its writer, writer of insert dropped without
+/// result gets an error instead of waiting forever
+#[derive(Debug)]
+pub(crate) struct Completer(Arc<Completion>);
+
+impl Completer {
+    pub fn complete(self, result: Result<(), ExtendibleHashTableError>) {
+        *self.0.result.lock() = Some(result);
+        self.0.done.notify_all();
+    }
+}
+
+impl Drop for Completer {
+    fn drop(&mut self) {
+        let mut result = self.0.result.lock();
+        if result.is_none() {
+            *result = Some(Err(ExtendibleHashTableError::Unknown));
+            self.0.done.notify_all();
+        }
+    }
+}
+
+/// Groups inserts which arrive for the same bucket within a short window. Writer whose
+/// insert opens a batch waits for the window, then applies the whole batch while others
+/// wait for their results.
+#[derive(Debug)]
+pub(crate) struct WriteCoalescer<K, V> {
+    window: Duration,
+    pending: Mutex<HashMap<PageId, Vec<PendingInsert<K, V>>>>,
+}
+
+impl<K, V> WriteCoalescer<K, V> {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Queue insert for bucket and wait for its result, `apply` gets the batch if this
+    /// insert opened it and has to complete every insert of it
+    pub fn submit(
+        &self,
+        bucket_page_id: PageId,
+        (key, value, hash): (K, V, u32),
+        apply: impl FnOnce(Vec<PendingInsert<K, V>>),
+    ) -> Result<(), ExtendibleHashTableError> {
+        let completion = Arc::new(Completion::default());
+        let insert = PendingInsert {
+            key,
+            value,
+            hash,
+            completer: Completer(Arc::clone(&completion)),
+        };
+        let opens_batch = {
+            let mut pending = self.pending.lock();
+            let batch = pending.entry(bucket_page_id).or_default();
+            batch.push(insert);
+            batch.len() == 1
+        };
+
+        if opens_batch {
+            thread::sleep(self.window);
+            let batch = self
+                .pending
+                .lock()
+                .remove(&bucket_page_id)
+                .unwrap_or_default();
+            apply(batch);
+        }
+
+        let mut result = completion.result.lock();
+        while result.is_none() {
+            completion.done.wait(&mut result);
+        }
+
+        result.take().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inserts_to_the_same_bucket_are_batched() {
+        let coalescer = Arc::new(WriteCoalescer::<u32, u32>::new(Duration::from_millis(200)));
+        let batch_sizes = Arc::new(Mutex::new(vec![]));
+
+        let handles = (0..3)
+            .map(|i| {
+                let coalescer = Arc::clone(&coalescer);
+                let batch_sizes = Arc::clone(&batch_sizes);
+                thread::spawn(move || {
+                    thread::sleep(Duration::from_millis(20 * i));
+                    coalescer.submit(PageId::new(1), (i as u32, 0, 0), |batch| {
+                        batch_sizes.lock().push(batch.len());
+                        for insert in batch {
+                            insert.completer.complete(Ok(()));
+                        }
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap().unwrap();
+        }
+        assert_eq!(*batch_sizes.lock(), vec![3]);
+
+        // insert dropped by batch fails instead of waiting
+        let result = coalescer.submit(PageId::new(1), (0, 0, 0), drop);
+        assert!(matches!(result, Err(ExtendibleHashTableError::Unknown)));
+    }
+}
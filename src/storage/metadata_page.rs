@@ -0,0 +1,123 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{
+    key_normalization::KeyNormalization,
+    page::PageId,
+    page_guard::{ReadPageGuard, WritePageGuard},
+};
+
+/// Page reserved for database wide metadata, never returned by `new_page`
+pub const METADATA_PAGE_ID: PageId = PageId::new(0);
+
+/// How hash table was created, so it can be opened by name alone. Types are tagged
+/// by their `std::any::type_name`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct TableParams {
+    pub key_type: String,
+    pub value_type: String,
+    pub directory_max_depth: u32,
+    pub bucket_max_size: usize,
+}
+
+impl TableParams {
+    pub fn new<K, V>(directory_max_depth: u32, bucket_max_size: usize) -> Self {
+        Self {
+            key_type: std::any::type_name::<K>().to_string(),
+            value_type: std::any::type_name::<V>().to_string(),
+            directory_max_depth,
+            bucket_max_size,
+        }
+    }
+
+    /// Whether table holds keys and values of given types
+    pub fn has_types<K, V>(&self) -> bool {
+        self.key_type == std::any::type_name::<K>() && self.value_type == std::any::type_name::<V>()
+    }
+}
+
+/// Database metadata: catalog of hash tables by name.
+/// Zeroed page of a fresh data file deserializes into empty metadata
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[repr(C)]
+pub struct MetadataPage {
+    header_page_ids: BTreeMap<String, PageId>,
+    // tables without entry compare keys exactly, page written before normalization existed
+    // has zeroes here which read as empty map
+    key_normalizations: BTreeMap<String, KeyNormalization>,
+    // set once everything else is durable on close, cleared on open
+    clean_shutdown: bool,
+    // tables created before parameters were stored have no entry
+    table_params: BTreeMap<String, TableParams>,
+}
+
+impl MetadataPage {
+    pub fn get_header_page_id(&self, name: &str) -> Option<PageId> {
+        self.header_page_ids.get(name).copied()
+    }
+
+    pub fn set_header_page_id(&mut self, name: String, header_page_id: PageId) {
+        self.header_page_ids.insert(name, header_page_id);
+    }
+
+    pub fn get_key_normalization(&self, name: &str) -> KeyNormalization {
+        self.key_normalizations
+            .get(name)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn set_key_normalization(&mut self, name: String, key_normalization: KeyNormalization) {
+        if key_normalization == KeyNormalization::Exact {
+            self.key_normalizations.remove(&name);
+        } else {
+            self.key_normalizations.insert(name, key_normalization);
+        }
+    }
+
+    pub fn get_table_params(&self, name: &str) -> Option<&TableParams> {
+        self.table_params.get(name)
+    }
+
+    pub fn set_table_params(&mut self, name: String, params: TableParams) {
+        self.table_params.insert(name, params);
+    }
+
+    pub fn is_clean_shutdown(&self) -> bool {
+        self.clean_shutdown
+    }
+
+    pub fn set_clean_shutdown(&mut self, clean_shutdown: bool) {
+        self.clean_shutdown = clean_shutdown;
+    }
+
+    pub fn get_names(&self) -> Vec<String> {
+        self.header_page_ids.keys().cloned().collect()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(&self).unwrap()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        bincode::deserialize(bytes).context("Metadata page is corrupted.")
+    }
+}
+
+impl TryFrom<&WritePageGuard<'_>> for MetadataPage {
+    type Error = anyhow::Error;
+
+    fn try_from(data: &WritePageGuard<'_>) -> Result<Self> {
+        Self::from_bytes(data)
+    }
+}
+
+impl TryFrom<&ReadPageGuard<'_>> for MetadataPage {
+    type Error = anyhow::Error;
+
+    fn try_from(data: &ReadPageGuard<'_>) -> Result<Self> {
+        Self::from_bytes(data)
+    }
+}
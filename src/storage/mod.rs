@@ -1 +1,2 @@
 pub mod extendible_hash_table;
+pub mod metadata_page;
@@ -0,0 +1,107 @@
+use parking_lot::{Condvar, Mutex};
+
+use crate::sharded_counter::ShardedCounter;
+
+#[derive(Debug, Default)]
+struct Permits {
+    limit: Option<usize>,
+    in_use: usize,
+}
+
+/// Counting semaphore for structure changes (bucket splits, directory growths) of hash
+/// tables sharing a buffer pool. Every change pins a few frames until it's done, so
+/// writers beyond the limit wait in queue instead of taking frames all at once.
+#[derive(Debug, Default)]
+pub(crate) struct StructureAdmission {
+    permits: Mutex<Permits>,
+    released: Condvar,
+    waits: ShardedCounter,
+}
+
+/// Held for the whole structure change, gives permit back on drop
+#[derive(Debug)]
+pub(crate) struct StructurePermit<'a>(&'a StructureAdmission);
+
+impl StructureAdmission {
+    pub fn new(limit: Option<usize>) -> Self {
+        Self {
+            permits: Mutex::new(Permits { limit, in_use: 0 }),
+            ..Default::default()
+        }
+    }
+
+    /// Change the limit, `None` admits every change right away. Changes in progress
+    /// keep their permits.
+    pub fn set_limit(&self, limit: Option<usize>) {
+        self.permits.lock().limit = limit;
+        self.released.notify_all();
+    }
+
+    /// Wait until there are fewer changes in progress than the limit
+    pub fn admit(&self) -> StructurePermit<'_> {
+        let mut permits = self.permits.lock();
+        if permits.limit.is_some_and(|limit| permits.in_use >= limit) {
+            self.waits.increment();
+            while permits.limit.is_some_and(|limit| permits.in_use >= limit) {
+                self.released.wait(&mut permits);
+            }
+        }
+        permits.in_use += 1;
+
+        StructurePermit(self)
+    }
+
+    /// Changes which had to wait for a permit
+    pub fn waits(&self) -> u64 {
+        self.waits.get()
+    }
+}
+
+impl Drop for StructurePermit<'_> {
+    fn drop(&mut self) {
+        self.0.permits.lock().in_use -= 1;
+        self.0.released.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+        time::Duration,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_changes_beyond_limit_wait() {
+        let admission = Arc::new(StructureAdmission::new(Some(2)));
+        let in_progress = Arc::new(AtomicUsize::new(0));
+        let most_in_progress = Arc::new(AtomicUsize::new(0));
+
+        let handles = (0..6)
+            .map(|_| {
+                let admission = Arc::clone(&admission);
+                let in_progress = Arc::clone(&in_progress);
+                let most_in_progress = Arc::clone(&most_in_progress);
+                thread::spawn(move || {
+                    let _permit = admission.admit();
+                    let current = in_progress.fetch_add(1, Ordering::SeqCst) + 1;
+                    most_in_progress.fetch_max(current, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(20));
+                    in_progress.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert!(most_in_progress.load(Ordering::SeqCst) <= 2);
+        assert!(admission.waits() > 0);
+    }
+}
@@ -0,0 +1,97 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::page::PageId;
+
+/// Structural change of hash table which touches several pages at once
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub(crate) enum StructureChange {
+    /// Catalog points to header of new hash table
+    NewHashTable,
+    /// Directory points to new bucket
+    NewBucket,
+    /// Header points to new directory
+    NewDirectory,
+    /// Bucket entries are split with new bucket
+    BucketSplit,
+    /// Bucket entries are split with new bucket and directory doubles
+    DirectoryGrowth,
+    /// Bucket entries are moved to its split image, which directory points to instead
+    BucketMerge,
+    /// Header points to directories of entries hashed again with another hasher
+    Rehash,
+}
+
+/// Pages as they are after the change
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub(crate) struct StructureRecord {
+    pub change: StructureChange,
+    pub pages: Vec<(PageId, Vec<u8>)>,
+}
+
+/// Log of the structural change being written to data file, it is empty when no change
+/// is in flight. Change is logged before any of its pages is written, so after crash
+/// logged pages are written again and data file never has only part of the change.
+/// Record is stored as little endian length followed by bincode payload,
+/// partially written record is treated as absent.
+#[derive(Debug)]
+pub(crate) struct StructureLog {
+    file: File,
+}
+
+impl StructureLog {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .with_context(|| format!("Can't open structure log {}.", path.display()))?;
+
+        Ok(Self { file })
+    }
+
+    /// Durably store record, change is committed once this returns
+    pub fn write(&mut self, record: &StructureRecord) -> Result<()> {
+        let payload = bincode::serialize(record)?;
+
+        self.file.set_len(0)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&(payload.len() as u64).to_le_bytes())?;
+        self.file.write_all(&payload)?;
+        self.file.sync_all()?;
+
+        Ok(())
+    }
+
+    pub fn read(&mut self) -> Result<Option<StructureRecord>> {
+        let mut data = Vec::new();
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.read_to_end(&mut data)?;
+
+        let Some((len, payload)) = data.split_first_chunk::<8>() else {
+            return Ok(None);
+        };
+        let len = u64::from_le_bytes(*len) as usize;
+        if payload.len() < len {
+            return Ok(None);
+        }
+
+        Ok(bincode::deserialize(&payload[..len]).ok())
+    }
+
+    pub fn clear(&mut self) -> Result<()> {
+        self.file.set_len(0)?;
+        self.file.sync_all()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use parking_lot::RwLock;
+
+use crate::{disk_manager::DiskManager, page::PageId};
+
+/// How pages of tablespace are spread over its data files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TablespaceLayout {
+    /// Pages fill the first file up to its max pages, then spill to the next one.
+    /// Files can be added at any time.
+    #[default]
+    FillThenSpill,
+    /// Page `i` goes to file `i % files`, so sequential pages are spread over all files.
+    /// Files can be added only while tablespace is empty.
+    RoundRobin,
+}
+
+#[derive(Debug)]
+struct DataFile {
+    disk_manager: DiskManager,
+    max_pages: usize,
+}
+
+/// Pages spread over several data files, logical page id is mapped to file and page
+/// within it by layout. Files have to be given in the same order on every open.
+#[derive(Debug)]
+pub(crate) struct TablespaceBackend {
+    layout: TablespaceLayout,
+    files: RwLock<Vec<DataFile>>,
+}
+
+impl TablespaceBackend {
+    pub fn open(files: &[(&Path, usize)], layout: TablespaceLayout) -> Result<Self> {
+        if files.is_empty() {
+            bail!("Tablespace needs a data file.");
+        }
+        let backend = Self {
+            layout,
+            files: RwLock::new(vec![]),
+        };
+        for (path, max_pages) in files {
+            backend.add_data_file(path, *max_pages)?;
+        }
+
+        Ok(backend)
+    }
+
+    pub fn add_data_file(&self, path: &Path, max_pages: usize) -> Result<()> {
+        if max_pages == 0 {
+            bail!("Data file {} must hold at least one page.", path.display());
+        }
+        let mut files = self.files.write();
+        // page ids would map to other files than the ones holding them
+        if self.layout == TablespaceLayout::RoundRobin && num_pages(&files, self.layout)? > 0 {
+            bail!("Data file can't be added to round robin tablespace which holds pages.");
+        }
+        files.push(DataFile {
+            disk_manager: DiskManager::open(path)?,
+            max_pages,
+        });
+
+        Ok(())
+    }
+
+    pub fn read_page(&self, page_id: PageId) -> Result<Vec<u8>> {
+        let files = self.files.read();
+        let (file, local_page_id) = self.locate(&files, page_id)?;
+
+        file.disk_manager.read_page(local_page_id)
+    }
+
+    pub fn write_page(&self, page_id: PageId, data: &[u8]) -> Result<()> {
+        let files = self.files.read();
+        let (file, local_page_id) = self.locate(&files, page_id)?;
+
+        file.disk_manager.write_page(local_page_id, data)
+    }
+
+    pub fn sync(&self) -> Result<()> {
+        for file in self.files.read().iter() {
+            file.disk_manager.sync()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn num_pages(&self) -> Result<usize> {
+        num_pages(&self.files.read(), self.layout)
+    }
+
+    fn locate<'a>(&self, files: &'a [DataFile], page_id: PageId) -> Result<(&'a DataFile, PageId)> {
+        let location = match self.layout {
+            TablespaceLayout::FillThenSpill => {
+                let page_id = page_id.as_usize();
+                let mut first_page_id = 0;
+                files.iter().find_map(|file| {
+                    let location = (page_id < first_page_id + file.max_pages)
+                        .then(|| (file, PageId::new(page_id - first_page_id)));
+                    first_page_id += file.max_pages;
+                    location
+                })
+            }
+            TablespaceLayout::RoundRobin => {
+                let file = &files[page_id.as_usize() % files.len()];
+                let local_page_id = page_id.as_usize() / files.len();
+                (local_page_id < file.max_pages).then_some((file, PageId::new(local_page_id)))
+            }
+        };
+        let Some(location) = location else {
+            bail!("Page {} is beyond capacity of tablespace.", page_id);
+        };
+
+        Ok(location)
+    }
+}
+
+fn num_pages(files: &[DataFile], layout: TablespaceLayout) -> Result<usize> {
+    let mut num_pages = 0;
+    let mut first_page_id = 0;
+    for (index, file) in files.iter().enumerate() {
+        let file_pages = file.disk_manager.num_pages()?;
+        if file_pages > 0 {
+            num_pages = match layout {
+                TablespaceLayout::FillThenSpill => first_page_id + file_pages,
+                TablespaceLayout::RoundRobin => {
+                    num_pages.max((file_pages - 1) * files.len() + index + 1)
+                }
+            };
+        }
+        first_page_id += file.max_pages;
+    }
+
+    Ok(num_pages)
+}
@@ -0,0 +1,137 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use anyhow::{bail, Context, Result};
+use tempfile::NamedTempFile;
+
+use crate::{
+    buffer_pool_manager::BufferPoolManager,
+    disk_manager::DiskManager,
+    page::PageId,
+    page_guard::{ReadPageGuard, WritePageGuard},
+};
+
+const REPLACER_K: usize = 2;
+
+/// Short-lived pages for spilling operators: sort runs, hash join partitions and aggregation
+/// spills. Pages live in their own temporary file behind their own buffer pool, so they never
+/// evict database pages. They are freed all at once by `free_all` or when allocator is dropped.
+#[derive(Debug)]
+pub struct TempPageAllocator {
+    dir: PathBuf,
+    pool_size: usize,
+    // dropped before the file is removed
+    buffer_pool_manager: BufferPoolManager,
+    file: NamedTempFile,
+    num_pages: AtomicUsize,
+}
+
+impl TempPageAllocator {
+    /// Keep pages in temporary file inside `dir`, up to `pool_size` of them are held in memory
+    pub fn new(dir: impl AsRef<Path>, pool_size: usize) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        let (file, buffer_pool_manager) = Self::create_file(&dir, pool_size)?;
+
+        Ok(Self {
+            dir,
+            pool_size,
+            buffer_pool_manager,
+            file,
+            num_pages: AtomicUsize::new(0),
+        })
+    }
+
+    fn create_file(dir: &Path, pool_size: usize) -> Result<(NamedTempFile, BufferPoolManager)> {
+        let file = tempfile::Builder::new()
+            .prefix(".temp-pages-")
+            .tempfile_in(dir)
+            .with_context(|| format!("Can't create temporary page file in {}.", dir.display()))?;
+        let disk_manager = DiskManager::open(file.path())?;
+        let buffer_pool_manager = BufferPoolManager::new(disk_manager, pool_size, REPLACER_K);
+
+        Ok((file, buffer_pool_manager))
+    }
+
+    pub fn new_page(&self) -> Result<(PageId, WritePageGuard<'_>)> {
+        let page = self
+            .buffer_pool_manager
+            .new_page()
+            .context("All temporary pages in memory are pinned.")?;
+        self.num_pages.fetch_add(1, Ordering::Relaxed);
+
+        Ok(page)
+    }
+
+    pub fn fetch_page_read(&self, page_id: PageId) -> Result<ReadPageGuard<'_>> {
+        self.check_allocated(page_id)?;
+
+        self.buffer_pool_manager
+            .fetch_page_read(page_id)
+            .with_context(|| format!("Can't fetch temporary page {}.", page_id))
+    }
+
+    pub fn fetch_page_write(&self, page_id: PageId) -> Result<WritePageGuard<'_>> {
+        self.check_allocated(page_id)?;
+
+        self.buffer_pool_manager
+            .fetch_page_write(page_id)
+            .with_context(|| format!("Can't fetch temporary page {}.", page_id))
+    }
+
+    // ids are handed out in order starting from 1 in fresh file
+    fn check_allocated(&self, page_id: PageId) -> Result<()> {
+        if page_id.as_usize() == 0 || page_id.as_usize() > self.num_pages() {
+            bail!("Temporary page {} is not allocated.", page_id);
+        }
+
+        Ok(())
+    }
+
+    /// Number of pages allocated since creation or the last `free_all`
+    pub fn num_pages(&self) -> usize {
+        self.num_pages.load(Ordering::Relaxed)
+    }
+
+    /// Free all pages at once, their file is replaced by an empty one
+    pub fn free_all(&mut self) -> Result<()> {
+        let (file, buffer_pool_manager) = Self::create_file(&self.dir, self.pool_size)?;
+        self.buffer_pool_manager = buffer_pool_manager;
+        self.file = file;
+        self.num_pages.store(0, Ordering::Relaxed);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_temp_page_allocator() {
+        let dir = TempDir::new().unwrap();
+        let mut allocator = TempPageAllocator::new(dir.path(), 2).unwrap();
+
+        let mut page_ids = vec![];
+        for i in 0..5 {
+            let (page_id, mut page) = allocator.new_page().unwrap();
+            page[0] = i;
+            page_ids.push(page_id);
+        }
+        // pages are read back from file after eviction
+        for (i, page_id) in page_ids.iter().enumerate() {
+            assert_eq!(allocator.fetch_page_read(*page_id).unwrap()[0], i as u8);
+        }
+        let path = allocator.file.path().to_path_buf();
+        assert!(path.exists());
+
+        allocator.free_all().unwrap();
+        assert_eq!(allocator.num_pages(), 0);
+        assert!(!path.exists());
+        assert!(allocator.fetch_page_read(page_ids[0]).is_err());
+    }
+}
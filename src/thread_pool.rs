@@ -1,9 +1,13 @@
 use std::{
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
     sync::{
         mpsc::{self, Sender},
         Arc, Mutex,
     },
-    thread,
+    task::{Context, Poll, Waker},
+    thread::{self, JoinHandle},
 };
 
 enum ThreadPoolMessage {
@@ -12,8 +16,10 @@ enum ThreadPoolMessage {
 }
 
 ///  Thread pool
+#[derive(Debug)]
 pub struct ThreadPool {
     sender: Sender<ThreadPoolMessage>,
+    threads: Mutex<Vec<JoinHandle<()>>>,
 }
 
 impl ThreadPool {
@@ -24,39 +30,113 @@ impl ThreadPool {
     {
         let (sender, receiver) = mpsc::channel::<ThreadPoolMessage>();
         let receiver = Arc::new(Mutex::new(receiver));
-        for _ in 0..threads {
-            let receiver = Arc::clone(&receiver);
-            thread::spawn(move || loop {
-                let receiver = receiver.lock().unwrap();
-                let message = receiver.recv();
-                drop(receiver);
-
-                match message {
-                    Ok(ThreadPoolMessage::RunJob(job)) => {
-                        job();
-                    }
-                    _ => {
-                        break;
+        let threads = (0..threads)
+            .map(|_| {
+                let receiver = Arc::clone(&receiver);
+                thread::spawn(move || loop {
+                    let receiver = receiver.lock().unwrap();
+                    let message = receiver.recv();
+                    drop(receiver);
+
+                    match message {
+                        Ok(ThreadPoolMessage::RunJob(job)) => {
+                            job();
+                        }
+                        _ => {
+                            break;
+                        }
                     }
-                }
-            });
+                })
+            })
+            .collect();
+
+        Self {
+            sender,
+            threads: Mutex::new(threads),
         }
-        Self { sender }
     }
 
-    /// start work on thread pool thread
+    /// start work on thread pool thread, job spawned after `join` is not run
     pub fn spawn<F>(&self, job: F)
     where
         F: FnOnce() + Send + 'static,
     {
-        self.sender
-            .send(ThreadPoolMessage::RunJob(Box::new(job)))
-            .unwrap();
+        let _ = self.sender.send(ThreadPoolMessage::RunJob(Box::new(job)));
+    }
+
+    /// Run jobs spawned so far and wait until all threads exit
+    pub fn join(&self) {
+        let threads = std::mem::take(&mut *self.threads.lock().unwrap());
+        // every thread takes one shutdown message, after all jobs queued before it
+        for _ in &threads {
+            let _ = self.sender.send(ThreadPoolMessage::Shutdown);
+        }
+        for thread in threads {
+            let _ = thread.join();
+        }
+    }
+
+    /// Start work on thread pool thread, its result is awaited on any async runtime.
+    /// Panic of the job is resumed in the task awaiting it.
+    pub fn spawn_future<F, T>(&self, job: F) -> JobFuture<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let state = Arc::new(Mutex::new(JobState {
+            result: None,
+            waker: None,
+        }));
+
+        let job_state = Arc::clone(&state);
+        self.spawn(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(job));
+            let mut state = job_state.lock().unwrap();
+            state.result = Some(result);
+            if let Some(waker) = state.waker.take() {
+                waker.wake();
+            }
+        });
+
+        JobFuture { state }
+    }
+}
+
+#[derive(Debug)]
+struct JobState<T> {
+    result: Option<thread::Result<T>>,
+    waker: Option<Waker>,
+}
+
+/// Result of job started by `ThreadPool::spawn_future`
+#[derive(Debug)]
+pub struct JobFuture<T> {
+    state: Arc<Mutex<JobState<T>>>,
+}
+
+impl<T> Future for JobFuture<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut state = self.state.lock().unwrap();
+        match state.result.take() {
+            Some(Ok(result)) => Poll::Ready(result),
+            Some(Err(panic)) => {
+                drop(state);
+                panic::resume_unwind(panic)
+            }
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
     }
 }
 
 impl Drop for ThreadPool {
     fn drop(&mut self) {
-        self.sender.send(ThreadPoolMessage::Shutdown).unwrap();
+        for _ in self.threads.lock().unwrap().iter() {
+            let _ = self.sender.send(ThreadPoolMessage::Shutdown);
+        }
     }
 }
@@ -0,0 +1,241 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use parking_lot::Mutex;
+
+use crate::{disk_manager::DiskManager, page::PageId};
+
+#[derive(Debug)]
+struct Accesses {
+    // pages not accessed since open count as accessed at open
+    last_access: HashMap<PageId, Instant>,
+    opened_at: Instant,
+    // pages back in hot tier whose cold copy is cleared once hot tier is synced
+    stale_cold: HashSet<PageId>,
+}
+
+/// Two storage tiers: pages live in hot tier and migrate to cold tier when they are
+/// not accessed for a while, cold page is moved back to hot tier on access.
+/// Placement is kept by pages themselves: slot of moved page is emptied in hot tier, so
+/// page which is all zeroes in hot tier is read from cold tier. Cold copy of page back
+/// in hot tier is cleared, so it can't shadow page later written as zeroes.
+#[derive(Debug)]
+pub(crate) struct TieredBackend {
+    hot: DiskManager,
+    cold: DiskManager,
+    // held for the whole page access, so migration doesn't empty page while it is used
+    accesses: Mutex<Accesses>,
+}
+
+impl TieredBackend {
+    pub fn open(hot_path: &Path, cold: DiskManager) -> Result<Self> {
+        Ok(Self {
+            hot: DiskManager::open(hot_path)?,
+            cold,
+            accesses: Mutex::new(Accesses {
+                last_access: HashMap::new(),
+                opened_at: Instant::now(),
+                stale_cold: HashSet::new(),
+            }),
+        })
+    }
+
+    pub fn read_page(&self, page_id: PageId) -> Result<Vec<u8>> {
+        let mut accesses = self.accesses.lock();
+        accesses.last_access.insert(page_id, Instant::now());
+
+        let data = self.hot.read_page(page_id)?;
+        if !is_empty(&data) || accesses.stale_cold.contains(&page_id) {
+            return Ok(data);
+        }
+
+        let data = self.cold.read_page(page_id)?;
+        if !is_empty(&data) {
+            self.hot.write_page(page_id, &data)?;
+            accesses.stale_cold.insert(page_id);
+        }
+
+        Ok(data)
+    }
+
+    pub fn write_page(&self, page_id: PageId, data: &[u8]) -> Result<()> {
+        let mut accesses = self.accesses.lock();
+        accesses.last_access.insert(page_id, Instant::now());
+
+        self.hot.write_page(page_id, data)?;
+        // page of zeroes looks moved out of hot tier, its cold copy must go
+        if is_empty(data) {
+            accesses.stale_cold.insert(page_id);
+        }
+
+        Ok(())
+    }
+
+    /// Move pages not accessed within `window` to cold tier, returns number of moved pages.
+    /// Pages are copied without blocking access to them and are durable in cold tier
+    /// before they are emptied in hot tier, page accessed meanwhile stays hot.
+    pub fn migrate_cold_pages(&self, window: Duration) -> Result<usize> {
+        let copied = self.copy_cold_pages(window)?;
+        if copied.is_empty() {
+            return Ok(0);
+        }
+        self.cold.sync()?;
+
+        self.empty_copied_pages(copied)
+    }
+
+    // pages copied to cold tier with their last access before the copy
+    fn copy_cold_pages(&self, window: Duration) -> Result<Vec<(PageId, Option<Instant>)>> {
+        let now = Instant::now();
+        let num_pages = self.hot.num_pages()?;
+        let candidates = {
+            let accesses = self.accesses.lock();
+            (0..num_pages)
+                .map(PageId::new)
+                .filter(|page_id| !accesses.stale_cold.contains(page_id))
+                .map(|page_id| (page_id, accesses.last_access.get(&page_id).copied()))
+                .filter(|(_, last_access)| {
+                    now.duration_since(last_access.unwrap_or(accesses.opened_at)) >= window
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut copied = vec![];
+        for (page_id, last_access) in candidates {
+            let data = self.hot.read_page(page_id)?;
+            // page moved already or never written
+            if is_empty(&data) {
+                continue;
+            }
+            self.cold.write_page(page_id, &data)?;
+            copied.push((page_id, last_access));
+        }
+
+        Ok(copied)
+    }
+
+    fn empty_copied_pages(&self, copied: Vec<(PageId, Option<Instant>)>) -> Result<usize> {
+        let mut accesses = self.accesses.lock();
+        let mut moved = 0;
+        for (page_id, last_access) in copied {
+            if accesses.last_access.get(&page_id).copied() != last_access {
+                accesses.stale_cold.insert(page_id);
+                continue;
+            }
+            empty_page(&self.hot, page_id)?;
+            moved += 1;
+        }
+
+        Ok(moved)
+    }
+
+    // hot tier is synced first, so page moved back from cold tier is never lost
+    pub fn sync(&self) -> Result<()> {
+        let mut accesses = self.accesses.lock();
+        self.hot.sync()?;
+
+        let num_cold_pages = self.cold.num_pages()?;
+        for page_id in &accesses.stale_cold {
+            if page_id.as_usize() < num_cold_pages {
+                empty_page(&self.cold, *page_id)?;
+            }
+        }
+        self.cold.sync()?;
+        accesses.stale_cold.clear();
+
+        Ok(())
+    }
+
+    pub fn num_pages(&self) -> Result<usize> {
+        Ok(self.hot.num_pages()?.max(self.cold.num_pages()?))
+    }
+}
+
+fn is_empty(data: &[u8]) -> bool {
+    data.iter().all(|byte| *byte == 0)
+}
+
+// zeroes are written first, storage which can't reclaim slot keeps them
+fn empty_page(disk_manager: &DiskManager, page_id: PageId) -> Result<()> {
+    disk_manager.write_page(page_id, &[])?;
+    disk_manager.reclaim_page(page_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::page::PAGE_SIZE;
+
+    #[test]
+    fn test_pages_migrate_between_tiers() {
+        let dir = TempDir::new().unwrap();
+        let hot_path = dir.path().join("hot.db");
+        let cold = || DiskManager::open(dir.path().join("cold.db")).unwrap();
+        let backend = TieredBackend::open(&hot_path, cold()).unwrap();
+
+        for page_id in 0..4 {
+            backend
+                .write_page(PageId::new(page_id), &[page_id as u8 + 1])
+                .unwrap();
+        }
+        backend.read_page(PageId::new(1)).unwrap();
+        assert_eq!(backend.migrate_cold_pages(Duration::ZERO).unwrap(), 4);
+        assert_eq!(backend.migrate_cold_pages(Duration::ZERO).unwrap(), 0);
+        // hot copies are emptied
+        assert!(is_empty(&backend.hot.read_page(PageId::new(2)).unwrap()));
+        drop(backend);
+
+        // placement survives reopen, cold page is faulted back to hot tier
+        let backend = TieredBackend::open(&hot_path, cold()).unwrap();
+        let data = backend.read_page(PageId::new(2)).unwrap();
+        assert_eq!(data[0], 3);
+        assert_eq!(data.len(), PAGE_SIZE);
+        assert_eq!(backend.hot.read_page(PageId::new(2)).unwrap()[0], 3);
+        assert_eq!(
+            backend.migrate_cold_pages(Duration::from_secs(60)).unwrap(),
+            0
+        );
+
+        // page written as zeroes isn't shadowed by its cold copy
+        backend.write_page(PageId::new(3), &[]).unwrap();
+        assert!(is_empty(&backend.read_page(PageId::new(3)).unwrap()));
+        backend.sync().unwrap();
+        drop(backend);
+        let backend = TieredBackend::open(&hot_path, cold()).unwrap();
+        assert!(is_empty(&backend.read_page(PageId::new(3)).unwrap()));
+        assert_eq!(backend.read_page(PageId::new(2)).unwrap()[0], 3);
+        assert_eq!(backend.read_page(PageId::new(1)).unwrap()[0], 2);
+    }
+
+    #[test]
+    fn test_page_accessed_during_migration_stays_hot() {
+        let dir = TempDir::new().unwrap();
+        let hot_path = dir.path().join("hot.db");
+        let cold = || DiskManager::open(dir.path().join("cold.db")).unwrap();
+        let backend = TieredBackend::open(&hot_path, cold()).unwrap();
+        backend.write_page(PageId::new(1), &[1]).unwrap();
+        backend.write_page(PageId::new(2), &[1]).unwrap();
+
+        let copied = backend.copy_cold_pages(Duration::ZERO).unwrap();
+        assert_eq!(copied.len(), 2);
+        backend.write_page(PageId::new(1), &[2]).unwrap();
+        backend.cold.sync().unwrap();
+        assert_eq!(backend.empty_copied_pages(copied).unwrap(), 1);
+
+        assert_eq!(backend.hot.read_page(PageId::new(1)).unwrap()[0], 2);
+        assert!(is_empty(&backend.hot.read_page(PageId::new(2)).unwrap()));
+        backend.sync().unwrap();
+        assert!(is_empty(&backend.cold.read_page(PageId::new(1)).unwrap()));
+        drop(backend);
+
+        let backend = TieredBackend::open(&hot_path, cold()).unwrap();
+        assert_eq!(backend.read_page(PageId::new(1)).unwrap()[0], 2);
+        assert_eq!(backend.read_page(PageId::new(2)).unwrap()[0], 1);
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::{
+    lru_k_replacer::{AccessType, FrameId},
+    page::PageId,
+    replacer::Replacer,
+};
+
+#[derive(Debug, PartialEq)]
+enum Queue {
+    // seen once, FIFO
+    In,
+    // seen again while resident or shortly after eviction, LRU
+    Main,
+}
+
+#[derive(Debug)]
+struct TwoQEntry {
+    queue: Queue,
+    page_id: Option<PageId>,
+    is_evictable: bool,
+}
+
+/// 2Q policy: pages seen once go through small FIFO queue, so one-off scans don't flush
+/// hot pages out of main LRU queue. Pages evicted from FIFO are remembered for a while
+/// and go straight to main queue when loaded again
+#[derive(Debug)]
+pub struct TwoQReplacer {
+    // front is the eviction end of both queues
+    in_queue: VecDeque<FrameId>,
+    main_queue: VecDeque<FrameId>,
+    out_queue: VecDeque<PageId>,
+    out_pages: HashSet<PageId>,
+    entries: HashMap<FrameId, TwoQEntry>,
+    loaded: HashMap<FrameId, (PageId, Queue)>,
+    max_in_size: usize,
+    max_out_size: usize,
+}
+
+impl TwoQReplacer {
+    pub fn new(num_of_frames: usize) -> Self {
+        Self {
+            in_queue: VecDeque::new(),
+            main_queue: VecDeque::new(),
+            out_queue: VecDeque::new(),
+            out_pages: HashSet::new(),
+            entries: HashMap::new(),
+            loaded: HashMap::new(),
+            max_in_size: (num_of_frames / 4).max(1),
+            max_out_size: (num_of_frames / 2).max(1),
+        }
+    }
+
+    fn remember_evicted(&mut self, page_id: PageId) {
+        self.out_queue.push_back(page_id);
+        self.out_pages.insert(page_id);
+        if self.out_queue.len() > self.max_out_size {
+            if let Some(page_id) = self.out_queue.pop_front() {
+                self.out_pages.remove(&page_id);
+            }
+        }
+    }
+
+    fn evict_from(&mut self, queue: Queue) -> Option<FrameId> {
+        let frames = match queue {
+            Queue::In => &mut self.in_queue,
+            Queue::Main => &mut self.main_queue,
+        };
+        let position = frames
+            .iter()
+            .position(|frame_id| self.entries[frame_id].is_evictable)?;
+        let frame_id = frames.remove(position)?;
+        let entry = self.entries.remove(&frame_id)?;
+
+        if let (Queue::In, Some(page_id)) = (entry.queue, entry.page_id) {
+            self.remember_evicted(page_id);
+        }
+
+        Some(frame_id)
+    }
+}
+
+impl Replacer for TwoQReplacer {
+    fn record_load(&mut self, frame_id: FrameId, page_id: PageId) {
+        let queue = if self.out_pages.remove(&page_id) {
+            self.out_queue.retain(|id| *id != page_id);
+            Queue::Main
+        } else {
+            Queue::In
+        };
+        self.loaded.insert(frame_id, (page_id, queue));
+    }
+
+    fn record_access(&mut self, frame_id: FrameId, _access_type: AccessType) {
+        if let Some(entry) = self.entries.get(&frame_id) {
+            // repeated access while in FIFO is treated as correlated and ignored
+            if entry.queue == Queue::Main {
+                self.main_queue.retain(|id| *id != frame_id);
+                self.main_queue.push_back(frame_id);
+            }
+            return;
+        }
+
+        let (page_id, queue) = match self.loaded.remove(&frame_id) {
+            Some((page_id, queue)) => (Some(page_id), queue),
+            None => (None, Queue::In),
+        };
+        match queue {
+            Queue::In => self.in_queue.push_back(frame_id),
+            Queue::Main => self.main_queue.push_back(frame_id),
+        }
+        self.entries.insert(
+            frame_id,
+            TwoQEntry {
+                queue,
+                page_id,
+                is_evictable: false,
+            },
+        );
+    }
+
+    fn set_evictable(&mut self, frame_id: FrameId, is_evictable: bool) {
+        if let Some(entry) = self.entries.get_mut(&frame_id) {
+            entry.is_evictable = is_evictable;
+        }
+    }
+
+    fn evict(&mut self) -> Option<FrameId> {
+        if self.in_queue.len() > self.max_in_size {
+            self.evict_from(Queue::In)
+                .or_else(|| self.evict_from(Queue::Main))
+        } else {
+            self.evict_from(Queue::Main)
+                .or_else(|| self.evict_from(Queue::In))
+        }
+    }
+
+    fn remove(&mut self, frame_id: FrameId) {
+        self.loaded.remove(&frame_id);
+        if let Some(entry) = self.entries.remove(&frame_id) {
+            match entry.queue {
+                Queue::In => self.in_queue.retain(|id| *id != frame_id),
+                Queue::Main => self.main_queue.retain(|id| *id != frame_id),
+            }
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.entries
+            .values()
+            .filter(|entry| entry.is_evictable)
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(replacer: &mut TwoQReplacer, frame_id: usize, page_id: usize) {
+        let frame_id = FrameId::new(frame_id);
+        replacer.record_load(frame_id, PageId::new(page_id));
+        replacer.record_access(frame_id, AccessType::Unknown);
+        replacer.set_evictable(frame_id, true);
+    }
+
+    #[test]
+    fn test_page_seen_again_goes_to_main_queue() {
+        let mut replacer = TwoQReplacer::new(4);
+        for frame_id in 0..4 {
+            load(&mut replacer, frame_id, frame_id);
+        }
+
+        // page 0 is evicted from FIFO and remembered
+        assert_eq!(replacer.evict(), Some(FrameId::new(0)));
+        load(&mut replacer, 0, 0);
+        assert_eq!(replacer.entries[&FrameId::new(0)].queue, Queue::Main);
+
+        // FIFO is drained first while it is over its size
+        assert_eq!(replacer.evict(), Some(FrameId::new(1)));
+        assert_eq!(replacer.evict(), Some(FrameId::new(2)));
+        assert_eq!(replacer.evict(), Some(FrameId::new(0)));
+        assert_eq!(replacer.evict(), Some(FrameId::new(3)));
+        assert_eq!(replacer.evict(), None);
+    }
+}
@@ -0,0 +1,69 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+
+/// Change of a single key made by `Kv` write
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChangeEvent<K, V> {
+    Insert { key: K, value: V },
+    Update { key: K, old_value: V, new_value: V },
+    Remove { key: K, old_value: V },
+}
+
+impl<K, V> ChangeEvent<K, V> {
+    /// Event for key value change, `None` stands for absent key
+    pub(crate) fn new(key: K, old_value: Option<V>, new_value: Option<V>) -> Option<Self> {
+        match (old_value, new_value) {
+            (None, Some(value)) => Some(Self::Insert { key, value }),
+            (Some(old_value), Some(new_value)) => Some(Self::Update {
+                key,
+                old_value,
+                new_value,
+            }),
+            (Some(old_value), None) => Some(Self::Remove { key, old_value }),
+            (None, None) => None,
+        }
+    }
+
+    pub fn key(&self) -> &K {
+        match self {
+            Self::Insert { key, .. } | Self::Update { key, .. } | Self::Remove { key, .. } => key,
+        }
+    }
+}
+
+/// Subscribers to changes of keys starting with prefix
+#[derive(Debug)]
+pub(crate) struct Watchers<K, V> {
+    watchers: Vec<(String, Sender<ChangeEvent<K, V>>)>,
+}
+
+impl<K, V> Watchers<K, V>
+where
+    K: Clone + ToString,
+    V: Clone,
+{
+    pub fn new() -> Self {
+        Self {
+            watchers: Vec::new(),
+        }
+    }
+
+    pub fn subscribe(&mut self, prefix: String) -> Receiver<ChangeEvent<K, V>> {
+        let (sender, receiver) = mpsc::channel();
+        self.watchers.push((prefix, sender));
+
+        receiver
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.watchers.is_empty()
+    }
+
+    /// Send event to watchers of the key, watchers with dropped receiver are unsubscribed
+    pub fn notify(&mut self, event: ChangeEvent<K, V>) {
+        let key = event.key().to_string();
+
+        self.watchers.retain(|(prefix, sender)| {
+            !key.starts_with(prefix.as_str()) || sender.send(event.clone()).is_ok()
+        });
+    }
+}
@@ -0,0 +1,151 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use parking_lot::Mutex;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+#[derive(Debug, Clone)]
+enum BatchOperation<K, V> {
+    Insert(K, V),
+    Remove(K),
+}
+
+/// Group of inserts and removes which `Kv::apply_batch` applies all-or-nothing
+#[derive(Debug, Clone)]
+pub struct WriteBatch<K, V> {
+    operations: Vec<BatchOperation<K, V>>,
+}
+
+impl<K, V> Default for WriteBatch<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> WriteBatch<K, V> {
+    pub fn new() -> Self {
+        Self {
+            operations: Vec::new(),
+        }
+    }
+
+    pub fn insert(&mut self, key: K, value: V) {
+        self.operations.push(BatchOperation::Insert(key, value));
+    }
+
+    pub fn remove(&mut self, key: K) {
+        self.operations.push(BatchOperation::Remove(key));
+    }
+
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    /// Operations in insertion order as (key, new value), `None` value means remove
+    pub(crate) fn into_changes(self) -> impl Iterator<Item = (K, Option<V>)> {
+        self.operations
+            .into_iter()
+            .map(|operation| match operation {
+                BatchOperation::Insert(key, value) => (key, Some(value)),
+                BatchOperation::Remove(key) => (key, None),
+            })
+    }
+}
+
+/// Change of a single key made by batch, `None` stands for absent key
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct BatchRecord<K, V> {
+    pub key: K,
+    pub old_value: Option<V>,
+    pub new_value: Option<V>,
+}
+
+impl<K, V> BatchRecord<K, V> {
+    pub fn new(key: K, old_value: Option<V>, new_value: Option<V>) -> Self {
+        Self {
+            key,
+            old_value,
+            new_value,
+        }
+    }
+}
+
+/// Redo/undo log of the batch being applied, it is empty when no batch is in flight.
+/// Record is stored as little endian length followed by bincode payload,
+/// partially written record is treated as absent.
+#[derive(Debug)]
+pub(crate) struct BatchLog {
+    file: Mutex<File>,
+}
+
+impl BatchLog {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .with_context(|| format!("Can't open batch log {}.", path.display()))?;
+
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Durably store batch records, batch is committed once this returns
+    pub fn write<K, V>(&self, records: &[BatchRecord<K, V>]) -> Result<()>
+    where
+        K: Serialize,
+        V: Serialize,
+    {
+        let payload = bincode::serialize(records)?;
+        let mut file = self.file.lock();
+
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&(payload.len() as u64).to_le_bytes())?;
+        file.write_all(&payload)?;
+        file.sync_all()?;
+
+        Ok(())
+    }
+
+    pub fn read<K, V>(&self) -> Result<Option<Vec<BatchRecord<K, V>>>>
+    where
+        K: DeserializeOwned,
+        V: DeserializeOwned,
+    {
+        let mut file = self.file.lock();
+        let mut data = Vec::new();
+        file.seek(SeekFrom::Start(0))?;
+        file.read_to_end(&mut data)?;
+
+        let Some((len, payload)) = data.split_first_chunk::<8>() else {
+            return Ok(None);
+        };
+        let len = u64::from_le_bytes(*len) as usize;
+        if payload.len() < len {
+            return Ok(None);
+        }
+
+        Ok(bincode::deserialize(&payload[..len]).ok())
+    }
+
+    pub fn clear(&self) -> Result<()> {
+        let file = self.file.lock();
+        file.set_len(0)?;
+        file.sync_all()?;
+
+        Ok(())
+    }
+}
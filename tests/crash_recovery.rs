@@ -0,0 +1,113 @@
+//! Crash recovery harness: workload runs in a child process which is killed at random
+//! points, then database is reopened and checked to contain every acknowledged batch.
+
+use std::{
+    env,
+    io::{BufRead, BufReader},
+    path::Path,
+    process::{Command, Stdio},
+    sync::mpsc,
+    thread,
+    time::Duration,
+};
+
+use cmu_db_rs::{rng_seed, seeded_rng, Kv, WriteBatch, SEED_ENV};
+use rand::Rng;
+use tempfile::TempDir;
+
+const CHILD_DB_ENV: &str = "CRASH_RECOVERY_DB";
+const COUNTER_KEY: u64 = u64::MAX;
+const ROUNDS: usize = 10;
+
+// keys written by batch number `i`, every key gets value `i`
+fn batch_keys(i: u64) -> [u64; 3] {
+    [i % 50, 1000 + i % 7, COUNTER_KEY]
+}
+
+/// Workload of the child process, it is skipped when test runs on its own
+#[test]
+fn crash_recovery_child() {
+    let Ok(path) = env::var(CHILD_DB_ENV) else {
+        return;
+    };
+    let kv = Kv::<u64, u64>::open(path).unwrap();
+    let start = kv.get(COUNTER_KEY).unwrap().map_or(0, |i| i + 1);
+
+    for i in start.. {
+        let mut batch = WriteBatch::new();
+        for key in batch_keys(i) {
+            batch.insert(key, i);
+        }
+        kv.apply_batch(batch).unwrap();
+        println!("committed {i}");
+    }
+}
+
+// run child until it is killed, returns last batch it acknowledged
+fn run_child(path: &Path, kill_after: Duration) -> Option<u64> {
+    let mut child = Command::new(env::current_exe().unwrap())
+        .args([
+            "crash_recovery_child",
+            "--exact",
+            "--nocapture",
+            "--test-threads=1",
+        ])
+        .env(CHILD_DB_ENV, path)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let stdout = child.stdout.take().unwrap();
+    let (sender, receiver) = mpsc::channel();
+    let reader = thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if let Some(i) = line.strip_prefix("committed ") {
+                let _ = sender.send(i.parse::<u64>().unwrap());
+            }
+        }
+    });
+
+    thread::sleep(kill_after);
+    child.kill().unwrap();
+    child.wait().unwrap();
+    reader.join().unwrap();
+
+    receiver.try_iter().last()
+}
+
+fn verify(path: &Path, acknowledged: Option<u64>) {
+    let kv = Kv::<u64, u64>::open(path).unwrap();
+    let counter = kv.get(COUNTER_KEY).unwrap();
+
+    // batch could be durable without being acknowledged, but never the other way around
+    assert!(counter >= acknowledged, "{counter:?} < {acknowledged:?}");
+    let Some(counter) = counter else {
+        return;
+    };
+
+    // batches are applied in order, each key holds number of the last batch which wrote it
+    for key in (0..50).chain(1000..1007) {
+        let expected = (0..=counter).rev().find(|i| batch_keys(*i).contains(&key));
+        assert_eq!(kv.get(key).unwrap(), expected, "key {key}");
+    }
+}
+
+#[test]
+fn test_committed_batches_survive_kill() {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("crash.db");
+    let mut rng = seeded_rng("crash recovery");
+    println!("seed {}, set {} to repeat", rng_seed(), SEED_ENV);
+    let mut acknowledged = None;
+
+    for _ in 0..ROUNDS {
+        let kill_after = Duration::from_millis(rng.gen_range(20..150));
+        acknowledged = run_child(&path, kill_after).max(acknowledged);
+
+        verify(&path, acknowledged);
+    }
+}